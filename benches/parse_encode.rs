@@ -0,0 +1,133 @@
+//! Parse and encode throughput over representative corpora.
+//!
+//! Run with `cargo bench`. Each case reports the mean time per iteration and
+//! throughput, measured over roughly half a second after a warm-up run.
+
+use bencode_rs::{parse_bencode, Document, Value};
+use std::collections::HashMap;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const TARGET: Duration = Duration::from_millis(500);
+
+fn bench<F: FnMut()>(name: &str, bytes: usize, mut f: F) {
+    f();
+    let mut iters = 1u64;
+    loop {
+        let start = Instant::now();
+        for _ in 0..iters {
+            f();
+        }
+        let elapsed = start.elapsed();
+        if elapsed >= TARGET || iters >= 1 << 30 {
+            let per_iter = elapsed / iters as u32;
+            let mb_per_s = bytes as f64 * iters as f64 / elapsed.as_secs_f64() / 1e6;
+            println!(
+                "{:<40} {:>12?}/iter {:>10.1} MB/s",
+                name, per_iter, mb_per_s
+            );
+            return;
+        }
+        iters *= 2;
+    }
+}
+
+fn dict(pairs: Vec<(&str, Value)>) -> Value {
+    let mut m = HashMap::new();
+    for (k, v) in pairs {
+        m.insert(Value::from(k), v);
+    }
+    Value::from(m)
+}
+
+/// A typical nREPL eval request.
+fn nrepl_message() -> Value {
+    dict(vec![
+        ("op", Value::from("eval")),
+        ("id", Value::from("4b1c8a1e-7f0e-4d43-9a2b-2f1e0c9d8b7a")),
+        (
+            "session",
+            Value::from("0f5c3d62-0d3e-4b1b-8e7e-6a0c1a2b3c4d"),
+        ),
+        ("code", Value::from("(reduce + (map inc (range 100)))")),
+    ])
+}
+
+/// A tracker announce response carrying 200 compact peers.
+fn tracker_response() -> Value {
+    let peers: Vec<u8> = (0..200u32 * 6).map(|i| (i * 7) as u8).collect();
+    dict(vec![
+        ("interval", Value::Int(1800)),
+        ("min interval", Value::Int(900)),
+        ("complete", Value::Int(523)),
+        ("incomplete", Value::Int(47)),
+        ("peers", Value::from(peers)),
+    ])
+}
+
+/// A multi-file torrent with 2000 files and 4000 piece hashes.
+fn large_torrent() -> Value {
+    let files = (0..2000)
+        .map(|i| {
+            dict(vec![
+                ("length", Value::Int(1_048_576 + i)),
+                (
+                    "path",
+                    Value::List(vec![
+                        Value::from(format!("dir{}", i % 20).as_str()),
+                        Value::from(format!("file{:05}.bin", i).as_str()),
+                    ]),
+                ),
+            ])
+        })
+        .collect();
+    let pieces: Vec<u8> = (0..4000u32 * 20).map(|i| (i * 31 + 7) as u8).collect();
+    dict(vec![
+        (
+            "announce",
+            Value::from("http://tracker.example.org:6969/announce"),
+        ),
+        ("creation date", Value::Int(1_589_000_000)),
+        (
+            "info",
+            dict(vec![
+                ("name", Value::from("dataset")),
+                ("piece length", Value::Int(524_288)),
+                ("pieces", Value::from(pieces)),
+                ("files", Value::List(files)),
+            ]),
+        ),
+    ])
+}
+
+fn main() {
+    let corpora = vec![
+        ("nrepl message", nrepl_message()),
+        ("tracker response", tracker_response()),
+        ("large torrent", large_torrent()),
+    ];
+
+    for (name, value) in &corpora {
+        let mut encoded = Vec::new();
+        value.write_bencode(&mut encoded).unwrap();
+        let len = encoded.len();
+
+        bench(&format!("{} / parse_bencode", name), len, || {
+            let mut reader = &encoded[..];
+            black_box(parse_bencode(&mut reader).unwrap());
+        });
+        bench(&format!("{} / Document::parse", name), len, || {
+            black_box(Document::parse(encoded.clone()).unwrap());
+        });
+        bench(&format!("{} / write_bencode", name), len, || {
+            let mut out = Vec::with_capacity(len);
+            value.write_bencode(&mut out).unwrap();
+            black_box(out);
+        });
+        bench(&format!("{} / write_bencode_vectored", name), len, || {
+            let mut out = Vec::with_capacity(len);
+            value.write_bencode_vectored(&mut out).unwrap();
+            black_box(out);
+        });
+    }
+}
@@ -0,0 +1,44 @@
+//! Echo server for bencode values over a Unix domain socket.
+//!
+//! ```text
+//! cargo run --example unix_echo /tmp/bencode.sock
+//! ```
+//!
+//! Every value a client sends is written straight back to it.
+
+#[cfg(unix)]
+fn main() {
+    use bencode_rs::net::{ReadValue, WriteValue};
+    use std::os::unix::net::UnixListener;
+    use std::thread;
+
+    let path = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "/tmp/bencode.sock".to_string());
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path).expect("bind socket");
+    println!("listening on {}", path);
+
+    for stream in listener.incoming() {
+        let mut stream = match stream {
+            Ok(s) => s,
+            Err(e) => {
+                eprintln!("accept failed: {}", e);
+                continue;
+            }
+        };
+        thread::spawn(move || {
+            while let Ok(value) = stream.read_value() {
+                println!("echo {}", value);
+                if stream.write_value(&value).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+}
+
+#[cfg(not(unix))]
+fn main() {
+    eprintln!("unix domain sockets are not available on this platform");
+}
@@ -0,0 +1,10 @@
+//! The streaming parser must return an error, never panic, on any input.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let mut reader = data;
+    // keep reading values until the input runs out or stops parsing
+    while let Ok(Some(_)) = bencode_rs::parse_bencode(&mut reader) {}
+});
@@ -0,0 +1,14 @@
+//! The slice parsers must not panic, and must agree on complete inputs.
+#![no_main]
+
+use bencode_rs::{combinator, Document};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let document = Document::parse(data).map(|doc| doc.to_value());
+    let slice = match combinator::value(data) {
+        Ok(([], value)) => Some(value),
+        _ => None,
+    };
+    assert_eq!(document.ok(), slice);
+});
@@ -0,0 +1,14 @@
+//! Anything that parses must re-encode byte for byte, and its canonical
+//! encoding must parse back to the same value.
+#![no_main]
+
+use bencode_rs::{testing, Document};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if Document::parse(data).is_ok() {
+        if let Err(e) = testing::check_roundtrip(data) {
+            panic!("{}", e);
+        }
+    }
+});
@@ -0,0 +1,626 @@
+//! `bencode`: inspect bencode files from the command line.
+//!
+//! ```text
+//! cargo run --features cli --bin bencode -- [COMMAND] [OPTIONS] [FILE]
+//! ```
+//!
+//! With no subcommand the input (a file, or stdin when `FILE` is missing or
+//! `-`) is printed as JSON.
+
+mod path;
+mod repl;
+mod show;
+mod validate;
+
+use bencode_rs::codec::ValueStream;
+use bencode_rs::json::{from_json_str, to_json_string, to_json_string_pretty, BinaryPolicy, Json};
+use bencode_rs::torrent::{InfoHash, MetaInfo};
+use bencode_rs::{Document, EncodeOptions, Value};
+use path::{display_path, parse_path};
+use std::fs;
+use std::io::{self, BufRead, Read, Write};
+use std::process::ExitCode;
+
+const USAGE: &str = "\
+usage: bencode [decode] [--stream] [OPTIONS] [FILE]
+       bencode get [OPTIONS] PATH [FILE]
+       bencode validate [--torrent] [FILE]
+       bencode infohash [--v1 | --v2] [--base32] [FILE]
+       bencode magnet [FILE]
+       bencode show [--width=N] [--hex] [FILE]
+       bencode set [--json] [--canonical] PATH VALUE [FILE...]
+       bencode delete [--canonical] PATH [FILE...]
+       bencode canonicalize [--check | --in-place] [FILE...]
+       bencode repl FILE
+
+Reads stdin when FILE is missing or `-`.
+
+commands:
+  decode      print the document as JSON (the default)
+  get         print the value at PATH, e.g. `info.name` or
+              `.announce-list[0][0]`; strings and integers print as
+              plain text, lists and dictionaries as JSON
+  validate    report parse errors, non-canonical encoding and, for
+              torrents, lint and layout problems; exits 1 if any
+  infohash    print a torrent's v1 and v2 infohashes, one `v1 HASH` or
+              `v2 HASH` line each
+  magnet      print a torrent's magnet link
+  show        print the document as an indented tree
+  set         set the value at PATH, inserting a missing dictionary key
+  delete      remove the value at PATH
+              set and delete rewrite each FILE in place, keeping the
+              original bytes of everything they do not touch; with no
+              FILE they read stdin and write stdout
+  canonicalize
+              re-encode with sorted keys and plain integers and string
+              lengths, writing to stdout and saying on stderr whether
+              the input was already canonical
+  repl        explore and edit a document interactively; `help` at the
+              prompt lists its commands
+
+options:
+  --pretty    indent JSON output
+  --display   decode: print the plain display form instead of JSON
+  --stream    decode: read a sequence of concatenated messages and print
+              one JSON line per message as it arrives, e.g. from `nc`
+  --json      get: print strings and integers as JSON too
+  --raw       get: write the value's original bencode bytes
+  --torrent   validate: check as a torrent even without an `info` key
+  --v1, --v2  infohash: print only that hash, without a label
+  --base32    infohash: base32 instead of hex
+  --width=N   show: cut strings after N characters, 0 for none (default 60)
+  --hex       show: print binary strings as hexdumps with file offsets
+  --json      set: parse VALUE as JSON (`42`, `[1, 2]`) instead of
+              taking it as a string
+  --canonical set, delete: re-encode the whole document with sorted keys
+  --check     canonicalize: only report, exiting 1 if a file is not
+              canonical
+  --in-place  canonicalize: rewrite the files instead of printing them
+  --hex       elsewhere: render binary strings as hex (default)
+  --base64    render binary strings as base64
+  --lossy     render binary strings as UTF-8 with replacement characters
+  -h, --help  print this help";
+
+/// Subcommand names; anything else is the input of the default `decode`.
+const COMMANDS: &[&str] = &[
+    "decode",
+    "get",
+    "validate",
+    "infohash",
+    "magnet",
+    "show",
+    "set",
+    "delete",
+    "canonicalize",
+    "repl",
+];
+
+fn main() -> ExitCode {
+    let mut args = Args::parse(std::env::args().skip(1));
+    if args.flag("-h") || args.flag("--help") {
+        println!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+    match run(&mut args) {
+        Ok(code) => code,
+        Err(Failure::Usage(msg)) => {
+            eprintln!("bencode: {}\n\n{}", msg, USAGE);
+            ExitCode::from(2)
+        }
+        Err(Failure::Error(msg)) => {
+            eprintln!("bencode: {}", msg);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+enum Failure {
+    /// Bad command line; the usage text is printed with it.
+    Usage(String),
+    Error(String),
+}
+
+impl From<bencode_rs::BencodeError> for Failure {
+    fn from(err: bencode_rs::BencodeError) -> Failure {
+        Failure::Error(err.to_string())
+    }
+}
+
+impl From<io::Error> for Failure {
+    fn from(err: io::Error) -> Failure {
+        Failure::Error(err.to_string())
+    }
+}
+
+type CliResult<T> = Result<T, Failure>;
+
+fn run(args: &mut Args) -> CliResult<ExitCode> {
+    let command = match args.positional.first() {
+        Some(first) if COMMANDS.contains(&first.as_str()) => args.positional.remove(0),
+        _ => return decode(args),
+    };
+    match command.as_str() {
+        "get" => get(args),
+        "validate" => validate(args),
+        "infohash" => infohash(args),
+        "magnet" => magnet(args),
+        "show" => show(args),
+        "set" => set(args),
+        "delete" => delete(args),
+        "canonicalize" => canonicalize(args),
+        "repl" => repl(args),
+        _ => decode(args),
+    }
+}
+
+fn decode(args: &mut Args) -> CliResult<ExitCode> {
+    let pretty = args.flag("--pretty");
+    let display = args.flag("--display");
+    let stream = args.flag("--stream");
+    let policy = binary_policy(args)?;
+    let input = args.input()?;
+    args.finish()?;
+    if stream && pretty {
+        return Err(Failure::Usage(
+            "--stream prints one line per message; drop --pretty".into(),
+        ));
+    }
+
+    let render = |value: &Value| {
+        if display {
+            value.to_string()
+        } else if pretty {
+            to_json_string_pretty(value, policy)
+        } else {
+            to_json_string(value, policy)
+        }
+    };
+    if stream {
+        let reader: Box<dyn Read> = if input == "-" {
+            Box::new(io::stdin())
+        } else {
+            Box::new(
+                fs::File::open(&input).map_err(|e| Failure::Error(format!("{}: {}", input, e)))?,
+            )
+        };
+        for value in ValueStream::new(reader) {
+            emit(&render(&value?))?;
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
+    emit(&render(&read_value(&input)?))?;
+    Ok(ExitCode::SUCCESS)
+}
+
+fn get(args: &mut Args) -> CliResult<ExitCode> {
+    let as_json = args.flag("--json");
+    let raw = args.flag("--raw");
+    let pretty = args.flag("--pretty");
+    let policy = binary_policy(args)?;
+    let path = args.path("get")?;
+    let input = args.input()?;
+    args.finish()?;
+    if raw && (as_json || pretty) {
+        return Err(Failure::Usage(
+            "--raw cannot be combined with --json or --pretty".into(),
+        ));
+    }
+
+    let doc = Document::parse(read_input(&input)?)?;
+    let segments: Vec<&str> = path.iter().map(String::as_str).collect();
+    let node = doc
+        .node(&segments)
+        .ok_or_else(|| Failure::Error(format!("no value at {}", display_path(&path))))?;
+    if raw {
+        // an unedited document has a span for every node
+        let bytes = doc.raw(&segments).unwrap_or_default();
+        let mut stdout = io::stdout().lock();
+        return match stdout.write_all(bytes).and_then(|_| stdout.flush()) {
+            Err(e) if e.kind() != io::ErrorKind::BrokenPipe => Err(e.into()),
+            _ => Ok(ExitCode::SUCCESS),
+        };
+    }
+    let value = node.to_value();
+    let text = match &value {
+        Value::Int(i) if !as_json => i.to_string(),
+        Value::Str(_) | Value::Bytes(_) if !as_json => {
+            let json = Json::from_value(&value, policy);
+            match json {
+                Json::String(s) => s,
+                other => other.to_string(),
+            }
+        }
+        _ if pretty => to_json_string_pretty(&value, policy),
+        _ => to_json_string(&value, policy),
+    };
+    emit(&text)?;
+    Ok(ExitCode::SUCCESS)
+}
+
+fn validate(args: &mut Args) -> CliResult<ExitCode> {
+    let torrent = args.flag("--torrent");
+    let input = args.input()?;
+    args.finish()?;
+
+    let name = if input == "-" { "<stdin>" } else { &input };
+    let diagnostics = validate::check(&read_input(&input)?, torrent);
+    if diagnostics.is_empty() {
+        emit(&format!("{}: ok", name))?;
+        return Ok(ExitCode::SUCCESS);
+    }
+    for diagnostic in &diagnostics {
+        emit(&format!("{}: {}", name, diagnostic))?;
+    }
+    Ok(ExitCode::FAILURE)
+}
+
+fn infohash(args: &mut Args) -> CliResult<ExitCode> {
+    let only_v1 = args.flag("--v1");
+    let only_v2 = args.flag("--v2");
+    let base32 = args.flag("--base32");
+    let input = args.input()?;
+    args.finish()?;
+    if only_v1 && only_v2 {
+        return Err(Failure::Usage("--v1 and --v2 are exclusive".into()));
+    }
+
+    let meta = MetaInfo::from_bytes(&read_input(&input)?)?;
+    let format = |hash: InfoHash| {
+        if base32 {
+            hash.to_base32()
+        } else {
+            hash.to_hex()
+        }
+    };
+    let v1 = Some(meta.info_hash()?)
+        .filter(|_| meta.info.is_v1() || !meta.info.is_v2())
+        .map(|h| format(InfoHash::V1(h)));
+    let v2 = meta.info_hash_v2()?.map(|h| format(InfoHash::V2(h)));
+    let missing = |version| Failure::Error(format!("not a {} torrent", version));
+    if only_v1 {
+        emit(&v1.ok_or_else(|| missing("v1"))?)?;
+    } else if only_v2 {
+        emit(&v2.ok_or_else(|| missing("v2"))?)?;
+    } else {
+        for (label, hash) in [("v1", v1), ("v2", v2)] {
+            if let Some(hash) = hash {
+                emit(&format!("{} {}", label, hash))?;
+            }
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn magnet(args: &mut Args) -> CliResult<ExitCode> {
+    let input = args.input()?;
+    args.finish()?;
+    let meta = MetaInfo::from_bytes(&read_input(&input)?)?;
+    emit(&meta.to_magnet()?)?;
+    Ok(ExitCode::SUCCESS)
+}
+
+fn show(args: &mut Args) -> CliResult<ExitCode> {
+    let hex = args.flag("--hex");
+    let width = match args.option("--width") {
+        Some(width) => width
+            .parse()
+            .map_err(|_| Failure::Usage(format!("bad --width `{}`", width)))?,
+        None => 60,
+    };
+    let input = args.input()?;
+    args.finish()?;
+
+    let doc = Document::parse(read_input(&input)?)?;
+    emit(&show::render(doc.root(), &show::ShowOptions { width, hex }))?;
+    Ok(ExitCode::SUCCESS)
+}
+
+fn set(args: &mut Args) -> CliResult<ExitCode> {
+    let json = args.flag("--json");
+    let canonical = args.flag("--canonical");
+    let path = args.path("set")?;
+    if args.positional.is_empty() {
+        return Err(Failure::Usage("set needs a VALUE".into()));
+    }
+    let value = args.positional.remove(0);
+    let value = if json {
+        from_json_str(&value)?
+    } else {
+        Value::from(value.as_str())
+    };
+    let files = args.files();
+    args.finish()?;
+
+    edit(&files, canonical, |doc| {
+        let segments: Vec<&str> = path.iter().map(String::as_str).collect();
+        doc.set(&segments, value.clone()).map_err(Failure::from)
+    })
+}
+
+fn delete(args: &mut Args) -> CliResult<ExitCode> {
+    let canonical = args.flag("--canonical");
+    let path = args.path("delete")?;
+    let files = args.files();
+    args.finish()?;
+
+    edit(&files, canonical, |doc| {
+        let segments: Vec<&str> = path.iter().map(String::as_str).collect();
+        match doc.remove(&segments)? {
+            Some(_) => Ok(()),
+            None => Err(Failure::Error(format!(
+                "no value at {}",
+                display_path(&path)
+            ))),
+        }
+    })
+}
+
+fn canonicalize(args: &mut Args) -> CliResult<ExitCode> {
+    let check = args.flag("--check");
+    let in_place = args.flag("--in-place");
+    let files = args.files();
+    args.finish()?;
+    if check && in_place {
+        return Err(Failure::Usage(
+            "--check and --in-place are exclusive".into(),
+        ));
+    }
+
+    let mut code = ExitCode::SUCCESS;
+    for file in &files {
+        let name = if file == "-" { "<stdin>" } else { file };
+        let bytes = read_input(file)?;
+        let mut canonical = Vec::new();
+        Document::parse(&bytes[..])?
+            .to_value()
+            .write_bencode_with(&mut canonical, EncodeOptions::strict())?;
+        let status = if canonical == bytes {
+            "already canonical"
+        } else {
+            "not canonical"
+        };
+        if check {
+            emit(&format!("{}: {}", name, status))?;
+            if canonical != bytes {
+                code = ExitCode::FAILURE;
+            }
+        } else {
+            eprintln!("bencode: {}: {}", name, status);
+            if !in_place || file == "-" {
+                write_output("-", &canonical)?;
+            } else if canonical != bytes {
+                write_output(file, &canonical)?;
+            }
+        }
+    }
+    Ok(code)
+}
+
+fn repl(args: &mut Args) -> CliResult<ExitCode> {
+    let input = args.input()?;
+    args.finish()?;
+    if input == "-" {
+        return Err(Failure::Usage(
+            "repl reads commands from stdin; give it a FILE".into(),
+        ));
+    }
+
+    let mut repl = repl::Repl::new(Document::parse(read_input(&input)?)?, input);
+    let stdin = io::stdin();
+    let mut line = String::new();
+    loop {
+        print!("{}", repl.prompt());
+        io::stdout().flush()?;
+        line.clear();
+        if stdin.lock().read_line(&mut line)? == 0 {
+            println!();
+            return Ok(ExitCode::SUCCESS);
+        }
+        match repl.execute(&line) {
+            Ok(repl::Step::Print(text)) if text.is_empty() => {}
+            Ok(repl::Step::Print(text)) => emit(&text)?,
+            Ok(repl::Step::Save(file, bytes)) => match write_output(&file, &bytes) {
+                Ok(()) => emit(&format!("saved {}", file))?,
+                Err(Failure::Error(msg) | Failure::Usage(msg)) => eprintln!("error: {}", msg),
+            },
+            Ok(repl::Step::Quit) => return Ok(ExitCode::SUCCESS),
+            Err(msg) => eprintln!("error: {}", msg),
+        }
+    }
+}
+
+/// Apply `change` to every file and write it back; `-` goes to stdout. A
+/// failure is reported and skips that file only.
+fn edit<F>(files: &[String], canonical: bool, change: F) -> CliResult<ExitCode>
+where
+    F: Fn(&mut Document) -> CliResult<()>,
+{
+    let mut code = ExitCode::SUCCESS;
+    for file in files {
+        let result = read_input(file).and_then(|bytes| {
+            let mut doc = Document::parse(bytes)?;
+            change(&mut doc)?;
+            let mut out = Vec::new();
+            if canonical {
+                doc.to_value()
+                    .write_bencode_with(&mut out, EncodeOptions::strict())?;
+            } else {
+                doc.write_bencode(&mut out)?;
+            }
+            write_output(file, &out)
+        });
+        match result {
+            Ok(()) => {}
+            Err(Failure::Error(msg)) if files.len() > 1 => {
+                eprintln!("bencode: {}: {}", file, msg);
+                code = ExitCode::FAILURE;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(code)
+}
+
+/// Replace a file through a temporary sibling so a failed write cannot
+/// truncate it, or write to stdout for `-`.
+fn write_output(path: &str, bytes: &[u8]) -> CliResult<()> {
+    if path == "-" {
+        let mut stdout = io::stdout().lock();
+        return match stdout.write_all(bytes).and_then(|_| stdout.flush()) {
+            Err(e) if e.kind() != io::ErrorKind::BrokenPipe => Err(e.into()),
+            _ => Ok(()),
+        };
+    }
+    let tmp = format!("{}.tmp", path);
+    fs::write(&tmp, bytes)
+        .and_then(|_| fs::rename(&tmp, path))
+        .map_err(|e| {
+            let _ = fs::remove_file(&tmp);
+            Failure::Error(format!("{}: {}", path, e))
+        })
+}
+
+fn binary_policy(args: &mut Args) -> CliResult<BinaryPolicy> {
+    let chosen: Vec<_> = [
+        ("--hex", BinaryPolicy::Hex),
+        ("--base64", BinaryPolicy::Base64),
+        ("--lossy", BinaryPolicy::Lossy),
+    ]
+    .iter()
+    .filter(|(flag, _)| args.flag(flag))
+    .map(|(_, policy)| *policy)
+    .collect();
+    match chosen[..] {
+        [] => Ok(BinaryPolicy::default()),
+        [policy] => Ok(policy),
+        _ => Err(Failure::Usage(
+            "--hex, --base64 and --lossy are exclusive".into(),
+        )),
+    }
+}
+
+/// Read a whole file, or stdin for `-`.
+fn read_input(path: &str) -> CliResult<Vec<u8>> {
+    if path == "-" {
+        let mut buf = Vec::new();
+        io::stdin().lock().read_to_end(&mut buf)?;
+        Ok(buf)
+    } else {
+        fs::read(path).map_err(|e| Failure::Error(format!("{}: {}", path, e)))
+    }
+}
+
+fn read_value(path: &str) -> CliResult<Value> {
+    Ok(Document::parse(read_input(path)?)?.to_value())
+}
+
+/// Print a line to stdout. A closed pipe (`bencode x | head`) is not an error.
+fn emit(text: &str) -> CliResult<()> {
+    match writeln!(io::stdout().lock(), "{}", text) {
+        Err(e) if e.kind() != io::ErrorKind::BrokenPipe => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Command-line arguments split into flags and positional arguments.
+/// Subcommands take the flags they know and [`Args::finish`] rejects the rest.
+struct Args {
+    positional: Vec<String>,
+    flags: Vec<String>,
+}
+
+impl Args {
+    fn parse<I: IntoIterator<Item = String>>(args: I) -> Args {
+        let mut parsed = Args {
+            positional: Vec::new(),
+            flags: Vec::new(),
+        };
+        let mut only_positional = false;
+        for arg in args {
+            if only_positional || arg == "-" || !arg.starts_with('-') {
+                parsed.positional.push(arg);
+            } else if arg == "--" {
+                only_positional = true;
+            } else {
+                parsed.flags.push(arg);
+            }
+        }
+        parsed
+    }
+
+    /// Take a boolean flag, returning whether it was given.
+    fn flag(&mut self, name: &str) -> bool {
+        let before = self.flags.len();
+        self.flags.retain(|f| f != name);
+        self.flags.len() != before
+    }
+
+    /// Take a `--name=value` option.
+    fn option(&mut self, name: &str) -> Option<String> {
+        let prefix = format!("{}=", name);
+        let at = self.flags.iter().position(|f| f.starts_with(&prefix))?;
+        Some(self.flags.remove(at)[prefix.len()..].to_string())
+    }
+
+    /// Take the PATH argument of `command`.
+    fn path(&mut self, command: &str) -> CliResult<Vec<String>> {
+        if self.positional.is_empty() {
+            return Err(Failure::Usage(format!("{} needs a PATH", command)));
+        }
+        parse_path(&self.positional.remove(0)).map_err(Failure::Usage)
+    }
+
+    /// Take every remaining argument as an input file, defaulting to stdin.
+    fn files(&mut self) -> Vec<String> {
+        match self.positional.len() {
+            0 => vec!["-".into()],
+            _ => self.positional.drain(..).collect(),
+        }
+    }
+
+    /// Take the input file argument, defaulting to stdin.
+    fn input(&mut self) -> CliResult<String> {
+        match self.positional.len() {
+            0 => Ok("-".into()),
+            1 => Ok(self.positional.remove(0)),
+            _ => Err(Failure::Usage("expected a single input file".into())),
+        }
+    }
+
+    fn finish(&self) -> CliResult<()> {
+        match (self.flags.first(), self.positional.first()) {
+            (Some(flag), _) => Err(Failure::Usage(format!("unknown option {}", flag))),
+            (_, Some(arg)) => Err(Failure::Usage(format!("unexpected argument {}", arg))),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Args {
+        Args::parse(line.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn test_args() {
+        let mut a = args("decode --pretty x.torrent -- --odd");
+        assert!(a.flag("--pretty"));
+        assert!(!a.flag("--pretty"));
+        assert_eq!(a.positional, ["decode", "x.torrent", "--odd"]);
+        assert!(a.finish().is_err());
+
+        let mut a = args("--width=8 --hex");
+        assert_eq!(a.option("--width").as_deref(), Some("8"));
+        assert_eq!(a.option("--width"), None);
+
+        let mut a = args("--hex --base64");
+        assert!(matches!(binary_policy(&mut a), Err(Failure::Usage(_))));
+        let mut a = args("--base64 -");
+        assert_eq!(binary_policy(&mut a).ok(), Some(BinaryPolicy::Base64));
+        assert_eq!(a.input().ok().as_deref(), Some("-"));
+        assert!(a.finish().is_ok());
+    }
+}
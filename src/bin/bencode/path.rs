@@ -0,0 +1,106 @@
+//! Paths into a document: `info.name`, `.announce-list[0][0]`, or
+//! `info["key.with.dots"]` for keys that need quoting. The leading `.` is
+//! optional, and `.` alone is the root.
+
+/// Split a path into [`bencode_rs::Document`] segments.
+pub fn parse_path(path: &str) -> Result<Vec<String>, String> {
+    let bad = |why: &str| format!("bad path `{}`: {}", path, why);
+    let mut segments = Vec::new();
+    let mut chars = path.strip_prefix('.').unwrap_or(path).chars().peekable();
+    let mut expect_key = false;
+    while let Some(&c) = chars.peek() {
+        match c {
+            '[' => {
+                chars.next();
+                let mut segment = String::new();
+                if chars.peek() == Some(&'"') {
+                    chars.next();
+                    loop {
+                        match chars.next() {
+                            Some('"') => break,
+                            Some('\\') => segment.extend(chars.next()),
+                            Some(c) => segment.push(c),
+                            None => return Err(bad("unterminated quote")),
+                        }
+                    }
+                } else {
+                    while let Some(c) = chars.next_if(char::is_ascii_digit) {
+                        segment.push(c);
+                    }
+                    if segment.is_empty() {
+                        return Err(bad("expected an index or a quoted key in []"));
+                    }
+                }
+                if chars.next() != Some(']') {
+                    return Err(bad("missing ]"));
+                }
+                segments.push(segment);
+                expect_key = false;
+            }
+            '.' if expect_key || segments.is_empty() => return Err(bad("empty key")),
+            '.' => {
+                chars.next();
+                expect_key = true;
+            }
+            _ => {
+                let mut segment = String::new();
+                while let Some(c) = chars.next_if(|c| *c != '.' && *c != '[') {
+                    segment.push(c);
+                }
+                segments.push(segment);
+                expect_key = false;
+            }
+        }
+    }
+    if expect_key {
+        return Err(bad("empty key"));
+    }
+    Ok(segments)
+}
+
+/// Render segments back in the syntax [`parse_path`] reads, for messages.
+pub fn display_path(segments: &[String]) -> String {
+    if segments.is_empty() {
+        return ".".into();
+    }
+    let mut out = String::new();
+    for segment in segments {
+        if !segment.is_empty() && segment.bytes().all(|b| b.is_ascii_digit()) {
+            out.push_str(&format!("[{}]", segment));
+        } else if segment.is_empty() || segment.contains(['.', '[', ']', '"', '\\']) {
+            let escaped = segment.replace('\\', "\\\\").replace('"', "\\\"");
+            out.push_str(&format!("[\"{}\"]", escaped));
+        } else {
+            if !out.is_empty() {
+                out.push('.');
+            }
+            out.push_str(segment);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_path() {
+        let ok = |p: &str| parse_path(p).unwrap();
+        assert!(ok(".").is_empty() && ok("").is_empty());
+        assert_eq!(ok("info.name"), ["info", "name"]);
+        assert_eq!(ok(".announce-list[0][0]"), ["announce-list", "0", "0"]);
+        assert_eq!(ok(r#"a["b.c"][2].d"#), ["a", "b.c", "2", "d"]);
+        assert_eq!(ok(r#"["q\"x"]"#), ["q\"x"]);
+        for bad in ["a..b", "a.", "..", "a[x]", "a[1", r#"a["b"#] {
+            assert!(parse_path(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_display_path() {
+        for path in [".", "info.name", "announce-list[0][0]", r#"a["b.c"][2].d"#] {
+            assert_eq!(display_path(&parse_path(path).unwrap()), path);
+        }
+    }
+}
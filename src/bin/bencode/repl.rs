@@ -0,0 +1,286 @@
+//! `bencode repl`: an interactive prompt for exploring and editing a
+//! document, one node at a time.
+
+use super::path::{display_path, parse_path};
+use super::show::{hexdump, render, summary, ShowOptions};
+use bencode_rs::document::{Node, NodeKind};
+use bencode_rs::json::{from_json_str, to_json_string_pretty, BinaryPolicy};
+use bencode_rs::{Document, Value};
+
+pub const HELP: &str = "\
+commands:
+  ls [PATH]              list the children of a node
+  cd [PATH | .. | /]     change the current node; PATH is relative unless
+                         it starts with /
+  pwd                    print the current path
+  cat [PATH] [| hex | json]
+                         print a node as a tree, a hexdump or JSON
+  set PATH VALUE         set a value; VALUE is JSON if it parses as JSON
+                         (`42`, `[1, 2]`, `\"42\"`), otherwise a string
+  rm PATH                remove a value
+  save [FILE]            write the document back, keeping the original
+                         bytes of everything not edited
+  help                   print this help
+  quit                   leave; `quit!` leaves with unsaved changes";
+
+/// What the prompt loop should do after a command.
+#[derive(Debug, PartialEq)]
+pub enum Step {
+    Print(String),
+    /// The document should be written to this file.
+    Save(String, Vec<u8>),
+    Quit,
+}
+
+pub struct Repl {
+    doc: Document,
+    file: String,
+    cwd: Vec<String>,
+    unsaved: bool,
+}
+
+impl Repl {
+    pub fn new(doc: Document, file: String) -> Repl {
+        Repl {
+            doc,
+            file,
+            cwd: Vec::new(),
+            unsaved: false,
+        }
+    }
+
+    /// Prompt text showing the current path.
+    pub fn prompt(&self) -> String {
+        let marker = if self.unsaved { "*" } else { "" };
+        format!("{}{}> ", display_path(&self.cwd), marker)
+    }
+
+    /// Run one command line. Errors are messages for the user; the session
+    /// carries on after them.
+    pub fn execute(&mut self, line: &str) -> Result<Step, String> {
+        let line = line.trim();
+        let (command, rest) = match line.split_once(char::is_whitespace) {
+            Some((command, rest)) => (command, rest.trim()),
+            None => (line, ""),
+        };
+        match command {
+            "" => Ok(Step::Print(String::new())),
+            "ls" => self.ls(rest),
+            "cd" if rest.is_empty() => {
+                self.cwd.clear();
+                Ok(Step::Print(String::new()))
+            }
+            "cd" => {
+                self.cwd = self.existing(rest)?.0;
+                Ok(Step::Print(String::new()))
+            }
+            "pwd" => Ok(Step::Print(display_path(&self.cwd))),
+            "cat" => self.cat(rest),
+            "set" => {
+                let (path, value) = rest
+                    .split_once(char::is_whitespace)
+                    .ok_or("usage: set PATH VALUE")?;
+                let value = value.trim();
+                let value = from_json_str(value).unwrap_or_else(|_| Value::from(value));
+                let path = self.resolve(path)?;
+                if path.is_empty() {
+                    return Err("cannot replace the root".into());
+                }
+                self.doc
+                    .set(&segments(&path), value)
+                    .map_err(|e| e.to_string())?;
+                self.unsaved = true;
+                Ok(Step::Print(String::new()))
+            }
+            "rm" => {
+                let path = self.existing(rest)?.0;
+                if path.is_empty() {
+                    return Err("cannot remove the root".into());
+                }
+                match self
+                    .doc
+                    .remove(&segments(&path))
+                    .map_err(|e| e.to_string())?
+                {
+                    Some(_) => {
+                        if self.cwd.starts_with(&path) {
+                            self.cwd = path[..path.len() - 1].to_vec();
+                        }
+                        self.unsaved = true;
+                        Ok(Step::Print(String::new()))
+                    }
+                    None => Err(format!("no value at {}", display_path(&path))),
+                }
+            }
+            "save" => {
+                if !rest.is_empty() {
+                    self.file = rest.to_string();
+                }
+                if self.file == "-" {
+                    return Err("read from stdin; give a FILE to save to".into());
+                }
+                self.unsaved = false;
+                Ok(Step::Save(self.file.clone(), self.doc.to_bytes()))
+            }
+            "help" => Ok(Step::Print(HELP.into())),
+            "quit" | "exit" if self.unsaved => {
+                Err("unsaved changes; `save` first or `quit!` to discard them".into())
+            }
+            "quit" | "exit" | "quit!" => Ok(Step::Quit),
+            _ => Err(format!("unknown command `{}`; try `help`", command)),
+        }
+    }
+
+    /// The absolute path `arg` names: relative to the current node unless it
+    /// starts with `/`, with `..` for the parent and an empty `arg` for the
+    /// current node itself.
+    fn resolve(&self, arg: &str) -> Result<Vec<String>, String> {
+        let mut path = self.cwd.clone();
+        match arg {
+            "" => {}
+            ".." => {
+                path.pop();
+            }
+            _ => match arg.strip_prefix('/') {
+                Some(absolute) => path = parse_path(absolute)?,
+                None => path.extend(parse_path(arg)?),
+            },
+        }
+        Ok(path)
+    }
+
+    fn node(&self, path: &[String]) -> Option<&Node> {
+        self.doc.node(&segments(path))
+    }
+
+    /// Like [`Repl::resolve`], for a node that must exist.
+    fn existing(&self, arg: &str) -> Result<(Vec<String>, &Node), String> {
+        let path = self.resolve(arg)?;
+        match self.node(&path) {
+            Some(node) => Ok((path, node)),
+            None => Err(format!("no value at {}", display_path(&path))),
+        }
+    }
+
+    fn ls(&self, arg: &str) -> Result<Step, String> {
+        let (_, node) = self.existing(arg)?;
+        let lines: Vec<String> = match node.kind() {
+            NodeKind::Dict(entries) => entries
+                .iter()
+                .map(|(key, value)| {
+                    let key = key.to_value();
+                    let name = String::from_utf8_lossy(key.as_bytes().unwrap_or_default());
+                    format!("{}  {}", name, summary(value, 40))
+                })
+                .collect(),
+            NodeKind::List(items) => items
+                .iter()
+                .enumerate()
+                .map(|(i, item)| format!("[{}]  {}", i, summary(item, 40)))
+                .collect(),
+            NodeKind::Leaf(_) => vec![summary(node, 40)],
+        };
+        Ok(Step::Print(lines.join("\n")))
+    }
+
+    fn cat(&self, arg: &str) -> Result<Step, String> {
+        let (arg, filter) = match arg.split_once('|') {
+            Some((arg, filter)) => (arg.trim(), filter.trim()),
+            None => (arg, ""),
+        };
+        let (_, node) = self.existing(arg)?;
+        match filter {
+            "" => Ok(Step::Print(render(
+                node,
+                &ShowOptions {
+                    width: 0,
+                    hex: false,
+                },
+            ))),
+            "json" => Ok(Step::Print(to_json_string_pretty(
+                &node.to_value(),
+                BinaryPolicy::Hex,
+            ))),
+            "hex" => match node.kind() {
+                NodeKind::Leaf(value @ (Value::Str(_) | Value::Bytes(_))) => {
+                    let bytes = value.as_bytes().unwrap_or_default();
+                    // edited nodes have no offset in the file
+                    let offset = node.span().map_or(0, |s| s.end - bytes.len());
+                    let mut out = String::new();
+                    hexdump(&mut out, bytes, offset, "");
+                    Ok(Step::Print(out.trim_end().to_string()))
+                }
+                _ => Err("hex works on strings only".into()),
+            },
+            _ => Err(format!("unknown filter `{}`; use hex or json", filter)),
+        }
+    }
+}
+
+fn segments(path: &[String]) -> Vec<&str> {
+    path.iter().map(String::as_str).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn repl() -> Repl {
+        let doc = Document::parse(&b"d4:infod4:name3:abc6:pieces2:\xff\x00e4:listli1eee"[..]);
+        Repl::new(doc.unwrap(), "x.torrent".into())
+    }
+
+    fn print(repl: &mut Repl, line: &str) -> String {
+        match repl.execute(line) {
+            Ok(Step::Print(text)) => text,
+            other => panic!("{}: {:?}", line, other),
+        }
+    }
+
+    #[test]
+    fn test_navigate() {
+        let mut r = repl();
+        assert_eq!(
+            print(&mut r, "ls"),
+            "info  dict, 2 keys\nlist  list, 1 item"
+        );
+        print(&mut r, "cd info");
+        assert_eq!(r.prompt(), "info> ");
+        assert_eq!(print(&mut r, "ls"), "name  \"abc\"\npieces  <2 bytes> ff00");
+        assert_eq!(
+            print(&mut r, "cat pieces | hex"),
+            "0000001d  ff 00                                             |..|"
+        );
+        print(&mut r, "cd /list[0]");
+        assert_eq!(print(&mut r, "pwd"), "list[0]");
+        print(&mut r, "cd ..");
+        print(&mut r, "cd ..");
+        assert_eq!(print(&mut r, "pwd"), ".");
+        assert!(r
+            .execute("cd nope")
+            .unwrap_err()
+            .contains("no value at nope"));
+        assert!(r.execute("frob").is_err());
+    }
+
+    #[test]
+    fn test_edit_and_save() {
+        let mut r = repl();
+        print(&mut r, "set info.name renamed file");
+        print(&mut r, "set info.private 1");
+        assert_eq!(r.prompt(), ".*> ");
+        assert!(r.execute("quit").is_err());
+        print(&mut r, "rm list");
+        match r.execute("save").unwrap() {
+            Step::Save(file, bytes) => {
+                assert_eq!(file, "x.torrent");
+                assert_eq!(
+                    bytes,
+                    b"d4:infod4:name12:renamed file6:pieces2:\xff\x007:privatei1eee"
+                );
+            }
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(r.execute("quit").unwrap(), Step::Quit);
+    }
+}
@@ -0,0 +1,173 @@
+//! `bencode show`: an indented tree view of a document.
+
+use bencode_rs::document::{Node, NodeKind};
+use bencode_rs::Value;
+use std::fmt::Write;
+
+pub struct ShowOptions {
+    /// Strings longer than this many characters are cut short; 0 shows them
+    /// in full.
+    pub width: usize,
+    /// Print binary strings as full hexdumps with file offsets.
+    pub hex: bool,
+}
+
+/// Render a node as a tree, one line per scalar or container header.
+pub fn render(root: &Node, options: &ShowOptions) -> String {
+    let mut out = String::new();
+    node(&mut out, root, 0, options);
+    out.truncate(out.trim_end().len());
+    out
+}
+
+// Writing into a String cannot fail, so `write!` results are ignored here
+// and below.
+fn node(out: &mut String, node: &Node, depth: usize, options: &ShowOptions) {
+    let indent = "  ".repeat(depth);
+    match node.kind() {
+        NodeKind::Dict(entries) => {
+            out.push_str(&count(entries.len(), "key"));
+            out.push('\n');
+            for (key, value) in entries {
+                let key = key.to_value();
+                let label = match std::str::from_utf8(key.as_bytes().unwrap_or_default()) {
+                    Ok(s) if !s.is_empty() && !s.contains(char::is_whitespace) => s.to_string(),
+                    _ => scalar(&key, 0),
+                };
+                let _ = write!(out, "{}  {}: ", indent, label);
+                self::node(out, value, depth + 1, options);
+            }
+        }
+        NodeKind::List(items) => {
+            out.push_str(&count(items.len(), "item"));
+            out.push('\n');
+            for (i, item) in items.iter().enumerate() {
+                let _ = write!(out, "{}  [{}]: ", indent, i);
+                self::node(out, item, depth + 1, options);
+            }
+        }
+        NodeKind::Leaf(Value::Bytes(bytes)) if options.hex => {
+            // a freshly parsed document has a span for every node; the
+            // contents are the last `len` bytes of it
+            let offset = node.span().map_or(0, |s| s.end - bytes.len());
+            let _ = writeln!(out, "{} bytes at offset {}", bytes.len(), offset);
+            hexdump(out, bytes, offset, &format!("{}  ", indent));
+        }
+        NodeKind::Leaf(value) => {
+            out.push_str(&scalar(value, options.width));
+            out.push('\n');
+        }
+    }
+}
+
+fn count(n: usize, noun: &str) -> String {
+    let kind = if noun == "key" { "dict" } else { "list" };
+    let plural = if n == 1 { "" } else { "s" };
+    format!("{}, {} {}{}", kind, n, noun, plural)
+}
+
+/// A one-line description: the container size, or the scalar itself cut to
+/// `width` characters.
+pub fn summary(node: &Node, width: usize) -> String {
+    match node.kind() {
+        NodeKind::Dict(entries) => count(entries.len(), "key"),
+        NodeKind::List(items) => count(items.len(), "item"),
+        NodeKind::Leaf(value) => scalar(value, width),
+    }
+}
+
+/// An integer, a quoted string, or `<N bytes> hex` for binary strings,
+/// cut to `width` characters (0 for no limit).
+fn scalar(value: &Value, width: usize) -> String {
+    let cut = |text: &str| {
+        if width == 0 || text.chars().count() <= width {
+            (text.to_string(), false)
+        } else {
+            (text.chars().take(width).collect::<String>(), true)
+        }
+    };
+    match value {
+        Value::Int(i) => i.to_string(),
+        Value::Str(s) => match cut(s) {
+            (text, false) => format!("{:?}", text),
+            (text, true) => format!("{:?}… ({} bytes)", text, s.len()),
+        },
+        Value::Bytes(b) => {
+            let (text, cut) = cut(&bencode_rs::hash::to_hex(b));
+            format!("<{} bytes> {}{}", b.len(), text, if cut { "…" } else { "" })
+        }
+        other => other.to_string(),
+    }
+}
+
+/// Classic `offset  hex  |ascii|` lines, 16 bytes each.
+pub fn hexdump(out: &mut String, bytes: &[u8], offset: usize, indent: &str) {
+    for (i, chunk) in bytes.chunks(16).enumerate() {
+        let _ = write!(out, "{}{:08x} ", indent, offset + i * 16);
+        for j in 0..16 {
+            match chunk.get(j) {
+                Some(b) => {
+                    let _ = write!(out, " {:02x}", b);
+                }
+                None => out.push_str("   "),
+            }
+            if j == 7 {
+                out.push(' ');
+            }
+        }
+        let ascii: String = chunk
+            .iter()
+            .map(|&b| {
+                if b.is_ascii_graphic() || b == b' ' {
+                    b as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        let _ = writeln!(out, "  |{}|", ascii);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bencode_rs::Document;
+
+    fn show(bytes: &[u8], width: usize, hex: bool) -> String {
+        let doc = Document::parse(bytes).unwrap();
+        render(doc.root(), &ShowOptions { width, hex })
+    }
+
+    #[test]
+    fn test_show_tree() {
+        let doc = b"d4:infod4:name10:abcdefghij6:pieces3:\xff\x00\x01e4:listli1el1:xeee";
+        assert_eq!(
+            show(doc, 4, false),
+            "dict, 2 keys
+  info: dict, 2 keys
+    name: \"abcd\"… (10 bytes)
+    pieces: <3 bytes> ff00…
+  list: list, 2 items
+    [0]: 1
+    [1]: list, 1 item
+      [0]: \"x\""
+        );
+        assert!(show(doc, 0, false).contains("name: \"abcdefghij\"\n"));
+    }
+
+    #[test]
+    fn test_show_hexdump() {
+        let mut doc = b"d6:pieces18:".to_vec();
+        doc.push(0xff);
+        doc.extend(0x41..0x52);
+        doc.push(b'e');
+        assert_eq!(
+            show(&doc, 0, true),
+            "dict, 1 key
+  pieces: 18 bytes at offset 12
+    0000000c  ff 41 42 43 44 45 46 47  48 49 4a 4b 4c 4d 4e 4f  |.ABCDEFGHIJKLMNO|
+    0000001c  50 51                                             |PQ|"
+        );
+    }
+}
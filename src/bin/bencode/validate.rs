@@ -0,0 +1,172 @@
+//! `bencode validate`: strict parsing plus, for torrents, the linter and
+//! structural checks.
+
+use super::path::display_path;
+use bencode_rs::document::{Node, NodeKind};
+use bencode_rs::torrent::{lint, MetaInfo};
+use bencode_rs::{Document, Value};
+use std::fmt::{self, Display};
+
+/// One problem found in a document.
+#[derive(Debug, PartialEq)]
+pub struct Diagnostic {
+    pub offset: Option<usize>,
+    pub path: Option<Vec<String>>,
+    pub message: String,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(offset) = self.offset {
+            write!(f, "offset {}: ", offset)?;
+        }
+        if let Some(path) = &self.path {
+            write!(f, "{}: ", display_path(path))?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Check `bytes` for anything a strict decoder would reject: parse errors,
+/// trailing data, non-canonical integers and string lengths, and unsorted or
+/// duplicate dictionary keys. Torrents (a top-level `info` dictionary, or any
+/// document with `torrent` set) are also linted and cross-checked.
+pub fn check(bytes: &[u8], torrent: bool) -> Vec<Diagnostic> {
+    let doc = match Document::parse(bytes) {
+        Ok(doc) => doc,
+        Err(e) => {
+            let mut message = e.to_string();
+            if let Some(snippet) = e.snippet(bytes) {
+                message = format!("{}\n{}", message.trim_end(), snippet);
+            }
+            return vec![Diagnostic {
+                offset: None,
+                path: None,
+                message,
+            }];
+        }
+    };
+    let mut out = Vec::new();
+    walk(doc.root(), doc.source(), &mut Vec::new(), &mut out);
+
+    if torrent || doc.root().child("info").is_some() {
+        let message = |message: String| Diagnostic {
+            offset: None,
+            path: None,
+            message,
+        };
+        out.extend(lint(&doc.to_value()).iter().map(|l| message(l.to_string())));
+        match MetaInfo::from_bytes(bytes).and_then(|meta| meta.validate()) {
+            Ok(()) => {}
+            Err(e) => out.push(message(e.to_string())),
+        }
+    }
+    out
+}
+
+fn walk(node: &Node, src: &[u8], path: &mut Vec<String>, out: &mut Vec<Diagnostic>) {
+    // a freshly parsed document has a span for every node
+    let span = match node.span() {
+        Some(span) => span,
+        None => return,
+    };
+    match node.kind() {
+        NodeKind::Leaf(Value::Int(i)) => {
+            let raw = &src[span.clone()];
+            if raw != format!("i{}e", i).as_bytes() {
+                let raw = String::from_utf8_lossy(raw);
+                let message = format!("integer `{}` is not canonical", raw);
+                report(out, span.start, path, message);
+            }
+        }
+        NodeKind::Leaf(value) => {
+            let len = value.as_bytes().map_or(0, <[u8]>::len);
+            let raw = &src[span.clone()];
+            let prefix = &raw[..raw.len() - len - 1];
+            if prefix != len.to_string().as_bytes() {
+                let prefix = String::from_utf8_lossy(prefix);
+                let message = format!("string length `{}` is not canonical", prefix);
+                report(out, span.start, path, message);
+            }
+        }
+        NodeKind::List(items) => {
+            for (i, item) in items.iter().enumerate() {
+                path.push(i.to_string());
+                walk(item, src, path, out);
+                path.pop();
+            }
+        }
+        NodeKind::Dict(entries) => {
+            let mut previous: Option<Vec<u8>> = None;
+            for (key, value) in entries {
+                let bytes = key.to_value().as_bytes().unwrap_or_default().to_vec();
+                let label = String::from_utf8_lossy(&bytes).into_owned();
+                let offset = key.span().map_or(span.start, |s| s.start);
+                match &previous {
+                    Some(prev) if *prev == bytes => {
+                        report(out, offset, path, format!("duplicate key `{}`", label))
+                    }
+                    Some(prev) if *prev > bytes => {
+                        let prev = String::from_utf8_lossy(prev);
+                        let message = format!("key `{}` sorts before `{}`", label, prev);
+                        report(out, offset, path, message)
+                    }
+                    _ => {}
+                }
+                previous = Some(bytes);
+                path.push(label);
+                walk(key, src, path, out);
+                walk(value, src, path, out);
+                path.pop();
+            }
+        }
+    }
+}
+
+fn report(out: &mut Vec<Diagnostic>, offset: usize, path: &[String], message: String) {
+    out.push(Diagnostic {
+        offset: Some(offset),
+        path: Some(path.to_vec()),
+        message,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(bytes: &[u8]) -> Vec<String> {
+        check(bytes, false).iter().map(|d| d.to_string()).collect()
+    }
+
+    #[test]
+    fn test_check_strict() {
+        assert!(messages(b"d1:ai1e1:bl1:xee").is_empty());
+        assert_eq!(
+            messages(b"d1:bi03e1:a02:xy1:ai-0ee"),
+            [
+                "offset 4: b: integer `i03e` is not canonical",
+                "offset 8: .: key `a` sorts before `b`",
+                "offset 11: a: string length `02` is not canonical",
+                "offset 16: .: duplicate key `a`",
+                "offset 19: a: integer `i-0e` is not canonical",
+            ]
+        );
+        assert_eq!(messages(b"li1ee").len(), 0);
+        let trailing = &messages(b"li1eex")[0];
+        assert!(trailing.contains("Trailing data at offset 5\n00000000  6c 69 31 65 65 78"));
+        let lines: Vec<_> = trailing.lines().collect();
+        assert_eq!(lines[2].find("^^"), lines[1].find("78"));
+        assert_eq!(lines[2].rfind('^'), lines[1].rfind('x'));
+    }
+
+    #[test]
+    fn test_check_torrent() {
+        let diagnostics = messages(b"d4:infod12:piece lengthi3e6:pieces0:ee");
+        assert!(diagnostics.contains(&"missing `info.name`".to_string()));
+        assert!(diagnostics.contains(&"piece length 3 is not a power of two".to_string()));
+        assert!(check(b"de", true)
+            .iter()
+            .any(|d| d.message == "missing `info` dictionary"));
+    }
+}
@@ -0,0 +1,366 @@
+//! Transcoding between bencode [`Value`]s and CBOR (RFC 8949).
+//!
+//! Unlike JSON, CBOR tells byte strings and text strings apart, so binary
+//! data such as piece hashes survives the trip unchanged: `Str` values become
+//! text strings and `Bytes` values byte strings. Map keys are written in the
+//! deterministic order of RFC 8949, sorted by their encoded bytes.
+//!
+//! Decoding accepts what bencode can represent. Booleans become 0 or 1,
+//! whole floats become integers, tags are dropped in favour of the tagged
+//! item, and `null`, `undefined` and fractional numbers are errors, as is
+//! nesting deeper than [`MAX_DEPTH`].
+//!
+//! ```
+//! use bencode_rs::cbor::{from_cbor, to_cbor};
+//! use bencode_rs::Value;
+//!
+//! let value = Value::List(vec![Value::from(1), Value::from(vec![0xff, 0x00])]);
+//! let cbor = to_cbor(&value);
+//! assert_eq!(cbor, [0x82, 0x01, 0x42, 0xff, 0x00]);
+//! assert_eq!(from_cbor(&cbor).unwrap(), value);
+//! ```
+
+use crate::document::MAX_DEPTH;
+use crate::{BencodeError, ErrorKind, HMap, Map, Result, Value};
+use std::convert::TryFrom;
+
+const UNSIGNED: u8 = 0;
+const NEGATIVE: u8 = 1;
+const BYTES: u8 = 2;
+const TEXT: u8 = 3;
+const ARRAY: u8 = 4;
+const MAP: u8 = 5;
+const TAG: u8 = 6;
+
+/// Additional information value marking an indefinite length.
+const INDEFINITE: u8 = 31;
+const BREAK: u8 = 0xff;
+
+/// Encode `value` as CBOR.
+pub fn to_cbor(value: &Value) -> Vec<u8> {
+    let mut out = Vec::new();
+    write(&mut out, value);
+    out
+}
+
+/// Decode a single CBOR item. Trailing bytes are an error.
+pub fn from_cbor(buf: &[u8]) -> Result<Value> {
+    let mut decoder = Decoder {
+        buf,
+        pos: 0,
+        depth: 0,
+    };
+    let value = decoder.value()?;
+    if decoder.pos != buf.len() {
+        return Err(decoder.error("trailing bytes"));
+    }
+    Ok(value)
+}
+
+fn head(out: &mut Vec<u8>, major: u8, n: u64) {
+    let major = major << 5;
+    if n < 24 {
+        out.push(major | n as u8);
+    } else if n <= u64::from(u8::MAX) {
+        out.extend_from_slice(&[major | 24, n as u8]);
+    } else if n <= u64::from(u16::MAX) {
+        out.push(major | 25);
+        out.extend_from_slice(&(n as u16).to_be_bytes());
+    } else if n <= u64::from(u32::MAX) {
+        out.push(major | 26);
+        out.extend_from_slice(&(n as u32).to_be_bytes());
+    } else {
+        out.push(major | 27);
+        out.extend_from_slice(&n.to_be_bytes());
+    }
+}
+
+fn write(out: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::Int(i) if *i >= 0 => head(out, UNSIGNED, *i as u64),
+        // -1 - i cannot overflow for negative i
+        Value::Int(i) => head(out, NEGATIVE, (-1 - *i) as u64),
+        Value::Str(s) => {
+            head(out, TEXT, s.len() as u64);
+            out.extend_from_slice(s.as_bytes());
+        }
+        Value::Bytes(b) => {
+            head(out, BYTES, b.len() as u64);
+            out.extend_from_slice(b);
+        }
+        Value::List(items) => {
+            head(out, ARRAY, items.len() as u64);
+            for item in items {
+                write(out, item);
+            }
+        }
+        Value::Map(map) => {
+            let mut entries: Vec<(Vec<u8>, &Value)> =
+                map.0.iter().map(|(k, v)| (to_cbor(k), v)).collect();
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+            head(out, MAP, entries.len() as u64);
+            for (key, value) in entries {
+                out.extend_from_slice(&key);
+                write(out, value);
+            }
+        }
+    }
+}
+
+struct Decoder<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    // containers entered so far
+    depth: usize,
+}
+
+impl Decoder<'_> {
+    fn error(&self, what: &str) -> BencodeError {
+        BencodeError::msg(format!("Invalid CBOR at offset {}: {}", self.pos, what))
+    }
+
+    fn take(&mut self, n: usize) -> Result<&[u8]> {
+        let end = self
+            .pos
+            .checked_add(n)
+            .filter(|end| *end <= self.buf.len())
+            .ok_or_else(|| self.error("unexpected end"))?;
+        let bytes = &self.buf[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    /// The argument of an item head; `None` for an indefinite length.
+    fn argument(&mut self, info: u8) -> Result<Option<u64>> {
+        let n = match info {
+            0..=23 => u64::from(info),
+            24 => u64::from(self.byte()?),
+            25 => u64::from(u16::from_be_bytes([self.byte()?, self.byte()?])),
+            26 => {
+                let mut b = [0; 4];
+                b.copy_from_slice(self.take(4)?);
+                u64::from(u32::from_be_bytes(b))
+            }
+            27 => {
+                let mut b = [0; 8];
+                b.copy_from_slice(self.take(8)?);
+                u64::from_be_bytes(b)
+            }
+            INDEFINITE => return Ok(None),
+            _ => return Err(self.error("reserved additional information")),
+        };
+        Ok(Some(n))
+    }
+
+    fn length(&mut self, info: u8) -> Result<Option<usize>> {
+        match self.argument(info)? {
+            Some(n) => usize::try_from(n)
+                .map(Some)
+                .map_err(|_| self.error("length too large")),
+            None => Ok(None),
+        }
+    }
+
+    fn at_break(&mut self) -> Result<bool> {
+        if self.buf.get(self.pos) == Some(&BREAK) {
+            self.pos += 1;
+            Ok(true)
+        } else if self.pos == self.buf.len() {
+            Err(self.error("unexpected end"))
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// A byte or text string, concatenating the chunks of an indefinite one.
+    fn string(&mut self, major: u8, info: u8) -> Result<Vec<u8>> {
+        match self.length(info)? {
+            Some(len) => Ok(self.take(len)?.to_vec()),
+            None => {
+                let mut out = Vec::new();
+                while !self.at_break()? {
+                    let chunk = self.byte()?;
+                    if chunk >> 5 != major || chunk & 0x1f == INDEFINITE {
+                        return Err(self.error("bad chunk in indefinite-length string"));
+                    }
+                    let len = self.length(chunk & 0x1f)?.unwrap_or_default();
+                    out.extend_from_slice(self.take(len)?);
+                }
+                Ok(out)
+            }
+        }
+    }
+
+    /// An item inside an array, map or tag.
+    fn nested(&mut self) -> Result<Value> {
+        if self.depth == MAX_DEPTH {
+            return Err(BencodeError::new(ErrorKind::DepthLimit(MAX_DEPTH)).at(self.pos));
+        }
+        self.depth += 1;
+        let value = self.value();
+        self.depth -= 1;
+        value
+    }
+
+    fn value(&mut self) -> Result<Value> {
+        let start = self.pos;
+        let initial = self.byte()?;
+        let (major, info) = (initial >> 5, initial & 0x1f);
+        Ok(match major {
+            UNSIGNED | NEGATIVE => {
+                let n = self
+                    .argument(info)?
+                    .ok_or_else(|| self.error("indefinite-length integer"))?;
+                let n = i64::try_from(n).map_err(|_| self.error("integer out of range"))?;
+                Value::Int(if major == UNSIGNED { n } else { -1 - n })
+            }
+            BYTES => Value::Bytes(self.string(major, info)?),
+            TEXT => {
+                let bytes = self.string(major, info)?;
+                Value::Str(String::from_utf8(bytes).map_err(|_| {
+                    self.pos = start;
+                    self.error("text string is not UTF-8")
+                })?)
+            }
+            ARRAY => {
+                let mut items = Vec::new();
+                match self.length(info)? {
+                    Some(len) => {
+                        for _ in 0..len {
+                            items.push(self.nested()?);
+                        }
+                    }
+                    None => {
+                        while !self.at_break()? {
+                            items.push(self.nested()?);
+                        }
+                    }
+                }
+                Value::List(items)
+            }
+            MAP => {
+                let mut map = Map::new();
+                let len = self.length(info)?;
+                let mut read = 0;
+                while match len {
+                    Some(len) => read < len,
+                    None => !self.at_break()?,
+                } {
+                    let key_at = self.pos;
+                    let key = self.nested()?;
+                    if key.as_bytes().is_none() {
+                        self.pos = key_at;
+                        return Err(self.error("map key is not a string"));
+                    }
+                    map.insert(key, self.nested()?);
+                    read += 1;
+                }
+                Value::Map(HMap(map))
+            }
+            TAG => {
+                self.argument(info)?;
+                self.nested()?
+            }
+            // major type 7: simple values and floats
+            _ => self.simple(info)?,
+        })
+    }
+
+    fn simple(&mut self, info: u8) -> Result<Value> {
+        let float = match info {
+            20 => return Ok(Value::Int(0)),
+            21 => return Ok(Value::Int(1)),
+            25 => {
+                let half = u16::from_be_bytes([self.byte()?, self.byte()?]);
+                f64::from(half_to_f32(half))
+            }
+            26 => {
+                let mut b = [0; 4];
+                b.copy_from_slice(self.take(4)?);
+                f64::from(f32::from_be_bytes(b))
+            }
+            27 => {
+                let mut b = [0; 8];
+                b.copy_from_slice(self.take(8)?);
+                f64::from_be_bytes(b)
+            }
+            22 => return Err(self.error("null has no bencode form")),
+            23 => return Err(self.error("undefined has no bencode form")),
+            _ => return Err(self.error("unsupported simple value")),
+        };
+        if float.fract() == 0.0 && float.abs() < 9.0e18 {
+            Ok(Value::Int(float as i64))
+        } else {
+            Err(self.error(&format!("number {} is not an integer", float)))
+        }
+    }
+}
+
+/// Widen an IEEE 754 half-precision float.
+fn half_to_f32(half: u16) -> f32 {
+    let sign = if half & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = i32::from((half >> 10) & 0x1f);
+    let mantissa = f32::from(half & 0x3ff);
+    sign * match exponent {
+        0 => mantissa * 2f32.powi(-24),
+        0x1f if mantissa == 0.0 => f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_bencode;
+
+    #[test]
+    fn test_cbor_roundtrip() {
+        let mut buf = b"d8:announce3:url4:infod6:lengthi-300e6:pieces20:".to_vec();
+        buf.extend_from_slice(&[0xaa; 20]);
+        buf.extend_from_slice(b"e4:listli0ei4294967296eee");
+        let value = parse_bencode(&mut &buf[..]).unwrap().unwrap();
+        let cbor = to_cbor(&value);
+        assert_eq!(from_cbor(&cbor).unwrap(), value);
+        // keys sorted by encoded bytes: shorter keys first
+        assert_eq!(&cbor[..2], &[0xa3, 0x64]);
+        assert_eq!(&cbor[2..6], b"info");
+        assert_eq!(to_cbor(&Value::from(-300)), [0x39, 0x01, 0x2b]);
+    }
+
+    #[test]
+    fn test_cbor_decode() {
+        // indefinite text and array, tag, booleans, half and double floats
+        let cbor = [
+            0x9f, 0x7f, 0x62, b'a', b'b', 0x61, b'c', 0xff, 0xc1, 0x1a, 0, 0, 0, 5, 0xf5, 0xf4,
+            0xf9, 0x3c, 0x00, 0xfb, 0x40, 0x59, 0, 0, 0, 0, 0, 0, 0xff,
+        ];
+        assert_eq!(
+            from_cbor(&cbor).unwrap(),
+            Value::List(vec![
+                Value::from("abc"),
+                Value::from(5),
+                Value::from(1),
+                Value::from(0),
+                Value::from(1),
+                Value::from(100),
+            ])
+        );
+
+        let err = |buf: &[u8]| from_cbor(buf).unwrap_err().to_string();
+        assert!(err(&[0xf6]).contains("null"));
+        assert!(err(&[0xf9, 0x38, 0x00]).contains("not an integer"));
+        assert!(err(&[0x1b, 0xff, 0, 0, 0, 0, 0, 0, 0]).contains("out of range"));
+        assert!(err(&[0xa1, 0x01, 0x01]).contains("offset 1: map key"));
+        assert!(err(&[0x62, b'a']).contains("unexpected end"));
+        assert!(err(&[0x01, 0x01]).contains("trailing"));
+
+        let deep = from_cbor(&[0x81; 100_000]).unwrap_err();
+        assert_eq!(deep.kind(), Some(ErrorKind::DepthLimit(MAX_DEPTH)));
+        assert_eq!(deep.position(), Some(MAX_DEPTH + 1));
+    }
+}
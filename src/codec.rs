@@ -0,0 +1,392 @@
+//! Message framing for bencode streams.
+//!
+//! [`BencodeCodec`] turns a growing byte buffer into complete values and back,
+//! following the shape of `tokio_util::codec::{Decoder, Encoder}`: bytes read
+//! from a socket are appended to a buffer, `decode` removes and returns one
+//! value once all of its bytes have arrived, and leaves partial input in place
+//! for the next read.
+//!
+//! All partial-message state lives in the codec (and in [`Decoder`], which
+//! also owns the buffer), never in a read call or future. Dropping a pending
+//! read and starting a new one, e.g. in a `select!` loop, loses nothing: the
+//! next `decode` resumes scanning where the previous one stopped.
+
+use crate::combinator;
+use crate::document::MAX_DEPTH;
+use crate::{BencodeError, ErrorKind, Result, Value};
+use std::io::{self, Read};
+use std::str::FromStr;
+
+/// Longest decimal length prefix accepted for a byte string.
+const MAX_LEN_DIGITS: usize = 20;
+
+/// Codec decoding a stream of concatenated bencode values.
+#[derive(Debug, Default)]
+pub struct BencodeCodec {
+    // resume point of the scan over the buffered, still incomplete value
+    scan_pos: usize,
+    depth: usize,
+    max_frame: Option<usize>,
+}
+
+impl BencodeCodec {
+    pub fn new() -> Self {
+        BencodeCodec::default()
+    }
+
+    /// A codec that fails with `BencodeError::FrameTooLarge` rather than
+    /// buffer more than `max_frame` bytes of a single message, so a peer that
+    /// trickles in an endless message cannot grow memory without bound.
+    pub fn with_max_frame(max_frame: usize) -> Self {
+        BencodeCodec {
+            max_frame: Some(max_frame),
+            ..BencodeCodec::default()
+        }
+    }
+
+    /// Remove one complete value from the front of `src`. Returns `Ok(None)`
+    /// if `src` does not hold a whole value yet.
+    ///
+    /// Between calls, bytes may only be appended to `src`; scanning resumes
+    /// from where the previous call stopped instead of starting over.
+    pub fn decode(&mut self, src: &mut Vec<u8>) -> Result<Option<Value>> {
+        if self.scan_pos > src.len() {
+            // the caller replaced the buffer; start over
+            self.reset();
+        }
+        let complete = match scan(src, &mut self.scan_pos, &mut self.depth) {
+            Ok(complete) => complete,
+            Err(e) => {
+                self.reset();
+                return Err(e);
+            }
+        };
+        let len = if complete { self.scan_pos } else { src.len() };
+        if let Some(max) = self.max_frame {
+            if len > max {
+                self.reset();
+                return Err(BencodeError::FrameTooLarge(max));
+            }
+        }
+        if !complete {
+            return Ok(None);
+        }
+        self.reset();
+        take_value(src, len).map(Some)
+    }
+
+    /// Forget any partially scanned value.
+    pub fn reset(&mut self) {
+        self.scan_pos = 0;
+        self.depth = 0;
+    }
+
+    /// Append the encoding of `item` to `dst`.
+    pub fn encode(&mut self, item: &Value, dst: &mut Vec<u8>) -> Result<()> {
+        item.write_bencode(dst)
+    }
+}
+
+/// Incremental decoder owning both the buffered bytes and the scan state, so
+/// it can be fed from any source in pieces of any size.
+#[derive(Debug, Default)]
+pub struct Decoder {
+    buf: Vec<u8>,
+    codec: BencodeCodec,
+}
+
+impl Decoder {
+    pub fn new() -> Self {
+        Decoder::default()
+    }
+
+    /// A decoder bounded like [`BencodeCodec::with_max_frame`].
+    pub fn with_max_frame(max_frame: usize) -> Self {
+        Decoder {
+            buf: Vec::new(),
+            codec: BencodeCodec::with_max_frame(max_frame),
+        }
+    }
+
+    /// Append freshly received bytes.
+    pub fn feed(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    /// Return the next complete value, if one has been fully received.
+    pub fn decode(&mut self) -> Result<Option<Value>> {
+        self.codec.decode(&mut self.buf)
+    }
+
+    /// Bytes received but not yet returned as part of a value.
+    pub fn buffered(&self) -> &[u8] {
+        &self.buf
+    }
+}
+
+/// Iterator over the values arriving on a reader, yielding each one as soon
+/// as its last byte has been read. This is the blocking counterpart of a
+/// `futures::Stream` of messages, e.g. nREPL or KRPC responses on a socket.
+pub struct ValueStream<R> {
+    reader: R,
+    decoder: Decoder,
+    done: bool,
+}
+
+impl<R: Read> ValueStream<R> {
+    pub fn new(reader: R) -> Self {
+        ValueStream::with_decoder(reader, Decoder::new())
+    }
+
+    /// A stream whose messages may not exceed `max_frame` bytes.
+    pub fn with_max_frame(reader: R, max_frame: usize) -> Self {
+        ValueStream::with_decoder(reader, Decoder::with_max_frame(max_frame))
+    }
+
+    fn with_decoder(reader: R, decoder: Decoder) -> Self {
+        ValueStream {
+            reader,
+            decoder,
+            done: false,
+        }
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
+
+    /// Return the reader. Bytes already read but not yet decoded are lost.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    fn next_value(&mut self) -> Result<Option<Value>> {
+        let mut chunk = [0; 4096];
+        loop {
+            if let Some(value) = self.decoder.decode()? {
+                return Ok(Some(value));
+            }
+            let n = match self.reader.read(&mut chunk) {
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            };
+            if n == 0 {
+                return if self.decoder.buffered().is_empty() {
+                    Ok(None)
+                } else {
+                    Err(BencodeError::Eof())
+                };
+            }
+            self.decoder.feed(&chunk[..n]);
+        }
+    }
+}
+
+impl<R: Read> Iterator for ValueStream<R> {
+    type Item = Result<Value>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let next = self.next_value();
+        if !matches!(next, Ok(Some(_))) {
+            // end of stream, or an error that leaves the buffer unusable
+            self.done = true;
+        }
+        next.transpose()
+    }
+}
+
+// Parse the value occupying `src[..len]` and drain those bytes.
+fn take_value(src: &mut Vec<u8>, len: usize) -> Result<Value> {
+    let (_, value) = combinator::value(&src[..len])?;
+    src.drain(..len);
+    Ok(value)
+}
+
+/// Advance `pos` over complete tokens of the value starting at the front of
+/// `buf`, tracking open containers in `depth`. Returns `true` once the value
+/// is complete. On `false`, `pos` rests at the start of the first incomplete
+/// token so that scanning can resume there when more bytes arrive. Nesting
+/// deeper than [`MAX_DEPTH`] is an error as soon as it is seen.
+pub(crate) fn scan(buf: &[u8], pos: &mut usize, depth: &mut usize) -> Result<bool> {
+    loop {
+        match buf.get(*pos) {
+            None => return Ok(false),
+            Some(b'i') => {
+                let body = &buf[*pos + 1..];
+                let end = body.iter().position(|b| *b == b'e');
+                let digits = &body[..end.unwrap_or(body.len())];
+                if let Some(i) = digits
+                    .iter()
+                    .position(|b| !(b.is_ascii_digit() || *b == b'-'))
+                {
+                    return Err(
+                        BencodeError::new(ErrorKind::InvalidCharacter(digits[i])).at(*pos + 1 + i)
+                    );
+                }
+                match end {
+                    Some(end) => *pos += end + 2,
+                    None => return Ok(false),
+                }
+            }
+            Some(b'l') | Some(b'd') if *depth == MAX_DEPTH => {
+                return Err(BencodeError::new(ErrorKind::DepthLimit(MAX_DEPTH)).at(*pos));
+            }
+            Some(b'l') | Some(b'd') => {
+                *depth += 1;
+                *pos += 1;
+                continue;
+            }
+            Some(b'e') if *depth > 0 => {
+                *depth -= 1;
+                *pos += 1;
+            }
+            Some(b'0'..=b'9') => {
+                let rest = &buf[*pos..];
+                let colon = rest.iter().position(|b| !b.is_ascii_digit());
+                let digits = colon.unwrap_or(rest.len());
+                if digits > MAX_LEN_DIGITS {
+                    return Err(BencodeError::new(ErrorKind::InvalidLength).at(*pos));
+                }
+                let colon = match colon {
+                    Some(c) if rest[c] == b':' => c,
+                    Some(c) => {
+                        return Err(
+                            BencodeError::new(ErrorKind::InvalidCharacter(rest[c])).at(*pos + c)
+                        )
+                    }
+                    None => return Ok(false),
+                };
+                let len = usize::from_str(&String::from_utf8_lossy(&rest[..colon]))
+                    .map_err(|_| BencodeError::new(ErrorKind::InvalidLength).at(*pos))?;
+                match (colon + 1).checked_add(len) {
+                    Some(end) if end <= rest.len() => *pos += end,
+                    Some(_) => return Ok(false),
+                    None => return Err(BencodeError::new(ErrorKind::InvalidLength).at(*pos)),
+                }
+            }
+            Some(b) => return Err(BencodeError::new(ErrorKind::InvalidCharacter(*b)).at(*pos)),
+        }
+        if *depth == 0 {
+            return Ok(true);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_codec_handles_partial_frames() {
+        let mut codec = BencodeCodec::new();
+        let mut buf = Vec::new();
+        let mut decoded = vec![];
+        for (i, b) in b"d2:op4:evale3:foo".iter().enumerate() {
+            buf.push(*b);
+            if let Some(v) = codec.decode(&mut buf).unwrap() {
+                decoded.push((i, v));
+            }
+        }
+        let mut expected = std::collections::HashMap::new();
+        expected.insert("op", "eval");
+        assert_eq!(
+            decoded,
+            vec![(11, Value::from(expected)), (16, Value::from("foo"))]
+        );
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_codec_rejects_garbage_early() {
+        let mut codec = BencodeCodec::new();
+        assert!(codec.decode(&mut b"x".to_vec()).is_err());
+        assert!(codec.decode(&mut b"li1x".to_vec()).is_err());
+        assert!(codec.decode(&mut b"12a".to_vec()).is_err());
+    }
+
+    #[test]
+    fn test_decoder_resumes_scan() {
+        let mut decoder = Decoder::new();
+        decoder.feed(b"l5:hello");
+        assert_eq!(decoder.decode().unwrap(), None);
+        assert_eq!(decoder.codec.scan_pos, 8);
+
+        decoder.feed(b"i4");
+        assert_eq!(decoder.decode().unwrap(), None);
+        // stays at the start of the unfinished integer
+        assert_eq!(decoder.codec.scan_pos, 8);
+
+        decoder.feed(b"2eei7e");
+        assert_eq!(
+            decoder.decode().unwrap(),
+            Some(Value::List(vec![Value::from("hello"), Value::Int(42)]))
+        );
+        assert_eq!(decoder.decode().unwrap(), Some(Value::Int(7)));
+        assert!(decoder.buffered().is_empty());
+    }
+
+    #[test]
+    fn test_bounded_decoder() {
+        let mut decoder = Decoder::with_max_frame(8);
+        decoder.feed(b"l1:a");
+        assert_eq!(decoder.decode().unwrap(), None);
+        decoder.feed(b"1:b1");
+        assert_eq!(decoder.decode().unwrap(), None);
+        decoder.feed(b":");
+        assert!(matches!(
+            decoder.decode(),
+            Err(BencodeError::FrameTooLarge(8))
+        ));
+
+        let mut stream = ValueStream::with_max_frame(&b"i1e999999999:"[..], 8);
+        assert_eq!(stream.next().unwrap().unwrap(), Value::Int(1));
+        assert!(matches!(
+            stream.next(),
+            Some(Err(BencodeError::FrameTooLarge(8)))
+        ));
+    }
+
+    #[test]
+    fn test_codec_limits_depth() {
+        let mut codec = BencodeCodec::with_max_frame(1 << 20);
+        let mut buf = [vec![b'l'; 200_000], vec![b'e'; 200_000]].concat();
+        let err = codec.decode(&mut buf).unwrap_err();
+        assert_eq!(err.kind(), Some(ErrorKind::DepthLimit(MAX_DEPTH)));
+        assert_eq!(err.position(), Some(MAX_DEPTH));
+
+        let mut buf = [vec![b'l'; MAX_DEPTH], vec![b'e'; MAX_DEPTH]].concat();
+        assert!(codec.decode(&mut buf).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_value_stream_yields_each_message() {
+        let input = &b"i1e3:twoli3ee4:fo"[..];
+        let mut stream = ValueStream::new(input);
+        assert_eq!(stream.next().unwrap().unwrap(), Value::Int(1));
+        assert_eq!(stream.next().unwrap().unwrap(), Value::from("two"));
+        assert_eq!(
+            stream.next().unwrap().unwrap(),
+            Value::List(vec![Value::Int(3)])
+        );
+        assert!(matches!(stream.next(), Some(Err(BencodeError::Eof()))));
+        assert!(stream.next().is_none());
+
+        assert_eq!(ValueStream::new(&b"i1ei2e"[..]).count(), 2);
+    }
+
+    #[test]
+    fn test_codec_encode_appends() {
+        let mut codec = BencodeCodec::new();
+        let mut buf = b"i1e".to_vec();
+        codec.encode(&Value::Int(2), &mut buf).unwrap();
+        assert_eq!(buf, b"i1ei2e");
+    }
+}
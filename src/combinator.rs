@@ -0,0 +1,241 @@
+//! Bencode parsing as composable functions over byte slices, for protocols
+//! that embed bencode among other binary data.
+//!
+//! Every parser takes the input and returns the rest of it along with what
+//! it parsed, in the shape of nom's `IResult`, so the pieces plug into
+//! hand-written or nom parser stacks with at most an error conversion.
+//! An error of kind [`UnexpectedEof`](ErrorKind::UnexpectedEof) means the
+//! input ended inside a value: wait for more bytes and try again. Error
+//! positions are byte offsets into the input given to the outermost parser.
+//!
+//! A `ut_metadata` data message (BEP 9), for instance, is a dictionary
+//! followed by raw piece bytes:
+//!
+//! ```
+//! use bencode_rs::combinator::{dict_of, integer};
+//!
+//! let message = b"d8:msg_typei1e5:piecei0e10:total_sizei3eeabc";
+//! let (data, fields) = dict_of(integer)(message).unwrap();
+//! assert_eq!(fields[0], (&b"msg_type"[..], 1));
+//! assert_eq!(data, b"abc");
+//! ```
+
+use crate::document::MAX_DEPTH;
+use crate::prelude::*;
+use crate::{BencodeError, ErrorKind, HMap, Map, Result, Value};
+use core::str::FromStr;
+
+/// The rest of the input and the parsed item.
+pub type IResult<'a, T> = Result<(&'a [u8], T)>;
+
+/// Dictionary entries as returned by [`dict_of`].
+pub type Entries<'a, T> = Vec<(&'a [u8], T)>;
+
+fn unexpected(byte: u8) -> BencodeError {
+    BencodeError::new(ErrorKind::InvalidCharacter(byte)).at(0)
+}
+
+/// The input ends inside a value.
+fn eof(input: &[u8]) -> BencodeError {
+    BencodeError::new(ErrorKind::UnexpectedEof).at(input.len())
+}
+
+/// Make the position of an error from a parser that was given `rest`
+/// relative to `input`; errors without one get the offset of `rest`.
+fn shift<'a>(input: &'a [u8], rest: &'a [u8]) -> impl FnOnce(BencodeError) -> BencodeError {
+    let offset = input.len() - rest.len();
+    move |err| {
+        let position = err.position().map_or(offset, |p| offset + p);
+        err.at(position)
+    }
+}
+
+/// Consume `byte`, the start of a value.
+fn tag(input: &[u8], byte: u8) -> Result<&[u8]> {
+    match input.first() {
+        None => Err(eof(input)),
+        Some(b) if *b == byte => Ok(&input[1..]),
+        Some(b) => Err(unexpected(*b)),
+    }
+}
+
+/// An integer: `i42e`.
+pub fn integer(input: &[u8]) -> IResult<'_, i64> {
+    let rest = tag(input, b'i')?;
+    let end = rest
+        .iter()
+        .position(|b| *b == b'e')
+        .ok_or_else(|| eof(input))?;
+    let n = i64::from_str(&String::from_utf8_lossy(&rest[..end]))
+        .map_err(|_| BencodeError::new(ErrorKind::InvalidInteger).at(0))?;
+    Ok((&rest[end + 1..], n))
+}
+
+/// A byte string: `4:spam`, borrowed from the input.
+pub fn byte_string(input: &[u8]) -> IResult<'_, &[u8]> {
+    match input.first() {
+        None => return Err(eof(input)),
+        Some(b'0'..=b'9') => {}
+        Some(b) => return Err(unexpected(*b)),
+    }
+    let colon = input
+        .iter()
+        .position(|b| *b == b':')
+        .ok_or_else(|| eof(input))?;
+    let len = usize::from_str(&String::from_utf8_lossy(&input[..colon]))
+        .map_err(|_| BencodeError::new(ErrorKind::InvalidLength).at(0))?;
+    let rest = &input[colon + 1..];
+    if rest.len() < len {
+        return Err(eof(input));
+    }
+    Ok((&rest[len..], &rest[..len]))
+}
+
+/// Consume the end marker `e` if it is next.
+fn end(input: &[u8]) -> Result<Option<&[u8]>> {
+    match input.first() {
+        None => Err(eof(input)),
+        Some(b'e') => Ok(Some(&input[1..])),
+        Some(_) => Ok(None),
+    }
+}
+
+/// A list whose elements are all parsed by `element`.
+pub fn list_of<'a, T, F>(mut element: F) -> impl FnMut(&'a [u8]) -> IResult<'a, Vec<T>>
+where
+    F: FnMut(&'a [u8]) -> IResult<'a, T>,
+{
+    move |input| {
+        let mut rest = tag(input, b'l')?;
+        let mut items = Vec::new();
+        loop {
+            if let Some(after) = end(rest).map_err(shift(input, rest))? {
+                return Ok((after, items));
+            }
+            let (after, item) = element(rest).map_err(shift(input, rest))?;
+            items.push(item);
+            rest = after;
+        }
+    }
+}
+
+/// A dictionary whose values are all parsed by `value`, as `(key, value)`
+/// pairs in input order. Key order and uniqueness are not checked.
+pub fn dict_of<'a, T, F>(mut value: F) -> impl FnMut(&'a [u8]) -> IResult<'a, Entries<'a, T>>
+where
+    F: FnMut(&'a [u8]) -> IResult<'a, T>,
+{
+    move |input| {
+        let mut rest = tag(input, b'd')?;
+        let mut entries = Vec::new();
+        loop {
+            if let Some(after) = end(rest).map_err(shift(input, rest))? {
+                return Ok((after, entries));
+            }
+            let (after, key) = byte_string(rest).map_err(shift(input, rest))?;
+            let (after, item) = value(after).map_err(shift(input, after))?;
+            entries.push((key, item));
+            rest = after;
+        }
+    }
+}
+
+/// Any value, decoded. Nesting deeper than [`MAX_DEPTH`] is an error.
+pub fn value(input: &[u8]) -> IResult<'_, Value> {
+    value_at(input, 0)
+}
+
+fn value_at(input: &[u8], depth: usize) -> IResult<'_, Value> {
+    let nested = |input| value_at(input, depth + 1);
+    match input.first() {
+        None => Err(eof(input)),
+        Some(b'l') | Some(b'd') if depth == MAX_DEPTH => {
+            Err(BencodeError::new(ErrorKind::DepthLimit(MAX_DEPTH)).at(0))
+        }
+        Some(b'i') => integer(input).map(|(rest, n)| (rest, Value::Int(n))),
+        Some(b'l') => list_of(nested)(input).map(|(rest, items)| (rest, Value::List(items))),
+        Some(b'd') => {
+            let (rest, entries) = dict_of(nested)(input)?;
+            let map: Map<Value, Value> = entries
+                .into_iter()
+                .map(|(k, v)| (Value::from_bytes(k.to_vec()), v))
+                .collect();
+            Ok((rest, Value::Map(HMap(map))))
+        }
+        Some(_) => byte_string(input).map(|(rest, s)| (rest, Value::from_bytes(s.to_vec()))),
+    }
+}
+
+/// Any value, undecoded: the exact bytes it occupies. Useful for hashing an
+/// embedded dictionary or passing it on unchanged.
+pub fn raw_value(input: &[u8]) -> IResult<'_, &[u8]> {
+    raw_value_at(input, 0)
+}
+
+fn raw_value_at(input: &[u8], depth: usize) -> IResult<'_, &[u8]> {
+    let rest = match input.first() {
+        None => return Err(eof(input)),
+        Some(b'l') | Some(b'd') if depth == MAX_DEPTH => {
+            return Err(BencodeError::new(ErrorKind::DepthLimit(MAX_DEPTH)).at(0))
+        }
+        Some(b'i') => integer(input)?.0,
+        Some(b'l') | Some(b'd') => {
+            let dict = input[0] == b'd';
+            let mut rest = &input[1..];
+            loop {
+                if let Some(after) = end(rest).map_err(shift(input, rest))? {
+                    break after;
+                }
+                if dict {
+                    rest = byte_string(rest).map_err(shift(input, rest))?.0;
+                }
+                rest = raw_value_at(rest, depth + 1).map_err(shift(input, rest))?.0;
+            }
+        }
+        Some(_) => byte_string(input)?.0,
+    };
+    Ok((rest, &input[..input.len() - rest.len()]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scalars() {
+        assert_eq!(integer(b"i-42exyz").unwrap(), (&b"xyz"[..], -42));
+        assert_eq!(byte_string(b"3:abcd").unwrap(), (&b"d"[..], &b"abc"[..]));
+        assert_eq!(byte_string(b"0:").unwrap(), (&b""[..], &b""[..]));
+        let err = integer(b"i12").unwrap_err();
+        assert_eq!(err.kind(), Some(ErrorKind::UnexpectedEof));
+        assert_eq!(err.position(), Some(3));
+        assert_eq!(byte_string(b"5:abc").unwrap_err().position(), Some(5));
+        assert!(integer(b"ixe").is_err());
+        assert!(byte_string(b"i1e")
+            .unwrap_err()
+            .to_string()
+            .contains("0x69"));
+    }
+
+    #[test]
+    fn test_composed() {
+        let (rest, lists) = list_of(list_of(integer))(b"lli1ei2eelee!").unwrap();
+        assert_eq!(lists, vec![vec![1, 2], vec![]]);
+        assert_eq!(rest, b"!");
+        let err = list_of(integer)(b"li1e3:abce").unwrap_err();
+        assert_eq!(err.kind(), Some(ErrorKind::InvalidCharacter(b'3')));
+        assert_eq!(err.position(), Some(4));
+        let err = dict_of(byte_string)(b"d1:a1:b").unwrap_err();
+        assert_eq!(err.kind(), Some(ErrorKind::UnexpectedEof));
+        assert_eq!(err.position(), Some(7));
+        let err = value(b"ld1:ai1e1:bi-ee").unwrap_err();
+        assert_eq!(err.kind(), Some(ErrorKind::InvalidInteger));
+        assert_eq!(err.position(), Some(11));
+
+        let input = b"d1:ali1e1:xe1:bi2ee\x00\x01";
+        let (rest, raw) = raw_value(input).unwrap();
+        assert_eq!((raw, rest), (&input[..19], &b"\x00\x01"[..]));
+        let (_, decoded) = value(input).unwrap();
+        assert_eq!(decoded, crate::Document::parse(raw).unwrap().to_value());
+    }
+}
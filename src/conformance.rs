@@ -0,0 +1,267 @@
+//! A conformance suite for bencode decoders.
+//!
+//! The cases live in `conformance/cases.txt` at the root of the repository,
+//! in a line-based format other implementations can read directly; the file
+//! header describes it. [`cases`] returns them parsed, and [`run`] checks a
+//! decoder against all of them.
+//!
+//! A decoder is tested through a function that takes the input bytes and
+//! returns `None` to reject them, or the accepted value re-encoded
+//! canonically (sorted keys, no redundant characters). Working on bytes keeps
+//! the harness independent of any particular value type.
+//!
+//! ```
+//! use bencode_rs::{conformance, Document, EncodeOptions};
+//!
+//! let report = conformance::run(|input| {
+//!     let value = Document::parse(input).ok()?.to_value();
+//!     let mut out = Vec::new();
+//!     value.write_bencode_with(&mut out, EncodeOptions::strict()).ok()?;
+//!     Some(out)
+//! });
+//! assert!(report.is_ok(), "{}", report);
+//! ```
+
+use crate::{BencodeError, Result};
+use std::fmt::{self, Display};
+use std::panic::{self, AssertUnwindSafe};
+
+const CASES: &str = include_str!("../conformance/cases.txt");
+
+/// What a decoder must do with a case's input.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expect {
+    /// Accept it; the canonical encoding of the result is given.
+    Accept(Vec<u8>),
+    /// Reject it.
+    Reject,
+    /// Either reject it, or accept it and produce this canonical encoding.
+    /// `None` means any result is acceptable.
+    Lenient(Option<Vec<u8>>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Case {
+    pub name: String,
+    pub input: Vec<u8>,
+    pub expect: Expect,
+}
+
+/// A case the decoder got wrong.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Failure {
+    pub name: String,
+    pub message: String,
+}
+
+/// The outcome of [`run`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Report {
+    pub passed: usize,
+    pub failures: Vec<Failure>,
+    /// Names of `lenient` cases the decoder accepted. These are not failures,
+    /// but a strict decoder should have none.
+    pub accepted_lenient: Vec<String>,
+}
+
+impl Report {
+    pub fn is_ok(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} passed, {} failed", self.passed, self.failures.len())?;
+        if !self.accepted_lenient.is_empty() {
+            write!(f, ", {} accepted leniently", self.accepted_lenient.len())?;
+        }
+        for failure in &self.failures {
+            write!(f, "\n  {}: {}", failure.name, failure.message)?;
+        }
+        Ok(())
+    }
+}
+
+/// The bundled conformance cases.
+pub fn cases() -> Vec<Case> {
+    // the file is checked by the tests below, so it always parses
+    parse_cases(CASES).expect("conformance/cases.txt is malformed")
+}
+
+/// Run `decode` against every case. It should return `None` to reject an
+/// input and the canonical encoding of the decoded value to accept it. A
+/// panic counts as a failure of that case rather than ending the run.
+pub fn run<F>(mut decode: F) -> Report
+where
+    F: FnMut(&[u8]) -> Option<Vec<u8>>,
+{
+    let mut report = Report::default();
+    for case in cases() {
+        let result = panic::catch_unwind(AssertUnwindSafe(|| decode(&case.input)));
+        let message = match (&case.expect, result) {
+            (_, Err(_)) => Some("decoder panicked".to_string()),
+            (Expect::Accept(_), Ok(None)) => Some("rejected a valid input".to_string()),
+            (Expect::Reject, Ok(Some(_))) => Some("accepted an invalid input".to_string()),
+            (Expect::Accept(expected), Ok(Some(actual)))
+            | (Expect::Lenient(Some(expected)), Ok(Some(actual)))
+                if *expected != actual =>
+            {
+                Some(format!(
+                    "expected {}, got {}",
+                    escape(expected),
+                    escape(&actual)
+                ))
+            }
+            (Expect::Lenient(_), Ok(Some(_))) => {
+                report.accepted_lenient.push(case.name.clone());
+                None
+            }
+            _ => None,
+        };
+        match message {
+            Some(message) => report.failures.push(Failure {
+                name: case.name,
+                message,
+            }),
+            None => report.passed += 1,
+        }
+    }
+    report
+}
+
+fn parse_cases(text: &str) -> Result<Vec<Case>> {
+    let mut cases = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let error = |msg: &str| BencodeError::msg(format!("Line {}: {}", i + 1, msg));
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let (expect, name, input, canonical) = match fields[..] {
+            [expect, name, input] => (expect, name, input, None),
+            [expect, name, input, canonical] => (expect, name, input, Some(canonical)),
+            _ => return Err(error("expected 3 or 4 fields")),
+        };
+        let input = unescape(input).ok_or_else(|| error("bad escape in input"))?;
+        let canonical = match canonical {
+            Some("-") => None,
+            Some(text) => Some(unescape(text).ok_or_else(|| error("bad escape"))?),
+            None => Some(input.clone()),
+        };
+        let expect = match (expect, canonical) {
+            ("accept", Some(canonical)) => Expect::Accept(canonical),
+            ("reject", _) if fields.len() == 3 => Expect::Reject,
+            ("lenient", canonical) if fields.len() == 4 => Expect::Lenient(canonical),
+            _ => return Err(error("bad expectation")),
+        };
+        cases.push(Case {
+            name: name.to_string(),
+            input,
+            expect,
+        });
+    }
+    Ok(cases)
+}
+
+fn unescape(text: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut bytes = text.bytes();
+    while let Some(b) = bytes.next() {
+        if b != b'\\' {
+            out.push(b);
+            continue;
+        }
+        match bytes.next()? {
+            b'\\' => out.push(b'\\'),
+            b'x' => {
+                let hex = [bytes.next()?, bytes.next()?];
+                let hex = std::str::from_utf8(&hex).ok()?;
+                out.push(u8::from_str_radix(hex, 16).ok()?);
+            }
+            _ => return None,
+        }
+    }
+    Some(out)
+}
+
+/// Bytes in the notation of `cases.txt`.
+pub(crate) fn escape(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|&b| match b {
+            b'\\' => "\\\\".to_string(),
+            b'!'..=b'~' => (b as char).to_string(),
+            _ => format!("\\x{:02x}", b),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Document, EncodeOptions};
+
+    fn document(input: &[u8]) -> Option<Vec<u8>> {
+        let value = Document::parse(input).ok()?.to_value();
+        let mut out = Vec::new();
+        value
+            .write_bencode_with(&mut out, EncodeOptions::strict())
+            .ok()?;
+        Some(out)
+    }
+
+    #[test]
+    fn test_conformance_cases_parse() {
+        let cases = cases();
+        assert!(cases.len() > 40);
+        let binary = cases.iter().find(|c| c.name == "string-binary").unwrap();
+        assert_eq!(binary.input, b"3:\xff\x00\x01");
+        assert_eq!(binary.expect, Expect::Accept(binary.input.clone()));
+        for case in &cases {
+            assert_eq!(unescape(&escape(&case.input)).unwrap(), case.input);
+        }
+        assert!(parse_cases("accept x").is_err());
+        assert!(parse_cases("reject x i1e i1e").is_err());
+        assert!(parse_cases("accept x \\x4").is_err());
+    }
+
+    #[test]
+    fn test_conformance_document_parser() {
+        let report = run(document);
+        assert!(report.is_ok(), "{}", report);
+        assert_eq!(report.passed, cases().len());
+        assert!(report
+            .accepted_lenient
+            .contains(&"dict-unsorted".to_string()));
+    }
+
+    #[test]
+    fn test_conformance_reports_failures() {
+        let mut calls = 0;
+        let report = run(|input| {
+            calls += 1;
+            match input {
+                b"i42e" => panic!("boom"),
+                b"i-42e" => Some(b"i42e".to_vec()),
+                b"x" => Some(Vec::new()),
+                _ => document(input),
+            }
+        });
+        assert_eq!(calls, cases().len());
+        let failures: Vec<String> = report
+            .failures
+            .iter()
+            .map(|f| format!("{}: {}", f.name, f.message))
+            .collect();
+        assert_eq!(
+            failures,
+            [
+                "int-positive: decoder panicked",
+                "int-negative: expected i-42e, got i42e",
+                "unknown-type: accepted an invalid input",
+            ]
+        );
+    }
+}
@@ -0,0 +1,227 @@
+//! Fallible access to the contents of a decoded [`Value`].
+//!
+//! The `as_*` accessors return `None` when the value has another type, for
+//! code that checks shapes itself. The `TryFrom` impls return a
+//! [`WrongType`](crate::ErrorKind::WrongType) error instead, so they compose
+//! with `?`:
+//!
+//! ```
+//! use bencode_rs::{from_slice, BencodeError};
+//! use std::convert::TryFrom;
+//!
+//! fn port(msg: &[u8]) -> Result<u16, BencodeError> {
+//!     let value = from_slice(msg)?;
+//!     let port = value.get("port").map(u64::try_from).transpose()?;
+//!     Ok(port.unwrap_or(6881) as u16)
+//! }
+//!
+//! assert_eq!(port(b"d4:porti51413ee").unwrap(), 51413);
+//! assert!(port(b"d4:port4:8080e").is_err());
+//! ```
+//!
+//! None of these panic, whatever the input.
+
+use crate::fields::expected;
+use crate::prelude::*;
+use crate::{BencodeError, HMap, Value};
+use core::convert::TryFrom;
+
+impl Value {
+    /// The integer in an `Int` value.
+    pub fn as_int(&self) -> Option<i64> {
+        match self {
+            Value::Int(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    /// The text of a string value that is valid UTF-8.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::Str(s) => Some(s),
+            Value::Bytes(b) => core::str::from_utf8(b).ok(),
+            _ => None,
+        }
+    }
+
+    /// The elements of a `List` value.
+    pub fn as_list(&self) -> Option<&[Value]> {
+        match self {
+            Value::List(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    /// The entries of a `Map` value.
+    pub fn as_map(&self) -> Option<&HMap> {
+        match self {
+            Value::Map(map) => Some(map),
+            _ => None,
+        }
+    }
+
+    /// Look up `key` in a dictionary; `None` for a missing key or a value
+    /// that is not a dictionary.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.as_map()?.get(&Value::from(key))
+    }
+}
+
+impl TryFrom<&Value> for i64 {
+    type Error = BencodeError;
+
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        value.as_int().ok_or_else(|| expected("integer", value))
+    }
+}
+
+impl TryFrom<&Value> for u64 {
+    type Error = BencodeError;
+
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        let n = i64::try_from(value)?;
+        u64::try_from(n)
+            .map_err(|_| BencodeError::msg(format!("must not be negative, found {}", n)))
+    }
+}
+
+impl<'a> TryFrom<&'a Value> for &'a str {
+    type Error = BencodeError;
+
+    fn try_from(value: &'a Value) -> Result<Self, Self::Error> {
+        match value.as_bytes() {
+            None => Err(expected("string", value)),
+            Some(_) => value
+                .as_str()
+                .ok_or_else(|| BencodeError::msg("String is not valid UTF-8")),
+        }
+    }
+}
+
+impl TryFrom<&Value> for String {
+    type Error = BencodeError;
+
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        <&str>::try_from(value).map(str::to_string)
+    }
+}
+
+impl TryFrom<Value> for String {
+    type Error = BencodeError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Str(s) => Ok(s),
+            Value::Bytes(b) => {
+                String::from_utf8(b).map_err(|_| BencodeError::msg("String is not valid UTF-8"))
+            }
+            other => Err(expected("string", &other)),
+        }
+    }
+}
+
+impl<'a> TryFrom<&'a Value> for &'a [u8] {
+    type Error = BencodeError;
+
+    fn try_from(value: &'a Value) -> Result<Self, Self::Error> {
+        value.as_bytes().ok_or_else(|| expected("string", value))
+    }
+}
+
+impl TryFrom<Value> for Vec<u8> {
+    type Error = BencodeError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Str(s) => Ok(s.into_bytes()),
+            Value::Bytes(b) => Ok(b),
+            other => Err(expected("string", &other)),
+        }
+    }
+}
+
+impl<'a> TryFrom<&'a Value> for &'a [Value] {
+    type Error = BencodeError;
+
+    fn try_from(value: &'a Value) -> Result<Self, Self::Error> {
+        value.as_list().ok_or_else(|| expected("list", value))
+    }
+}
+
+impl TryFrom<Value> for Vec<Value> {
+    type Error = BencodeError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::List(items) => Ok(items),
+            other => Err(expected("list", &other)),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::{parse_bencode, ErrorKind};
+    use std::collections::HashMap;
+
+    fn parse(input: &[u8]) -> Value {
+        parse_bencode(&mut &input[..]).unwrap().unwrap()
+    }
+
+    #[test]
+    fn test_accessors() {
+        let value = parse(b"d1:ai-3e1:b3:xyz1:cli1ee1:d2:\xff\xfee");
+        assert_eq!(value.get("a").and_then(Value::as_int), Some(-3));
+        assert_eq!(value.get("b").and_then(Value::as_str), Some("xyz"));
+        assert_eq!(
+            value.get("c").and_then(Value::as_list).map(<[_]>::len),
+            Some(1)
+        );
+        assert_eq!(value.get("d").and_then(Value::as_str), None);
+        assert_eq!(value.get("missing"), None);
+        assert_eq!(value.get("a").unwrap().get("a"), None);
+        assert_eq!(value.as_map().map(|m| m.0.len()), Some(4));
+        assert_eq!(Value::Int(1).as_map(), None);
+    }
+
+    #[test]
+    fn test_try_from() {
+        let value = parse(b"d1:ai-3e1:b3:xyz1:d2:\xff\xfee");
+        let (a, b, d) = (
+            value.get("a").unwrap(),
+            value.get("b").unwrap(),
+            value.get("d").unwrap(),
+        );
+        assert_eq!(i64::try_from(a).unwrap(), -3);
+        assert!(u64::try_from(a)
+            .unwrap_err()
+            .to_string()
+            .contains("negative"));
+        assert_eq!(<&str>::try_from(b).unwrap(), "xyz");
+        assert_eq!(String::try_from(b.clone()).unwrap(), "xyz");
+        assert!(String::try_from(d).is_err());
+        assert!(String::try_from(d.clone()).is_err());
+        assert_eq!(<&[u8]>::try_from(d).unwrap(), b"\xff\xfe");
+        assert_eq!(Vec::<u8>::try_from(d.clone()).unwrap(), b"\xff\xfe");
+
+        let err = i64::try_from(b).unwrap_err();
+        assert_eq!(
+            err.kind(),
+            Some(ErrorKind::WrongType {
+                expected: "integer",
+                found: "string"
+            })
+        );
+        assert!(<&[Value]>::try_from(a).is_err());
+        assert!(Vec::<Value>::try_from(value.clone()).is_err());
+    }
+
+    #[test]
+    fn test_string_map() {
+        let map = HashMap::<String, String>::try_from(parse(b"d1:ai1e1:b1:xe")).unwrap();
+        assert_eq!(map["a"], "1");
+        assert_eq!(map["b"], "x");
+        assert!(HashMap::<String, String>::try_from(Value::Int(1)).is_err());
+    }
+}
@@ -0,0 +1,85 @@
+//! Pointing at the bytes an error is about, for command-line tools that
+//! report corrupt files.
+
+use crate::prelude::*;
+use crate::BencodeError;
+use core::fmt::Write;
+
+impl BencodeError {
+    /// A hexdump row of `source` around this error's position, with the
+    /// offending byte marked in both the hex and the ASCII column. `source`
+    /// must be the input that was parsed; `None` if the error has no
+    /// position inside it.
+    ///
+    /// ```
+    /// use bencode_rs::Document;
+    ///
+    /// let input = b"d3:keyi12x4ee";
+    /// let err = Document::parse(&input[..]).unwrap_err();
+    /// let snippet = err.snippet(input).unwrap();
+    /// let lines: Vec<_> = snippet.lines().collect();
+    /// assert!(lines[0].starts_with("00000000  64 33 3a 6b 65 79 69 31  32 78"));
+    /// assert!(lines[0].ends_with("|d3:keyi12x4ee|"));
+    /// // under the `i` that starts the bad integer
+    /// assert_eq!(lines[1].trim(), "^^                                     ^");
+    /// ```
+    pub fn snippet(&self, source: &[u8]) -> Option<String> {
+        let position = self.position().filter(|p| *p < source.len())?;
+        let start = position - position % 16;
+        let row = &source[start..source.len().min(start + 16)];
+        let mut line = format!("{:08x} ", start);
+        let mut marker = " ".repeat(line.len());
+        for j in 0..16 {
+            let hex = match row.get(j) {
+                Some(b) => format!(" {:02x}", b),
+                None => "   ".to_string(),
+            };
+            line.push_str(&hex);
+            marker.push_str(if start + j == position { " ^^" } else { "   " });
+            if j == 7 {
+                line.push(' ');
+                marker.push(' ');
+            }
+        }
+        let _ = write!(line, "  |");
+        marker.push_str("   ");
+        for (j, &b) in row.iter().enumerate() {
+            line.push(if b.is_ascii_graphic() || b == b' ' {
+                b as char
+            } else {
+                '.'
+            });
+            marker.push(if start + j == position { '^' } else { ' ' });
+        }
+        line.push('|');
+        Some(format!("{}\n{}", line, marker.trim_end()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+    use crate::{BencodeError, Document};
+
+    #[test]
+    fn test_snippet_marks_position() {
+        let mut input = vec![b'l'; 20];
+        input.extend_from_slice(b"i1e\x01");
+        let err = Document::parse(&input[..]).unwrap_err();
+        assert_eq!(err.position(), Some(23));
+        let snippet = err.snippet(&input).unwrap();
+        let lines: Vec<_> = snippet.lines().collect();
+        assert_eq!(
+            lines[0],
+            "00000010  6c 6c 6c 6c 69 31 65 01                           |lllli1e.|"
+        );
+        assert_eq!(lines[1].find("^^"), Some(lines[0].find(" 01").unwrap() + 1));
+        assert_eq!(lines[1].rfind('^'), lines[0].find('.'));
+    }
+
+    #[test]
+    fn test_snippet_without_position() {
+        assert_eq!(BencodeError::Eof().snippet(b"i1"), None);
+        assert_eq!(BencodeError::msg("x").at(5).snippet(b"i1e"), None);
+    }
+}
@@ -0,0 +1,290 @@
+//! Differential testing against a reference implementation.
+//!
+//! [`compare`] feeds each input of a corpus to this crate's decoder and to a
+//! caller-supplied one, and compares the results. Both sides are functions
+//! from input bytes to the canonical re-encoding of what they decoded, or
+//! `None` when they reject the input, as in [`conformance`](crate::conformance).
+//! The first input on which they disagree is shrunk to a small input that
+//! still shows the same disagreement.
+//!
+//! ```
+//! use bencode_rs::differential::{compare, Outcome};
+//!
+//! // a reference decoder that does not know negative integers
+//! let reference = |input: &[u8]| {
+//!     if input.windows(2).any(|w| w == b"i-") {
+//!         return None;
+//!     }
+//!     bencode_rs::differential::decode(input)
+//! };
+//! let corpus = [&b"li1ei2ee"[..], b"d1:ali-5eee"];
+//! let divergence = compare(&corpus, reference).unwrap();
+//! assert_eq!(divergence.minimized, b"i-0e");
+//! assert_eq!(divergence.theirs, Outcome::Rejected);
+//! ```
+
+use crate::conformance::escape;
+use crate::document::NodeKind;
+use crate::{Document, EncodeOptions};
+use std::fmt::{self, Display};
+use std::mem;
+use std::ops::Range;
+use std::panic::{self, AssertUnwindSafe};
+
+/// What a decoder did with one input.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Outcome {
+    /// Accepted, with the canonical encoding of the result.
+    Accepted(Vec<u8>),
+    Rejected,
+    Panicked,
+}
+
+impl Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Accepted(bytes) => write!(f, "accepted as {}", escape(bytes)),
+            Outcome::Rejected => write!(f, "rejected"),
+            Outcome::Panicked => write!(f, "panicked"),
+        }
+    }
+}
+
+/// The first disagreement [`compare`] found.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Divergence {
+    /// Position of the input in the corpus.
+    pub index: usize,
+    pub input: Vec<u8>,
+    /// The smallest input found that makes the decoders disagree in the
+    /// same way as `input` does.
+    pub minimized: Vec<u8>,
+    /// What this crate did with `minimized`.
+    pub ours: Outcome,
+    /// What the reference did with `minimized`.
+    pub theirs: Outcome,
+}
+
+impl Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "input {} diverges; minimized to {}: ours {}, theirs {}",
+            self.index,
+            escape(&self.minimized),
+            self.ours,
+            self.theirs
+        )
+    }
+}
+
+/// This crate's decoder in the form [`compare`] expects: a complete
+/// document, re-encoded with sorted keys.
+pub fn decode(input: &[u8]) -> Option<Vec<u8>> {
+    let value = Document::parse(input).ok()?.to_value();
+    let mut out = Vec::new();
+    value
+        .write_bencode_with(&mut out, EncodeOptions::strict())
+        .ok()?;
+    Some(out)
+}
+
+/// Compare this crate's [`decode`] with `reference` on every input of
+/// `corpus`, returning the first divergence, or `None` if they agree on all.
+pub fn compare<I, F>(corpus: &[I], reference: F) -> Option<Divergence>
+where
+    I: AsRef<[u8]>,
+    F: FnMut(&[u8]) -> Option<Vec<u8>>,
+{
+    compare_with(corpus, decode, reference)
+}
+
+/// Like [`compare`], with both sides supplied: useful for checking a new
+/// parser against the one it replaces.
+pub fn compare_with<I, A, B>(corpus: &[I], mut ours: A, mut theirs: B) -> Option<Divergence>
+where
+    I: AsRef<[u8]>,
+    A: FnMut(&[u8]) -> Option<Vec<u8>>,
+    B: FnMut(&[u8]) -> Option<Vec<u8>>,
+{
+    let mut run = |input: &[u8]| (outcome(&mut ours, input), outcome(&mut theirs, input));
+    for (index, input) in corpus.iter().enumerate() {
+        let input = input.as_ref();
+        let (a, b) = run(input);
+        if a == b {
+            continue;
+        }
+        let kind = (mem::discriminant(&a), mem::discriminant(&b));
+        let minimized = minimize(input, |candidate| {
+            let (a, b) = run(candidate);
+            a != b && (mem::discriminant(&a), mem::discriminant(&b)) == kind
+        });
+        let (ours, theirs) = run(&minimized);
+        return Some(Divergence {
+            index,
+            input: input.to_vec(),
+            minimized,
+            ours,
+            theirs,
+        });
+    }
+    None
+}
+
+fn outcome<F: FnMut(&[u8]) -> Option<Vec<u8>>>(decode: &mut F, input: &[u8]) -> Outcome {
+    match panic::catch_unwind(AssertUnwindSafe(|| decode(input))) {
+        Ok(Some(bytes)) => Outcome::Accepted(bytes),
+        Ok(None) => Outcome::Rejected,
+        Err(_) => Outcome::Panicked,
+    }
+}
+
+/// Shrink `input` while `fails` holds. While it parses, it is replaced by
+/// one of its nested values or has a list item or dictionary entry cut out;
+/// then runs of bytes are removed, halving the run length down to single
+/// bytes, and finally each byte is replaced with a simpler one (`0`, or `e`
+/// to close containers early).
+fn minimize<F: FnMut(&[u8]) -> bool>(input: &[u8], mut fails: F) -> Vec<u8> {
+    let mut best = input.to_vec();
+    while let Some(smaller) = structural(&best).into_iter().find(|c| fails(c)) {
+        best = smaller;
+    }
+    let mut chunk = best.len().max(1);
+    loop {
+        let mut start = 0;
+        while start < best.len() {
+            let end = (start + chunk).min(best.len());
+            let candidate = [&best[..start], &best[end..]].concat();
+            if fails(&candidate) {
+                best = candidate;
+            } else {
+                start += chunk;
+            }
+        }
+        if chunk == 1 {
+            break;
+        }
+        chunk /= 2;
+    }
+    for i in 0..best.len() {
+        for &simpler in b"0e" {
+            if best[i] > simpler {
+                let mut candidate = best.clone();
+                candidate[i] = simpler;
+                if fails(&candidate) {
+                    best = candidate;
+                    break;
+                }
+            }
+        }
+    }
+    best
+}
+
+// Smaller documents made from `input`: each nested value on its own, then
+// the document with one list item or dictionary entry removed.
+fn structural(input: &[u8]) -> Vec<Vec<u8>> {
+    let doc = match Document::parse(input) {
+        Ok(doc) => doc,
+        Err(_) => return vec![],
+    };
+    let mut nested = Vec::new();
+    let mut removed = Vec::new();
+    let mut stack = vec![doc.root()];
+    while let Some(node) = stack.pop() {
+        let cut = |span: Range<usize>| [&input[..span.start], &input[span.end..]].concat();
+        match node.kind() {
+            NodeKind::List(items) => {
+                for item in items {
+                    let span = item.span().unwrap_or_default();
+                    nested.push(input[span.clone()].to_vec());
+                    removed.push(cut(span));
+                    stack.push(item);
+                }
+            }
+            NodeKind::Dict(entries) => {
+                for (key, value) in entries {
+                    let (key, span) = (
+                        key.span().unwrap_or_default(),
+                        value.span().unwrap_or_default(),
+                    );
+                    nested.push(input[span.clone()].to_vec());
+                    removed.push(cut(key.start..span.end));
+                    stack.push(value);
+                }
+            }
+            NodeKind::Leaf(_) => {}
+        }
+    }
+    nested.extend(removed);
+    nested
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::{random_value, GenConfig, Rng};
+
+    fn corpus(seed: u64, count: usize) -> Vec<Vec<u8>> {
+        let mut rng = Rng::new(seed);
+        let config = GenConfig::default();
+        (0..count)
+            .map(|_| random_value(&mut rng, &config).to_bencode().into_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn test_differential_agreement() {
+        let mut inputs = corpus(1, 200);
+        inputs.push(b"d1:ae".to_vec());
+        inputs.push(b"i1ei2e".to_vec());
+        assert_eq!(compare(&inputs, decode), None);
+
+        // the slice combinators agree with Document on complete inputs
+        let combinator = |input: &[u8]| match crate::combinator::value(input) {
+            Ok(([], value)) => {
+                let mut out = Vec::new();
+                value
+                    .write_bencode_with(&mut out, EncodeOptions::strict())
+                    .ok()?;
+                Some(out)
+            }
+            _ => None,
+        };
+        assert_eq!(compare(&inputs, combinator), None);
+    }
+
+    #[test]
+    fn test_differential_minimizes() {
+        // a reference that re-encodes long strings wrongly
+        let reference = |input: &[u8]| {
+            let out = decode(input)?;
+            if out.windows(3).any(|w| w == b"10:") {
+                Some(b"le".to_vec())
+            } else {
+                Some(out)
+            }
+        };
+        let inputs = [&b"l3:abce"[..], b"d1:ald1:bl10:0123456789eeee"];
+        let divergence = compare(&inputs, reference).unwrap();
+        assert_eq!(divergence.index, 1);
+        assert_eq!(divergence.minimized, b"10:0000000000");
+        assert_eq!(
+            divergence.to_string(),
+            "input 1 diverges; minimized to 10:0000000000: \
+             ours accepted as 10:0000000000, theirs accepted as le"
+        );
+    }
+
+    #[test]
+    fn test_differential_panics() {
+        let divergence = compare_with(&[&b"li1ei2ee"[..]], decode, |input: &[u8]| {
+            assert!(!input.contains(&b'2'));
+            decode(input)
+        })
+        .unwrap();
+        assert_eq!(divergence.minimized, b"i2e");
+        assert_eq!(divergence.ours, Outcome::Accepted(b"i2e".to_vec()));
+        assert_eq!(divergence.theirs, Outcome::Panicked);
+    }
+}
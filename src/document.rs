@@ -0,0 +1,674 @@
+//! Parsed documents that remember the raw byte span of every node.
+//!
+//! Re-encoding a [`Document`] copies untouched sub-trees straight from the
+//! source buffer, so original key order, integer spelling and any other quirk
+//! of the input survive. Only nodes changed through [`Document::set`] or
+//! [`Document::remove`] (and the containers holding them) are re-encoded.
+//!
+//! Parsing and re-encoding an unedited document is byte-exact for every input
+//! [`Document::parse`] accepts, including unsorted or duplicate keys, leading
+//! zeros in integers and string lengths, and `-0`. This makes it the type to
+//! use for resume files and signed payloads, where `encode(parse(bytes))`
+//! must equal `bytes`. Integers must fit in an `i64`.
+//!
+//! For one-off edits of large buffers, [`patch`] skips building the node tree
+//! altogether: it scans for the target span and splices in the new encoding.
+
+#[cfg(feature = "std")]
+use crate::encode::IoSink;
+use crate::encode::{self, Sink, VecSink};
+use crate::prelude::*;
+use crate::{BencodeError, EncodeOptions, ErrorKind, HMap, Map, Result, Value};
+use core::ops::Range;
+use core::str::FromStr;
+#[cfg(feature = "std")]
+use std::io;
+
+/// How deeply lists and dictionaries may nest in a [`Document`]. Parsing is
+/// recursive, so the limit keeps hostile input from overflowing the stack.
+pub const MAX_DEPTH: usize = 256;
+
+/// A single parsed node.
+#[derive(Clone, Debug)]
+pub struct Node {
+    // `None` once this node or one of its descendants has been edited
+    span: Option<Range<usize>>,
+    kind: NodeKind,
+}
+
+#[derive(Clone, Debug)]
+pub enum NodeKind {
+    Leaf(Value),
+    List(Vec<Node>),
+    Dict(Vec<(Node, Node)>),
+}
+
+/// A bencode document parsed with raw spans.
+#[derive(Clone, Debug)]
+pub struct Document {
+    source: Vec<u8>,
+    root: Node,
+}
+
+impl Node {
+    fn fresh(value: Value) -> Node {
+        Node {
+            span: None,
+            kind: NodeKind::Leaf(value),
+        }
+    }
+
+    /// Byte range of this node in the source buffer, if it is untouched.
+    pub fn span(&self) -> Option<Range<usize>> {
+        self.span.clone()
+    }
+
+    pub fn kind(&self) -> &NodeKind {
+        &self.kind
+    }
+
+    /// Look up a direct child: a dictionary key, or a list index.
+    pub fn child(&self, segment: &str) -> Option<&Node> {
+        match &self.kind {
+            NodeKind::Dict(entries) => entries
+                .iter()
+                .find(|(k, _)| k.key_matches(segment))
+                .map(|(_, v)| v),
+            NodeKind::List(items) => usize::from_str(segment).ok().and_then(|i| items.get(i)),
+            NodeKind::Leaf(_) => None,
+        }
+    }
+
+    fn child_mut(&mut self, segment: &str) -> Option<&mut Node> {
+        match &mut self.kind {
+            NodeKind::Dict(entries) => entries
+                .iter_mut()
+                .find(|(k, _)| k.key_matches(segment))
+                .map(|(_, v)| v),
+            NodeKind::List(items) => usize::from_str(segment)
+                .ok()
+                .and_then(move |i| items.get_mut(i)),
+            NodeKind::Leaf(_) => None,
+        }
+    }
+
+    fn key_matches(&self, segment: &str) -> bool {
+        self.key_bytes() == Some(segment.as_bytes())
+    }
+
+    fn key_bytes(&self) -> Option<&[u8]> {
+        match &self.kind {
+            NodeKind::Leaf(v) => v.as_bytes(),
+            _ => None,
+        }
+    }
+
+    /// Convert this node into a plain [`Value`].
+    pub fn to_value(&self) -> Value {
+        match &self.kind {
+            NodeKind::Leaf(v) => v.clone(),
+            NodeKind::List(items) => Value::List(items.iter().map(Node::to_value).collect()),
+            NodeKind::Dict(entries) => {
+                let mut map = Map::new();
+                for (k, v) in entries {
+                    map.insert(k.to_value(), v.to_value());
+                }
+                Value::Map(HMap::new(map))
+            }
+        }
+    }
+
+    fn encode<S: Sink + ?Sized>(&self, source: &[u8], sink: &mut S) -> Result<()> {
+        if let Some(span) = &self.span {
+            return sink.put(&source[span.clone()]);
+        }
+        match &self.kind {
+            // fresh values get the canonical form: sorted keys, so the
+            // output does not depend on hash map iteration order
+            NodeKind::Leaf(v) => encode::encode_with(v, sink, EncodeOptions::strict()),
+            NodeKind::List(items) => {
+                sink.put(b"l")?;
+                for item in items {
+                    item.encode(source, sink)?;
+                }
+                sink.put(b"e")
+            }
+            NodeKind::Dict(entries) => {
+                sink.put(b"d")?;
+                for (k, v) in entries {
+                    k.encode(source, sink)?;
+                    v.encode(source, sink)?;
+                }
+                sink.put(b"e")
+            }
+        }
+    }
+}
+
+impl Document {
+    /// Parse a complete document. Trailing bytes after the root value are an
+    /// error, as is nesting deeper than [`MAX_DEPTH`].
+    pub fn parse<B: Into<Vec<u8>>>(bytes: B) -> Result<Document> {
+        let source = bytes.into();
+        let mut pos = 0;
+        let root = parse_node(&source, &mut pos, 0)?;
+        if pos != source.len() {
+            return Err(BencodeError::new(ErrorKind::Trailing).at(pos));
+        }
+        Ok(Document { source, root })
+    }
+
+    /// Read and parse a complete document.
+    #[cfg(feature = "std")]
+    pub fn from_reader<R: io::Read + ?Sized>(reader: &mut R) -> Result<Document> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        Document::parse(bytes)
+    }
+
+    /// Whether anything was changed since parsing; if not,
+    /// [`Document::to_bytes`] returns exactly [`Document::source`].
+    pub fn is_modified(&self) -> bool {
+        self.root.span.is_none()
+    }
+
+    /// The buffer this document was parsed from.
+    pub fn source(&self) -> &[u8] {
+        &self.source
+    }
+
+    pub fn root(&self) -> &Node {
+        &self.root
+    }
+
+    /// Find a node by path. Segments are dictionary keys, or indexes for lists.
+    pub fn node(&self, path: &[&str]) -> Option<&Node> {
+        path.iter()
+            .try_fold(&self.root, |node, segment| node.child(segment))
+    }
+
+    /// Original bytes of the node at `path`, if it has not been edited.
+    pub fn raw(&self, path: &[&str]) -> Option<&[u8]> {
+        self.node(path)
+            .and_then(|n| n.span.clone())
+            .map(|span| &self.source[span])
+    }
+
+    pub fn to_value(&self) -> Value {
+        self.root.to_value()
+    }
+
+    /// Replace (or insert) the value at `path`. A missing dictionary key is
+    /// inserted before the first existing key that sorts after it. The new
+    /// value is written canonically, with sorted keys; one without a valid
+    /// encoding, such as a dictionary with integer keys, is an error.
+    pub fn set(&mut self, path: &[&str], value: Value) -> Result<()> {
+        encode::encode_with(
+            &value,
+            &mut VecSink(&mut Vec::new()),
+            EncodeOptions::strict(),
+        )?;
+        let (last, parent) = match path.split_last() {
+            Some(split) => split,
+            None => {
+                self.root = Node::fresh(value);
+                return Ok(());
+            }
+        };
+        let parent = self.dirty_node_mut(parent)?;
+        match &mut parent.kind {
+            NodeKind::Dict(entries) => {
+                match entries.iter_mut().find(|(k, _)| k.key_matches(last)) {
+                    Some((_, v)) => *v = Node::fresh(value),
+                    None => {
+                        let at = entries
+                            .iter()
+                            .position(|(k, _)| {
+                                k.key_bytes().is_some_and(|key| key > last.as_bytes())
+                            })
+                            .unwrap_or(entries.len());
+                        let key = Node::fresh(Value::from(*last));
+                        entries.insert(at, (key, Node::fresh(value)));
+                    }
+                }
+                Ok(())
+            }
+            NodeKind::List(items) => {
+                match usize::from_str(last).ok().and_then(|i| items.get_mut(i)) {
+                    Some(item) => {
+                        *item = Node::fresh(value);
+                        Ok(())
+                    }
+                    None => Err(BencodeError::msg(format!("No list index {}", last))),
+                }
+            }
+            NodeKind::Leaf(_) => Err(BencodeError::msg(format!(
+                "Cannot set {} inside a non-container value",
+                last
+            ))),
+        }
+    }
+
+    /// Remove the value at `path`, returning it if it existed.
+    pub fn remove(&mut self, path: &[&str]) -> Result<Option<Value>> {
+        let (last, parent) = match path.split_last() {
+            Some(split) => split,
+            None => return Err(BencodeError::msg("Cannot remove the root")),
+        };
+        if self.node(path).is_none() {
+            return Ok(None);
+        }
+        let parent = self.dirty_node_mut(parent)?;
+        let removed = match &mut parent.kind {
+            NodeKind::Dict(entries) => entries
+                .iter()
+                .position(|(k, _)| k.key_matches(last))
+                .map(|i| entries.remove(i).1),
+            NodeKind::List(items) => usize::from_str(last).ok().map(|i| items.remove(i)),
+            NodeKind::Leaf(_) => None,
+        };
+        Ok(removed.map(|n| n.to_value()))
+    }
+
+    // Walk to `path`, dropping the spans of every node on the way since
+    // their encoding is about to change.
+    fn dirty_node_mut(&mut self, path: &[&str]) -> Result<&mut Node> {
+        let mut node = &mut self.root;
+        node.span = None;
+        for segment in path {
+            node = node
+                .child_mut(segment)
+                .ok_or_else(|| BencodeError::msg(format!("No such path segment: {}", segment)))?;
+            node.span = None;
+        }
+        Ok(node)
+    }
+
+    /// Encode the document, copying untouched sub-trees verbatim.
+    #[cfg(feature = "std")]
+    pub fn write_bencode<W: io::Write + ?Sized>(&self, writer: &mut W) -> Result<()> {
+        self.root.encode(&self.source, &mut IoSink(writer))
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(self.source.len());
+        // cannot fail: `set` only stores values that encode
+        let _ = self.root.encode(&self.source, &mut VecSink(&mut buf));
+        buf
+    }
+}
+
+/// Where a path resolves to in a raw buffer.
+enum Location {
+    /// Span of an existing value.
+    Found(Range<usize>),
+    /// The final dictionary key is missing; this is where it sorts.
+    Insert(usize),
+}
+
+/// Byte range of the value at `path` in `source`, found by scanning spans
+/// without decoding anything along the way.
+pub fn locate(source: &[u8], path: &[&str]) -> Result<Option<Range<usize>>> {
+    match resolve(source, path)? {
+        Some(Location::Found(span)) => Ok(Some(span)),
+        _ => Ok(None),
+    }
+}
+
+/// Replace (or insert, for a missing dictionary key) the value at `path`,
+/// rewriting only those bytes and leaving the rest of `buf` untouched. The
+/// new value is written canonically, as with [`Document::set`].
+pub fn patch(buf: &mut Vec<u8>, path: &[&str], value: &Value) -> Result<()> {
+    let mut encoded = vec![];
+    match resolve(buf, path)? {
+        Some(Location::Found(span)) => {
+            encode::encode_with(value, &mut VecSink(&mut encoded), EncodeOptions::strict())?;
+            buf.splice(span, encoded);
+        }
+        Some(Location::Insert(at)) => {
+            // resolve only answers Insert for a non-empty path
+            let key = path.last().map_or("", |k| *k);
+            encode::put_str(key.as_bytes(), &mut VecSink(&mut encoded))?;
+            encode::encode_with(value, &mut VecSink(&mut encoded), EncodeOptions::strict())?;
+            buf.splice(at..at, encoded);
+        }
+        None => {
+            return Err(BencodeError::msg(format!(
+                "No such path: {}",
+                path.join("/")
+            )))
+        }
+    }
+    Ok(())
+}
+
+fn resolve(src: &[u8], path: &[&str]) -> Result<Option<Location>> {
+    let mut start = 0;
+    for (depth, segment) in path.iter().enumerate() {
+        let last = depth + 1 == path.len();
+        match src.get(start) {
+            Some(b'l') | Some(b'd') if depth >= MAX_DEPTH => return Err(too_deep(start)),
+            Some(b'd') => {
+                let mut pos = start + 1;
+                let mut found = None;
+                let mut insert = None;
+                // keys may be unsorted or repeated, so scan them all; the
+                // last match wins, as in `Document::to_value`
+                while !at_end(src, &mut pos)? {
+                    let key_start = pos;
+                    let key = string_at(src, &mut pos)?;
+                    if key == segment.as_bytes() {
+                        found = Some(pos);
+                    } else if insert.is_none() && key > segment.as_bytes() {
+                        insert = Some(key_start);
+                    }
+                    pos = skip(src, pos, depth + 1)?;
+                }
+                match found {
+                    Some(pos) => start = pos,
+                    // `at_end` stepped past the terminator
+                    None if last => return Ok(Some(Location::Insert(insert.unwrap_or(pos - 1)))),
+                    None => return Ok(None),
+                }
+            }
+            Some(b'l') => {
+                let index = match usize::from_str(segment) {
+                    Ok(i) => i,
+                    Err(_) => return Ok(None),
+                };
+                let mut pos = start + 1;
+                for _ in 0..index {
+                    if at_end(src, &mut pos)? {
+                        return Ok(None);
+                    }
+                    pos = skip(src, pos, depth + 1)?;
+                }
+                if at_end(src, &mut pos)? {
+                    return Ok(None);
+                }
+                start = pos;
+            }
+            _ => return Ok(None),
+        }
+    }
+    Ok(Some(Location::Found(start..skip(src, start, path.len())?)))
+}
+
+// The input ends inside a value.
+fn eof(src: &[u8]) -> BencodeError {
+    BencodeError::new(ErrorKind::UnexpectedEof).at(src.len())
+}
+
+// A container at `pos` nests deeper than `MAX_DEPTH`.
+fn too_deep(pos: usize) -> BencodeError {
+    BencodeError::new(ErrorKind::DepthLimit(MAX_DEPTH)).at(pos)
+}
+
+// Offset just past the value starting at `pos`, which is nested `depth`
+// containers deep.
+fn skip(src: &[u8], pos: usize, depth: usize) -> Result<usize> {
+    match src.get(pos) {
+        None => Err(eof(src)),
+        Some(b'i') => Ok(find(src, pos + 1, b'e')? + 1),
+        Some(b'l') | Some(b'd') if depth >= MAX_DEPTH => Err(too_deep(pos)),
+        Some(b'l') | Some(b'd') => {
+            let mut pos = pos + 1;
+            while !at_end(src, &mut pos)? {
+                pos = skip(src, pos, depth + 1)?;
+            }
+            Ok(pos)
+        }
+        Some(_) => {
+            let mut pos = pos;
+            string_at(src, &mut pos)?;
+            Ok(pos)
+        }
+    }
+}
+
+// Contents of the byte string starting at `pos`, advancing past it.
+fn string_at<'a>(src: &'a [u8], pos: &mut usize) -> Result<&'a [u8]> {
+    let colon = find(src, *pos, b':')?;
+    let len = usize::from_str(&String::from_utf8_lossy(&src[*pos..colon]))
+        .map_err(|_| BencodeError::new(ErrorKind::InvalidLength).at(*pos))?;
+    let end = len
+        .checked_add(1)
+        .and_then(|n| colon.checked_add(n))
+        .filter(|end| *end <= src.len())
+        .ok_or_else(|| eof(src))?;
+    *pos = end;
+    Ok(&src[colon + 1..end])
+}
+
+fn parse_node(src: &[u8], pos: &mut usize, depth: usize) -> Result<Node> {
+    let start = *pos;
+    let kind = match src.get(start) {
+        None => return Err(eof(src)),
+        Some(b'l') | Some(b'd') if depth == MAX_DEPTH => return Err(too_deep(start)),
+        Some(b'i') => {
+            let end = find(src, start + 1, b'e')?;
+            let s = String::from_utf8_lossy(&src[start + 1..end]);
+            *pos = end + 1;
+            let n = i64::from_str(&s)
+                .map_err(|_| BencodeError::new(ErrorKind::InvalidInteger).at(start))?;
+            NodeKind::Leaf(Value::Int(n))
+        }
+        Some(b'l') => {
+            *pos += 1;
+            let mut items = vec![];
+            while !at_end(src, pos)? {
+                items.push(parse_node(src, pos, depth + 1)?);
+            }
+            NodeKind::List(items)
+        }
+        Some(b'd') => {
+            *pos += 1;
+            let mut entries = vec![];
+            while !at_end(src, pos)? {
+                let key_start = *pos;
+                let key = parse_node(src, pos, depth + 1)?;
+                if key.key_bytes().is_none() {
+                    return Err(BencodeError::new(ErrorKind::NonStringKey).at(key_start));
+                }
+                let val = parse_node(src, pos, depth + 1)?;
+                entries.push((key, val));
+            }
+            NodeKind::Dict(entries)
+        }
+        Some(b'0'..=b'9') => NodeKind::Leaf(Value::from_bytes(string_at(src, pos)?.to_vec())),
+        Some(b) => return Err(BencodeError::new(ErrorKind::InvalidCharacter(*b)).at(start)),
+    };
+    Ok(Node {
+        span: Some(start..*pos),
+        kind,
+    })
+}
+
+// Consume the container terminator if it is next.
+fn at_end(src: &[u8], pos: &mut usize) -> Result<bool> {
+    match src.get(*pos) {
+        None => Err(eof(src)),
+        Some(b'e') => {
+            *pos += 1;
+            Ok(true)
+        }
+        Some(_) => Ok(false),
+    }
+}
+
+fn find(src: &[u8], from: usize, byte: u8) -> Result<usize> {
+    src[from..]
+        .iter()
+        .position(|b| *b == byte)
+        .map(|i| from + i)
+        .ok_or_else(|| eof(src))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_document_roundtrip_is_byte_exact() {
+        // unsorted keys and a non-canonical integer
+        let src = b"d3:zzzi007e3:aaal1:x1:yee".to_vec();
+        let doc = Document::parse(src.clone()).unwrap();
+        assert_eq!(doc.to_bytes(), src);
+        assert_eq!(doc.raw(&["aaa"]), Some(&b"l1:x1:ye"[..]));
+        assert_eq!(
+            doc.node(&["aaa", "1"]).unwrap().to_value(),
+            Value::from("y")
+        );
+    }
+
+    /// Deterministically generate valid but non-canonical documents.
+    #[cfg(feature = "std")]
+    fn quirky(seed: &mut u64, depth: u32, out: &mut Vec<u8>) {
+        *seed = seed
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        let pick = (*seed >> 33) % if depth == 0 { 3 } else { 5 };
+        match pick {
+            0 => {
+                let spellings: [&[u8]; 5] = [b"i0e", b"i-0e", b"i007e", b"i-42e", b"i+5e"];
+                out.extend_from_slice(spellings[(*seed >> 40) as usize % spellings.len()]);
+            }
+            1 => out.extend_from_slice(b"003:\xff\x00e"),
+            2 => out.extend_from_slice(b"0:"),
+            3 => {
+                out.push(b'l');
+                for _ in 0..(*seed >> 45) % 4 {
+                    quirky(seed, depth - 1, out);
+                }
+                out.push(b'e');
+            }
+            _ => {
+                // keys out of order and repeated
+                out.push(b'd');
+                for key in [&b"1:z"[..], b"1:a", b"1:z", b"01:m"] {
+                    out.extend_from_slice(key);
+                    quirky(seed, depth - 1, out);
+                }
+                out.push(b'e');
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_generated_documents_roundtrip_exactly() {
+        let mut seed = 7;
+        for _ in 0..500 {
+            let mut src = Vec::new();
+            quirky(&mut seed, 4, &mut src);
+            let mut doc = Document::from_reader(&mut &src[..]).unwrap();
+            assert!(!doc.is_modified());
+            assert_eq!(doc.to_bytes(), src, "{}", String::from_utf8_lossy(&src));
+
+            // an edit leaves the bytes of its siblings alone
+            if let NodeKind::Dict(_) = doc.root().kind() {
+                let untouched = doc.raw(&["a"]).unwrap().to_vec();
+                doc.set(&["m"], Value::Int(1)).unwrap();
+                assert!(doc.is_modified());
+                let out = doc.to_bytes();
+                assert!(out.starts_with(b"d1:z"));
+                assert!(out.windows(untouched.len()).any(|w| w == &untouched[..]));
+            }
+        }
+    }
+
+    #[test]
+    fn test_document_edit_keeps_untouched_bytes() {
+        let mut doc = Document::parse(&b"d1:bi007e1:ad1:xi01eee"[..]).unwrap();
+        doc.set(&["a", "x"], Value::Int(2)).unwrap();
+        assert_eq!(doc.to_bytes(), b"d1:bi007e1:ad1:xi2eee".to_vec());
+
+        doc.set(&["c"], Value::from("new")).unwrap();
+        assert_eq!(doc.to_bytes(), b"d1:bi007e1:ad1:xi2ee1:c3:newe".to_vec());
+
+        assert_eq!(doc.remove(&["b"]).unwrap(), Some(Value::Int(7)));
+        assert_eq!(doc.to_bytes(), b"d1:ad1:xi2ee1:c3:newe".to_vec());
+    }
+
+    #[test]
+    fn test_document_set_dict_is_canonical() {
+        let dict = |keys: &[&str]| {
+            let map: Map<Value, Value> = keys
+                .iter()
+                .map(|k| (Value::from(*k), Value::Int(1)))
+                .collect();
+            Value::from(map)
+        };
+        let mut doc = Document::parse(&b"d1:ai0ee"[..]).unwrap();
+        doc.set(&["b"], dict(&["e", "c", "a", "d", "b"])).unwrap();
+        assert_eq!(
+            doc.to_bytes(),
+            b"d1:ai0e1:bd1:ai1e1:bi1e1:ci1e1:di1e1:ei1eee".to_vec()
+        );
+        let mut buf = b"de".to_vec();
+        patch(&mut buf, &["x"], &dict(&["z", "y"])).unwrap();
+        assert_eq!(buf, b"d1:xd1:yi1e1:zi1eee".to_vec());
+
+        let mut bad = Map::new();
+        bad.insert(Value::Int(1), Value::Int(2));
+        assert!(doc.set(&["c"], Value::from(bad)).is_err());
+        assert!(doc.node(&["c"]).is_none());
+    }
+
+    #[test]
+    fn test_document_rejects_trailing_data() {
+        assert!(Document::parse(&b"i1ei2e"[..]).is_err());
+        assert!(Document::parse(&b"l1:a"[..]).is_err());
+    }
+
+    #[test]
+    fn test_locate_rejects_deep_nesting() {
+        let mut src = vec![b'l'; MAX_DEPTH + 1];
+        src.extend(vec![b'e'; MAX_DEPTH + 1]);
+        for path in [&[][..], &["0"], &["0", "0"]] {
+            let err = locate(&src, path).unwrap_err();
+            assert_eq!(err.kind(), Some(ErrorKind::DepthLimit(MAX_DEPTH)));
+            assert_eq!(err.position(), Some(MAX_DEPTH));
+        }
+        src.drain(..1);
+        src.pop();
+        assert_eq!(locate(&src, &["0"]).unwrap(), Some(1..src.len() - 1));
+    }
+
+    #[test]
+    fn test_string_length_overflow_is_an_error() {
+        let src = b"d18446744073709551615:aaaaaaaaaaaaaaaaaae";
+        let err = Document::parse(&src[..]).unwrap_err();
+        assert_eq!(err.kind(), Some(ErrorKind::UnexpectedEof));
+        assert!(locate(src, &["a"]).is_err());
+        assert!(patch(&mut src.to_vec(), &["a"], &Value::Int(1)).is_err());
+    }
+
+    #[test]
+    fn test_patch_splices_single_value() {
+        let mut buf = b"d1:ai1e1:bl3:one3:twoe1:di4ee".to_vec();
+        assert_eq!(locate(&buf, &["b", "1"]).unwrap(), Some(16..21));
+
+        patch(&mut buf, &["b", "1"], &Value::from("2")).unwrap();
+        assert_eq!(buf, b"d1:ai1e1:bl3:one1:2e1:di4ee".to_vec());
+
+        patch(&mut buf, &["c"], &Value::Int(3)).unwrap();
+        patch(&mut buf, &["e"], &Value::Int(5)).unwrap();
+        assert_eq!(buf, b"d1:ai1e1:bl3:one1:2e1:ci3e1:di4e1:ei5ee".to_vec());
+
+        assert!(patch(&mut buf, &["x", "y"], &Value::Int(0)).is_err());
+    }
+
+    #[test]
+    fn test_patch_unsorted_dict() {
+        let mut buf = b"d1:zi1e1:bi2e1:ai3e1:bi4ee".to_vec();
+        assert_eq!(locate(&buf, &["a"]).unwrap(), Some(16..19));
+        // the last of the repeated keys is the one that counts
+        assert_eq!(locate(&buf, &["b"]).unwrap(), Some(22..25));
+
+        patch(&mut buf, &["a"], &Value::Int(5)).unwrap();
+        assert_eq!(buf, b"d1:zi1e1:bi2e1:ai5e1:bi4ee".to_vec());
+        patch(&mut buf, &["c"], &Value::Int(6)).unwrap();
+        assert_eq!(buf, b"d1:ci6e1:zi1e1:bi2e1:ai5e1:bi4ee".to_vec());
+    }
+}
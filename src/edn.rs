@@ -0,0 +1,141 @@
+//! EDN rendering, so Clojure tooling can read bencode dumps with
+//! `clojure.edn/read-string`.
+
+use crate::prelude::*;
+use crate::Value;
+use core::fmt::Write;
+
+impl Value {
+    /// Render as EDN, the way Clojure would print the equivalent data.
+    ///
+    /// Dictionaries become maps with keys sorted by their bytes. Keys that
+    /// are valid keyword names become keywords and the rest strings. Lists
+    /// become vectors. Byte strings that are not UTF-8 have no EDN form and
+    /// are written as hex in a `#bencode/bytes` tagged literal.
+    ///
+    /// ```
+    /// use bencode_rs::from_slice;
+    ///
+    /// let msg = b"d2:id1:12:op4:eval4:code7:(+ 1 2)e";
+    /// let value = from_slice(msg).unwrap();
+    /// assert_eq!(value.to_edn_string(), r#"{:code "(+ 1 2)", :id "1", :op "eval"}"#);
+    /// ```
+    pub fn to_edn_string(&self) -> String {
+        let mut out = String::new();
+        write_edn(&mut out, self);
+        out
+    }
+}
+
+fn write_edn(out: &mut String, value: &Value) {
+    match value {
+        Value::Int(i) => {
+            // writing into a String cannot fail
+            let _ = write!(out, "{}", i);
+        }
+        Value::Str(s) => write_string(out, s),
+        Value::Bytes(b) => match core::str::from_utf8(b) {
+            Ok(s) => write_string(out, s),
+            Err(_) => {
+                out.push_str("#bencode/bytes \"");
+                out.push_str(&crate::hash::to_hex(b));
+                out.push('"');
+            }
+        },
+        Value::List(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(' ');
+                }
+                write_edn(out, item);
+            }
+            out.push(']');
+        }
+        Value::Map(map) => {
+            let mut entries: Vec<_> = map.0.iter().collect();
+            entries.sort_by(|a, b| a.0.as_bytes().cmp(&b.0.as_bytes()));
+            out.push('{');
+            for (i, (key, value)) in entries.into_iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                match key.as_bytes().and_then(|k| core::str::from_utf8(k).ok()) {
+                    Some(name) if is_keyword(name) => {
+                        out.push(':');
+                        out.push_str(name);
+                    }
+                    _ => write_edn(out, key),
+                }
+                out.push(' ');
+                write_edn(out, value);
+            }
+            out.push('}');
+        }
+    }
+}
+
+/// Whether `name` reads back as a keyword: a symbol that does not start
+/// like a number. Namespaced names are left as strings.
+fn is_keyword(name: &str) -> bool {
+    let symbol_char = |c: char| c.is_ascii_alphanumeric() || "*+!-_?<>=.".contains(c);
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (None, _) => false,
+        (Some(c), _) if c.is_ascii_digit() => false,
+        (Some('+' | '-' | '.'), Some(c)) if c.is_ascii_digit() => false,
+        _ => name.chars().all(symbol_char),
+    }
+}
+
+fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{HMap, Map};
+
+    #[test]
+    fn test_edn_rendering() {
+        let mut map = Map::new();
+        map.insert(Value::from("new-session"), Value::from("a\"b\\c\n"));
+        map.insert(
+            Value::from("status"),
+            Value::List(vec![Value::from("done")]),
+        );
+        map.insert(Value::from("2x"), Value::from(-7));
+        map.insert(Value::from("with space"), Value::List(vec![]));
+        map.insert(Value::from("hash"), Value::from(vec![0xff, 0x00]));
+        let value = Value::Map(HMap::new(map));
+        assert_eq!(
+            value.to_edn_string(),
+            r#"{"2x" -7, :hash #bencode/bytes "ff00", :new-session "a\"b\\c\n", :status ["done"], "with space" []}"#
+        );
+    }
+
+    #[test]
+    fn test_keyword_names() {
+        for name in ["op", "out-subscribe", "ok?", "a.b", "-", "+x"] {
+            assert!(is_keyword(name), "{}", name);
+        }
+        for name in ["", "1a", "-1", ".5", "a b", "ns/name", "a:b"] {
+            assert!(!is_keyword(name), "{}", name);
+        }
+    }
+}
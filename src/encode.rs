@@ -0,0 +1,452 @@
+//! Bencode encoding on top of a small sink abstraction, so the same code path
+//! serves both `io::Write` and `fmt::Write` targets.
+
+use crate::prelude::*;
+use crate::{BencodeError, ErrorKind, Result, Value};
+use core::fmt;
+#[cfg(feature = "std")]
+use std::io;
+
+/// Destination for encoded bytes.
+pub(crate) trait Sink {
+    fn put(&mut self, bytes: &[u8]) -> Result<()>;
+}
+
+/// Sink appending to a buffer; never fails.
+pub(crate) struct VecSink<'a>(pub &'a mut Vec<u8>);
+
+impl Sink for VecSink<'_> {
+    fn put(&mut self, bytes: &[u8]) -> Result<()> {
+        self.0.extend_from_slice(bytes);
+        Ok(())
+    }
+}
+
+/// Sink writing to any `io::Write` (files, sockets, `Vec<u8>`).
+#[cfg(feature = "std")]
+pub(crate) struct IoSink<'a, W: io::Write + ?Sized>(pub &'a mut W);
+
+#[cfg(feature = "std")]
+impl<W: io::Write + ?Sized> Sink for IoSink<'_, W> {
+    fn put(&mut self, bytes: &[u8]) -> Result<()> {
+        self.0.write_all(bytes)?;
+        Ok(())
+    }
+}
+
+/// Sink writing to any `fmt::Write` (`String`, `fmt::Formatter`).
+pub(crate) struct FmtSink<'a, W: fmt::Write + ?Sized>(pub &'a mut W);
+
+impl<W: fmt::Write + ?Sized> Sink for FmtSink<'_, W> {
+    fn put(&mut self, bytes: &[u8]) -> Result<()> {
+        let s = core::str::from_utf8(bytes)
+            .map_err(|_| BencodeError::msg("Output is not valid UTF-8"))?;
+        self.0.write_str(s)?;
+        Ok(())
+    }
+}
+
+const DIGIT_PAIRS: &[u8; 200] = b"\
+0001020304050607080910111213141516171819\
+2021222324252627282930313233343536373839\
+4041424344454647484950515253545556575859\
+6061626364656667686970717273747576777879\
+8081828384858687888990919293949596979899";
+
+/// Longest decimal rendering of a `u64` or a signed `i64`.
+pub(crate) const MAX_INT_LEN: usize = 20;
+
+/// Render `n` as decimal digits at the tail of `buf` and return them, two
+/// digits per step and without touching the heap.
+pub(crate) fn format_u64(mut n: u64, buf: &mut [u8; MAX_INT_LEN]) -> &[u8] {
+    let mut pos = buf.len();
+    while n >= 100 {
+        let pair = (n % 100) as usize * 2;
+        n /= 100;
+        pos -= 2;
+        buf[pos..pos + 2].copy_from_slice(&DIGIT_PAIRS[pair..pair + 2]);
+    }
+    if n >= 10 {
+        let pair = n as usize * 2;
+        pos -= 2;
+        buf[pos..pos + 2].copy_from_slice(&DIGIT_PAIRS[pair..pair + 2]);
+    } else {
+        pos -= 1;
+        buf[pos] = b'0' + n as u8;
+    }
+    &buf[pos..]
+}
+
+/// Signed counterpart of [`format_u64`].
+pub(crate) fn format_i64(n: i64, buf: &mut [u8; MAX_INT_LEN]) -> &[u8] {
+    let len = format_u64(n.unsigned_abs(), buf).len();
+    let mut pos = buf.len() - len;
+    if n < 0 {
+        pos -= 1;
+        buf[pos] = b'-';
+    }
+    &buf[pos..]
+}
+
+/// Sink that refuses to grow past a byte budget.
+#[cfg(feature = "std")]
+struct LimitSink<S> {
+    inner: S,
+    written: usize,
+    limit: usize,
+}
+
+#[cfg(feature = "std")]
+impl<S: Sink> Sink for LimitSink<S> {
+    fn put(&mut self, bytes: &[u8]) -> Result<()> {
+        let total = self.written.saturating_add(bytes.len());
+        if total > self.limit {
+            return Err(BencodeError::msg(format!(
+                "Encoded output exceeds limit of {} bytes",
+                self.limit
+            )));
+        }
+        self.inner.put(bytes)?;
+        self.written = total;
+        Ok(())
+    }
+}
+
+/// Encode `value` into `writer`, failing as soon as the output would exceed
+/// `max_bytes`. The chunk that would cross the limit is never written, so at
+/// most `max_bytes` reach the writer. Returns the number of bytes written.
+#[cfg(feature = "std")]
+pub fn to_writer_limited<W: io::Write + ?Sized>(
+    value: &Value,
+    writer: &mut W,
+    max_bytes: usize,
+) -> Result<usize> {
+    let mut sink = LimitSink {
+        inner: IoSink(writer),
+        written: 0,
+        limit: max_bytes,
+    };
+    encode(value, &mut sink)?;
+    Ok(sink.written)
+}
+
+/// Switches controlling how values are encoded.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct EncodeOptions {
+    /// Refuse values that have no conforming bencode representation, such as
+    /// non-string dictionary keys, instead of emitting invalid output. Strict
+    /// mode also writes dictionary keys in sorted order, as the spec requires.
+    pub strict: bool,
+}
+
+impl EncodeOptions {
+    pub fn strict() -> Self {
+        EncodeOptions { strict: true }
+    }
+}
+
+pub(crate) fn encode<S: Sink + ?Sized>(value: &Value, sink: &mut S) -> Result<()> {
+    encode_with(value, sink, EncodeOptions::default())
+}
+
+pub(crate) fn encode_with<S: Sink + ?Sized>(
+    value: &Value,
+    sink: &mut S,
+    options: EncodeOptions,
+) -> Result<()> {
+    match value {
+        Value::Map(hm) if options.strict => {
+            let mut entries = Vec::with_capacity(hm.0.len());
+            for (key, val) in hm.0.iter() {
+                match key.as_bytes() {
+                    Some(bytes) => entries.push((bytes, val)),
+                    None => return Err(BencodeError::new(ErrorKind::NonStringKey)),
+                }
+            }
+            // keys are distinct: a `Str` and a `Bytes` with the same bytes
+            // are the same map key
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            sink.put(b"d")?;
+            for (key, val) in entries {
+                put_str(key, sink)?;
+                encode_with(val, sink, options)
+                    .map_err(|e| e.in_field(&String::from_utf8_lossy(key)))?;
+            }
+            sink.put(b"e")
+        }
+        Value::Map(hm) => {
+            sink.put(b"d")?;
+            for (key, val) in hm.0.iter() {
+                encode_with(key, sink, options)?;
+                encode_with(val, sink, options)?;
+            }
+            sink.put(b"e")
+        }
+        Value::List(v) => {
+            sink.put(b"l")?;
+            for item in v {
+                encode_with(item, sink, options)?;
+            }
+            sink.put(b"e")
+        }
+        Value::Str(s) => put_str(s.as_bytes(), sink),
+        Value::Bytes(b) => put_str(b, sink),
+        Value::Int(i) => put_int(*i, sink),
+    }
+}
+
+/// Byte strings at least this long are passed to `write_vectored` in place.
+#[cfg(feature = "std")]
+const VECTORED_MIN_LEN: usize = 4096;
+
+#[cfg(feature = "std")]
+enum Part<'a> {
+    Scratch(usize, usize),
+    Borrowed(&'a [u8]),
+}
+
+/// Gathers encoded output as small copied pieces plus borrowed large payloads.
+#[cfg(feature = "std")]
+#[derive(Default)]
+struct Gather<'a> {
+    scratch: Vec<u8>,
+    parts: Vec<Part<'a>>,
+}
+
+#[cfg(feature = "std")]
+impl<'a> Gather<'a> {
+    fn copy(&mut self, bytes: &[u8]) {
+        let start = self.scratch.len();
+        self.scratch.extend_from_slice(bytes);
+        match self.parts.last_mut() {
+            Some(Part::Scratch(_, end)) if *end == start => *end = self.scratch.len(),
+            _ => self.parts.push(Part::Scratch(start, self.scratch.len())),
+        }
+    }
+
+    fn string(&mut self, bytes: &'a [u8]) {
+        let mut digits = [0; MAX_INT_LEN];
+        self.copy(format_u64(bytes.len() as u64, &mut digits));
+        self.copy(b":");
+        if bytes.len() >= VECTORED_MIN_LEN {
+            self.parts.push(Part::Borrowed(bytes));
+        } else {
+            self.copy(bytes);
+        }
+    }
+
+    fn value(&mut self, value: &'a Value) {
+        match value {
+            Value::Map(hm) => {
+                self.copy(b"d");
+                for (key, val) in hm.0.iter() {
+                    self.value(key);
+                    self.value(val);
+                }
+                self.copy(b"e");
+            }
+            Value::List(v) => {
+                self.copy(b"l");
+                for item in v {
+                    self.value(item);
+                }
+                self.copy(b"e");
+            }
+            Value::Str(s) => self.string(s.as_bytes()),
+            Value::Bytes(b) => self.string(b),
+            Value::Int(i) => {
+                let mut buf = [0; MAX_INT_LEN];
+                self.copy(b"i");
+                self.copy(format_i64(*i, &mut buf));
+                self.copy(b"e");
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+pub(crate) fn write_vectored<W: io::Write + ?Sized>(value: &Value, writer: &mut W) -> Result<()> {
+    let mut gather = Gather::default();
+    gather.value(value);
+
+    let mut slices: Vec<io::IoSlice> = gather
+        .parts
+        .iter()
+        .map(|part| match part {
+            Part::Scratch(start, end) => io::IoSlice::new(&gather.scratch[*start..*end]),
+            Part::Borrowed(bytes) => io::IoSlice::new(bytes),
+        })
+        .collect();
+    let mut remaining = &mut slices[..];
+    while !remaining.is_empty() {
+        match writer.write_vectored(remaining) {
+            Ok(0) => return Err(BencodeError::Io(io::ErrorKind::WriteZero.into())),
+            Ok(n) => io::IoSlice::advance_slices(&mut remaining, n),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn put_str<S: Sink + ?Sized>(bytes: &[u8], sink: &mut S) -> Result<()> {
+    let mut buf = [0; MAX_INT_LEN];
+    sink.put(format_u64(bytes.len() as u64, &mut buf))?;
+    sink.put(b":")?;
+    sink.put(bytes)
+}
+
+pub(crate) fn put_int<S: Sink + ?Sized>(i: i64, sink: &mut S) -> Result<()> {
+    // "i" + digits + "e" in a single put
+    let mut out = [0; MAX_INT_LEN + 2];
+    let mut buf = [0; MAX_INT_LEN];
+    let digits = format_i64(i, &mut buf);
+    out[0] = b'i';
+    out[1..=digits.len()].copy_from_slice(digits);
+    out[digits.len() + 1] = b'e';
+    sink.put(&out[..digits.len() + 2])
+}
+
+/// Encode an integer into `buf`, returning the number of bytes written.
+/// Never allocates on success.
+pub fn encode_int_into(i: i64, buf: &mut [u8]) -> Result<usize> {
+    let mut digits = [0; MAX_INT_LEN];
+    let digits = format_i64(i, &mut digits);
+    let len = digits.len() + 2;
+    let out = buf_prefix(buf, len)?;
+    out[0] = b'i';
+    out[1..len - 1].copy_from_slice(digits);
+    out[len - 1] = b'e';
+    Ok(len)
+}
+
+/// Encode a byte string into `buf`, returning the number of bytes written.
+/// Never allocates on success.
+pub fn encode_str_into(s: &[u8], buf: &mut [u8]) -> Result<usize> {
+    let mut digits = [0; MAX_INT_LEN];
+    let digits = format_u64(s.len() as u64, &mut digits);
+    let len = digits.len() + 1 + s.len();
+    let out = buf_prefix(buf, len)?;
+    out[..digits.len()].copy_from_slice(digits);
+    out[digits.len()] = b':';
+    out[digits.len() + 1..].copy_from_slice(s);
+    Ok(len)
+}
+
+fn buf_prefix(buf: &mut [u8], len: usize) -> Result<&mut [u8]> {
+    let available = buf.len();
+    buf.get_mut(..len).ok_or_else(|| {
+        BencodeError::msg(format!(
+            "Buffer too small: need {} bytes, have {}",
+            len, available
+        ))
+    })
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_encode_io_and_fmt_agree() {
+        let mut m = HashMap::new();
+        m.insert(
+            Value::Str("list".to_string()),
+            Value::List(vec![Value::Int(-3), Value::Str("spam".to_string())]),
+        );
+        let value = Value::from(m);
+
+        let mut bytes = Vec::new();
+        value.write_bencode(&mut bytes).unwrap();
+        let mut text = String::new();
+        value.fmt_bencode(&mut text).unwrap();
+
+        assert_eq!(bytes, b"d4:listli-3e4:spamee");
+        assert_eq!(text.as_bytes(), &bytes[..]);
+    }
+
+    #[test]
+    fn test_strict_encode_sorts_and_rejects_non_string_keys() {
+        let mut m = HashMap::new();
+        for key in &["b", "a", "c"] {
+            m.insert(Value::from(*key), Value::Int(1));
+        }
+        let mut out = Vec::new();
+        Value::from(m.clone())
+            .write_bencode_with(&mut out, EncodeOptions::strict())
+            .unwrap();
+        assert_eq!(out, b"d1:ai1e1:bi1e1:ci1ee");
+
+        m.insert(Value::Int(4), Value::Int(1));
+        let bad = Value::List(vec![Value::from(m)]);
+        assert!(bad
+            .write_bencode_with(&mut Vec::new(), EncodeOptions::strict())
+            .is_err());
+        assert!(bad.write_bencode(&mut Vec::new()).is_ok());
+    }
+
+    #[test]
+    fn test_encode_into_stack_buffer() {
+        let mut buf = [0; 16];
+        let n = encode_int_into(-42, &mut buf).unwrap();
+        assert_eq!(&buf[..n], b"i-42e");
+        let n = encode_str_into(b"spam", &mut buf).unwrap();
+        assert_eq!(&buf[..n], b"4:spam");
+
+        assert!(encode_str_into(&[0; 15], &mut buf).is_err());
+        assert!(encode_int_into(i64::MIN, &mut buf[..4]).is_err());
+    }
+
+    #[test]
+    fn test_vectored_write_matches_plain_write() {
+        let blob = vec![0xab; VECTORED_MIN_LEN * 2];
+        let value = Value::List(vec![
+            Value::from("head"),
+            Value::from(blob.clone()),
+            Value::Int(7),
+            Value::from(blob),
+        ]);
+        let mut plain = Vec::new();
+        value.write_bencode(&mut plain).unwrap();
+        let mut vectored = Vec::new();
+        value.write_bencode_vectored(&mut vectored).unwrap();
+        assert_eq!(plain, vectored);
+    }
+
+    #[test]
+    fn test_to_writer_limited() {
+        let value = Value::List(vec![Value::from("spam"), Value::from("eggs")]);
+        let mut out = Vec::new();
+        assert_eq!(to_writer_limited(&value, &mut out, 14).unwrap(), 14);
+        assert_eq!(out, b"l4:spam4:eggse");
+
+        out.clear();
+        assert!(to_writer_limited(&value, &mut out, 10).is_err());
+        assert!(out.len() <= 10);
+    }
+
+    #[test]
+    fn test_format_int_matches_std() {
+        let mut buf = [0; MAX_INT_LEN];
+        for n in [
+            0,
+            7,
+            10,
+            99,
+            100,
+            12345,
+            -1,
+            -10,
+            -987_654_321,
+            i64::MAX,
+            i64::MIN,
+        ] {
+            assert_eq!(format_i64(n, &mut buf), n.to_string().as_bytes());
+        }
+        assert_eq!(
+            format_u64(u64::MAX, &mut buf),
+            u64::MAX.to_string().as_bytes()
+        );
+    }
+}
@@ -0,0 +1,206 @@
+use crate::fields::{DictBuilder, Fields};
+use crate::{
+    parse_bencode, BencodeError, EncodeOptions, FromBencode, HMap, Result, ToBencode, Value,
+};
+use std::collections::{BTreeMap, HashMap};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+const HANDSHAKE_FIELDS: &[&str] = &[
+    "m",
+    "p",
+    "v",
+    "yourip",
+    "ipv6",
+    "ipv4",
+    "reqq",
+    "metadata_size",
+];
+
+/// The extended handshake, sent once by each peer after the BitTorrent
+/// handshake.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ExtendedHandshake {
+    /// `m`: extension name to the message id the sender wants to receive it
+    /// under. An id of 0 disables the extension.
+    pub extensions: BTreeMap<String, u8>,
+    /// `p`: the sender's listen port.
+    pub port: Option<u16>,
+    /// `v`: client name and version.
+    pub client: Option<String>,
+    /// `yourip`: the receiver's address as the sender sees it.
+    pub your_ip: Option<IpAddr>,
+    pub ipv4: Option<Ipv4Addr>,
+    pub ipv6: Option<Ipv6Addr>,
+    /// `reqq`: how many outstanding requests the sender accepts.
+    pub reqq: Option<u64>,
+    /// `metadata_size`: size of the info dictionary, for BEP 9.
+    pub metadata_size: Option<u64>,
+    pub extra: HashMap<String, Value>,
+}
+
+impl ExtendedHandshake {
+    pub fn new() -> Self {
+        ExtendedHandshake::default()
+    }
+
+    /// Advertise `name` under message `id`.
+    pub fn with_extension(mut self, name: &str, id: u8) -> Self {
+        self.extensions.insert(name.to_string(), id);
+        self
+    }
+
+    /// The message id the peer wants for `name`, if it supports it.
+    pub fn extension_id(&self, name: &str) -> Option<u8> {
+        self.extensions.get(name).copied().filter(|id| *id != 0)
+    }
+
+    /// Decode the payload of an extended message with id 0.
+    pub fn from_bytes(payload: &[u8]) -> Result<ExtendedHandshake> {
+        match parse_bencode(&mut &payload[..])? {
+            Some(value) => ExtendedHandshake::from_value(&value),
+            None => Err(BencodeError::msg("Empty extended handshake")),
+        }
+    }
+
+    /// Encode as an extended message payload. Fails if a value in `extra`
+    /// holds a dictionary with a non-string key.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        self.to_value()
+            .write_bencode_with(&mut buf, EncodeOptions::strict())?;
+        Ok(buf)
+    }
+}
+
+impl FromBencode for ExtendedHandshake {
+    fn from_value(value: &Value) -> Result<Self> {
+        let fields = Fields::new(value)?;
+        let mut extensions = BTreeMap::new();
+        match fields.get("m") {
+            None => {}
+            Some(Value::Map(m)) => {
+                for (name, id) in &m.0 {
+                    let name = match name.as_bytes() {
+                        Some(name) => String::from_utf8_lossy(name).into_owned(),
+                        None => return Err(BencodeError::msg("keys must be strings").in_field("m")),
+                    };
+                    let id = match id {
+                        Value::Int(id) if (0..=255).contains(id) => *id as u8,
+                        _ => {
+                            return Err(BencodeError::msg(format!(
+                                "`{}` must map to a message id from 0 to 255",
+                                name
+                            ))
+                            .in_field("m"))
+                        }
+                    };
+                    extensions.insert(name, id);
+                }
+            }
+            Some(_) => return Err(BencodeError::msg("expected dictionary").in_field("m")),
+        }
+
+        let port = match fields.uint("p")? {
+            Some(p) if p > u64::from(u16::MAX) => {
+                return Err(BencodeError::msg(format!("{} is out of range", p)).in_field("p"))
+            }
+            p => p.map(|p| p as u16),
+        };
+        let your_ip = match fields.bytes("yourip")? {
+            None => None,
+            Some(b) if b.len() == 4 => Some(IpAddr::V4(ipv4(b))),
+            Some(b) if b.len() == 16 => Some(IpAddr::V6(ipv6(b))),
+            Some(b) => {
+                return Err(
+                    BencodeError::msg(format!("expected 4 or 16 bytes, found {}", b.len()))
+                        .in_field("yourip"),
+                )
+            }
+        };
+        let ipv4 = match fields.bytes("ipv4")? {
+            None => None,
+            Some(b) if b.len() == 4 => Some(ipv4(b)),
+            Some(_) => return Err(BencodeError::msg("expected 4 bytes").in_field("ipv4")),
+        };
+        let ipv6 = match fields.bytes("ipv6")? {
+            None => None,
+            Some(b) if b.len() == 16 => Some(ipv6(b)),
+            Some(_) => return Err(BencodeError::msg("expected 16 bytes").in_field("ipv6")),
+        };
+
+        Ok(ExtendedHandshake {
+            extensions,
+            port,
+            client: fields.string("v")?,
+            your_ip,
+            ipv4,
+            ipv6,
+            reqq: fields.uint("reqq")?,
+            metadata_size: fields.uint("metadata_size")?,
+            extra: fields.rest(HANDSHAKE_FIELDS),
+        })
+    }
+}
+
+impl ToBencode for ExtendedHandshake {
+    fn to_value(&self) -> Value {
+        let m = Value::Map(HMap(
+            self.extensions
+                .iter()
+                .map(|(name, id)| (Value::from(name.as_str()), Value::Int(i64::from(*id))))
+                .collect(),
+        ));
+        let your_ip = self.your_ip.map(|ip| match ip {
+            IpAddr::V4(ip) => ip.octets().to_vec(),
+            IpAddr::V6(ip) => ip.octets().to_vec(),
+        });
+        DictBuilder::new()
+            .set("m", m)
+            .opt("p", self.port.map(i64::from))
+            .opt("v", self.client.as_deref())
+            .opt("yourip", your_ip)
+            .opt("ipv4", self.ipv4.map(|ip| ip.octets().to_vec()))
+            .opt("ipv6", self.ipv6.map(|ip| ip.octets().to_vec()))
+            .opt("reqq", self.reqq.map(|n| n as i64))
+            .opt("metadata_size", self.metadata_size.map(|n| n as i64))
+            .extend(&self.extra)
+            .build()
+    }
+}
+
+fn ipv4(b: &[u8]) -> Ipv4Addr {
+    Ipv4Addr::new(b[0], b[1], b[2], b[3])
+}
+
+fn ipv6(b: &[u8]) -> Ipv6Addr {
+    let mut octets = [0; 16];
+    octets.copy_from_slice(b);
+    Ipv6Addr::from(octets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extended_handshake_roundtrip() {
+        let payload = b"d1:md11:ut_metadatai3e6:ut_pexi0ee13:metadata_sizei31235e1:pi6881e4:reqqi250e1:v13:Transmission 6:yourip4:\x7f\x00\x00\x01e";
+        let hs = ExtendedHandshake::from_bytes(payload).unwrap();
+        assert_eq!(hs.extension_id("ut_metadata"), Some(3));
+        assert_eq!(hs.extension_id("ut_pex"), None);
+        assert_eq!(hs.port, Some(6881));
+        assert_eq!(hs.client.as_deref(), Some("Transmission "));
+        assert_eq!(hs.your_ip, Some(IpAddr::V4(Ipv4Addr::LOCALHOST)));
+        assert_eq!(hs.reqq, Some(250));
+        assert_eq!(hs.metadata_size, Some(31235));
+        assert_eq!(hs.to_bytes().unwrap(), &payload[..]);
+    }
+
+    #[test]
+    fn test_extended_handshake_errors() {
+        assert!(ExtendedHandshake::from_bytes(b"d1:md1:xi300eee").is_err());
+        assert!(ExtendedHandshake::from_bytes(b"d6:yourip3:abce").is_err());
+        let hs = ExtendedHandshake::new().with_extension("ut_pex", 1);
+        assert_eq!(hs.to_bytes().unwrap(), b"d1:md6:ut_pexi1eee");
+    }
+}
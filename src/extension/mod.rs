@@ -0,0 +1,11 @@
+//! Peer-wire extension protocol (BEP 10) payloads.
+//!
+//! Extended messages are bencoded dictionaries sent inside peer-wire message
+//! 20. The first, with extended id 0, is the handshake in which both peers
+//! announce which extensions they support and under which ids.
+
+mod handshake;
+mod pex;
+
+pub use handshake::ExtendedHandshake;
+pub use pex::{PexFlags, PexMessage, PexPeer};
@@ -0,0 +1,228 @@
+use crate::fields::{DictBuilder, Fields};
+use crate::tracker::compact;
+use crate::{parse_bencode, BencodeError, EncodeOptions, FromBencode, Result, ToBencode, Value};
+use std::collections::HashMap;
+use std::net::{SocketAddr, SocketAddrV4, SocketAddrV6};
+
+const PEX_FIELDS: &[&str] = &[
+    "added", "added.f", "dropped", "added6", "added6.f", "dropped6",
+];
+
+/// A `ut_pex` peer exchange message: peers the sender connected to or
+/// dropped since its last message.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PexMessage {
+    /// From `added` and `added6`, with their flags.
+    pub added: Vec<PexPeer>,
+    /// From `dropped` and `dropped6`.
+    pub dropped: Vec<SocketAddr>,
+    pub extra: HashMap<String, Value>,
+}
+
+/// A newly connected peer and what the sender knows about it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PexPeer {
+    pub addr: SocketAddr,
+    pub flags: PexFlags,
+}
+
+/// The per-peer flag byte of `added.f` and `added6.f`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PexFlags(pub u8);
+
+impl PexFlags {
+    pub const PREFERS_ENCRYPTION: u8 = 0x01;
+    pub const SEED: u8 = 0x02;
+    pub const UTP: u8 = 0x04;
+    pub const HOLEPUNCH: u8 = 0x08;
+    pub const REACHABLE: u8 = 0x10;
+
+    pub fn contains(self, flag: u8) -> bool {
+        self.0 & flag == flag
+    }
+
+    pub fn prefers_encryption(self) -> bool {
+        self.contains(PexFlags::PREFERS_ENCRYPTION)
+    }
+
+    /// The peer is a seed or upload-only.
+    pub fn is_seed(self) -> bool {
+        self.contains(PexFlags::SEED)
+    }
+
+    pub fn supports_utp(self) -> bool {
+        self.contains(PexFlags::UTP)
+    }
+
+    pub fn supports_holepunch(self) -> bool {
+        self.contains(PexFlags::HOLEPUNCH)
+    }
+
+    /// The sender reached the peer with an outgoing connection.
+    pub fn is_reachable(self) -> bool {
+        self.contains(PexFlags::REACHABLE)
+    }
+}
+
+impl PexMessage {
+    /// Decode the payload of a `ut_pex` extended message.
+    pub fn from_bytes(payload: &[u8]) -> Result<PexMessage> {
+        match parse_bencode(&mut &payload[..])? {
+            Some(value) => PexMessage::from_value(&value),
+            None => Err(BencodeError::msg("Empty PEX message")),
+        }
+    }
+
+    /// Encode as a `ut_pex` extended message payload. Fails if a value in
+    /// `extra` holds a dictionary with a non-string key.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        self.to_value()
+            .write_bencode_with(&mut buf, EncodeOptions::strict())?;
+        Ok(buf)
+    }
+}
+
+impl FromBencode for PexMessage {
+    fn from_value(value: &Value) -> Result<Self> {
+        let fields = Fields::new(value)?;
+        let bytes = |key| fields.bytes(key).map(|b| b.unwrap_or(&[]));
+
+        let added4 = compact::decode_peers(bytes("added")?)?;
+        let added6 = compact::decode_peers6(bytes("added6")?)?;
+        let flags4 = flags(bytes("added.f")?, added4.len(), "added.f")?;
+        let flags6 = flags(bytes("added6.f")?, added6.len(), "added6.f")?;
+        let mut added: Vec<PexPeer> = added4
+            .into_iter()
+            .map(SocketAddr::V4)
+            .zip(flags4)
+            .map(|(addr, flags)| PexPeer { addr, flags })
+            .collect();
+        added.extend(
+            added6
+                .into_iter()
+                .map(SocketAddr::V6)
+                .zip(flags6)
+                .map(|(addr, flags)| PexPeer { addr, flags }),
+        );
+
+        let mut dropped: Vec<SocketAddr> = compact::decode_peers(bytes("dropped")?)?
+            .into_iter()
+            .map(SocketAddr::V4)
+            .collect();
+        dropped.extend(
+            compact::decode_peers6(bytes("dropped6")?)?
+                .into_iter()
+                .map(SocketAddr::V6),
+        );
+
+        Ok(PexMessage {
+            added,
+            dropped,
+            extra: fields.rest(PEX_FIELDS),
+        })
+    }
+}
+
+impl ToBencode for PexMessage {
+    fn to_value(&self) -> Value {
+        let (mut added4, mut flags4, mut added6, mut flags6) = (vec![], vec![], vec![], vec![]);
+        for peer in &self.added {
+            match peer.addr {
+                SocketAddr::V4(addr) => {
+                    added4.push(addr);
+                    flags4.push(peer.flags.0);
+                }
+                SocketAddr::V6(addr) => {
+                    added6.push(addr);
+                    flags6.push(peer.flags.0);
+                }
+            }
+        }
+        let (dropped4, dropped6) = split(&self.dropped);
+        // IPv4 keys are always sent, IPv6 ones only when there is something
+        let v6 = |bytes: Vec<u8>| if bytes.is_empty() { None } else { Some(bytes) };
+        DictBuilder::new()
+            .set("added", compact::encode_peers(&added4))
+            .set("added.f", flags4)
+            .set("dropped", compact::encode_peers(&dropped4))
+            .opt("added6", v6(compact::encode_peers6(&added6)))
+            .opt("added6.f", v6(flags6))
+            .opt("dropped6", v6(compact::encode_peers6(&dropped6)))
+            .extend(&self.extra)
+            .build()
+    }
+}
+
+/// One flag byte per peer; missing flags default to none set.
+fn flags(bytes: &[u8], peers: usize, key: &str) -> Result<Vec<PexFlags>> {
+    if bytes.is_empty() {
+        return Ok(vec![PexFlags::default(); peers]);
+    }
+    if bytes.len() != peers {
+        return Err(
+            BencodeError::msg(format!("{} flags for {} peers", bytes.len(), peers)).in_field(key),
+        );
+    }
+    Ok(bytes.iter().map(|f| PexFlags(*f)).collect())
+}
+
+fn split(addrs: &[SocketAddr]) -> (Vec<SocketAddrV4>, Vec<SocketAddrV6>) {
+    let (mut v4, mut v6) = (Vec::new(), Vec::new());
+    for addr in addrs {
+        match addr {
+            SocketAddr::V4(addr) => v4.push(*addr),
+            SocketAddr::V6(addr) => v6.push(*addr),
+        }
+    }
+    (v4, v6)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pex_roundtrip() {
+        let mut payload = b"d5:added12:".to_vec();
+        payload.extend_from_slice(&[1, 2, 3, 4, 0, 80, 5, 6, 7, 8, 0, 81]);
+        payload.extend_from_slice(b"7:added.f2:\x12\x05");
+        payload.extend_from_slice(b"7:dropped6:\x09\x09\x09\x09\x00\x01e");
+        let msg = PexMessage::from_bytes(&payload).unwrap();
+
+        assert_eq!(msg.added.len(), 2);
+        assert_eq!(msg.added[0].addr.to_string(), "1.2.3.4:80");
+        assert!(msg.added[0].flags.is_seed() && msg.added[0].flags.is_reachable());
+        assert!(!msg.added[0].flags.supports_utp());
+        assert!(msg.added[1].flags.prefers_encryption() && msg.added[1].flags.supports_utp());
+        assert_eq!(
+            msg.dropped,
+            vec!["9.9.9.9:1".parse::<SocketAddr>().unwrap()]
+        );
+        assert_eq!(msg.to_bytes().unwrap(), payload);
+    }
+
+    #[test]
+    fn test_pex_ipv6_and_missing_flags() {
+        let mut payload = b"d6:added618:".to_vec();
+        payload.extend_from_slice(&std::net::Ipv6Addr::LOCALHOST.octets());
+        payload.extend_from_slice(&[0, 9]);
+        payload.extend_from_slice(b"e");
+        let msg = PexMessage::from_bytes(&payload).unwrap();
+        assert_eq!(msg.added[0].addr.to_string(), "[::1]:9");
+        assert_eq!(msg.added[0].flags, PexFlags(0));
+
+        assert!(PexMessage::from_bytes(b"d5:added6:abcdef7:added.f2:xxe").is_err());
+    }
+
+    #[test]
+    fn test_pex_to_bytes_rejects_non_string_key() {
+        let mut map = HashMap::new();
+        map.insert(Value::Int(1), Value::Int(2));
+        let mut msg = PexMessage::default();
+        msg.extra.insert("x".to_string(), Value::from(map));
+        let err = msg.to_bytes().unwrap_err();
+        assert_eq!(err.kind(), Some(crate::ErrorKind::NonStringKey));
+        assert_eq!(err.path(), "x");
+    }
+}
@@ -0,0 +1,317 @@
+//! C interface for embedding the parser.
+//!
+//! Build a shared library with
+//! `cargo rustc --release --features ffi --crate-type cdylib` and declare:
+//!
+//! ```c
+//! typedef struct BencodeValue BencodeValue;
+//!
+//! enum BencodeStatus {
+//!     BENCODE_OK = 0,
+//!     BENCODE_NULL_POINTER = 1,
+//!     BENCODE_PARSE_ERROR = 2,
+//!     BENCODE_NOT_FOUND = 3,
+//!     BENCODE_WRONG_TYPE = 4,
+//!     BENCODE_PANIC = 5,
+//! };
+//!
+//! enum BencodeKind {
+//!     BENCODE_INT = 0, BENCODE_STRING = 1, BENCODE_LIST = 2, BENCODE_DICT = 3,
+//! };
+//!
+//! int bencode_parse(const uint8_t *data, size_t len, BencodeValue **out);
+//! const BencodeValue *bencode_get(const BencodeValue *value, const char *path);
+//! int bencode_kind(const BencodeValue *value);
+//! int bencode_int(const BencodeValue *value, int64_t *out);
+//! int bencode_string(const BencodeValue *value, const uint8_t **data, size_t *len);
+//! int bencode_encode(const BencodeValue *value, uint8_t **out, size_t *len);
+//! void bencode_buffer_free(uint8_t *data, size_t len);
+//! void bencode_free(BencodeValue *value);
+//! ```
+//!
+//! Values returned by `bencode_get` are borrowed from the parsed root and
+//! stay valid until it is passed to `bencode_free`.
+
+use crate::{parse_bencode, EncodeOptions, Value};
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_int};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+
+/// Opaque handle for C callers.
+#[repr(transparent)]
+pub struct BencodeValue(Value);
+
+/// Result codes returned by the FFI functions.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BencodeStatus {
+    Ok = 0,
+    NullPointer = 1,
+    /// Malformed input, or bytes left over after the value.
+    ParseError = 2,
+    NotFound = 3,
+    WrongType = 4,
+    /// A bug inside the library; nothing was returned.
+    Panic = 5,
+}
+
+/// What a [`BencodeValue`] holds, as returned by [`bencode_kind`].
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BencodeKind {
+    Int = 0,
+    String = 1,
+    List = 2,
+    Dict = 3,
+}
+
+/// Parse one complete value from `data[..len]` into a new handle in `*out`,
+/// to be released with [`bencode_free`].
+///
+/// # Safety
+///
+/// `data` must point to `len` readable bytes and `out` to writable storage
+/// for a pointer.
+#[no_mangle]
+pub unsafe extern "C" fn bencode_parse(
+    data: *const u8,
+    len: usize,
+    out: *mut *mut BencodeValue,
+) -> BencodeStatus {
+    if data.is_null() || out.is_null() {
+        return BencodeStatus::NullPointer;
+    }
+    let mut input = slice::from_raw_parts(data, len);
+    let parsed = panic::catch_unwind(AssertUnwindSafe(|| parse_bencode(&mut input)));
+    match parsed {
+        Ok(Ok(Some(value))) if input.is_empty() => {
+            *out = Box::into_raw(Box::new(BencodeValue(value)));
+            BencodeStatus::Ok
+        }
+        Ok(_) => BencodeStatus::ParseError,
+        Err(_) => BencodeStatus::Panic,
+    }
+}
+
+/// Look up a descendant of `value` by a dot-separated path of dictionary keys
+/// and list indexes, e.g. `"info.files.0.length"`; an empty path is `value`
+/// itself. Returns null when there is no such value.
+///
+/// # Safety
+///
+/// `value` must be null or a live handle and `path` null or a NUL-terminated
+/// string.
+#[no_mangle]
+pub unsafe extern "C" fn bencode_get(
+    value: *const BencodeValue,
+    path: *const c_char,
+) -> *const BencodeValue {
+    if value.is_null() || path.is_null() {
+        return ptr::null();
+    }
+    let path = CStr::from_ptr(path).to_bytes();
+    let mut node = &(*value).0;
+    if !path.is_empty() {
+        for segment in path.split(|b| *b == b'.') {
+            let next = match node {
+                Value::Map(map) => map.get(&Value::from(segment.to_vec())),
+                Value::List(items) => std::str::from_utf8(segment)
+                    .ok()
+                    .and_then(|s| s.parse::<usize>().ok())
+                    .and_then(|i| items.get(i)),
+                _ => None,
+            };
+            node = match next {
+                Some(next) => next,
+                None => return ptr::null(),
+            };
+        }
+    }
+    // `BencodeValue` is `repr(transparent)`, so this points at a valid handle
+    node as *const Value as *const BencodeValue
+}
+
+/// The [`BencodeKind`] of `value` as an integer, or -1 for null.
+///
+/// # Safety
+///
+/// `value` must be null or a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn bencode_kind(value: *const BencodeValue) -> c_int {
+    match value.as_ref().map(|v| &v.0) {
+        None => -1,
+        Some(Value::Int(_)) => BencodeKind::Int as c_int,
+        Some(Value::Str(_) | Value::Bytes(_)) => BencodeKind::String as c_int,
+        Some(Value::List(_)) => BencodeKind::List as c_int,
+        Some(Value::Map(_)) => BencodeKind::Dict as c_int,
+    }
+}
+
+/// Store an integer value in `*out`.
+///
+/// # Safety
+///
+/// `value` must be null or a live handle and `out` null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn bencode_int(value: *const BencodeValue, out: *mut i64) -> BencodeStatus {
+    if out.is_null() {
+        return BencodeStatus::NullPointer;
+    }
+    match value.as_ref().map(|v| &v.0) {
+        None => BencodeStatus::NullPointer,
+        Some(Value::Int(i)) => {
+            *out = *i;
+            BencodeStatus::Ok
+        }
+        Some(_) => BencodeStatus::WrongType,
+    }
+}
+
+/// Point `*data` and `*len` at the bytes of a string value. The bytes are
+/// not NUL-terminated and are borrowed from the root handle.
+///
+/// # Safety
+///
+/// `value` must be null or a live handle and `data` and `len` null or
+/// writable.
+#[no_mangle]
+pub unsafe extern "C" fn bencode_string(
+    value: *const BencodeValue,
+    data: *mut *const u8,
+    len: *mut usize,
+) -> BencodeStatus {
+    if data.is_null() || len.is_null() {
+        return BencodeStatus::NullPointer;
+    }
+    match value.as_ref().map(|v| v.0.as_bytes()) {
+        None => BencodeStatus::NullPointer,
+        Some(Some(bytes)) => {
+            *data = bytes.as_ptr();
+            *len = bytes.len();
+            BencodeStatus::Ok
+        }
+        Some(None) => BencodeStatus::WrongType,
+    }
+}
+
+/// Encode `value` with sorted keys into a new buffer in `*out` and `*len`,
+/// to be released with [`bencode_buffer_free`].
+///
+/// # Safety
+///
+/// `value` must be null or a live handle and `out` and `len` null or
+/// writable.
+#[no_mangle]
+pub unsafe extern "C" fn bencode_encode(
+    value: *const BencodeValue,
+    out: *mut *mut u8,
+    len: *mut usize,
+) -> BencodeStatus {
+    if out.is_null() || len.is_null() {
+        return BencodeStatus::NullPointer;
+    }
+    let value = match value.as_ref() {
+        Some(value) => &value.0,
+        None => return BencodeStatus::NullPointer,
+    };
+    let mut buf = Vec::new();
+    // dictionaries from the parser only have string keys
+    if value
+        .write_bencode_with(&mut buf, EncodeOptions::strict())
+        .is_err()
+    {
+        return BencodeStatus::WrongType;
+    }
+    let buf = buf.into_boxed_slice();
+    *len = buf.len();
+    *out = Box::into_raw(buf) as *mut u8;
+    BencodeStatus::Ok
+}
+
+/// Release a buffer from [`bencode_encode`].
+///
+/// # Safety
+///
+/// `data` and `len` must come from one successful `bencode_encode` call, and
+/// the buffer must not be used afterwards. Null is ignored.
+#[no_mangle]
+pub unsafe extern "C" fn bencode_buffer_free(data: *mut u8, len: usize) {
+    if !data.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(data, len)));
+    }
+}
+
+/// Release a handle from [`bencode_parse`], and with it every value borrowed
+/// from it.
+///
+/// # Safety
+///
+/// `value` must come from `bencode_parse` and not be used afterwards. Null
+/// is ignored; handles from `bencode_get` must not be passed here.
+#[no_mangle]
+pub unsafe extern "C" fn bencode_free(value: *mut BencodeValue) {
+    if !value.is_null() {
+        drop(Box::from_raw(value));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ffi_roundtrip() {
+        let input = b"d4:infod6:lengthi42e4:name3:abce4:listl1:xee";
+        unsafe {
+            let mut root = ptr::null_mut();
+            assert_eq!(
+                bencode_parse(input.as_ptr(), input.len(), &mut root),
+                BencodeStatus::Ok
+            );
+
+            let length = bencode_get(root, b"info.length\0".as_ptr() as *const c_char);
+            let mut n = 0;
+            assert_eq!(bencode_int(length, &mut n), BencodeStatus::Ok);
+            assert_eq!(n, 42);
+
+            let item = bencode_get(root, b"list.0\0".as_ptr() as *const c_char);
+            assert_eq!(bencode_kind(item), BencodeKind::String as c_int);
+            let (mut data, mut len) = (ptr::null(), 0);
+            assert_eq!(bencode_string(item, &mut data, &mut len), BencodeStatus::Ok);
+            assert_eq!(slice::from_raw_parts(data, len), b"x");
+            assert_eq!(bencode_int(item, &mut n), BencodeStatus::WrongType);
+
+            assert!(bencode_get(root, b"list.1\0".as_ptr() as *const c_char).is_null());
+            assert_eq!(bencode_kind(ptr::null()), -1);
+
+            let (mut out, mut out_len) = (ptr::null_mut(), 0);
+            assert_eq!(
+                bencode_encode(root, &mut out, &mut out_len),
+                BencodeStatus::Ok
+            );
+            assert_eq!(slice::from_raw_parts(out, out_len), &input[..]);
+            bencode_buffer_free(out, out_len);
+            bencode_free(root);
+        }
+    }
+
+    #[test]
+    fn test_ffi_errors() {
+        let mut root = ptr::null_mut();
+        unsafe {
+            for bad in [&b"i1ei2e"[..], b"l1:x", b"x"] {
+                assert_eq!(
+                    bencode_parse(bad.as_ptr(), bad.len(), &mut root),
+                    BencodeStatus::ParseError
+                );
+            }
+            assert_eq!(
+                bencode_parse(ptr::null(), 0, &mut root),
+                BencodeStatus::NullPointer
+            );
+        }
+        assert!(root.is_null());
+    }
+}
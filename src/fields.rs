@@ -0,0 +1,161 @@
+//! Typed field access on decoded dictionaries, shared by the protocol and
+//! file-format modules.
+
+// without `std` only the error helpers have users
+#![cfg_attr(not(feature = "std"), allow(dead_code))]
+
+use crate::prelude::*;
+use crate::{BencodeError, ErrorKind, HMap, Map, Result, Value};
+use core::iter::FromIterator;
+
+/// Read-only view of a dictionary with typed, fallible accessors. Every
+/// accessor returns `Ok(None)` for a missing key and an error for a key whose
+/// value has the wrong type.
+#[derive(Clone, Copy)]
+pub(crate) struct Fields<'a> {
+    map: &'a HMap,
+}
+
+impl<'a> Fields<'a> {
+    pub(crate) fn new(value: &'a Value) -> Result<Self> {
+        match value {
+            Value::Map(map) => Ok(Fields { map }),
+            _ => Err(expected("dictionary", value)),
+        }
+    }
+
+    pub(crate) fn get(&self, key: &str) -> Option<&'a Value> {
+        self.map.get(&Value::from(key))
+    }
+
+    pub(crate) fn bytes(&self, key: &str) -> Result<Option<&'a [u8]>> {
+        match self.get(key) {
+            None => Ok(None),
+            Some(v) => v
+                .as_bytes()
+                .map(Some)
+                .ok_or_else(|| wrong_type(key, "string", v)),
+        }
+    }
+
+    pub(crate) fn string(&self, key: &str) -> Result<Option<String>> {
+        Ok(self
+            .bytes(key)?
+            .map(|b| String::from_utf8_lossy(b).into_owned()))
+    }
+
+    pub(crate) fn int(&self, key: &str) -> Result<Option<i64>> {
+        match self.get(key) {
+            None => Ok(None),
+            Some(Value::Int(i)) => Ok(Some(*i)),
+            Some(v) => Err(wrong_type(key, "integer", v)),
+        }
+    }
+
+    /// An integer field that must not be negative, e.g. a length or count.
+    pub(crate) fn uint(&self, key: &str) -> Result<Option<u64>> {
+        match self.int(key)? {
+            Some(n) if n < 0 => {
+                Err(BencodeError::msg(format!("must not be negative, found {}", n)).in_field(key))
+            }
+            n => Ok(n.map(|n| n as u64)),
+        }
+    }
+
+    pub(crate) fn list(&self, key: &str) -> Result<Option<&'a [Value]>> {
+        match self.get(key) {
+            None => Ok(None),
+            Some(Value::List(items)) => Ok(Some(items)),
+            Some(v) => Err(wrong_type(key, "list", v)),
+        }
+    }
+
+    /// Keys and values not covered by `known`, with keys as strings.
+    pub(crate) fn rest<M: FromIterator<(String, Value)>>(&self, known: &[&str]) -> M {
+        self.map
+            .0
+            .iter()
+            .filter_map(|(k, v)| {
+                let key = String::from_utf8_lossy(k.as_bytes()?).into_owned();
+                if known.contains(&key.as_str()) {
+                    None
+                } else {
+                    Some((key, v.clone()))
+                }
+            })
+            .collect()
+    }
+}
+
+/// Prefix errors from a nested value with where it sits. Nested calls build
+/// up one path, so a bad file entry in a torrent reports as
+/// "Field `info.files[12].path`: expected list, found string".
+pub(crate) fn within<T>(context: &str, result: Result<T>) -> Result<T> {
+    result.map_err(|e| e.in_field(context))
+}
+
+/// Turn a missing optional field into an error.
+pub(crate) fn require<T>(key: &str, value: Option<T>) -> Result<T> {
+    value.ok_or_else(|| missing(key))
+}
+
+pub(crate) fn missing(key: &str) -> BencodeError {
+    BencodeError::new(ErrorKind::MissingField(key.to_string()))
+}
+
+pub(crate) fn wrong_type(key: &str, wanted: &'static str, found: &Value) -> BencodeError {
+    expected(wanted, found).in_field(key)
+}
+
+/// A value of the wrong type where `wanted` was expected.
+pub(crate) fn expected(wanted: &'static str, found: &Value) -> BencodeError {
+    BencodeError::new(ErrorKind::WrongType {
+        expected: wanted,
+        found: type_name(found),
+    })
+}
+
+pub(crate) fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Map(_) => "dictionary",
+        Value::List(_) => "list",
+        Value::Str(_) | Value::Bytes(_) => "string",
+        Value::Int(_) => "integer",
+    }
+}
+
+/// Builder for output dictionaries; `None` values are left out.
+#[derive(Default)]
+pub(crate) struct DictBuilder(Map<Value, Value>);
+
+impl DictBuilder {
+    pub(crate) fn new() -> Self {
+        DictBuilder::default()
+    }
+
+    pub(crate) fn set<V: Into<Value>>(mut self, key: &str, value: V) -> Self {
+        self.0.insert(Value::from(key), value.into());
+        self
+    }
+
+    pub(crate) fn opt<V: Into<Value>>(self, key: &str, value: Option<V>) -> Self {
+        match value {
+            Some(v) => self.set(key, v),
+            None => self,
+        }
+    }
+
+    pub(crate) fn extend<'a, I>(mut self, rest: I) -> Self
+    where
+        I: IntoIterator<Item = (&'a String, &'a Value)>,
+    {
+        for (k, v) in rest {
+            self.0.insert(Value::from(k.as_str()), v.clone());
+        }
+        self
+    }
+
+    pub(crate) fn build(self) -> Value {
+        Value::from(self.0)
+    }
+}
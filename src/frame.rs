@@ -0,0 +1,75 @@
+//! Length-prefixed framing for carrying bencode inside message-oriented
+//! protocols. Each frame is a 4-byte big-endian payload length followed by
+//! exactly one encoded value.
+
+use crate::{from_slice, BencodeError, Result, Value};
+use std::convert::TryFrom;
+use std::io::{self, Read, Write};
+
+/// Write `value` as a single length-prefixed frame.
+pub fn write_frame<W: Write + ?Sized>(writer: &mut W, value: &Value) -> Result<()> {
+    let mut payload = Vec::new();
+    value.write_bencode(&mut payload)?;
+    let len = u32::try_from(payload.len())
+        .map_err(|_| BencodeError::msg("Frame payload exceeds u32::MAX bytes"))?;
+    writer.write_all(&len.to_be_bytes())?;
+    writer.write_all(&payload)?;
+    Ok(())
+}
+
+/// Read one frame written by [`write_frame`]. A clean end of stream before the
+/// length prefix is reported as `BencodeError::Eof`.
+pub fn read_frame<R: Read + ?Sized>(reader: &mut R) -> Result<Value> {
+    let mut prefix = [0; 4];
+    if let Err(e) = reader.read_exact(&mut prefix) {
+        return match e.kind() {
+            io::ErrorKind::UnexpectedEof => Err(BencodeError::Eof()),
+            _ => Err(e.into()),
+        };
+    }
+    let len = u32::from_be_bytes(prefix) as u64;
+
+    // grow the buffer as bytes arrive rather than trusting the prefix up front
+    let mut payload = Vec::new();
+    reader.take(len).read_to_end(&mut payload)?;
+    if payload.len() as u64 != len {
+        return Err(BencodeError::Io(io::ErrorKind::UnexpectedEof.into()));
+    }
+    from_slice(&payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_roundtrip() {
+        let first = Value::List(vec![Value::from("ping"), Value::Int(1)]);
+        let second = Value::from("pong");
+
+        let mut buf = Vec::new();
+        write_frame(&mut buf, &first).unwrap();
+        write_frame(&mut buf, &second).unwrap();
+        assert_eq!(&buf[..4], &[0, 0, 0, 11]);
+
+        let mut reader = &buf[..];
+        assert_eq!(read_frame(&mut reader).unwrap(), first);
+        assert_eq!(read_frame(&mut reader).unwrap(), second);
+        assert!(matches!(read_frame(&mut reader), Err(BencodeError::Eof())));
+    }
+
+    #[test]
+    fn test_frame_rejects_bad_payload() {
+        let truncated = [0, 0, 0, 9, b'i', b'1', b'e'];
+        assert!(read_frame(&mut &truncated[..]).is_err());
+
+        let trailing = [0, 0, 0, 4, b'i', b'1', b'e', b'x'];
+        let err = read_frame(&mut &trailing[..]).unwrap_err();
+        assert_eq!(err.kind(), Some(crate::ErrorKind::Trailing));
+        assert_eq!(err.position(), Some(3));
+
+        let empty = [0, 0, 0, 0];
+        let err = read_frame(&mut &empty[..]).unwrap_err();
+        assert_eq!(err.kind(), Some(crate::ErrorKind::UnexpectedEof));
+    }
+}
@@ -0,0 +1,282 @@
+//! Reproducible random values, for fuzz corpora, benchmarks and tests.
+//!
+//! Everything is driven by [`Rng`], a small seeded generator, so the same
+//! seed and [`GenConfig`] always produce the same values on every platform.
+//!
+//! ```
+//! use bencode_rs::generator::{random_value, Charset, GenConfig, Rng};
+//!
+//! let config = GenConfig {
+//!     max_depth: 3,
+//!     max_items: 4,
+//!     string_len: 8,
+//!     charset: Charset::Alphanumeric,
+//!     ..GenConfig::default()
+//! };
+//! let mut rng = Rng::new(42);
+//! let corpus: Vec<Vec<u8>> = (0..100)
+//!     .map(|_| random_value(&mut rng, &config).to_bencode().into_bytes())
+//!     .collect();
+//! assert_eq!(corpus.len(), 100);
+//! ```
+
+use crate::{HMap, Map, Value};
+
+/// A SplitMix64 generator: fast, seedable, and not for cryptographic use.
+#[derive(Clone, Debug)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0..n`; `n` must not be zero.
+    pub fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n
+    }
+
+    /// True with probability `1 / n`.
+    fn one_in(&mut self, n: u64) -> bool {
+        self.below(n) == 0
+    }
+}
+
+/// Which bytes generated strings, including dictionary keys, are made of.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Charset {
+    /// `a-z`, `A-Z` and `0-9`.
+    Alphanumeric,
+    /// Any Unicode scalar value, encoded as UTF-8.
+    Unicode,
+    /// Any byte, so most strings decode as [`Value::Bytes`].
+    Binary,
+}
+
+/// Limits for [`random_value`]. The `ints`, `strings`, `lists` and `dicts`
+/// switches choose which kinds of value may appear; without either scalar
+/// kind the innermost containers are left empty, and with nothing allowed the
+/// result is an empty list.
+#[derive(Clone, Debug)]
+pub struct GenConfig {
+    /// Nesting depth of lists and dictionaries; 0 generates only scalars.
+    pub max_depth: usize,
+    /// Most elements in a list or entries in a dictionary.
+    pub max_items: usize,
+    /// Longest string, in bytes for [`Charset::Binary`] and characters
+    /// otherwise.
+    pub string_len: usize,
+    pub charset: Charset,
+    /// Inclusive bounds for integers.
+    pub int_range: (i64, i64),
+    pub ints: bool,
+    pub strings: bool,
+    pub lists: bool,
+    pub dicts: bool,
+}
+
+impl Default for GenConfig {
+    fn default() -> GenConfig {
+        GenConfig {
+            max_depth: 4,
+            max_items: 8,
+            string_len: 16,
+            charset: Charset::Binary,
+            int_range: (i64::MIN, i64::MAX),
+            ints: true,
+            strings: true,
+            lists: true,
+            dicts: true,
+        }
+    }
+}
+
+/// Generate one value within the limits of `config`.
+pub fn random_value(rng: &mut Rng, config: &GenConfig) -> Value {
+    value(rng, config, config.max_depth)
+}
+
+fn value(rng: &mut Rng, config: &GenConfig, depth: usize) -> Value {
+    let mut kinds = Vec::with_capacity(4);
+    if config.ints {
+        kinds.push(0);
+    }
+    if config.strings {
+        kinds.push(1);
+    }
+    if depth > 0 || kinds.is_empty() {
+        if config.lists {
+            kinds.push(2);
+        }
+        if config.dicts {
+            kinds.push(3);
+        }
+    }
+    if kinds.is_empty() {
+        return Value::List(vec![]);
+    }
+    let depth = depth.saturating_sub(1);
+    match kinds[rng.below(kinds.len() as u64) as usize] {
+        0 => Value::Int(int(rng, config.int_range)),
+        1 => Value::from_bytes(string(rng, config)),
+        2 => {
+            let len = items(rng, config, depth);
+            Value::List((0..len).map(|_| value(rng, config, depth)).collect())
+        }
+        _ => {
+            let len = items(rng, config, depth);
+            let mut map = Map::new();
+            for _ in 0..len {
+                let key = Value::from_bytes(string(rng, config));
+                map.insert(key, value(rng, config, depth));
+            }
+            Value::Map(HMap(map))
+        }
+    }
+}
+
+// Containers past the depth limit can only be empty, which is also how
+// they are built when no scalar kind is allowed.
+fn items(rng: &mut Rng, config: &GenConfig, depth: usize) -> usize {
+    if depth == 0 && !config.ints && !config.strings {
+        0
+    } else {
+        rng.below(config.max_items as u64 + 1) as usize
+    }
+}
+
+fn int(rng: &mut Rng, (min, max): (i64, i64)) -> i64 {
+    let (min, max) = (min.min(max), min.max(max));
+    let span = (max as i128 - min as i128) as u128 + 1;
+    // favour the edges and small numbers, where encoding bugs live
+    let pick = match rng.below(4) {
+        0 => [min, max, 0, -1, 1][rng.below(5) as usize],
+        1 => rng.below(201) as i64 - 100,
+        _ => (min as i128 + (rng.next_u64() as u128 % span) as i128) as i64,
+    };
+    pick.clamp(min, max)
+}
+
+fn string(rng: &mut Rng, config: &GenConfig) -> Vec<u8> {
+    const ALPHANUMERIC: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+    let len = rng.below(config.string_len as u64 + 1) as usize;
+    match config.charset {
+        Charset::Alphanumeric => (0..len)
+            .map(|_| ALPHANUMERIC[rng.below(ALPHANUMERIC.len() as u64) as usize])
+            .collect(),
+        Charset::Binary => (0..len).map(|_| rng.next_u64() as u8).collect(),
+        Charset::Unicode => (0..len)
+            .map(|_| {
+                // mostly ASCII, with some of every encoded length
+                let c = if rng.one_in(2) {
+                    rng.below(0x80) as u32
+                } else {
+                    rng.below(0x11_0000) as u32
+                };
+                char::from_u32(c).unwrap_or('\u{fffd}')
+            })
+            .collect::<String>()
+            .into_bytes(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn depth(value: &Value) -> usize {
+        match value {
+            Value::List(items) => 1 + items.iter().map(depth).max().unwrap_or(0),
+            Value::Map(map) => 1 + map.0.values().map(depth).max().unwrap_or(0),
+            _ => 0,
+        }
+    }
+
+    #[test]
+    fn test_generator_is_reproducible() {
+        let config = GenConfig::default();
+        let run = |seed| {
+            let mut rng = Rng::new(seed);
+            (0..20)
+                .map(|_| random_value(&mut rng, &config))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(run(1), run(1));
+        assert_ne!(run(1), run(2));
+        assert_eq!(Rng::new(0).next_u64(), 0xe220_a839_7b1d_cdaf);
+    }
+
+    #[test]
+    fn test_generator_respects_limits() {
+        let config = GenConfig {
+            max_depth: 2,
+            max_items: 3,
+            string_len: 5,
+            charset: Charset::Alphanumeric,
+            int_range: (-10, 10),
+            ..GenConfig::default()
+        };
+        let mut rng = Rng::new(9);
+        for _ in 0..500 {
+            let value = random_value(&mut rng, &config);
+            assert!(depth(&value) <= 2);
+            let mut stack = vec![&value];
+            while let Some(value) = stack.pop() {
+                match value {
+                    Value::Int(n) => assert!((-10..=10).contains(n)),
+                    Value::Str(s) => {
+                        assert!(s.len() <= 5 && s.bytes().all(|b| b.is_ascii_alphanumeric()))
+                    }
+                    Value::Bytes(_) => panic!("alphanumeric strings are UTF-8"),
+                    Value::List(items) => {
+                        assert!(items.len() <= 3);
+                        stack.extend(items);
+                    }
+                    Value::Map(map) => {
+                        assert!(map.0.len() <= 3);
+                        stack.extend(map.0.values());
+                    }
+                }
+            }
+            let encoded = value.to_bencode().into_bytes();
+            let decoded = crate::parse_bencode(&mut &encoded[..]).unwrap();
+            assert_eq!(decoded, Some(value));
+        }
+    }
+
+    #[test]
+    fn test_generator_type_controls() {
+        let mut rng = Rng::new(3);
+        let only_lists = GenConfig {
+            ints: false,
+            strings: false,
+            dicts: false,
+            ..GenConfig::default()
+        };
+        for _ in 0..50 {
+            let value = random_value(&mut rng, &only_lists);
+            assert!(depth(&value) >= 1 && depth(&value) <= 4);
+            assert!(!value.to_bencode().contains(|c| c != 'l' && c != 'e'));
+        }
+        let nothing = GenConfig {
+            lists: false,
+            ..only_lists
+        };
+        assert_eq!(random_value(&mut rng, &nothing), Value::List(vec![]));
+        let scalars = GenConfig {
+            max_depth: 0,
+            ..GenConfig::default()
+        };
+        for _ in 0..50 {
+            assert_eq!(depth(&random_value(&mut rng, &scalars)), 0);
+        }
+    }
+}
@@ -0,0 +1,551 @@
+//! Conversion between bencode [`Value`]s and JSON.
+//!
+//! JSON has no byte strings, so [`BinaryPolicy`] decides how strings that
+//! are not valid UTF-8 (piece hashes, compact peers) are rendered.
+//!
+//! **The conversion is one-way for binary data.** Going the other way, JSON
+//! strings always become bencode strings as they are: a piece hash written
+//! as hex comes back as 40 hex characters, not the 20 original bytes. The
+//! rendered string does not record that it was binary, and ordinary text
+//! such as `"cafe"` is valid hex and base64 too, so no policy can be undone
+//! reliably. `null`, fractional numbers and nesting deeper than [`MAX_DEPTH`]
+//! are errors.
+//!
+//! ```
+//! use bencode_rs::json::{from_json_str, to_json_string, BinaryPolicy};
+//!
+//! let value = from_json_str(r#"{"name": "x", "length": 3}"#).unwrap();
+//! let expected = bencode_rs::parse_bencode(&mut &b"d6:lengthi3e4:name1:xe"[..]).unwrap();
+//! assert_eq!(Some(value.clone()), expected);
+//! assert_eq!(
+//!     to_json_string(&value, BinaryPolicy::Hex),
+//!     r#"{"length":3,"name":"x"}"#
+//! );
+//! ```
+
+use crate::document::MAX_DEPTH;
+use crate::{BencodeError, ErrorKind, HMap, Map, Result, Value};
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fmt::{self, Display, Write};
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// A JSON value.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+    Array(Vec<Json>),
+    /// Keys are kept sorted, which matches bencode's canonical order for
+    /// UTF-8 keys.
+    Object(BTreeMap<String, Json>),
+}
+
+/// How byte strings that are not valid UTF-8 are rendered as JSON strings.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum BinaryPolicy {
+    /// Lowercase hex digits.
+    #[default]
+    Hex,
+    /// Standard base64 with padding.
+    Base64,
+    /// Invalid sequences replaced with U+FFFD; cannot be reversed.
+    Lossy,
+}
+
+impl BinaryPolicy {
+    fn render(self, bytes: &[u8]) -> String {
+        match std::str::from_utf8(bytes) {
+            Ok(s) => s.to_string(),
+            Err(_) => match self {
+                BinaryPolicy::Hex => crate::hash::to_hex(bytes),
+                BinaryPolicy::Base64 => base64(bytes),
+                BinaryPolicy::Lossy => String::from_utf8_lossy(bytes).into_owned(),
+            },
+        }
+    }
+}
+
+impl Json {
+    /// Convert a bencode value, rendering binary strings with `policy`.
+    /// Dictionary keys that are not strings use their display form.
+    pub fn from_value(value: &Value, policy: BinaryPolicy) -> Json {
+        match value {
+            Value::Int(i) => Json::Int(*i),
+            Value::Str(s) => Json::String(s.clone()),
+            Value::Bytes(b) => Json::String(policy.render(b)),
+            Value::List(items) => {
+                Json::Array(items.iter().map(|v| Json::from_value(v, policy)).collect())
+            }
+            Value::Map(map) => Json::Object(
+                map.0
+                    .iter()
+                    .map(|(k, v)| {
+                        let key = match k.as_bytes() {
+                            Some(bytes) => policy.render(bytes),
+                            None => k.to_string(),
+                        };
+                        (key, Json::from_value(v, policy))
+                    })
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl Json {
+    /// Parse JSON text.
+    pub fn parse(text: &str) -> Result<Json> {
+        let mut parser = Parser {
+            src: text.as_bytes(),
+            pos: 0,
+            depth: 0,
+        };
+        let json = parser.value()?;
+        parser.skip_whitespace();
+        if parser.pos != parser.src.len() {
+            return Err(parser.error("trailing characters"));
+        }
+        Ok(json)
+    }
+
+    /// Indented JSON text, two spaces per level.
+    pub fn to_pretty_string(&self) -> String {
+        let mut out = String::new();
+        // writing into a String cannot fail
+        let _ = self.write(&mut out, Some(0));
+        out
+    }
+
+    // `indent` is the current depth for pretty output, `None` for compact.
+    fn write(&self, out: &mut String, indent: Option<usize>) -> fmt::Result {
+        let newline = |out: &mut String, depth: usize| {
+            out.push('\n');
+            out.push_str(&"  ".repeat(depth));
+        };
+        match self {
+            Json::Null => out.push_str("null"),
+            Json::Bool(b) => write!(out, "{}", b)?,
+            Json::Int(i) => write!(out, "{}", i)?,
+            Json::Float(f) if f.is_finite() => write!(out, "{}", f)?,
+            Json::Float(_) => out.push_str("null"),
+            Json::String(s) => write_string(out, s)?,
+            Json::Array(items) if items.is_empty() => out.push_str("[]"),
+            Json::Array(items) => {
+                out.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    if let Some(depth) = indent {
+                        newline(out, depth + 1);
+                    }
+                    item.write(out, indent.map(|d| d + 1))?;
+                }
+                if let Some(depth) = indent {
+                    newline(out, depth);
+                }
+                out.push(']');
+            }
+            Json::Object(map) if map.is_empty() => out.push_str("{}"),
+            Json::Object(map) => {
+                out.push('{');
+                for (i, (key, value)) in map.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    if let Some(depth) = indent {
+                        newline(out, depth + 1);
+                    }
+                    write_string(out, key)?;
+                    out.push_str(if indent.is_some() { ": " } else { ":" });
+                    value.write(out, indent.map(|d| d + 1))?;
+                }
+                if let Some(depth) = indent {
+                    newline(out, depth);
+                }
+                out.push('}');
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Display for Json {
+    /// Compact JSON text.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut out = String::new();
+        self.write(&mut out, None)?;
+        f.write_str(&out)
+    }
+}
+
+/// `value` as compact JSON text.
+pub fn to_json_string(value: &Value, policy: BinaryPolicy) -> String {
+    Json::from_value(value, policy).to_string()
+}
+
+/// `value` as indented JSON text.
+pub fn to_json_string_pretty(value: &Value, policy: BinaryPolicy) -> String {
+    Json::from_value(value, policy).to_pretty_string()
+}
+
+/// Parse JSON text into a bencode value. Strings are taken as text, so
+/// binary strings rendered by [`to_json_string`] do not turn back into the
+/// original bytes.
+pub fn from_json_str(text: &str) -> Result<Value> {
+    Value::try_from(Json::parse(text)?)
+}
+
+impl From<&Value> for Json {
+    /// Convert with the default [`BinaryPolicy::Hex`].
+    fn from(value: &Value) -> Json {
+        Json::from_value(value, BinaryPolicy::default())
+    }
+}
+
+impl TryFrom<&Json> for Value {
+    type Error = BencodeError;
+
+    /// Booleans become 0 or 1 and whole floats integers.
+    fn try_from(json: &Json) -> Result<Value> {
+        Ok(match json {
+            Json::Null => return Err(BencodeError::msg("JSON null has no bencode form")),
+            Json::Bool(b) => Value::Int(i64::from(*b)),
+            Json::Int(i) => Value::Int(*i),
+            Json::Float(f) if f.fract() == 0.0 && f.abs() < 9.0e18 => Value::Int(*f as i64),
+            Json::Float(f) => {
+                return Err(BencodeError::msg(format!(
+                    "JSON number {} is not an integer",
+                    f
+                )))
+            }
+            Json::String(s) => Value::Str(s.clone()),
+            Json::Array(items) => {
+                Value::List(items.iter().map(Value::try_from).collect::<Result<_>>()?)
+            }
+            Json::Object(map) => {
+                let mut out = Map::new();
+                for (k, v) in map {
+                    out.insert(Value::from(k.as_str()), Value::try_from(v)?);
+                }
+                Value::Map(HMap(out))
+            }
+        })
+    }
+}
+
+impl TryFrom<Json> for Value {
+    type Error = BencodeError;
+
+    fn try_from(json: Json) -> Result<Value> {
+        Value::try_from(&json)
+    }
+}
+
+fn write_string(out: &mut String, s: &str) -> fmt::Result {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32)?,
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    Ok(())
+}
+
+struct Parser<'a> {
+    src: &'a [u8],
+    pos: usize,
+    // containers entered so far
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, what: &str) -> BencodeError {
+        BencodeError::msg(format!("Invalid JSON at offset {}: {}", self.pos, what))
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.src.get(self.pos) {
+            self.pos += 1;
+        }
+    }
+
+    fn eat(&mut self, byte: u8) -> bool {
+        self.skip_whitespace();
+        if self.src.get(self.pos) == Some(&byte) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn literal(&mut self, word: &str, json: Json) -> Result<Json> {
+        if self.src[self.pos..].starts_with(word.as_bytes()) {
+            self.pos += word.len();
+            Ok(json)
+        } else {
+            Err(self.error("unknown literal"))
+        }
+    }
+
+    /// An element of an array or object.
+    fn nested(&mut self) -> Result<Json> {
+        if self.depth == MAX_DEPTH {
+            return Err(BencodeError::new(ErrorKind::DepthLimit(MAX_DEPTH)).at(self.pos));
+        }
+        self.depth += 1;
+        let json = self.value();
+        self.depth -= 1;
+        json
+    }
+
+    fn value(&mut self) -> Result<Json> {
+        self.skip_whitespace();
+        match self.src.get(self.pos) {
+            None => Err(self.error("unexpected end")),
+            Some(b'n') => self.literal("null", Json::Null),
+            Some(b't') => self.literal("true", Json::Bool(true)),
+            Some(b'f') => self.literal("false", Json::Bool(false)),
+            Some(b'"') => self.string().map(Json::String),
+            Some(b'[') => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                if !self.eat(b']') {
+                    loop {
+                        items.push(self.nested()?);
+                        if self.eat(b']') {
+                            break;
+                        }
+                        if !self.eat(b',') {
+                            return Err(self.error("expected `,` or `]`"));
+                        }
+                    }
+                }
+                Ok(Json::Array(items))
+            }
+            Some(b'{') => {
+                self.pos += 1;
+                let mut map = BTreeMap::new();
+                if !self.eat(b'}') {
+                    loop {
+                        self.skip_whitespace();
+                        if self.src.get(self.pos) != Some(&b'"') {
+                            return Err(self.error("expected a string key"));
+                        }
+                        let key = self.string()?;
+                        if !self.eat(b':') {
+                            return Err(self.error("expected `:`"));
+                        }
+                        map.insert(key, self.nested()?);
+                        if self.eat(b'}') {
+                            break;
+                        }
+                        if !self.eat(b',') {
+                            return Err(self.error("expected `,` or `}`"));
+                        }
+                    }
+                }
+                Ok(Json::Object(map))
+            }
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            Some(_) => Err(self.error("unexpected character")),
+        }
+    }
+
+    fn number(&mut self) -> Result<Json> {
+        let start = self.pos;
+        let mut float = false;
+        while let Some(&b) = self.src.get(self.pos) {
+            match b {
+                b'0'..=b'9' | b'-' | b'+' => {}
+                b'.' | b'e' | b'E' => float = true,
+                _ => break,
+            }
+            self.pos += 1;
+        }
+        // the scanned bytes are all ASCII
+        let text = std::str::from_utf8(&self.src[start..self.pos]).unwrap_or_default();
+        if !float {
+            if let Ok(i) = text.parse() {
+                return Ok(Json::Int(i));
+            }
+        }
+        text.parse()
+            .map(Json::Float)
+            .map_err(|_| self.error("bad number"))
+    }
+
+    fn hex4(&mut self) -> Result<u32> {
+        let digits = self
+            .src
+            .get(self.pos..self.pos + 4)
+            .and_then(|d| std::str::from_utf8(d).ok())
+            .and_then(|d| u32::from_str_radix(d, 16).ok())
+            .ok_or_else(|| self.error("bad \\u escape"))?;
+        self.pos += 4;
+        Ok(digits)
+    }
+
+    fn string(&mut self) -> Result<String> {
+        self.pos += 1;
+        let mut out = Vec::new();
+        loop {
+            let b = *self
+                .src
+                .get(self.pos)
+                .ok_or_else(|| self.error("unterminated string"))?;
+            self.pos += 1;
+            match b {
+                b'"' => break,
+                b'\\' => {
+                    let escape = *self
+                        .src
+                        .get(self.pos)
+                        .ok_or_else(|| self.error("unterminated string"))?;
+                    self.pos += 1;
+                    let c = match escape {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => {
+                            let mut code = self.hex4()?;
+                            if (0xd800..0xdc00).contains(&code)
+                                && self.src[self.pos..].starts_with(b"\\u")
+                            {
+                                self.pos += 2;
+                                let low = self.hex4()?;
+                                code = 0x10000
+                                    + ((code - 0xd800) << 10)
+                                    + (low.wrapping_sub(0xdc00) & 0x3ff);
+                            }
+                            char::from_u32(code).unwrap_or('\u{fffd}')
+                        }
+                        _ => return Err(self.error("bad escape")),
+                    };
+                    let mut buf = [0; 4];
+                    out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                }
+                b => out.push(b),
+            }
+        }
+        String::from_utf8(out).map_err(|_| self.error("invalid UTF-8"))
+    }
+}
+
+fn base64(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, b)| n | u32::from(*b) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64_ALPHABET[(n >> (18 - 6 * i)) as usize & 63] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_bencode;
+
+    #[test]
+    fn test_value_to_json() {
+        let value = parse_bencode(&mut &b"d4:hash3:\xff\x00\x014:listli1e3:abcee"[..])
+            .unwrap()
+            .unwrap();
+        let json = Json::from(&value);
+        let mut expected = BTreeMap::new();
+        expected.insert("hash".to_string(), Json::String("ff0001".into()));
+        expected.insert(
+            "list".to_string(),
+            Json::Array(vec![Json::Int(1), Json::String("abc".into())]),
+        );
+        assert_eq!(json, Json::Object(expected));
+
+        let bytes = Value::Bytes(vec![0xff, 0, 1, 2]);
+        assert_eq!(
+            Json::from_value(&bytes, BinaryPolicy::Base64),
+            Json::String("/wABAg==".into())
+        );
+        assert_eq!(
+            Json::from_value(&bytes, BinaryPolicy::Lossy),
+            Json::String("\u{fffd}\u{0}\u{1}\u{2}".into())
+        );
+        assert_eq!(base64(b"ab"), "YWI=");
+    }
+
+    #[test]
+    fn test_json_text_roundtrip() {
+        let text = r#" {"a": [1, -2.5, true, null, "q\"\u00e9\ud83d\ude00\n"], "b": {}, "c": []} "#;
+        let json = Json::parse(text).unwrap();
+        assert_eq!(
+            json.to_string(),
+            "{\"a\":[1,-2.5,true,null,\"q\\\"é😀\\n\"],\"b\":{},\"c\":[]}"
+        );
+        assert_eq!(Json::parse(&json.to_pretty_string()).unwrap(), json);
+        assert_eq!(
+            Json::parse("{\"k\":[1]}").unwrap().to_pretty_string(),
+            "{\n  \"k\": [\n    1\n  ]\n}"
+        );
+        for bad in ["", "[1,]", "{\"a\" 1}", "tru", "\"abc", "1 2"] {
+            assert!(Json::parse(bad).is_err(), "{}", bad);
+        }
+
+        let value = from_json_str(r#"{"n": 12, "s": "x"}"#).unwrap();
+        assert_eq!(
+            to_json_string(&value, BinaryPolicy::Hex),
+            r#"{"n":12,"s":"x"}"#
+        );
+        assert!(from_json_str("[0.5]").is_err());
+    }
+
+    #[test]
+    fn test_json_to_value() {
+        let value = Value::Map(HMap(
+            vec![(Value::from("a"), Value::from(vec![Value::from(1i64)]))]
+                .into_iter()
+                .collect(),
+        ));
+        assert_eq!(Value::try_from(Json::from(&value)).unwrap(), value);
+        assert_eq!(Value::try_from(Json::Bool(true)).unwrap(), Value::Int(1));
+        assert_eq!(Value::try_from(Json::Float(3.0)).unwrap(), Value::Int(3));
+        assert!(Value::try_from(Json::Float(0.5)).is_err());
+        assert!(Value::try_from(Json::Array(vec![Json::Null])).is_err());
+
+        let deep = from_json_str(&"[".repeat(100_000)).unwrap_err();
+        assert_eq!(deep.kind(), Some(ErrorKind::DepthLimit(MAX_DEPTH)));
+        let shallow = format!("{}{}", "[".repeat(MAX_DEPTH), "]".repeat(MAX_DEPTH));
+        assert!(from_json_str(&shallow).is_ok());
+
+        // binary strings come back as their rendering
+        let hash = Value::from(vec![0xff, 0x00]);
+        let json = to_json_string(&hash, BinaryPolicy::Hex);
+        assert_eq!(from_json_str(&json).unwrap(), Value::from("ff00"));
+    }
+}
@@ -0,0 +1,295 @@
+//! BEP 44 arbitrary data items stored in the DHT.
+//!
+//! Immutable items are addressed by the SHA-1 of their encoded value. Mutable
+//! items are addressed by an ed25519 public key (and optional salt) and carry
+//! a signature over their salt, sequence number and value. This crate does no
+//! ed25519 itself: signing and verification go through the [`Signer`] and
+//! [`Verifier`] traits so any implementation can be plugged in.
+
+use super::message::{NodeId, Query, Response};
+use crate::fields::{require, DictBuilder, Fields};
+use crate::hash::{Digest, Sha1};
+use crate::{BencodeError, EncodeOptions, Result, Value};
+
+/// Largest encoded `v` a node will store.
+pub const MAX_VALUE_LEN: usize = 1000;
+/// Largest `salt` a node will store.
+pub const MAX_SALT_LEN: usize = 64;
+
+/// Produces ed25519 signatures for mutable items.
+pub trait Signer {
+    fn public_key(&self) -> [u8; 32];
+
+    fn sign(&self, message: &[u8]) -> [u8; 64];
+}
+
+/// Checks ed25519 signatures on mutable items.
+pub trait Verifier {
+    fn verify(&self, public_key: &[u8; 32], message: &[u8], signature: &[u8; 64]) -> bool;
+}
+
+/// An item stored under the hash of its value.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImmutableItem {
+    pub value: Value,
+}
+
+/// A signed item stored under its author's public key and salt.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MutableItem {
+    pub public_key: [u8; 32],
+    pub salt: Vec<u8>,
+    pub seq: i64,
+    pub value: Value,
+    pub signature: [u8; 64],
+}
+
+impl ImmutableItem {
+    pub fn new(value: Value) -> Result<Self> {
+        encode_value(&value)?;
+        Ok(ImmutableItem { value })
+    }
+
+    /// DHT key of the item: SHA-1 of the encoded value.
+    pub fn target(&self) -> Result<[u8; 20]> {
+        Ok(Sha1::digest(&encode_value(&self.value)?))
+    }
+
+    /// Arguments of a `put` query storing this item.
+    pub fn put_query(&self, id: NodeId, token: &[u8]) -> Query {
+        let args = DictBuilder::new()
+            .set("id", id.0.to_vec())
+            .set("token", token.to_vec())
+            .set("v", self.value.clone())
+            .build();
+        Query::Other {
+            method: "put".into(),
+            args,
+        }
+    }
+
+    /// Take the item out of a `get` response, checking it hashes to `target`.
+    pub fn from_get_response(response: &Response, target: &[u8; 20]) -> Result<Self> {
+        let value = require("v", response.extra.get("v"))?.clone();
+        let item = ImmutableItem::new(value)?;
+        if &item.target()? != target {
+            return Err(BencodeError::msg(
+                "Immutable item does not match its target",
+            ));
+        }
+        Ok(item)
+    }
+}
+
+impl MutableItem {
+    /// Sign `value` under `signer`'s key.
+    pub fn sign<S: Signer>(signer: &S, salt: &[u8], seq: i64, value: Value) -> Result<Self> {
+        let signable = signable(salt, seq, &value)?;
+        Ok(MutableItem {
+            public_key: signer.public_key(),
+            salt: salt.to_vec(),
+            seq,
+            value,
+            signature: signer.sign(&signable),
+        })
+    }
+
+    /// Check the signature and size limits.
+    pub fn verify<V: Verifier>(&self, verifier: &V) -> Result<()> {
+        let signable = signable(&self.salt, self.seq, &self.value)?;
+        if verifier.verify(&self.public_key, &signable, &self.signature) {
+            Ok(())
+        } else {
+            Err(BencodeError::msg("Invalid mutable item signature"))
+        }
+    }
+
+    /// DHT key of the item: SHA-1 of the public key followed by the salt.
+    pub fn target(&self) -> [u8; 20] {
+        mutable_target(&self.public_key, &self.salt)
+    }
+
+    /// Arguments of a `put` query storing this item. With `cas`, the store
+    /// only succeeds if the current sequence number equals it.
+    pub fn put_query(&self, id: NodeId, token: &[u8], cas: Option<i64>) -> Query {
+        let salt = if self.salt.is_empty() {
+            None
+        } else {
+            Some(self.salt.clone())
+        };
+        let args = DictBuilder::new()
+            .set("id", id.0.to_vec())
+            .set("token", token.to_vec())
+            .set("k", self.public_key.to_vec())
+            .opt("salt", salt)
+            .set("seq", self.seq)
+            .opt("cas", cas)
+            .set("sig", self.signature.to_vec())
+            .set("v", self.value.clone())
+            .build();
+        Query::Other {
+            method: "put".into(),
+            args,
+        }
+    }
+
+    /// Take the item out of a `get` response for `salt` and verify it.
+    pub fn from_get_response<V: Verifier>(
+        response: &Response,
+        salt: &[u8],
+        verifier: &V,
+    ) -> Result<Self> {
+        let extra = DictBuilder::new().extend(&response.extra).build();
+        let fields = Fields::new(&extra)?;
+        let item = MutableItem {
+            public_key: fixed(require("k", fields.bytes("k")?)?, "k")?,
+            salt: salt.to_vec(),
+            seq: require("seq", fields.int("seq")?)?,
+            value: require("v", fields.get("v"))?.clone(),
+            signature: fixed(require("sig", fields.bytes("sig")?)?, "sig")?,
+        };
+        item.verify(verifier)?;
+        Ok(item)
+    }
+}
+
+/// DHT key of a mutable item with `public_key` and `salt`.
+pub fn mutable_target(public_key: &[u8; 32], salt: &[u8]) -> [u8; 20] {
+    let mut sha = Sha1::new();
+    sha.update(public_key);
+    sha.update(salt);
+    sha.finalize()
+}
+
+/// The bytes a mutable item's signature covers: the `salt`, `seq` and `v`
+/// entries as they would appear in a sorted dictionary, without the
+/// surrounding `d` and `e`.
+pub fn signable(salt: &[u8], seq: i64, value: &Value) -> Result<Vec<u8>> {
+    if salt.len() > MAX_SALT_LEN {
+        return Err(BencodeError::msg(format!(
+            "Salt of {} bytes exceeds {}",
+            salt.len(),
+            MAX_SALT_LEN
+        )));
+    }
+    let mut buf = Vec::new();
+    let mut entries = Vec::new();
+    if !salt.is_empty() {
+        entries.push(Value::from("salt"));
+        entries.push(Value::from(salt.to_vec()));
+    }
+    entries.push(Value::from("seq"));
+    entries.push(Value::Int(seq));
+    entries.push(Value::from("v"));
+    for entry in &entries {
+        entry.write_bencode(&mut buf)?;
+    }
+    buf.extend_from_slice(&encode_value(value)?);
+    Ok(buf)
+}
+
+/// Canonical encoding of `v`, enforcing the size limit.
+fn encode_value(value: &Value) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    value.write_bencode_with(&mut buf, EncodeOptions::strict())?;
+    if buf.len() > MAX_VALUE_LEN {
+        return Err(BencodeError::msg(format!(
+            "Item value of {} bytes exceeds {}",
+            buf.len(),
+            MAX_VALUE_LEN
+        )));
+    }
+    Ok(buf)
+}
+
+fn fixed<const N: usize>(bytes: &[u8], key: &str) -> Result<[u8; N]> {
+    let mut out = [0; N];
+    if bytes.len() != N {
+        return Err(
+            BencodeError::msg(format!("expected {} bytes, found {}", N, bytes.len())).in_field(key),
+        );
+    }
+    out.copy_from_slice(bytes);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Stand-in "signature": the SHA-1 of key and message, padded. Enough to
+    // check that the right bytes are signed and verified.
+    struct FakeKey;
+
+    impl Signer for FakeKey {
+        fn public_key(&self) -> [u8; 32] {
+            [7; 32]
+        }
+
+        fn sign(&self, message: &[u8]) -> [u8; 64] {
+            let mut sig = [0; 64];
+            sig[..20].copy_from_slice(&Sha1::digest(message));
+            sig
+        }
+    }
+
+    impl Verifier for FakeKey {
+        fn verify(&self, public_key: &[u8; 32], message: &[u8], signature: &[u8; 64]) -> bool {
+            public_key == &self.public_key() && signature == &self.sign(message)
+        }
+    }
+
+    #[test]
+    fn test_signable_matches_bep44_example() {
+        let value = Value::from("Hello World!");
+        assert_eq!(
+            signable(b"", 1, &value).unwrap(),
+            b"3:seqi1e1:v12:Hello World!"
+        );
+        assert_eq!(
+            signable(b"foobar", 1, &value).unwrap(),
+            b"4:salt6:foobar3:seqi1e1:v12:Hello World!"
+        );
+        assert!(signable(&[0; 65], 1, &value).is_err());
+        assert!(ImmutableItem::new(Value::from(vec![0u8; MAX_VALUE_LEN])).is_err());
+    }
+
+    #[test]
+    fn test_mutable_item_put_and_get() {
+        let item = MutableItem::sign(&FakeKey, b"salt", 3, Value::from("data")).unwrap();
+        item.verify(&FakeKey).unwrap();
+
+        // what a storing node would send back for a `get`
+        let query = item.put_query(NodeId([1; 20]), b"tok", None);
+        let mut response = Response::new(NodeId([2; 20]));
+        if let Query::Other {
+            args: Value::Map(args),
+            ..
+        } = &query
+        {
+            for key in &["k", "seq", "sig", "v"] {
+                let value = args.get(&Value::from(*key)).unwrap().clone();
+                response.extra.insert(key.to_string(), value);
+            }
+        }
+        let fetched = MutableItem::from_get_response(&response, b"salt", &FakeKey).unwrap();
+        assert_eq!(fetched, item);
+        assert_eq!(fetched.target(), mutable_target(&[7; 32], b"salt"));
+        assert!(MutableItem::from_get_response(&response, b"other", &FakeKey).is_err());
+    }
+
+    #[test]
+    fn test_immutable_item_target() {
+        let item = ImmutableItem::new(Value::from("Hello World!")).unwrap();
+        let target = item.target().unwrap();
+        assert_eq!(target, Sha1::digest(b"12:Hello World!"));
+
+        let mut response = Response::new(NodeId([2; 20]));
+        response.extra.insert("v".into(), item.value.clone());
+        assert_eq!(
+            ImmutableItem::from_get_response(&response, &target).unwrap(),
+            item
+        );
+        assert!(ImmutableItem::from_get_response(&response, &[0; 20]).is_err());
+    }
+}
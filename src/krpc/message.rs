@@ -0,0 +1,490 @@
+use super::node;
+use crate::fields::{require, DictBuilder, Fields};
+use crate::tracker::compact;
+use crate::{parse_bencode, BencodeError, EncodeOptions, FromBencode, Result, ToBencode, Value};
+use std::collections::HashMap;
+use std::fmt;
+use std::net::{SocketAddr, SocketAddrV4, SocketAddrV6};
+
+const RESPONSE_FIELDS: &[&str] = &["id", "nodes", "nodes6", "values", "token"];
+
+/// 160-bit identifier of a DHT node.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(pub [u8; 20]);
+
+impl NodeId {
+    /// XOR distance to `other`, the DHT's routing metric. Compare results
+    /// with `Ord` to find the closer node.
+    pub fn distance(&self, other: &NodeId) -> NodeId {
+        let mut out = [0; 20];
+        for (o, (a, b)) in out.iter_mut().zip(self.0.iter().zip(other.0.iter())) {
+            *o = a ^ b;
+        }
+        NodeId(out)
+    }
+}
+
+impl fmt::Debug for NodeId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "NodeId({})", crate::hash::to_hex(&self.0))
+    }
+}
+
+/// A KRPC packet.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Message {
+    /// `t`: chosen by the querying node and echoed in the reply.
+    pub transaction_id: Vec<u8>,
+    /// `v`: optional client version string.
+    pub version: Option<Vec<u8>>,
+    pub body: Body,
+}
+
+/// What a [`Message`] is, from its `y` key.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Body {
+    Query(Query),
+    Response(Response),
+    Error(KrpcError),
+}
+
+/// A query (`y` = `q`) with its arguments.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Query {
+    Ping {
+        id: NodeId,
+    },
+    FindNode {
+        id: NodeId,
+        target: NodeId,
+    },
+    GetPeers {
+        id: NodeId,
+        info_hash: [u8; 20],
+    },
+    AnnouncePeer {
+        id: NodeId,
+        info_hash: [u8; 20],
+        port: u16,
+        /// Use the UDP source port instead of `port`.
+        implied_port: bool,
+        token: Vec<u8>,
+    },
+    /// Any other method, with its raw arguments dictionary.
+    Other {
+        method: String,
+        args: Value,
+    },
+}
+
+/// A response (`y` = `r`). Which fields are set depends on the query it
+/// answers.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Response {
+    pub id: NodeId,
+    /// Nodes close to the target, from `nodes` and `nodes6` alike.
+    pub nodes: Vec<(NodeId, SocketAddr)>,
+    /// Peers for a `get_peers` query.
+    pub values: Vec<SocketAddrV4>,
+    /// Write token to present in a later `announce_peer`.
+    pub token: Option<Vec<u8>>,
+    pub extra: HashMap<String, Value>,
+}
+
+/// An error (`y` = `e`): a code and a human-readable message.
+#[derive(Clone, Debug, PartialEq)]
+pub struct KrpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl KrpcError {
+    pub const GENERIC: i64 = 201;
+    pub const SERVER: i64 = 202;
+    pub const PROTOCOL: i64 = 203;
+    pub const METHOD_UNKNOWN: i64 = 204;
+
+    pub fn new(code: i64, message: &str) -> Self {
+        KrpcError {
+            code,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl Response {
+    pub fn new(id: NodeId) -> Self {
+        Response {
+            id,
+            nodes: Vec::new(),
+            values: Vec::new(),
+            token: None,
+            extra: HashMap::new(),
+        }
+    }
+}
+
+impl Query {
+    /// The `q` method name.
+    pub fn method(&self) -> &str {
+        match self {
+            Query::Ping { .. } => "ping",
+            Query::FindNode { .. } => "find_node",
+            Query::GetPeers { .. } => "get_peers",
+            Query::AnnouncePeer { .. } => "announce_peer",
+            Query::Other { method, .. } => method,
+        }
+    }
+
+    /// The querying node's id, if the arguments carry one.
+    pub fn id(&self) -> Option<NodeId> {
+        match self {
+            Query::Ping { id }
+            | Query::FindNode { id, .. }
+            | Query::GetPeers { id, .. }
+            | Query::AnnouncePeer { id, .. } => Some(*id),
+            Query::Other { args, .. } => Fields::new(args)
+                .ok()
+                .and_then(|f| hash20(&f, "id").ok())
+                .map(NodeId),
+        }
+    }
+
+    fn from_args(method: &str, args: &Value) -> Result<Query> {
+        let fields = Fields::new(args)?;
+        let id = || hash20(&fields, "id").map(NodeId);
+        Ok(match method {
+            "ping" => Query::Ping { id: id()? },
+            "find_node" => Query::FindNode {
+                id: id()?,
+                target: NodeId(hash20(&fields, "target")?),
+            },
+            "get_peers" => Query::GetPeers {
+                id: id()?,
+                info_hash: hash20(&fields, "info_hash")?,
+            },
+            "announce_peer" => {
+                let port = require("port", fields.uint("port")?)?;
+                if port > u64::from(u16::MAX) {
+                    return Err(
+                        BencodeError::msg(format!("{} is out of range", port)).in_field("port")
+                    );
+                }
+                Query::AnnouncePeer {
+                    id: id()?,
+                    info_hash: hash20(&fields, "info_hash")?,
+                    port: port as u16,
+                    implied_port: fields.int("implied_port")? == Some(1),
+                    token: require("token", fields.bytes("token")?)?.to_vec(),
+                }
+            }
+            _ => Query::Other {
+                method: method.to_string(),
+                args: args.clone(),
+            },
+        })
+    }
+
+    fn args(&self) -> Value {
+        let builder = DictBuilder::new();
+        match self {
+            Query::Ping { id } => builder.set("id", id.0.to_vec()).build(),
+            Query::FindNode { id, target } => builder
+                .set("id", id.0.to_vec())
+                .set("target", target.0.to_vec())
+                .build(),
+            Query::GetPeers { id, info_hash } => builder
+                .set("id", id.0.to_vec())
+                .set("info_hash", info_hash.to_vec())
+                .build(),
+            Query::AnnouncePeer {
+                id,
+                info_hash,
+                port,
+                implied_port,
+                token,
+            } => builder
+                .set("id", id.0.to_vec())
+                .opt("implied_port", if *implied_port { Some(1) } else { None })
+                .set("info_hash", info_hash.to_vec())
+                .set("port", i64::from(*port))
+                .set("token", token.clone())
+                .build(),
+            Query::Other { args, .. } => args.clone(),
+        }
+    }
+}
+
+impl Message {
+    pub fn query(transaction_id: &[u8], query: Query) -> Self {
+        Message::new(transaction_id, Body::Query(query))
+    }
+
+    pub fn response(transaction_id: &[u8], response: Response) -> Self {
+        Message::new(transaction_id, Body::Response(response))
+    }
+
+    pub fn error(transaction_id: &[u8], error: KrpcError) -> Self {
+        Message::new(transaction_id, Body::Error(error))
+    }
+
+    fn new(transaction_id: &[u8], body: Body) -> Self {
+        Message {
+            transaction_id: transaction_id.to_vec(),
+            version: None,
+            body,
+        }
+    }
+
+    /// Decode a UDP datagram.
+    pub fn from_bytes(packet: &[u8]) -> Result<Message> {
+        match parse_bencode(&mut &packet[..])? {
+            Some(value) => Message::from_value(&value),
+            None => Err(BencodeError::msg("Empty KRPC packet")),
+        }
+    }
+
+    /// Encode as a UDP datagram, keys sorted. Fails if a value in a
+    /// response's `extra` holds a dictionary with a non-string key.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        self.to_value()
+            .write_bencode_with(&mut buf, EncodeOptions::strict())?;
+        Ok(buf)
+    }
+}
+
+impl FromBencode for Message {
+    fn from_value(value: &Value) -> Result<Self> {
+        let fields = Fields::new(value)?;
+        let transaction_id = require("t", fields.bytes("t")?)?.to_vec();
+        let kind = require("y", fields.string("y")?)?;
+        let body = match kind.as_str() {
+            "q" => {
+                let method = require("q", fields.string("q")?)?;
+                let args = require("a", fields.get("a"))?;
+                Body::Query(Query::from_args(&method, args)?)
+            }
+            "r" => Body::Response(Response::from_value(require("r", fields.get("r"))?)?),
+            "e" => {
+                let error = require("e", fields.list("e")?)?;
+                match error {
+                    [Value::Int(code), message] if message.as_bytes().is_some() => {
+                        Body::Error(KrpcError {
+                            code: *code,
+                            message: message.to_string(),
+                        })
+                    }
+                    _ => return Err(BencodeError::msg("expected [code, message]").in_field("e")),
+                }
+            }
+            other => {
+                return Err(
+                    BencodeError::msg(format!("unknown message type `{}`", other)).in_field("y"),
+                )
+            }
+        };
+        Ok(Message {
+            transaction_id,
+            version: fields.bytes("v")?.map(<[u8]>::to_vec),
+            body,
+        })
+    }
+}
+
+impl ToBencode for Message {
+    fn to_value(&self) -> Value {
+        let builder = DictBuilder::new()
+            .set("t", self.transaction_id.clone())
+            .opt("v", self.version.clone());
+        match &self.body {
+            Body::Query(query) => builder
+                .set("y", "q")
+                .set("q", query.method())
+                .set("a", query.args()),
+            Body::Response(response) => builder.set("y", "r").set("r", response.to_value()),
+            Body::Error(error) => builder.set("y", "e").set(
+                "e",
+                vec![Value::Int(error.code), Value::from(error.message.as_str())],
+            ),
+        }
+        .build()
+    }
+}
+
+impl FromBencode for Response {
+    fn from_value(value: &Value) -> Result<Self> {
+        let fields = Fields::new(value)?;
+        let values = match fields.list("values")? {
+            None => Vec::new(),
+            Some(values) => {
+                let mut peers = Vec::new();
+                for value in values {
+                    match value.as_bytes() {
+                        Some(bytes) => peers.extend(compact::decode_peers(bytes)?),
+                        None => {
+                            return Err(
+                                BencodeError::msg("expected a list of strings").in_field("values")
+                            )
+                        }
+                    }
+                }
+                peers
+            }
+        };
+        let mut nodes: Vec<(NodeId, SocketAddr)> = Vec::new();
+        if let Some(bytes) = fields.bytes("nodes")? {
+            let v4 = node::decode_nodes(bytes)?;
+            nodes.extend(v4.into_iter().map(|(id, a)| (id, SocketAddr::V4(a))));
+        }
+        if let Some(bytes) = fields.bytes("nodes6")? {
+            let v6 = node::decode_nodes6(bytes)?;
+            nodes.extend(v6.into_iter().map(|(id, a)| (id, SocketAddr::V6(a))));
+        }
+        Ok(Response {
+            id: NodeId(hash20(&fields, "id")?),
+            nodes,
+            values,
+            token: fields.bytes("token")?.map(<[u8]>::to_vec),
+            extra: fields.rest(RESPONSE_FIELDS),
+        })
+    }
+}
+
+impl ToBencode for Response {
+    fn to_value(&self) -> Value {
+        let values = if self.values.is_empty() {
+            None
+        } else {
+            Some(Value::List(
+                self.values
+                    .iter()
+                    .map(|peer| Value::from(compact::encode_peers(&[*peer])))
+                    .collect(),
+            ))
+        };
+        let mut v4: Vec<(NodeId, SocketAddrV4)> = Vec::new();
+        let mut v6: Vec<(NodeId, SocketAddrV6)> = Vec::new();
+        for (id, addr) in &self.nodes {
+            match addr {
+                SocketAddr::V4(addr) => v4.push((*id, *addr)),
+                SocketAddr::V6(addr) => v6.push((*id, *addr)),
+            }
+        }
+        let nodes = if v4.is_empty() {
+            None
+        } else {
+            Some(node::encode_nodes(&v4))
+        };
+        let nodes6 = if v6.is_empty() {
+            None
+        } else {
+            Some(node::encode_nodes6(&v6))
+        };
+        DictBuilder::new()
+            .set("id", self.id.0.to_vec())
+            .opt("nodes", nodes)
+            .opt("nodes6", nodes6)
+            .opt("values", values)
+            .opt("token", self.token.clone())
+            .extend(&self.extra)
+            .build()
+    }
+}
+
+/// Hands out short transaction ids for outgoing queries. Ids are two bytes,
+/// as most DHT implementations use, and wrap around after 65536 queries.
+#[derive(Clone, Debug, Default)]
+pub struct TransactionIds {
+    next: u16,
+}
+
+impl TransactionIds {
+    pub fn new() -> Self {
+        TransactionIds::default()
+    }
+
+    pub fn next_id(&mut self) -> [u8; 2] {
+        let id = self.next;
+        self.next = self.next.wrapping_add(1);
+        id.to_be_bytes()
+    }
+}
+
+/// A required 20-byte id or hash field.
+fn hash20(fields: &Fields, key: &str) -> Result<[u8; 20]> {
+    let bytes = require(key, fields.bytes(key)?)?;
+    if bytes.len() != 20 {
+        return Err(
+            BencodeError::msg(format!("expected 20 bytes, found {}", bytes.len())).in_field(key),
+        );
+    }
+    let mut out = [0; 20];
+    out.copy_from_slice(bytes);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn test_ping_query_from_bep5() {
+        let packet = b"d1:ad2:id20:abcdefghij0123456789e1:q4:ping1:t2:aa1:y1:qe";
+        let msg = Message::from_bytes(packet).unwrap();
+        assert_eq!(msg.transaction_id, b"aa");
+        match &msg.body {
+            Body::Query(Query::Ping { id }) => assert_eq!(&id.0, b"abcdefghij0123456789"),
+            other => panic!("expected ping, got {:?}", other),
+        }
+        assert_eq!(msg.to_bytes().unwrap(), packet);
+    }
+
+    #[test]
+    fn test_announce_peer_and_get_peers_response() {
+        let query = Message::query(
+            b"xy",
+            Query::AnnouncePeer {
+                id: NodeId([1; 20]),
+                info_hash: [2; 20],
+                port: 6881,
+                implied_port: true,
+                token: b"tok".to_vec(),
+            },
+        );
+        assert_eq!(
+            Message::from_bytes(&query.to_bytes().unwrap()).unwrap(),
+            query
+        );
+
+        let mut response = Response::new(NodeId([3; 20]));
+        response.token = Some(b"tok".to_vec());
+        response.nodes = vec![(NodeId([4; 20]), "[::1]:5".parse().unwrap())];
+        response.values = vec![SocketAddrV4::new(Ipv4Addr::new(1, 2, 3, 4), 80)];
+        let msg = Message::response(b"xy", response);
+        let bytes = msg.to_bytes().unwrap();
+        // each peer is its own 6-byte string in the `values` list
+        assert!(bytes.windows(11).any(|w| w == b"6:valuesl6:"));
+        assert_eq!(Message::from_bytes(&bytes).unwrap(), msg);
+    }
+
+    #[test]
+    fn test_error_message_and_transaction_ids() {
+        let packet = b"d1:eli201e23:A Generic Error Ocurrede1:t2:aa1:y1:ee";
+        let msg = Message::from_bytes(packet).unwrap();
+        assert_eq!(
+            msg.body,
+            Body::Error(KrpcError::new(
+                KrpcError::GENERIC,
+                "A Generic Error Ocurred"
+            ))
+        );
+        assert_eq!(msg.to_bytes().unwrap(), packet);
+        assert!(Message::from_bytes(b"d1:t2:aa1:y1:xe").is_err());
+
+        let mut ids = TransactionIds::new();
+        assert_eq!(ids.next_id(), [0, 0]);
+        assert_eq!(ids.next_id(), [0, 1]);
+    }
+}
@@ -0,0 +1,12 @@
+//! DHT KRPC messages (BEP 5).
+//!
+//! Every KRPC packet is a single bencoded dictionary sent over UDP. A
+//! [`Message`] carries the transaction id the querying node chose, which the
+//! responding node echoes back, and a [`Body`] that is a query, a response or
+//! an error.
+
+pub mod item;
+mod message;
+pub mod node;
+
+pub use message::{Body, KrpcError, Message, NodeId, Query, Response, TransactionIds};
@@ -0,0 +1,101 @@
+//! Compact node info: the packed `nodes` and `nodes6` strings of KRPC
+//! responses.
+
+use super::message::NodeId;
+use crate::tracker::compact;
+use crate::{BencodeError, Result};
+use std::net::{SocketAddrV4, SocketAddrV6};
+
+/// Node id followed by a compact IPv4 address and port.
+const NODE_V4_LEN: usize = 26;
+/// Node id followed by a compact IPv6 address and port.
+const NODE_V6_LEN: usize = 38;
+
+/// Decode `nodes`: a 20-byte id and 6-byte compact address per node.
+pub fn decode_nodes(bytes: &[u8]) -> Result<Vec<(NodeId, SocketAddrV4)>> {
+    check_len(bytes, NODE_V4_LEN, "nodes")?;
+    bytes
+        .chunks_exact(NODE_V4_LEN)
+        .map(|c| Ok((node_id(&c[..20]), compact::decode_peers(&c[20..])?[0])))
+        .collect()
+}
+
+/// Encode nodes into the `nodes` format.
+pub fn encode_nodes(nodes: &[(NodeId, SocketAddrV4)]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(nodes.len() * NODE_V4_LEN);
+    for (id, addr) in nodes {
+        out.extend_from_slice(&id.0);
+        out.extend_from_slice(&compact::encode_peers(&[*addr]));
+    }
+    out
+}
+
+/// Decode `nodes6` (BEP 32): a 20-byte id and 18-byte compact address per
+/// node.
+pub fn decode_nodes6(bytes: &[u8]) -> Result<Vec<(NodeId, SocketAddrV6)>> {
+    check_len(bytes, NODE_V6_LEN, "nodes6")?;
+    bytes
+        .chunks_exact(NODE_V6_LEN)
+        .map(|c| Ok((node_id(&c[..20]), compact::decode_peers6(&c[20..])?[0])))
+        .collect()
+}
+
+/// Encode nodes into the `nodes6` format.
+pub fn encode_nodes6(nodes: &[(NodeId, SocketAddrV6)]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(nodes.len() * NODE_V6_LEN);
+    for (id, addr) in nodes {
+        out.extend_from_slice(&id.0);
+        out.extend_from_slice(&compact::encode_peers6(&[*addr]));
+    }
+    out
+}
+
+fn node_id(bytes: &[u8]) -> NodeId {
+    let mut id = [0; 20];
+    id.copy_from_slice(bytes);
+    NodeId(id)
+}
+
+fn check_len(bytes: &[u8], node_len: usize, key: &str) -> Result<()> {
+    if bytes.len().is_multiple_of(node_len) {
+        Ok(())
+    } else {
+        Err(BencodeError::msg(format!(
+            "length {} is not a multiple of {}",
+            bytes.len(),
+            node_len
+        ))
+        .in_field(key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    #[test]
+    fn test_compact_nodes_roundtrip() {
+        let nodes = vec![
+            (
+                NodeId([1; 20]),
+                SocketAddrV4::new(Ipv4Addr::new(1, 2, 3, 4), 6881),
+            ),
+            (
+                NodeId([2; 20]),
+                SocketAddrV4::new(Ipv4Addr::new(5, 6, 7, 8), 80),
+            ),
+        ];
+        let bytes = encode_nodes(&nodes);
+        assert_eq!(bytes.len(), 52);
+        assert_eq!(&bytes[20..26], &[1, 2, 3, 4, 0x1a, 0xe1]);
+        assert_eq!(decode_nodes(&bytes).unwrap(), nodes);
+        assert!(decode_nodes(&bytes[..30]).is_err());
+
+        let nodes6 = vec![(
+            NodeId([3; 20]),
+            SocketAddrV6::new(Ipv6Addr::LOCALHOST, 1, 0, 0),
+        )];
+        assert_eq!(decode_nodes6(&encode_nodes6(&nodes6)).unwrap(), nodes6);
+    }
+}
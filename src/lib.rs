@@ -1,54 +1,445 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+use crate::prelude::*;
+use alloc::collections::BTreeMap;
+use core::cmp::Ordering;
+use core::convert::TryFrom;
+use core::fmt::{self, Display};
+use core::hash::{Hash, Hasher};
+#[cfg(feature = "std")]
 use std::collections::HashMap;
-use std::convert::TryInto;
-use std::fmt::{self, Display};
-use std::hash::{Hash, Hasher};
-use std::io::BufRead;
-use std::iter::Iterator;
+#[cfg(feature = "std")]
+use std::io::{self, BufRead, Read};
+#[cfg(feature = "std")]
 use std::str::FromStr;
-use std::string::ToString;
 
-type Result<T> = std::result::Result<T, BencodeError>;
+#[cfg(feature = "cbor")]
+pub mod cbor;
+#[cfg(feature = "std")]
+pub mod codec;
+pub mod combinator;
+#[cfg(feature = "std")]
+pub mod conformance;
+pub mod conversions;
+mod diagnostic;
+#[cfg(feature = "std")]
+pub mod differential;
+pub mod document;
+mod edn;
+mod encode;
+#[cfg(feature = "std")]
+pub mod extension;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+mod fields;
+#[cfg(feature = "std")]
+pub mod frame;
+#[cfg(feature = "std")]
+pub mod generator;
+pub mod hash;
+#[cfg(feature = "json")]
+pub mod json;
+#[cfg(feature = "std")]
+pub mod krpc;
+#[cfg(feature = "msgpack")]
+pub mod msgpack;
+#[cfg(feature = "std")]
+pub mod net;
+#[cfg(feature = "std")]
+pub mod nrepl;
+#[cfg(feature = "std")]
+mod percent;
+#[cfg(feature = "std")]
+pub mod resume;
+#[cfg(feature = "std")]
+pub mod rpc;
+#[cfg(feature = "std")]
+pub mod schema;
+#[cfg(feature = "std")]
+pub mod testing;
+#[cfg(feature = "std")]
+pub mod torrent;
+#[cfg(feature = "std")]
+pub mod tracker;
+#[cfg(feature = "std")]
+pub mod transport;
+#[cfg(feature = "std")]
+pub mod websocket;
+#[cfg(feature = "std")]
+pub mod writer;
+
+pub use document::Document;
+#[cfg(feature = "std")]
+pub use encode::to_writer_limited;
+pub use encode::{encode_int_into, encode_str_into, EncodeOptions};
+
+/// What the prelude would provide with `std`.
+mod prelude {
+    pub(crate) use alloc::string::{String, ToString};
+    pub(crate) use alloc::vec::Vec;
+    pub(crate) use alloc::{format, vec};
+}
+
+type Result<T> = core::result::Result<T, BencodeError>;
 
 #[derive(Debug)]
 pub enum BencodeError {
-    Error(String),
+    /// Malformed input, or a value that does not have the shape asked for.
+    Error {
+        kind: ErrorKind,
+        /// Byte offset in the input, where known.
+        position: Option<usize>,
+        /// Where in the value the problem is, as in `info.files[3].length`;
+        /// empty at the top level or when not known.
+        path: String,
+    },
+    #[cfg(feature = "std")]
     Io(std::io::Error),
     Eof(),
-    Parse(std::num::ParseIntError),
+    Parse(core::num::ParseIntError),
+    /// An incoming message grew past the decoder's size limit (in bytes).
+    FrameTooLarge(usize),
+}
+
+/// What went wrong in a [`BencodeError::Error`]. More kinds may be added,
+/// so matches on it need a wildcard arm.
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// A byte that cannot appear at this point.
+    InvalidCharacter(u8),
+    /// The input ended inside a value. Readers that see a stream close
+    /// mid-value report this as [`BencodeError::Eof`] instead; see
+    /// [`BencodeError::kind`].
+    UnexpectedEof,
+    /// Lists and dictionaries nested deeper than the parser allows.
+    DepthLimit(usize),
+    /// A string length prefix that is malformed or too large.
+    InvalidLength,
+    /// An integer that is malformed or does not fit in an `i64`.
+    InvalidInteger,
+    /// A dictionary key that appears more than once.
+    DuplicateKey(String),
+    /// A dictionary key that is not a string.
+    NonStringKey,
+    /// Bytes left over after a complete value.
+    Trailing,
+    /// A required dictionary key is absent.
+    MissingField(String),
+    /// A value has another type than the one asked for.
+    WrongType {
+        expected: &'static str,
+        found: &'static str,
+    },
+    /// Anything else, described in words.
+    Message(String),
+}
+
+impl ErrorKind {
+    /// A stable identifier for this kind, for mapping errors to metrics or
+    /// API responses without matching on messages. Codes never change
+    /// meaning once assigned; new kinds get new codes.
+    ///
+    /// | Code  | Kind |
+    /// |-------|------|
+    /// | E0101 | [`InvalidInteger`](ErrorKind::InvalidInteger) |
+    /// | E0102 | [`InvalidLength`](ErrorKind::InvalidLength) |
+    /// | E0103 | [`InvalidCharacter`](ErrorKind::InvalidCharacter) |
+    /// | E0104 | [`UnexpectedEof`](ErrorKind::UnexpectedEof) |
+    /// | E0105 | [`Trailing`](ErrorKind::Trailing) |
+    /// | E0106 | [`DepthLimit`](ErrorKind::DepthLimit) |
+    /// | E0201 | [`DuplicateKey`](ErrorKind::DuplicateKey) |
+    /// | E0202 | [`NonStringKey`](ErrorKind::NonStringKey) |
+    /// | E0301 | [`MissingField`](ErrorKind::MissingField) |
+    /// | E0302 | [`WrongType`](ErrorKind::WrongType) |
+    /// | E0901 | [`Message`](ErrorKind::Message) |
+    ///
+    /// E01xx are syntax errors, E02xx dictionaries that break the format's
+    /// rules, E03xx well-formed values of the wrong shape. See
+    /// [`BencodeError::code`] for the errors that have no kind.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ErrorKind::InvalidInteger => "E0101",
+            ErrorKind::InvalidLength => "E0102",
+            ErrorKind::InvalidCharacter(_) => "E0103",
+            ErrorKind::UnexpectedEof => "E0104",
+            ErrorKind::Trailing => "E0105",
+            ErrorKind::DepthLimit(_) => "E0106",
+            ErrorKind::DuplicateKey(_) => "E0201",
+            ErrorKind::NonStringKey => "E0202",
+            ErrorKind::MissingField(_) => "E0301",
+            ErrorKind::WrongType { .. } => "E0302",
+            ErrorKind::Message(_) => "E0901",
+        }
+    }
+}
+
+impl Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorKind::InvalidCharacter(b) => write!(f, "Unexpected byte 0x{:02x}", b),
+            ErrorKind::UnexpectedEof => write!(f, "Unexpected end of input"),
+            ErrorKind::DepthLimit(n) => write!(f, "Nesting exceeds {} levels", n),
+            ErrorKind::InvalidLength => write!(f, "Invalid string length"),
+            ErrorKind::InvalidInteger => write!(f, "Invalid integer"),
+            ErrorKind::DuplicateKey(key) => write!(f, "Duplicate dictionary key {}", key),
+            ErrorKind::NonStringKey => write!(f, "Dictionary key is not a string"),
+            ErrorKind::Trailing => write!(f, "Trailing data"),
+            ErrorKind::MissingField(key) => write!(f, "Missing field `{}`", key),
+            ErrorKind::WrongType { expected, found } => {
+                write!(f, "expected {}, found {}", expected, found)
+            }
+            ErrorKind::Message(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl BencodeError {
+    pub fn new(kind: ErrorKind) -> BencodeError {
+        BencodeError::Error {
+            kind,
+            position: None,
+            path: String::new(),
+        }
+    }
+
+    /// An [`ErrorKind::Message`] error.
+    pub fn msg<S: Into<String>>(msg: S) -> BencodeError {
+        BencodeError::new(ErrorKind::Message(msg.into()))
+    }
+
+    /// Record the byte offset the error refers to.
+    pub fn at(mut self, offset: usize) -> BencodeError {
+        if let BencodeError::Error { position, .. } = &mut self {
+            *position = Some(offset);
+        }
+        self
+    }
+
+    /// Prefix the path with the key or `[index]` segment of the value the
+    /// error happened in. Nested calls build up one path, innermost first.
+    pub fn in_field(mut self, segment: &str) -> BencodeError {
+        if let BencodeError::Error { path, .. } = &mut self {
+            if !path.is_empty() && !path.starts_with('[') {
+                path.insert(0, '.');
+            }
+            path.insert_str(0, segment);
+        }
+        self
+    }
+
+    /// The kind of error. I/O failures have none, except that running out
+    /// of input mid-value is always [`ErrorKind::UnexpectedEof`] and a
+    /// malformed number is [`ErrorKind::InvalidInteger`].
+    pub fn kind(&self) -> Option<ErrorKind> {
+        match self {
+            BencodeError::Error { kind, .. } => Some(kind.clone()),
+            BencodeError::Eof() => Some(ErrorKind::UnexpectedEof),
+            #[cfg(feature = "std")]
+            BencodeError::Io(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                Some(ErrorKind::UnexpectedEof)
+            }
+            BencodeError::Parse(_) => Some(ErrorKind::InvalidInteger),
+            #[cfg(feature = "std")]
+            BencodeError::Io(_) => None,
+            BencodeError::FrameTooLarge(_) => None,
+        }
+    }
+
+    /// The stable code of the error: that of its [`kind`](Self::kind), or
+    /// E0401 for other I/O failures and E0402 for [`FrameTooLarge`].
+    ///
+    /// [`FrameTooLarge`]: BencodeError::FrameTooLarge
+    pub fn code(&self) -> &'static str {
+        match self {
+            BencodeError::Error { kind, .. } => kind.code(),
+            BencodeError::FrameTooLarge(_) => "E0402",
+            _ => self.kind().map_or("E0401", |kind| kind.code()),
+        }
+    }
+
+    /// Byte offset in the input the error refers to, if known.
+    pub fn position(&self) -> Option<usize> {
+        match self {
+            BencodeError::Error { position, .. } => *position,
+            _ => None,
+        }
+    }
+
+    /// Path to the value the error refers to; empty if not known.
+    pub fn path(&self) -> &str {
+        match self {
+            BencodeError::Error { path, .. } => path,
+            _ => "",
+        }
+    }
 }
 
 impl Display for BencodeError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            BencodeError::Error(s) => write!(f, "Bencode Error: {} ", s),
+            BencodeError::Error {
+                kind,
+                position,
+                path,
+            } => {
+                write!(f, "Bencode Error: ")?;
+                if !path.is_empty() {
+                    write!(f, "Field `{}`: ", path)?;
+                }
+                write!(f, "{}", kind)?;
+                if let Some(offset) = position {
+                    write!(f, " at offset {}", offset)?;
+                }
+                write!(f, " ")
+            }
+            #[cfg(feature = "std")]
             BencodeError::Io(e) => write!(f, "Bencode Io: {}", e),
             BencodeError::Parse(e) => write!(f, "Bencode Parse: {}", e),
             BencodeError::Eof() => write!(f, "Bencode Eof"),
+            BencodeError::FrameTooLarge(limit) => {
+                write!(f, "Bencode frame exceeds {} bytes", limit)
+            }
         }
     }
 }
 
+#[cfg(feature = "std")]
+impl std::error::Error for BencodeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BencodeError::Io(e) => Some(e),
+            BencodeError::Parse(e) => Some(e),
+            BencodeError::Error { .. } | BencodeError::Eof() | BencodeError::FrameTooLarge(_) => {
+                None
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
 impl From<std::io::Error> for BencodeError {
     fn from(err: std::io::Error) -> BencodeError {
         BencodeError::Io(err)
     }
 }
 
-impl From<std::num::ParseIntError> for BencodeError {
-    fn from(err: std::num::ParseIntError) -> BencodeError {
+impl From<fmt::Error> for BencodeError {
+    fn from(err: fmt::Error) -> BencodeError {
+        BencodeError::msg(err.to_string())
+    }
+}
+
+impl From<core::num::ParseIntError> for BencodeError {
+    fn from(err: core::num::ParseIntError) -> BencodeError {
         BencodeError::Parse(err)
     }
 }
 
+/// The map behind [`HMap`]. It is a `BTreeMap` with or without the `std`
+/// feature, so enabling `std` never changes a public type; `HashMap`
+/// conversions are available with `std`.
+pub type Map<K, V> = BTreeMap<K, V>;
+
 #[derive(Clone, Debug, Eq)]
-pub struct HMap(pub HashMap<Value, Value>);
+pub struct HMap(pub Map<Value, Value>);
 
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug)]
 pub enum Value {
     Map(HMap),
     List(Vec<Value>),
     Str(String),
-    Int(i32),
+    /// Byte string kept as raw bytes. The parsers produce it only for
+    /// strings that are not valid UTF-8 (see [`Value::from_bytes`]), but
+    /// `From<Vec<u8>>` always does, so binary data such as a piece hash
+    /// stays binary in formats that tell the two apart, like CBOR. Compares
+    /// and hashes equal to a `Str` holding the same bytes.
+    Bytes(Vec<u8>),
+    Int(i64),
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Map(a), Value::Map(b)) => a == b,
+            (Value::List(a), Value::List(b)) => a == b,
+            (Value::Int(a), Value::Int(b)) => a == b,
+            _ => match (self.as_bytes(), other.as_bytes()) {
+                (Some(a), Some(b)) => a == b,
+                _ => false,
+            },
+        }
+    }
+}
+
+impl Eq for Value {}
+
+impl Hash for Value {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self {
+            Value::Map(hm) => {
+                state.write_u8(0);
+                hm.hash(state);
+            }
+            Value::List(v) => {
+                state.write_u8(1);
+                v.hash(state);
+            }
+            Value::Str(s) => {
+                state.write_u8(2);
+                s.as_bytes().hash(state);
+            }
+            Value::Bytes(b) => {
+                state.write_u8(2);
+                b.as_slice().hash(state);
+            }
+            Value::Int(i) => {
+                state.write_u8(3);
+                i.hash(state);
+            }
+        }
+    }
+}
+
+/// Integers sort before strings, strings before lists and lists before
+/// dictionaries. Strings compare by their bytes, so a `Str` and a `Bytes`
+/// holding the same bytes are equal here too.
+impl Ord for Value {
+    fn cmp(&self, other: &Value) -> Ordering {
+        let rank = |value: &Value| match value {
+            Value::Int(_) => 0,
+            Value::Str(_) | Value::Bytes(_) => 1,
+            Value::List(_) => 2,
+            Value::Map(_) => 3,
+        };
+        match (self, other) {
+            (Value::Int(a), Value::Int(b)) => a.cmp(b),
+            (Value::List(a), Value::List(b)) => a.cmp(b),
+            (Value::Map(a), Value::Map(b)) => a.cmp(b),
+            _ => match (self.as_bytes(), other.as_bytes()) {
+                (Some(a), Some(b)) => a.cmp(b),
+                _ => rank(self).cmp(&rank(other)),
+            },
+        }
+    }
+}
+
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Value) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Types that can be decoded from a [`Value`].
+pub trait FromBencode: Sized {
+    fn from_value(value: &Value) -> Result<Self>;
+}
+
+/// Types that can be converted into a [`Value`] for encoding.
+pub trait ToBencode {
+    fn to_value(&self) -> Value;
 }
 
 impl From<&str> for Value {
@@ -57,15 +448,41 @@ impl From<&str> for Value {
     }
 }
 
-impl From<HashMap<Value, Value>> for Value {
-    fn from(m: HashMap<Value, Value>) -> Self {
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::Str(s)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(i: i64) -> Self {
+        Value::Int(i)
+    }
+}
+
+impl From<Vec<Value>> for Value {
+    fn from(v: Vec<Value>) -> Self {
+        Value::List(v)
+    }
+}
+
+/// Always a `Bytes` value, whatever the bytes; use [`Value::from_bytes`]
+/// for `Str` when they are UTF-8.
+impl From<Vec<u8>> for Value {
+    fn from(b: Vec<u8>) -> Self {
+        Value::Bytes(b)
+    }
+}
+
+impl From<Map<Value, Value>> for Value {
+    fn from(m: Map<Value, Value>) -> Self {
         Value::Map(HMap::new(m))
     }
 }
 
-impl From<HashMap<&str, &str>> for Value {
-    fn from(map: HashMap<&str, &str>) -> Self {
-        let mut m = HashMap::new();
+impl From<Map<&str, &str>> for Value {
+    fn from(map: Map<&str, &str>) -> Self {
+        let mut m = Map::new();
         for (k, v) in map {
             m.insert(Value::Str(k.to_string()), Value::Str(v.to_string()));
         }
@@ -74,27 +491,48 @@ impl From<HashMap<&str, &str>> for Value {
     }
 }
 
-impl TryInto<HashMap<String, String>> for Value {
+impl TryFrom<Value> for Map<String, String> {
     type Error = BencodeError;
 
-    fn try_into(self) -> std::result::Result<HashMap<String, String>, Self::Error> {
-        match self {
-            Value::Map(hm) => {
-                let mut map = HashMap::<String, String>::new();
-                for key in hm.0.keys() {
-                    // safe to unwrap here
-                    map.insert(format!("{}", &key), format!("{}", &hm.get(key).unwrap()));
-                }
-                Ok(map)
-            }
-            _ => Err(BencodeError::Error("Expected HashMap Value".into())),
+    fn try_from(value: Value) -> core::result::Result<Self, Self::Error> {
+        match value {
+            Value::Map(hm) => Ok(hm
+                .0
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()),
+            other => Err(fields::expected("dictionary", &other)),
         }
     }
 }
 
+#[cfg(feature = "std")]
+impl From<HashMap<Value, Value>> for Value {
+    fn from(m: HashMap<Value, Value>) -> Self {
+        Value::Map(HMap::new(m))
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<HashMap<&str, &str>> for Value {
+    fn from(map: HashMap<&str, &str>) -> Self {
+        Value::from(map.into_iter().collect::<Map<_, _>>())
+    }
+}
+
+#[cfg(feature = "std")]
+impl TryFrom<Value> for HashMap<String, String> {
+    type Error = BencodeError;
+
+    fn try_from(value: Value) -> core::result::Result<Self, Self::Error> {
+        Map::<String, String>::try_from(value).map(|map| map.into_iter().collect())
+    }
+}
+
 impl HMap {
-    pub fn new(map: HashMap<Value, Value>) -> Self {
-        HMap(map)
+    /// Collect any map or iterator of entries, such as a `HashMap`.
+    pub fn new<I: IntoIterator<Item = (Value, Value)>>(map: I) -> Self {
+        HMap(map.into_iter().collect())
     }
 
     pub fn get(&self, key: &Value) -> Option<&Value> {
@@ -104,12 +542,23 @@ impl HMap {
 
 impl Hash for HMap {
     fn hash<H: Hasher>(&self, state: &mut H) {
-        let mut keys: Vec<String> = self.0.keys().map(|k| format!("{:?}", k)).collect();
-        let mut vals: Vec<String> = self.0.values().map(|v| format!("{:?}", v)).collect();
-        keys.sort();
-        vals.sort();
-        keys.hash(state);
-        vals.hash(state);
+        // a BTreeMap iterates in key order already
+        self.0.len().hash(state);
+        for entry in self.0.iter() {
+            entry.hash(state);
+        }
+    }
+}
+
+impl Ord for HMap {
+    fn cmp(&self, other: &HMap) -> Ordering {
+        self.0.cmp(&other.0)
+    }
+}
+
+impl PartialOrd for HMap {
+    fn partial_cmp(&self, other: &HMap) -> Option<Ordering> {
+        Some(self.cmp(other))
     }
 }
 
@@ -137,6 +586,7 @@ impl Display for Value {
                     result.push_str(&item.to_string());
                     result.push_str(", ");
                 }
+                #[allow(clippy::manual_pattern_char_comparison)]
                 let mut result = result
                     .trim_end_matches(|c| c == ',' || c == ' ')
                     .to_string();
@@ -144,97 +594,189 @@ impl Display for Value {
                 write!(f, "{}", result)
             }
             Value::Str(s) => write!(f, "{}", s),
+            Value::Bytes(b) => write!(f, "{}", String::from_utf8_lossy(b)),
             Value::Int(i) => write!(f, "{}", i),
         }
     }
 }
 
 impl Value {
-    pub fn to_bencode(&self) -> String {
+    /// Build a string value from raw bytes: `Str` when they are valid UTF-8,
+    /// `Bytes` otherwise.
+    pub fn from_bytes(bytes: Vec<u8>) -> Value {
+        match String::from_utf8(bytes) {
+            Ok(s) => Value::Str(s),
+            Err(e) => Value::Bytes(e.into_bytes()),
+        }
+    }
+
+    /// Raw bytes of a `Str` or `Bytes` value.
+    pub fn as_bytes(&self) -> Option<&[u8]> {
         match self {
-            Value::Map(hm) => {
-                let mut result = String::from("d");
-                for (key, val) in hm.0.iter() {
-                    result.push_str(&format!("{}{}", key.to_bencode(), val.to_bencode()));
-                }
-                result.push('e');
-                result
-            }
-            Value::List(v) => {
-                let mut result = String::from("l");
-                for item in v {
-                    result.push_str(&item.to_bencode());
-                }
-                result.push('e');
-                result
-            }
-            Value::Str(s) => format!("{}:{}", s.len(), s),
-            Value::Int(i) => format!("i{}e", i),
+            Value::Str(s) => Some(s.as_bytes()),
+            Value::Bytes(b) => Some(b),
+            _ => None,
         }
     }
+
+    pub fn to_bencode(&self) -> String {
+        let mut buf = Vec::new();
+        // writing into a Vec never fails
+        let _ = encode::encode(self, &mut encode::VecSink(&mut buf));
+        String::from_utf8_lossy(&buf).into_owned()
+    }
+
+    /// Encode this value into any `io::Write`, e.g. a socket or a file.
+    #[cfg(feature = "std")]
+    pub fn write_bencode<W: io::Write + ?Sized>(&self, writer: &mut W) -> Result<()> {
+        encode::encode(self, &mut encode::IoSink(writer))
+    }
+
+    /// Encode this value into any `io::Write` with explicit [`EncodeOptions`].
+    #[cfg(feature = "std")]
+    pub fn write_bencode_with<W: io::Write + ?Sized>(
+        &self,
+        writer: &mut W,
+        options: EncodeOptions,
+    ) -> Result<()> {
+        encode::encode_with(self, &mut encode::IoSink(writer), options)
+    }
+
+    /// Encode this value into an `io::Write` using vectored writes: large byte
+    /// strings are handed to the writer in place instead of being copied into
+    /// an output buffer alongside the surrounding structure.
+    #[cfg(feature = "std")]
+    pub fn write_bencode_vectored<W: io::Write + ?Sized>(&self, writer: &mut W) -> Result<()> {
+        encode::write_vectored(self, writer)
+    }
+
+    /// Encode this value into any `fmt::Write`, e.g. a `String` or a `Formatter`.
+    pub fn fmt_bencode<W: fmt::Write + ?Sized>(&self, writer: &mut W) -> Result<()> {
+        encode::encode(self, &mut encode::FmtSink(writer))
+    }
+}
+
+/// Decode one complete value from `input`. Bytes after it are an
+/// [`ErrorKind::Trailing`] error; use [`combinator::value`] to parse a value
+/// embedded in other data.
+///
+/// ```
+/// use bencode_rs::{from_slice, to_vec, Value};
+///
+/// let value = from_slice(b"d1:bi2e1:ali1eee").unwrap();
+/// assert_eq!(value.get("b"), Some(&Value::Int(2)));
+/// assert_eq!(to_vec(&value).unwrap(), b"d1:ali1ee1:bi2ee");
+/// assert!(from_slice(b"i1ei2e").is_err());
+/// ```
+pub fn from_slice(input: &[u8]) -> Result<Value> {
+    match combinator::value(input)? {
+        ([], value) => Ok(value),
+        (rest, _) => Err(BencodeError::new(ErrorKind::Trailing).at(input.len() - rest.len())),
+    }
+}
+
+/// Encode `value` canonically, with dictionary keys sorted. Values with no
+/// valid encoding, such as dictionaries with non-string keys, are an error.
+pub fn to_vec(value: &Value) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    encode::encode_with(
+        value,
+        &mut encode::VecSink(&mut buf),
+        EncodeOptions::strict(),
+    )?;
+    Ok(buf)
 }
 
+/// Read one value from `reader`; `Ok(None)` for a bare end marker. Nesting
+/// deeper than [`MAX_DEPTH`](document::MAX_DEPTH) is an error.
+#[cfg(feature = "std")]
 pub fn parse_bencode(reader: &mut dyn BufRead) -> Result<Option<Value>> {
-    let mut buf = vec![];
-    buf.resize(1, 0);
+    parse_at(reader, &mut 0, 0)
+}
+
+/// `pos` counts the bytes consumed so far, for error positions.
+#[cfg(feature = "std")]
+fn parse_at(reader: &mut dyn BufRead, pos: &mut usize, depth: usize) -> Result<Option<Value>> {
+    let start = *pos;
+    let eof = |pos: usize| BencodeError::new(ErrorKind::UnexpectedEof).at(pos);
+    let mut buf = vec![0; 1];
     match reader.read_exact(&mut buf[0..1]) {
-        Ok(()) => match buf[0] {
-            b'i' => match reader.read_until(b'e', &mut buf) {
-                Ok(cnt) => {
-                    let s = String::from_utf8_lossy(&buf[1..cnt]);
-                    let n = i32::from_str(&s)?;
+        Ok(()) => {
+            *pos += 1;
+            match buf[0] {
+                b'i' => {
+                    *pos += reader.read_until(b'e', &mut buf)?;
+                    if buf.pop() != Some(b'e') || buf.is_empty() {
+                        return Err(eof(*pos));
+                    }
+                    let n = i64::from_str(&String::from_utf8_lossy(&buf[1..]))
+                        .map_err(|_| BencodeError::new(ErrorKind::InvalidInteger).at(start))?;
                     Ok(Some(Value::Int(n)))
                 }
-                Err(e) => Err(e.into()),
-            },
-            b'd' => {
-                let mut map = HashMap::new();
-                loop {
-                    match parse_bencode(reader) {
-                        Ok(None) => return Ok(Some(Value::Map(HMap(map)))),
-                        Ok(Some(v)) => map.insert(v, parse_bencode(reader)?.unwrap()),
-                        Err(e) => return Err(e),
-                    };
+                b'd' | b'l' if depth == document::MAX_DEPTH => {
+                    Err(BencodeError::new(ErrorKind::DepthLimit(document::MAX_DEPTH)).at(start))
                 }
-            }
-            b'l' => {
-                let mut list = Vec::<Value>::new();
-                loop {
-                    match parse_bencode(reader) {
-                        Ok(None) => return Ok(Some(Value::List(list))),
-                        Ok(Some(v)) => list.push(v),
-                        Err(e) => return Err(e),
+                b'd' => {
+                    let mut map = Map::new();
+                    loop {
+                        match parse_at(reader, pos, depth + 1) {
+                            Ok(None) => return Ok(Some(Value::Map(HMap(map)))),
+                            Ok(Some(k)) => match parse_at(reader, pos, depth + 1)? {
+                                Some(v) => map.insert(k, v),
+                                // the dictionary ended where a value was due
+                                None => {
+                                    return Err(BencodeError::new(ErrorKind::InvalidCharacter(
+                                        b'e',
+                                    ))
+                                    .at(*pos - 1))
+                                }
+                            },
+                            Err(e) => return Err(e),
+                        };
                     }
                 }
-            }
-            b'e' => Ok(None),
-            b'0' => {
-                reader.read_until(b':', &mut buf)?;
-                Ok(Some(Value::Str("".to_string())))
-            }
-            _ => match reader.read_until(b':', &mut buf) {
-                Ok(_) => {
-                    buf.resize(buf.len() - 1, 0);
-                    let mut s = String::from("");
-                    buf.iter().for_each(|i| s.push(*i as char));
-                    let cnt = usize::from_str(&s)?;
-                    buf.resize(cnt, 0);
-                    reader.read_exact(&mut buf[0..cnt])?;
-                    Ok(Some(Value::Str(
-                        String::from_utf8_lossy(&buf[..]).to_string(),
-                    )))
+                b'l' => {
+                    let mut list = Vec::<Value>::new();
+                    loop {
+                        match parse_at(reader, pos, depth + 1) {
+                            Ok(None) => return Ok(Some(Value::List(list))),
+                            Ok(Some(v)) => list.push(v),
+                            Err(e) => return Err(e),
+                        }
+                    }
                 }
-                Err(e) => Err(BencodeError::Io(e)),
-            },
-        },
+                b'e' => Ok(None),
+                b'0' => {
+                    *pos += reader.read_until(b':', &mut buf)?;
+                    Ok(Some(Value::Str("".to_string())))
+                }
+                _ => {
+                    *pos += reader.read_until(b':', &mut buf)?;
+                    if buf.pop() != Some(b':') {
+                        return Err(eof(*pos));
+                    }
+                    let cnt = usize::from_str(&String::from_utf8_lossy(&buf))
+                        .map_err(|_| BencodeError::new(ErrorKind::InvalidLength).at(start))?;
+                    // read through `take` so a bogus length cannot allocate up front
+                    let mut bytes = Vec::new();
+                    Read::take(&mut *reader, cnt as u64).read_to_end(&mut bytes)?;
+                    *pos += bytes.len();
+                    if bytes.len() < cnt {
+                        return Err(eof(*pos));
+                    }
+                    Ok(Some(Value::from_bytes(bytes)))
+                }
+            }
+        }
         Err(e) => match e.kind() {
-            std::io::ErrorKind::UnexpectedEof => Err(BencodeError::Eof()),
+            std::io::ErrorKind::UnexpectedEof => Err(eof(*pos)),
             _ => Err(BencodeError::Io(e)),
         },
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
+#[allow(clippy::useless_vec)]
 mod tests {
     use super::*;
     use std::io::BufReader;
@@ -272,6 +814,20 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_bencode_bytes() {
+        let raw = b"4:\xff\x00ab";
+        let val = parse_bencode(&mut &raw[..]).unwrap().unwrap();
+        assert!(matches!(val, Value::Bytes(_)));
+        assert_eq!(val.as_bytes(), Some(&b"\xff\x00ab"[..]));
+
+        let mut out = Vec::new();
+        val.write_bencode(&mut out).unwrap();
+        assert_eq!(out, raw);
+
+        assert_eq!(Value::from(b"abc".to_vec()), Value::from("abc"));
+    }
+
     #[test]
     fn test_parse_bencode_list() {
         let left = vec![
@@ -312,4 +868,194 @@ mod tests {
         assert_eq!(left2, parse_bencode(&mut right2).unwrap().unwrap());
         assert_eq!(left2.to_bencode(), sright2);
     }
+
+    #[test]
+    fn test_parse_bencode_malformed() {
+        let inputs: &[&[u8]] = &[
+            b"d1:ae",
+            b"i",
+            b"i12",
+            b"3:ab",
+            b"12",
+            b"99999999999999999999:x",
+            b"18446744073709551615:x",
+        ];
+        for input in inputs {
+            assert!(parse_bencode(&mut &input[..]).is_err(), "{:?}", input);
+        }
+        let err = parse_bencode(&mut &b"i12"[..]).unwrap_err();
+        assert_eq!(err.kind(), Some(ErrorKind::UnexpectedEof));
+        assert_eq!(err.position(), Some(3));
+        let err = parse_bencode(&mut &b"d1:ae"[..]).unwrap_err();
+        assert_eq!(err.kind(), Some(ErrorKind::InvalidCharacter(b'e')));
+        assert_eq!(err.position(), Some(4));
+        let err = parse_bencode(&mut &b"li1e3x:abce"[..]).unwrap_err();
+        assert_eq!(err.kind(), Some(ErrorKind::InvalidLength));
+        assert_eq!(err.position(), Some(4));
+
+        let deep = [vec![b'l'; 100_000], vec![b'e'; 100_000]].concat();
+        let err = parse_bencode(&mut &deep[..]).unwrap_err();
+        assert_eq!(err.kind(), Some(ErrorKind::DepthLimit(document::MAX_DEPTH)));
+    }
+
+    #[test]
+    fn test_slice_roundtrip() {
+        let input = b"d1:bli1e2:\xff\x00e1:ad1:xi-3eee";
+        let value = from_slice(input).unwrap();
+        assert_eq!(
+            Some(&value),
+            parse_bencode(&mut &input[..]).unwrap().as_ref()
+        );
+        assert_eq!(to_vec(&value).unwrap(), b"d1:ad1:xi-3ee1:bli1e2:\xff\x00ee");
+
+        let err = from_slice(b"i1ei2e").unwrap_err();
+        assert_eq!(err.kind(), Some(ErrorKind::Trailing));
+        assert_eq!(err.position(), Some(3));
+        let err = from_slice(b"l").unwrap_err();
+        assert_eq!(err.kind(), Some(ErrorKind::UnexpectedEof));
+        assert_eq!(err.position(), Some(1));
+        let mut map = HashMap::new();
+        map.insert(Value::Int(1), Value::Int(2));
+        assert!(to_vec(&Value::from(map)).is_err());
+    }
+
+    #[test]
+    fn test_value_order() {
+        let mut values = vec![
+            Value::from(vec![Value::Int(1)]),
+            Value::Bytes(b"\xff".to_vec()),
+            Value::from("a"),
+            Value::Int(7),
+            Value::Int(-1),
+        ];
+        values.sort();
+        assert_eq!(
+            values,
+            [
+                Value::Int(-1),
+                Value::Int(7),
+                Value::from("a"),
+                Value::Bytes(b"\xff".to_vec()),
+                Value::from(vec![Value::Int(1)]),
+            ]
+        );
+        assert_eq!(
+            Value::from("ab").cmp(&Value::Bytes(b"ab".to_vec())),
+            Ordering::Equal
+        );
+        let map = |k: &str| Value::from(HashMap::from([(Value::from(k), Value::Int(0))]));
+        assert!(map("a") < map("b"));
+    }
+
+    #[test]
+    fn test_error_source() {
+        use std::error::Error;
+
+        struct Broken;
+        impl Read for Broken {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Err(io::Error::other("broken"))
+            }
+        }
+        fn parse<R: Read>(input: R) -> std::result::Result<Option<Value>, Box<dyn Error>> {
+            Ok(parse_bencode(&mut BufReader::new(input))?)
+        }
+        let err = parse(b"l".chain(Broken)).unwrap_err();
+        assert!(err.source().unwrap().is::<io::Error>());
+
+        let err = BencodeError::from("1x2".parse::<i64>().unwrap_err());
+        let source = err.source().expect("integer errors keep their cause");
+        assert!(source.is::<std::num::ParseIntError>());
+
+        // malformed input is reported by kind and position instead
+        assert!(parse(&b"i1x2e"[..]).unwrap_err().source().is_none());
+        assert!(BencodeError::Eof().source().is_none());
+    }
+
+    #[test]
+    fn test_error_kind_and_position() {
+        let err = |input: &[u8]| Document::parse(input).unwrap_err();
+        let cases: [(&[u8], ErrorKind, Option<usize>); 6] = [
+            (b"li1ex", ErrorKind::InvalidCharacter(b'x'), Some(4)),
+            (b"i1ei2e", ErrorKind::Trailing, Some(3)),
+            (b"di1ei2ee", ErrorKind::NonStringKey, Some(1)),
+            (b"li1.5ee", ErrorKind::InvalidInteger, Some(1)),
+            (b"1x:a", ErrorKind::InvalidLength, Some(0)),
+            (b"l1:a", ErrorKind::UnexpectedEof, Some(4)),
+        ];
+        for (input, kind, position) in cases.iter().cloned() {
+            let e = err(input);
+            assert_eq!(
+                (e.kind(), e.position()),
+                (Some(kind), position),
+                "{:?}",
+                input
+            );
+        }
+        assert_eq!(
+            err(b"i1ei2e").to_string(),
+            "Bencode Error: Trailing data at offset 3 "
+        );
+
+        let deep = [vec![b'l'; 300], vec![b'e'; 300]].concat();
+        assert_eq!(
+            err(&deep).kind(),
+            Some(ErrorKind::DepthLimit(document::MAX_DEPTH))
+        );
+        assert!(combinator::value(&deep).is_err());
+        let shallow = [
+            vec![b'l'; document::MAX_DEPTH],
+            vec![b'e'; document::MAX_DEPTH],
+        ]
+        .concat();
+        assert!(Document::parse(shallow).is_ok());
+    }
+
+    #[test]
+    fn test_error_path() {
+        let err = BencodeError::new(ErrorKind::MissingField("length".into()))
+            .in_field("[3]")
+            .in_field("files")
+            .in_field("info");
+        assert_eq!(err.path(), "info.files[3]");
+        assert_eq!(
+            err.to_string(),
+            "Bencode Error: Field `info.files[3]`: Missing field `length` "
+        );
+        assert_eq!(BencodeError::Eof().in_field("info").path(), "");
+
+        let mut map = HashMap::new();
+        map.insert(Value::Int(1), Value::Int(2));
+        let mut outer = HashMap::new();
+        outer.insert(Value::from("a"), Value::from(map));
+        let err = Value::from(outer)
+            .write_bencode_with(&mut Vec::new(), EncodeOptions::strict())
+            .unwrap_err();
+        assert_eq!(err.kind(), Some(ErrorKind::NonStringKey));
+        assert_eq!(err.path(), "a");
+    }
+
+    #[test]
+    fn test_error_codes() {
+        let code = |input: &[u8]| Document::parse(input).unwrap_err().code();
+        assert_eq!(code(b"i1x2e"), "E0101");
+        assert_eq!(code(b"3x:abc"), "E0102");
+        assert_eq!(code(b"x"), "E0103");
+        assert_eq!(code(b"li1e"), "E0104");
+        assert_eq!(code(b"i1ei2e"), "E0105");
+        assert_eq!(code(b"di1ei2ee"), "E0202");
+
+        assert_eq!(
+            parse_bencode(&mut &b"i1x2e"[..]).unwrap_err().code(),
+            "E0101"
+        );
+        assert_eq!(
+            parse_bencode(&mut &b"4:abc"[..]).unwrap_err().code(),
+            "E0104"
+        );
+        let io = BencodeError::from(io::Error::from(io::ErrorKind::BrokenPipe));
+        assert_eq!(io.code(), "E0401");
+        assert_eq!(BencodeError::FrameTooLarge(1).code(), "E0402");
+        assert_eq!(BencodeError::msg("x").in_field("a").code(), "E0901");
+    }
 }
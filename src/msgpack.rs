@@ -0,0 +1,382 @@
+//! Transcoding between bencode [`Value`]s and MessagePack.
+//!
+//! `Str` values become MessagePack strings and `Bytes` values bin objects,
+//! so binary data survives the trip. Integers use the smallest encoding that
+//! holds them, and map keys are sorted by their encoded bytes so equal values
+//! always produce the same output.
+//!
+//! Decoding accepts what bencode can represent. Booleans become 0 or 1 and
+//! whole floats integers; `nil`, fractional numbers, extension types and
+//! integers above `i64::MAX` are errors, as is nesting deeper than
+//! [`MAX_DEPTH`].
+//!
+//! ```
+//! use bencode_rs::msgpack::{from_msgpack, to_msgpack};
+//! use bencode_rs::Value;
+//!
+//! let value = Value::List(vec![Value::from(-1), Value::from("ok")]);
+//! let packed = to_msgpack(&value).unwrap();
+//! assert_eq!(packed, [0x92, 0xff, 0xa2, b'o', b'k']);
+//! assert_eq!(from_msgpack(&packed).unwrap(), value);
+//! ```
+
+use crate::document::MAX_DEPTH;
+use crate::{BencodeError, ErrorKind, HMap, Map, Result, Value};
+use std::convert::TryFrom;
+
+/// Encode `value` as MessagePack. Fails only for strings, lists or
+/// dictionaries with more than `u32::MAX` elements, which the format cannot
+/// express.
+pub fn to_msgpack(value: &Value) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    write(&mut out, value)?;
+    Ok(out)
+}
+
+/// Decode a single MessagePack object. Trailing bytes are an error.
+pub fn from_msgpack(buf: &[u8]) -> Result<Value> {
+    let mut decoder = Decoder {
+        buf,
+        pos: 0,
+        depth: 0,
+    };
+    let value = decoder.value()?;
+    if decoder.pos != buf.len() {
+        return Err(decoder.error("trailing bytes"));
+    }
+    Ok(value)
+}
+
+/// A length prefix: the fix form `(base, max)` when given and `len` fits,
+/// otherwise the smallest of the 8-bit (if given), 16-bit and 32-bit forms.
+fn length(
+    out: &mut Vec<u8>,
+    len: usize,
+    fix: Option<(u8, usize)>,
+    tag8: Option<u8>,
+    tag16: u8,
+    tag32: u8,
+) -> Result<()> {
+    match (fix, tag8) {
+        (Some((base, max)), _) if len <= max => out.push(base | len as u8),
+        (_, Some(tag)) if len <= usize::from(u8::MAX) => out.extend_from_slice(&[tag, len as u8]),
+        _ if len <= usize::from(u16::MAX) => {
+            out.push(tag16);
+            out.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        _ => {
+            let len = u32::try_from(len).map_err(|_| {
+                BencodeError::msg(format!("{} elements is too long for MessagePack", len))
+            })?;
+            out.push(tag32);
+            out.extend_from_slice(&len.to_be_bytes());
+        }
+    }
+    Ok(())
+}
+
+fn write_int(out: &mut Vec<u8>, i: i64) {
+    match i {
+        0..=0x7f => out.push(i as u8),
+        -32..=-1 => out.push(i as i8 as u8),
+        0x80..=0xff => out.extend_from_slice(&[0xcc, i as u8]),
+        0x100..=0xffff => {
+            out.push(0xcd);
+            out.extend_from_slice(&(i as u16).to_be_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            out.push(0xce);
+            out.extend_from_slice(&(i as u32).to_be_bytes());
+        }
+        0x1_0000_0000.. => {
+            out.push(0xcf);
+            out.extend_from_slice(&(i as u64).to_be_bytes());
+        }
+        -0x80..=-33 => out.extend_from_slice(&[0xd0, i as i8 as u8]),
+        -0x8000..=-0x81 => {
+            out.push(0xd1);
+            out.extend_from_slice(&(i as i16).to_be_bytes());
+        }
+        -0x8000_0000..=-0x8001 => {
+            out.push(0xd2);
+            out.extend_from_slice(&(i as i32).to_be_bytes());
+        }
+        _ => {
+            out.push(0xd3);
+            out.extend_from_slice(&i.to_be_bytes());
+        }
+    }
+}
+
+fn write(out: &mut Vec<u8>, value: &Value) -> Result<()> {
+    match value {
+        Value::Int(i) => write_int(out, *i),
+        Value::Str(s) => {
+            length(out, s.len(), Some((0xa0, 31)), Some(0xd9), 0xda, 0xdb)?;
+            out.extend_from_slice(s.as_bytes());
+        }
+        Value::Bytes(b) => {
+            length(out, b.len(), None, Some(0xc4), 0xc5, 0xc6)?;
+            out.extend_from_slice(b);
+        }
+        Value::List(items) => {
+            length(out, items.len(), Some((0x90, 15)), None, 0xdc, 0xdd)?;
+            for item in items {
+                write(out, item)?;
+            }
+        }
+        Value::Map(map) => {
+            let mut entries = map
+                .0
+                .iter()
+                .map(|(k, v)| Ok((to_msgpack(k)?, v)))
+                .collect::<Result<Vec<_>>>()?;
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+            length(out, entries.len(), Some((0x80, 15)), None, 0xde, 0xdf)?;
+            for (key, value) in entries {
+                out.extend_from_slice(&key);
+                write(out, value)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+struct Decoder<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    // containers entered so far
+    depth: usize,
+}
+
+impl Decoder<'_> {
+    fn error(&self, what: &str) -> BencodeError {
+        BencodeError::msg(format!(
+            "Invalid MessagePack at offset {}: {}",
+            self.pos, what
+        ))
+    }
+
+    fn take(&mut self, n: usize) -> Result<&[u8]> {
+        let end = self
+            .pos
+            .checked_add(n)
+            .filter(|end| *end <= self.buf.len())
+            .ok_or_else(|| self.error("unexpected end"))?;
+        let bytes = &self.buf[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let mut out = [0; N];
+        out.copy_from_slice(self.take(N)?);
+        Ok(out)
+    }
+
+    fn len8(&mut self) -> Result<usize> {
+        Ok(usize::from(self.array::<1>()?[0]))
+    }
+
+    fn len16(&mut self) -> Result<usize> {
+        Ok(usize::from(u16::from_be_bytes(self.array()?)))
+    }
+
+    fn len32(&mut self) -> Result<usize> {
+        usize::try_from(u32::from_be_bytes(self.array()?))
+            .map_err(|_| self.error("length too large"))
+    }
+
+    fn string(&mut self, len: usize) -> Result<Value> {
+        let at = self.pos;
+        let bytes = self.take(len)?.to_vec();
+        String::from_utf8(bytes).map(Value::Str).map_err(|_| {
+            self.pos = at;
+            self.error("string is not UTF-8")
+        })
+    }
+
+    /// An element of an array or map.
+    fn nested(&mut self) -> Result<Value> {
+        if self.depth == MAX_DEPTH {
+            return Err(BencodeError::new(ErrorKind::DepthLimit(MAX_DEPTH)).at(self.pos));
+        }
+        self.depth += 1;
+        let value = self.value();
+        self.depth -= 1;
+        value
+    }
+
+    fn list(&mut self, len: usize) -> Result<Value> {
+        // capacity is not taken from untrusted input
+        let mut items = Vec::new();
+        for _ in 0..len {
+            items.push(self.nested()?);
+        }
+        Ok(Value::List(items))
+    }
+
+    fn map(&mut self, len: usize) -> Result<Value> {
+        let mut map = Map::new();
+        for _ in 0..len {
+            let at = self.pos;
+            let key = self.nested()?;
+            if key.as_bytes().is_none() {
+                self.pos = at;
+                return Err(self.error("map key is not a string"));
+            }
+            map.insert(key, self.nested()?);
+        }
+        Ok(Value::Map(HMap(map)))
+    }
+
+    fn float(&self, f: f64) -> Result<Value> {
+        if f.fract() == 0.0 && f.abs() < 9.0e18 {
+            Ok(Value::Int(f as i64))
+        } else {
+            Err(self.error(&format!("number {} is not an integer", f)))
+        }
+    }
+
+    fn value(&mut self) -> Result<Value> {
+        let tag = self.array::<1>()?[0];
+        match tag {
+            0x80..=0x8f => self.map(usize::from(tag & 0x0f)),
+            0x90..=0x9f => self.list(usize::from(tag & 0x0f)),
+            0xdc => {
+                let len = self.len16()?;
+                self.list(len)
+            }
+            0xdd => {
+                let len = self.len32()?;
+                self.list(len)
+            }
+            0xde => {
+                let len = self.len16()?;
+                self.map(len)
+            }
+            0xdf => {
+                let len = self.len32()?;
+                self.map(len)
+            }
+            _ => self.scalar(tag),
+        }
+    }
+
+    /// Everything but arrays and maps, kept out of [`Decoder::value`] so the
+    /// recursion through nested containers uses as little stack as possible.
+    #[inline(never)]
+    fn scalar(&mut self, tag: u8) -> Result<Value> {
+        match tag {
+            0x00..=0x7f => Ok(Value::Int(i64::from(tag))),
+            0xa0..=0xbf => self.string(usize::from(tag & 0x1f)),
+            0xc0 => Err(self.error("nil has no bencode form")),
+            0xc2 => Ok(Value::Int(0)),
+            0xc3 => Ok(Value::Int(1)),
+            0xc4 => {
+                let len = self.len8()?;
+                Ok(Value::Bytes(self.take(len)?.to_vec()))
+            }
+            0xc5 => {
+                let len = self.len16()?;
+                Ok(Value::Bytes(self.take(len)?.to_vec()))
+            }
+            0xc6 => {
+                let len = self.len32()?;
+                Ok(Value::Bytes(self.take(len)?.to_vec()))
+            }
+            0xc7..=0xc9 | 0xd4..=0xd8 => Err(self.error("extension types have no bencode form")),
+            0xca => {
+                let f = f32::from_be_bytes(self.array()?);
+                self.float(f64::from(f))
+            }
+            0xcb => {
+                let f = f64::from_be_bytes(self.array()?);
+                self.float(f)
+            }
+            0xcc => Ok(Value::Int(i64::from(self.array::<1>()?[0]))),
+            0xcd => Ok(Value::Int(i64::from(u16::from_be_bytes(self.array()?)))),
+            0xce => Ok(Value::Int(i64::from(u32::from_be_bytes(self.array()?)))),
+            0xcf => {
+                let n = u64::from_be_bytes(self.array()?);
+                i64::try_from(n)
+                    .map(Value::Int)
+                    .map_err(|_| self.error("integer out of range"))
+            }
+            0xd0 => Ok(Value::Int(i64::from(i8::from_be_bytes(self.array()?)))),
+            0xd1 => Ok(Value::Int(i64::from(i16::from_be_bytes(self.array()?)))),
+            0xd2 => Ok(Value::Int(i64::from(i32::from_be_bytes(self.array()?)))),
+            0xd3 => Ok(Value::Int(i64::from_be_bytes(self.array()?))),
+            0xd9 => {
+                let len = self.len8()?;
+                self.string(len)
+            }
+            0xda => {
+                let len = self.len16()?;
+                self.string(len)
+            }
+            0xdb => {
+                let len = self.len32()?;
+                self.string(len)
+            }
+            0xe0..=0xff => Ok(Value::Int(i64::from(tag as i8))),
+            0xc1 => Err(self.error("reserved byte 0xc1")),
+            _ => unreachable!("containers are decoded by value"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_bencode;
+
+    #[test]
+    fn test_msgpack_roundtrip() {
+        let mut buf = b"d8:announce3:url4:infod6:lengthi-300e6:pieces20:".to_vec();
+        buf.extend_from_slice(&[0xaa; 20]);
+        buf.extend_from_slice(b"e4:listli0ei200ei-100ei70000ei-9000000000eee");
+        let value = parse_bencode(&mut &buf[..]).unwrap().unwrap();
+        let packed = to_msgpack(&value).unwrap();
+        assert_eq!(from_msgpack(&packed).unwrap(), value);
+        // fixmap of three, keys sorted by encoded bytes: shorter strings first
+        assert_eq!(&packed[..2], &[0x83, 0xa4]);
+        assert_eq!(&packed[2..6], b"info");
+
+        let int = |i| to_msgpack(&Value::from(i)).unwrap();
+        assert_eq!(int(200), [0xcc, 200]);
+        assert_eq!(int(-33), [0xd0, 0xdf]);
+        assert_eq!(int(70000), [0xce, 0, 1, 0x11, 0x70]);
+        let long = to_msgpack(&Value::from("x".repeat(40).as_str())).unwrap();
+        assert_eq!(&long[..2], &[0xd9, 40]);
+    }
+
+    #[test]
+    fn test_msgpack_decode() {
+        // array16 of true, float64 100.0, bin8, str8
+        let packed = [
+            0xdc, 0, 4, 0xc3, 0xcb, 0x40, 0x59, 0, 0, 0, 0, 0, 0, 0xc4, 1, 0xff, 0xd9, 1, b'a',
+        ];
+        assert_eq!(
+            from_msgpack(&packed).unwrap(),
+            Value::List(vec![
+                Value::from(1),
+                Value::from(100),
+                Value::from(vec![0xff]),
+                Value::from("a"),
+            ])
+        );
+
+        let err = |buf: &[u8]| from_msgpack(buf).unwrap_err().to_string();
+        assert!(err(&[0xc0]).contains("nil"));
+        assert!(err(&[0xca, 0x3f, 0xc0, 0, 0]).contains("1.5 is not an integer"));
+        assert!(err(&[0xcf, 0xff, 0, 0, 0, 0, 0, 0, 0]).contains("out of range"));
+        assert!(err(&[0x81, 0x01, 0x01]).contains("offset 1: map key"));
+        assert!(err(&[0xd4, 1, 0]).contains("extension"));
+        assert!(err(&[0xa2, b'a']).contains("unexpected end"));
+        assert!(err(&[0x01, 0x01]).contains("trailing"));
+
+        let deep = from_msgpack(&[0x91; 100_000]).unwrap_err();
+        assert_eq!(deep.kind(), Some(ErrorKind::DepthLimit(MAX_DEPTH)));
+    }
+}
@@ -0,0 +1,237 @@
+//! Blocking one-value-at-a-time helpers for sockets and other streams.
+//!
+//! ```no_run
+//! use bencode_rs::net::{ReadValue, WriteValue};
+//! use bencode_rs::Value;
+//! use std::net::TcpStream;
+//!
+//! let mut stream = TcpStream::connect("127.0.0.1:7888").unwrap();
+//! stream.write_value(&Value::from("ping")).unwrap();
+//! let reply = stream.read_value().unwrap();
+//! ```
+//!
+//! The traits are implemented for every `Read`/`Write`, so they work the same
+//! on `UnixStream`; see `examples/unix_echo.rs` for a Unix socket echo server.
+
+use crate::{parse_bencode, BencodeError, ErrorKind, Result, Value};
+use std::io::{self, BufRead, Read, Write};
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+/// Read exactly one bencode value.
+pub trait ReadValue {
+    /// Read one complete value. Never consumes bytes past its end, so the
+    /// stream can be handed to other code afterwards; for many small values
+    /// on a raw socket, wrapping it in a `BufReader` and calling
+    /// `parse_bencode` is cheaper.
+    fn read_value(&mut self) -> Result<Value>;
+}
+
+/// Write exactly one bencode value.
+pub trait WriteValue {
+    /// Encode `value` and flush, so it is on the wire when this returns.
+    fn write_value(&mut self, value: &Value) -> Result<()>;
+}
+
+impl<R: Read + ?Sized> ReadValue for R {
+    fn read_value(&mut self) -> Result<Value> {
+        let mut reader = Unbuffered {
+            inner: self,
+            byte: [0],
+            filled: false,
+        };
+        parse_bencode(&mut reader)?
+            .ok_or_else(|| BencodeError::new(ErrorKind::InvalidCharacter(b'e')).at(0))
+    }
+}
+
+impl<W: Write + ?Sized> WriteValue for W {
+    fn write_value(&mut self, value: &Value) -> Result<()> {
+        value.write_bencode(self)?;
+        self.flush()?;
+        Ok(())
+    }
+}
+
+/// Streams whose blocking reads can be given a timeout.
+pub trait ReadTimeout {
+    fn read_timeout(&self) -> io::Result<Option<Duration>>;
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+}
+
+impl ReadTimeout for TcpStream {
+    fn read_timeout(&self) -> io::Result<Option<Duration>> {
+        TcpStream::read_timeout(self)
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+}
+
+#[cfg(unix)]
+impl ReadTimeout for std::os::unix::net::UnixStream {
+    fn read_timeout(&self) -> io::Result<Option<Duration>> {
+        std::os::unix::net::UnixStream::read_timeout(self)
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        std::os::unix::net::UnixStream::set_read_timeout(self, timeout)
+    }
+}
+
+/// Reader that fails with `TimedOut` once `deadline` has passed, shrinking the
+/// stream's read timeout before every read so the whole message, not just
+/// each read, is bounded.
+pub struct DeadlineReader<'a, R: ReadTimeout> {
+    inner: &'a mut R,
+    deadline: Instant,
+}
+
+impl<'a, R: ReadTimeout> DeadlineReader<'a, R> {
+    pub fn new(inner: &'a mut R, timeout: Duration) -> Self {
+        DeadlineReader {
+            inner,
+            deadline: Instant::now() + timeout,
+        }
+    }
+}
+
+impl<R: ReadTimeout + Read> Read for DeadlineReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.deadline.saturating_duration_since(Instant::now());
+        if remaining == Duration::from_secs(0) {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        self.inner.set_read_timeout(Some(remaining))?;
+        match self.inner.read(buf) {
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Err(io::ErrorKind::TimedOut.into()),
+            other => other,
+        }
+    }
+}
+
+/// Read one value, giving up with a `TimedOut` I/O error if it has not fully
+/// arrived within `timeout`. The stream's previous read timeout is restored.
+pub fn parse_with_timeout<R: Read + ReadTimeout>(
+    reader: &mut R,
+    timeout: Duration,
+) -> Result<Value> {
+    let previous = reader.read_timeout()?;
+    let result = DeadlineReader::new(reader, timeout).read_value();
+    reader.set_read_timeout(previous)?;
+    result
+}
+
+// A `BufRead` that buffers at most one byte, so the parser never reads ahead.
+struct Unbuffered<'a, R: ?Sized> {
+    inner: &'a mut R,
+    byte: [u8; 1],
+    filled: bool,
+}
+
+impl<R: Read + ?Sized> Read for Unbuffered<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.filled {
+            buf[0] = self.byte[0];
+            self.filled = false;
+            return Ok(1);
+        }
+        self.inner.read(buf)
+    }
+}
+
+impl<R: Read + ?Sized> BufRead for Unbuffered<'_, R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if !self.filled {
+            if self.inner.read(&mut self.byte)? == 0 {
+                return Ok(&[]);
+            }
+            self.filled = true;
+        }
+        Ok(&self.byte)
+    }
+
+    fn consume(&mut self, amt: usize) {
+        if amt > 0 {
+            self.filled = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+
+    #[test]
+    fn test_read_value_stops_at_value_end() {
+        let mut input = &b"l3:abci-5eerest"[..];
+        assert_eq!(
+            input.read_value().unwrap(),
+            Value::List(vec![Value::from("abc"), Value::Int(-5)])
+        );
+        assert_eq!(input, b"rest");
+    }
+
+    #[test]
+    fn test_tcp_echo() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            while let Ok(v) = stream.read_value() {
+                stream.write_value(&v).unwrap();
+            }
+        });
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        for v in &[Value::from("ping"), Value::Int(42)] {
+            stream.write_value(v).unwrap();
+            assert_eq!(&stream.read_value().unwrap(), v);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unix_stream_pair() {
+        use std::os::unix::net::UnixStream;
+
+        let (mut a, mut b) = UnixStream::pair().unwrap();
+        let msg = Value::List(vec![Value::from("op"), Value::from("eval")]);
+        a.write_value(&msg).unwrap();
+        assert_eq!(b.read_value().unwrap(), msg);
+    }
+
+    #[test]
+    fn test_parse_with_timeout_gives_up_mid_message() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.write_all(b"i42e").unwrap();
+            // start a second message and then stall
+            stream.write_all(b"l3:abc").unwrap();
+            thread::sleep(Duration::from_secs(2));
+        });
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let timeout = Duration::from_millis(200);
+        assert_eq!(
+            parse_with_timeout(&mut stream, timeout).unwrap(),
+            Value::Int(42)
+        );
+        let started = Instant::now();
+        match parse_with_timeout(&mut stream, timeout) {
+            Err(BencodeError::Io(e)) => assert_eq!(e.kind(), io::ErrorKind::TimedOut),
+            other => panic!("expected timeout, got {:?}", other),
+        }
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert_eq!(stream.read_timeout().unwrap(), None);
+    }
+}
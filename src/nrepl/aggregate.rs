@@ -0,0 +1,109 @@
+use super::{Response, Responses};
+use crate::{Result, Value};
+use std::collections::HashMap;
+
+/// The consolidated outcome of one request: all streamed `out`/`err` text
+/// concatenated, every `value` in order, and the union of status flags.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Aggregate {
+    pub id: Option<String>,
+    pub session: Option<String>,
+    pub out: String,
+    pub err: String,
+    pub values: Vec<String>,
+    pub status: Vec<String>,
+    /// Other fields, the latest response winning (e.g. `ns`, `ex`).
+    pub extra: HashMap<String, Value>,
+}
+
+impl Aggregate {
+    /// Collector for the responses to request `id`.
+    pub fn new(id: &str) -> Self {
+        Aggregate {
+            id: Some(id.to_string()),
+            ..Aggregate::default()
+        }
+    }
+
+    /// Merge in `response`, returning `true` once the request is done.
+    /// Responses to other requests are ignored.
+    pub fn push(&mut self, response: &Response) -> bool {
+        if self.id.is_some() && response.id != self.id {
+            return false;
+        }
+        if self.session.is_none() {
+            self.session = response.session.clone();
+        }
+        if let Some(out) = &response.out {
+            self.out.push_str(out);
+        }
+        if let Some(err) = &response.err {
+            self.err.push_str(err);
+        }
+        if let Some(value) = &response.value {
+            self.values.push(value.clone());
+        }
+        for flag in &response.status {
+            if !self.status.contains(flag) {
+                self.status.push(flag.clone());
+            }
+        }
+        for (k, v) in &response.extra {
+            self.extra.insert(k.clone(), v.clone());
+        }
+        self.is_done()
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.status.iter().any(|s| s == "done")
+    }
+
+    /// The last value produced, which for `eval` is the result of the last form.
+    pub fn value(&self) -> Option<&str> {
+        self.values.last().map(String::as_str)
+    }
+}
+
+impl Responses {
+    /// Wait for `done` and merge every response into one [`Aggregate`].
+    pub fn aggregate(self) -> Result<Aggregate> {
+        let mut aggregate = Aggregate::new(&self.id());
+        for response in self {
+            if aggregate.push(&response?) {
+                break;
+            }
+        }
+        Ok(aggregate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_aggregate_merges_until_done() {
+        let part = |out: Option<&str>, value: Option<&str>, status: &[&str]| Response {
+            id: Some("5".into()),
+            out: out.map(String::from),
+            value: value.map(String::from),
+            status: status.iter().map(|s| s.to_string()).collect(),
+            ..Response::default()
+        };
+        let mut agg = Aggregate::new("5");
+        assert!(!agg.push(&part(Some("a"), None, &[])));
+        assert!(!agg.push(&part(Some("b\n"), Some("1"), &[])));
+        let other = Response {
+            id: Some("6".into()),
+            out: Some("ignored".into()),
+            ..Response::default()
+        };
+        assert!(!agg.push(&other));
+        assert!(agg.push(&part(None, Some("2"), &["done"])));
+
+        assert_eq!(agg.out, "ab\n");
+        assert_eq!(agg.values, vec!["1", "2"]);
+        assert_eq!(agg.value(), Some("2"));
+        assert_eq!(agg.status, vec!["done"]);
+    }
+}
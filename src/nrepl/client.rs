@@ -0,0 +1,203 @@
+use super::{Request, Response};
+use crate::rpc::{Call, RpcClient};
+use crate::{BencodeError, FromBencode, Result, ToBencode};
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Blocking nREPL client.
+pub struct Client<W: Write = TcpStream> {
+    rpc: RpcClient<W>,
+    timeout: Duration,
+}
+
+/// Streamed responses to one request, ending after the response whose
+/// `status` contains `done`.
+pub struct Responses {
+    call: Call,
+    timeout: Duration,
+    done: bool,
+}
+
+impl Client<TcpStream> {
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> Result<Self> {
+        let stream = TcpStream::connect(addr)?;
+        Ok(Client::new(stream.try_clone()?, stream))
+    }
+}
+
+#[cfg(unix)]
+impl Client<std::os::unix::net::UnixStream> {
+    /// Connect to a server listening on a Unix domain socket.
+    pub fn connect_unix<P: AsRef<std::path::Path>>(path: P) -> Result<Self> {
+        let stream = std::os::unix::net::UnixStream::connect(path)?;
+        Ok(Client::new(stream.try_clone()?, stream))
+    }
+}
+
+impl<W: Write> Client<W> {
+    pub fn new<R: Read + Send + 'static>(reader: R, writer: W) -> Self {
+        Client {
+            rpc: RpcClient::new(reader, writer),
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// How long to wait for each response before giving up.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Send a request; its `id` is assigned automatically.
+    pub fn send(&self, request: &Request) -> Result<Responses> {
+        let call = self.rpc.send(request.to_value())?;
+        Ok(Responses {
+            call,
+            timeout: self.timeout,
+            done: false,
+        })
+    }
+
+    /// Create a new session and return its id.
+    pub fn clone_session(&self) -> Result<String> {
+        for response in self.send(&Request::new("clone"))? {
+            if let Some(session) = response?.extra.get("new-session") {
+                return Ok(session.to_string());
+            }
+        }
+        Err(BencodeError::msg("clone response without new-session"))
+    }
+
+    pub fn eval(&self, session: &str, code: &str) -> Result<Responses> {
+        self.send(&Request {
+            session: Some(session.to_string()),
+            code: Some(code.to_string()),
+            ..Request::new("eval")
+        })
+    }
+
+    /// Interrupt the evaluation with request id `interrupt_id`.
+    pub fn interrupt(&self, session: &str, interrupt_id: &str) -> Result<Responses> {
+        self.send(&Request {
+            session: Some(session.to_string()),
+            ..Request::new("interrupt").with("interrupt-id", interrupt_id)
+        })
+    }
+
+    /// Load `file` (its contents) as if it lived at `path`.
+    pub fn load_file(&self, session: &str, file: &str, path: &str) -> Result<Responses> {
+        let name = path.rsplit('/').next().unwrap_or(path);
+        self.send(&Request {
+            session: Some(session.to_string()),
+            ..Request::new("load-file")
+                .with("file", file)
+                .with("file-path", path)
+                .with("file-name", name)
+        })
+    }
+}
+
+impl Responses {
+    /// The id of the request these responses belong to.
+    pub fn id(&self) -> String {
+        String::from_utf8_lossy(self.call.id()).into_owned()
+    }
+}
+
+impl Iterator for Responses {
+    type Item = Result<Response>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let response = self
+            .call
+            .recv(self.timeout)
+            .and_then(|v| Response::from_value(&v));
+        match &response {
+            Ok(r) => self.done = r.is_done(),
+            Err(_) => self.done = true,
+        }
+        Some(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::ValueStream;
+    use crate::Value;
+    use std::collections::HashMap;
+    use std::net::TcpListener;
+    use std::thread;
+
+    fn response(id: &Value, pairs: &[(&str, Value)]) -> Value {
+        let mut m = HashMap::new();
+        m.insert(Value::from("id"), id.clone());
+        for (k, v) in pairs {
+            m.insert(Value::from(*k), v.clone());
+        }
+        Value::from(m)
+    }
+
+    // Minimal fake server: answers clone and eval like a real nREPL would.
+    fn serve(listener: TcpListener) {
+        let (stream, _) = listener.accept().unwrap();
+        let mut out = stream.try_clone().unwrap();
+        for request in ValueStream::new(stream) {
+            let request = match request.unwrap() {
+                Value::Map(hm) => hm,
+                _ => panic!("request is not a dict"),
+            };
+            let id = request.get(&Value::from("id")).unwrap();
+            let done = Value::List(vec![Value::from("done")]);
+            let op = request.get(&Value::from("op")).unwrap().to_string();
+            let replies = match op.as_str() {
+                "clone" => vec![response(
+                    id,
+                    &[("new-session", Value::from("s1")), ("status", done)],
+                )],
+                _ => vec![
+                    response(id, &[("out", Value::from("hi\n"))]),
+                    response(id, &[("value", Value::from("3"))]),
+                    response(id, &[("status", done)]),
+                ],
+            };
+            for r in replies {
+                r.write_bencode(&mut out).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn test_client_clone_and_eval() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || serve(listener));
+
+        let client = Client::connect(addr).unwrap();
+        let session = client.clone_session().unwrap();
+        assert_eq!(session, "s1");
+
+        let result = client
+            .eval(&session, "(+ 1 2)")
+            .unwrap()
+            .aggregate()
+            .unwrap();
+        assert_eq!(result.out, "hi\n");
+        assert_eq!(result.value(), Some("3"));
+
+        let responses: Vec<Response> = client
+            .eval(&session, "(do (println \"hi\") (+ 1 2))")
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(responses.len(), 3);
+        assert_eq!(responses[0].out.as_deref(), Some("hi\n"));
+        assert_eq!(responses[1].value.as_deref(), Some("3"));
+        assert!(responses[2].is_done());
+    }
+}
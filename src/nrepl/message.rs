@@ -0,0 +1,175 @@
+use crate::fields::{DictBuilder, Fields};
+use crate::{FromBencode, Result, ToBencode, Value};
+use std::collections::HashMap;
+
+/// A request sent to an nREPL server.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Request {
+    pub op: String,
+    pub id: Option<String>,
+    pub session: Option<String>,
+    pub code: Option<String>,
+    /// Op-specific fields, e.g. `interrupt-id` or `file`.
+    pub extra: HashMap<String, Value>,
+}
+
+/// A single response streamed back by an nREPL server.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Response {
+    pub id: Option<String>,
+    pub session: Option<String>,
+    pub value: Option<String>,
+    pub out: Option<String>,
+    pub err: Option<String>,
+    pub status: Vec<String>,
+    /// Any other fields, e.g. `ns` or `new-session`.
+    pub extra: HashMap<String, Value>,
+}
+
+const REQUEST_FIELDS: &[&str] = &["op", "id", "session", "code"];
+const RESPONSE_FIELDS: &[&str] = &["id", "session", "value", "out", "err", "status"];
+
+impl Request {
+    pub fn new(op: &str) -> Self {
+        Request {
+            op: op.to_string(),
+            ..Request::default()
+        }
+    }
+
+    /// Add an op-specific field.
+    pub fn with(mut self, key: &str, value: &str) -> Self {
+        self.extra.insert(key.to_string(), Value::from(value));
+        self
+    }
+
+    /// Look up an op-specific field as a string.
+    pub fn field(&self, key: &str) -> Option<String> {
+        self.extra
+            .get(key)
+            .and_then(Value::as_bytes)
+            .map(|b| String::from_utf8_lossy(b).into_owned())
+    }
+}
+
+impl Response {
+    /// An empty response to `request`, carrying over its id and session.
+    pub fn reply_to(request: &Request) -> Self {
+        Response {
+            id: request.id.clone(),
+            session: request.session.clone(),
+            ..Response::default()
+        }
+    }
+
+    pub fn has_status(&self, flag: &str) -> bool {
+        self.status.iter().any(|s| s == flag)
+    }
+
+    /// Whether this is the final response to its request.
+    pub fn is_done(&self) -> bool {
+        self.has_status("done")
+    }
+}
+
+impl FromBencode for Request {
+    fn from_value(value: &Value) -> Result<Self> {
+        let fields = Fields::new(value)?;
+        Ok(Request {
+            op: crate::fields::require("op", fields.string("op")?)?,
+            id: fields.string("id")?,
+            session: fields.string("session")?,
+            code: fields.string("code")?,
+            extra: fields.rest(REQUEST_FIELDS),
+        })
+    }
+}
+
+impl ToBencode for Request {
+    fn to_value(&self) -> Value {
+        DictBuilder::new()
+            .set("op", self.op.as_str())
+            .opt("id", self.id.as_deref())
+            .opt("session", self.session.as_deref())
+            .opt("code", self.code.as_deref())
+            .extend(&self.extra)
+            .build()
+    }
+}
+
+impl FromBencode for Response {
+    fn from_value(value: &Value) -> Result<Self> {
+        let fields = Fields::new(value)?;
+        let status = fields
+            .list("status")?
+            .unwrap_or(&[])
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        Ok(Response {
+            id: fields.string("id")?,
+            session: fields.string("session")?,
+            value: fields.string("value")?,
+            out: fields.string("out")?,
+            err: fields.string("err")?,
+            status,
+            extra: fields.rest(RESPONSE_FIELDS),
+        })
+    }
+}
+
+impl ToBencode for Response {
+    fn to_value(&self) -> Value {
+        let status = if self.status.is_empty() {
+            None
+        } else {
+            Some(Value::List(
+                self.status
+                    .iter()
+                    .map(|s| Value::from(s.as_str()))
+                    .collect(),
+            ))
+        };
+        DictBuilder::new()
+            .opt("id", self.id.as_deref())
+            .opt("session", self.session.as_deref())
+            .opt("value", self.value.as_deref())
+            .opt("out", self.out.as_deref())
+            .opt("err", self.err.as_deref())
+            .opt("status", status)
+            .extend(&self.extra)
+            .build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_bencode;
+
+    #[test]
+    fn test_request_roundtrip() {
+        let req = Request {
+            id: Some("7".into()),
+            code: Some("(+ 1 2)".into()),
+            ..Request::new("eval")
+        }
+        .with("ns", "user");
+        let decoded = Request::from_value(&req.to_value()).unwrap();
+        assert_eq!(decoded, req);
+        assert_eq!(decoded.field("ns").as_deref(), Some("user"));
+    }
+
+    #[test]
+    fn test_response_from_wire() {
+        let wire = b"d2:id1:72:ns4:user6:statusl4:doneee";
+        let value = parse_bencode(&mut &wire[..]).unwrap().unwrap();
+        let resp = Response::from_value(&value).unwrap();
+        assert_eq!(resp.id.as_deref(), Some("7"));
+        assert!(resp.is_done());
+        assert_eq!(resp.extra.get("ns"), Some(&Value::from("user")));
+        assert_eq!(Response::from_value(&resp.to_value()).unwrap(), resp);
+
+        assert!(Request::from_value(&value).is_err());
+    }
+}
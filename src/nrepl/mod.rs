@@ -0,0 +1,17 @@
+//! nREPL protocol support.
+//!
+//! nREPL speaks bencoded dictionaries over a socket: requests carry an `op`,
+//! an `id` and usually a `session`, and the server streams back one or more
+//! responses per request, the last one having `done` in its `status` list.
+
+mod aggregate;
+mod client;
+mod message;
+mod server;
+mod session;
+
+pub use aggregate::Aggregate;
+pub use client::{Client, Responses};
+pub use message::{Request, Response};
+pub use server::{Handler, Listener, Server};
+pub use session::SessionManager;
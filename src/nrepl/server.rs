@@ -0,0 +1,353 @@
+use super::{Request, Response};
+use crate::codec::ValueStream;
+use crate::{BencodeError, FromBencode, Result, ToBencode, Value};
+use std::collections::HashSet;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A step in the server's handler chain. Returning `None` passes the request
+/// on to the next handler; `Some` answers it with zero or more responses.
+///
+/// Handlers need not set `id`, `session` or the final `done` status; the
+/// server fills those in.
+pub trait Handler: Send + Sync {
+    fn handle(&self, request: &Request) -> Option<Vec<Response>>;
+}
+
+impl<F> Handler for F
+where
+    F: Fn(&Request) -> Option<Vec<Response>> + Send + Sync,
+{
+    fn handle(&self, request: &Request) -> Option<Vec<Response>> {
+        self(request)
+    }
+}
+
+struct Shared {
+    handlers: Vec<Box<dyn Handler>>,
+    sessions: Mutex<HashSet<String>>,
+    next_session: AtomicU64,
+}
+
+/// Blocking nREPL server. `clone`, `close` and `ls-sessions` are handled
+/// built in; every other op goes through the handler chain in the order the
+/// handlers were added.
+pub struct Server {
+    shared: Arc<Shared>,
+}
+
+impl Default for Server {
+    fn default() -> Self {
+        Server {
+            shared: Arc::new(Shared {
+                handlers: vec![],
+                sessions: Mutex::new(HashSet::new()),
+                next_session: AtomicU64::new(1),
+            }),
+        }
+    }
+}
+
+impl Server {
+    pub fn new() -> Self {
+        Server::default()
+    }
+
+    /// Append a handler to the chain. Must be called before serving.
+    pub fn handler<H: Handler + 'static>(mut self, handler: H) -> Self {
+        if let Some(shared) = Arc::get_mut(&mut self.shared) {
+            shared.handlers.push(Box::new(handler));
+        }
+        self
+    }
+
+    /// Listen on `addr`, serving each connection on its own thread.
+    pub fn bind<A: ToSocketAddrs>(self, addr: A) -> Result<Listener> {
+        Ok(Listener {
+            listener: TcpListener::bind(addr)?,
+            server: self,
+        })
+    }
+
+    /// Serve requests read from `reader` until it is closed. A value that is
+    /// not a valid request gets a single response with status `error`,
+    /// `malformed-request` and `done`, and the connection stays open.
+    pub fn serve_connection<R: Read, W: Write>(&self, reader: R, mut writer: W) -> Result<()> {
+        for request in ValueStream::new(reader) {
+            let request = request?;
+            let responses = match Request::from_value(&request) {
+                Ok(request) => self.dispatch(&request),
+                Err(err) => vec![malformed(&request, &err)],
+            };
+            for response in responses {
+                response.to_value().write_bencode(&mut writer)?;
+            }
+            writer.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Compute all responses for one request, ending with a `done` status.
+    pub fn dispatch(&self, request: &Request) -> Vec<Response> {
+        let mut responses = match self.builtin(request) {
+            Some(responses) => responses,
+            None => self.run_handlers(request),
+        };
+        if responses.is_empty() {
+            responses.push(Response::default());
+        }
+        for response in responses.iter_mut() {
+            if response.id.is_none() {
+                response.id = request.id.clone();
+            }
+            if response.session.is_none() {
+                response.session = request.session.clone();
+            }
+        }
+        if let Some(last) = responses.last_mut() {
+            if !last.is_done() {
+                last.status.push("done".into());
+            }
+        }
+        responses
+    }
+
+    fn run_handlers(&self, request: &Request) -> Vec<Response> {
+        if let Some(session) = &request.session {
+            if !self.session_exists(session) {
+                return vec![error_response(&["error", "unknown-session"])];
+            }
+        }
+        self.shared
+            .handlers
+            .iter()
+            .find_map(|h| h.handle(request))
+            .unwrap_or_else(|| vec![error_response(&["error", "unknown-op"])])
+    }
+
+    fn builtin(&self, request: &Request) -> Option<Vec<Response>> {
+        let mut sessions = self.shared.sessions.lock().ok()?;
+        match request.op.as_str() {
+            "clone" => {
+                let session = self.new_session_id();
+                sessions.insert(session.clone());
+                let mut response = Response::default();
+                response
+                    .extra
+                    .insert("new-session".into(), session.as_str().into());
+                Some(vec![response])
+            }
+            "close" => {
+                let closed = request.session.as_ref().is_some_and(|s| sessions.remove(s));
+                if closed {
+                    let mut response = Response::default();
+                    response.status.push("session-closed".into());
+                    Some(vec![response])
+                } else {
+                    Some(vec![error_response(&["error", "unknown-session"])])
+                }
+            }
+            "ls-sessions" => {
+                let mut ids: Vec<_> = sessions.iter().map(|s| s.as_str().into()).collect();
+                ids.sort_by_key(|v: &Value| v.to_string());
+                let mut response = Response::default();
+                response.extra.insert("sessions".into(), ids.into());
+                Some(vec![response])
+            }
+            _ => None,
+        }
+    }
+
+    fn session_exists(&self, session: &str) -> bool {
+        self.shared
+            .sessions
+            .lock()
+            .is_ok_and(|s| s.contains(session))
+    }
+
+    fn new_session_id(&self) -> String {
+        let n = self.shared.next_session.fetch_add(1, Ordering::Relaxed);
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.subsec_nanos());
+        format!("{:08x}-{:04x}", nanos, n)
+    }
+
+    /// Listen on a Unix domain socket at `path`, serving each connection on
+    /// its own thread. Runs until accepting fails.
+    #[cfg(unix)]
+    pub fn serve_unix<P: AsRef<std::path::Path>>(self, path: P) -> Result<()> {
+        let listener = std::os::unix::net::UnixListener::bind(path)?;
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let server = self.clone_handle();
+            thread::spawn(move || {
+                if let Ok(reader) = stream.try_clone() {
+                    let _ = server.serve_connection(reader, stream);
+                }
+            });
+        }
+        Ok(())
+    }
+
+    fn clone_handle(&self) -> Server {
+        Server {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+/// A bound server, ready to accept connections.
+pub struct Listener {
+    listener: TcpListener,
+    server: Server,
+}
+
+impl Listener {
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Accept connections forever, one thread per connection.
+    pub fn serve(self) -> Result<()> {
+        for stream in self.listener.incoming() {
+            let stream: TcpStream = stream?;
+            let server = self.server.clone_handle();
+            thread::spawn(move || {
+                if let Ok(reader) = stream.try_clone() {
+                    // a broken connection only ends its own thread
+                    let _ = server.serve_connection(reader, stream);
+                }
+            });
+        }
+        Ok(())
+    }
+}
+
+fn error_response(status: &[&str]) -> Response {
+    Response {
+        status: status.iter().map(|s| s.to_string()).collect(),
+        ..Response::default()
+    }
+}
+
+/// Reply to a value that does not decode as a [`Request`], echoing its `id`
+/// if it has a usable one.
+fn malformed(request: &Value, err: &BencodeError) -> Response {
+    Response {
+        id: request
+            .get("id")
+            .and_then(Value::as_bytes)
+            .map(|id| String::from_utf8_lossy(id).into_owned()),
+        err: Some(err.to_string()),
+        ..error_response(&["error", "malformed-request", "done"])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nrepl::Client;
+
+    fn echo(request: &Request) -> Option<Vec<Response>> {
+        if request.op != "eval" {
+            return None;
+        }
+        let code = request.code.clone().unwrap_or_default();
+        Some(vec![
+            Response {
+                out: Some(format!("evaluating {}\n", code)),
+                ..Response::default()
+            },
+            Response {
+                value: Some(code),
+                ..Response::default()
+            },
+        ])
+    }
+
+    #[test]
+    fn test_dispatch_adds_id_and_done() {
+        let server = Server::new().handler(echo);
+        let request = Request {
+            id: Some("1".into()),
+            code: Some("42".into()),
+            ..Request::new("eval")
+        };
+        let responses = server.dispatch(&request);
+        assert_eq!(responses.len(), 2);
+        assert!(responses.iter().all(|r| r.id.as_deref() == Some("1")));
+        assert!(!responses[0].is_done());
+        assert!(responses[1].is_done());
+
+        let unknown = server.dispatch(&Request::new("frobnicate"));
+        assert!(unknown[0].has_status("unknown-op") && unknown[0].is_done());
+    }
+
+    #[test]
+    fn test_malformed_request_keeps_connection() {
+        // no `op`, not a dictionary, then a valid request
+        let input = [&b"d2:id1:7e"[..], b"li1ee", b"d2:id1:82:op4:evale"].concat();
+        let mut output = Vec::new();
+        let server = Server::new().handler(echo);
+        server.serve_connection(&input[..], &mut output).unwrap();
+
+        let responses: Vec<_> = ValueStream::new(&output[..])
+            .map(|v| Response::from_value(&v.unwrap()).unwrap())
+            .collect();
+        assert_eq!(responses[0].id.as_deref(), Some("7"));
+        assert!(responses[0].has_status("malformed-request") && responses[0].is_done());
+        assert!(responses[0].err.as_deref().unwrap().contains("`op`"));
+        assert!(responses[1].id.is_none() && responses[1].has_status("error"));
+        assert_eq!(responses[2].id.as_deref(), Some("8"));
+        assert!(responses.last().unwrap().is_done());
+    }
+
+    #[test]
+    fn test_server_with_client() {
+        let listener = Server::new().handler(echo).bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || listener.serve());
+
+        let client = Client::connect(addr).unwrap();
+        let session = client.clone_session().unwrap();
+        let values: Vec<_> = client
+            .eval(&session, "(+ 1 2)")
+            .unwrap()
+            .filter_map(|r| r.unwrap().value)
+            .collect();
+        assert_eq!(values, vec!["(+ 1 2)".to_string()]);
+
+        let stale = client.eval("no-such-session", "1").unwrap().next().unwrap();
+        assert!(stale.unwrap().has_status("unknown-session"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_server_over_unix_socket() {
+        let path = std::env::temp_dir().join(format!("bencode-nrepl-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let server_path = path.clone();
+        thread::spawn(move || Server::new().handler(echo).serve_unix(server_path));
+
+        let mut client = None;
+        for _ in 0..100 {
+            match Client::connect_unix(&path) {
+                Ok(c) => {
+                    client = Some(c);
+                    break;
+                }
+                Err(_) => thread::sleep(std::time::Duration::from_millis(10)),
+            }
+        }
+        let client = client.expect("server did not come up");
+        let session = client.clone_session().unwrap();
+        let result = client.eval(&session, "1").unwrap().aggregate().unwrap();
+        assert_eq!(result.value(), Some("1"));
+        let _ = std::fs::remove_file(&path);
+    }
+}
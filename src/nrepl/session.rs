@@ -0,0 +1,161 @@
+use super::{Request, Response};
+use crate::{BencodeError, Result};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+#[derive(Default)]
+struct Session {
+    pending: HashSet<String>,
+    inbox: VecDeque<Response>,
+}
+
+/// Client-side bookkeeping for multiplexing several sessions over one
+/// connection: which sessions exist, which requests are still waiting for
+/// `done`, and a per-session inbox that incoming responses are routed into.
+///
+/// The manager does no I/O; feed it outgoing requests with [`track`] and
+/// incoming responses with [`route`].
+///
+/// [`track`]: SessionManager::track
+/// [`route`]: SessionManager::route
+#[derive(Default)]
+pub struct SessionManager {
+    sessions: HashMap<String, Session>,
+    // request id -> session id
+    requests: HashMap<String, String>,
+}
+
+impl SessionManager {
+    pub fn new() -> Self {
+        SessionManager::default()
+    }
+
+    pub fn add_session(&mut self, session: &str) {
+        self.sessions.entry(session.to_string()).or_default();
+    }
+
+    /// Forget a session, returning the ids of requests that never finished.
+    pub fn remove_session(&mut self, session: &str) -> Vec<String> {
+        let mut abandoned: Vec<String> = match self.sessions.remove(session) {
+            Some(state) => state.pending.into_iter().collect(),
+            None => vec![],
+        };
+        for id in &abandoned {
+            self.requests.remove(id);
+        }
+        abandoned.sort();
+        abandoned
+    }
+
+    pub fn sessions(&self) -> Vec<&str> {
+        let mut ids: Vec<&str> = self.sessions.keys().map(String::as_str).collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    /// Record an outgoing request. It must carry both an id and a known session.
+    pub fn track(&mut self, request: &Request) -> Result<()> {
+        let id = request
+            .id
+            .as_ref()
+            .ok_or_else(|| BencodeError::msg("Request has no id"))?;
+        let session = request
+            .session
+            .as_ref()
+            .ok_or_else(|| BencodeError::msg("Request has no session"))?;
+        let state = self
+            .sessions
+            .get_mut(session)
+            .ok_or_else(|| BencodeError::msg(format!("Unknown session {}", session)))?;
+        state.pending.insert(id.clone());
+        self.requests.insert(id.clone(), session.clone());
+        Ok(())
+    }
+
+    /// Route an incoming response to its session's inbox, returning that
+    /// session's id. The request is no longer pending once `done` arrives.
+    /// Responses for unknown requests and sessions are dropped.
+    pub fn route(&mut self, response: Response) -> Option<String> {
+        let by_request = response
+            .id
+            .as_ref()
+            .and_then(|id| self.requests.get(id))
+            .cloned();
+        let session = by_request.or_else(|| response.session.clone())?;
+        let state = self.sessions.get_mut(&session)?;
+        if response.is_done() {
+            if let Some(id) = &response.id {
+                state.pending.remove(id);
+                self.requests.remove(id);
+            }
+        }
+        state.inbox.push_back(response);
+        Some(session)
+    }
+
+    /// Take all responses routed to `session` so far.
+    pub fn take_responses(&mut self, session: &str) -> Vec<Response> {
+        self.sessions
+            .get_mut(session)
+            .map(|s| s.inbox.drain(..).collect())
+            .unwrap_or_default()
+    }
+
+    /// Ids of requests in `session` still waiting for `done`.
+    pub fn pending(&self, session: &str) -> Vec<&str> {
+        let mut ids: Vec<&str> = self
+            .sessions
+            .get(session)
+            .map(|s| s.pending.iter().map(String::as_str).collect())
+            .unwrap_or_default();
+        ids.sort_unstable();
+        ids
+    }
+
+    pub fn is_pending(&self, request_id: &str) -> bool {
+        self.requests.contains_key(request_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(id: &str, session: &str) -> Request {
+        Request {
+            id: Some(id.into()),
+            session: Some(session.into()),
+            ..Request::new("eval")
+        }
+    }
+
+    fn response(id: &str, done: bool) -> Response {
+        Response {
+            id: Some(id.into()),
+            status: if done { vec!["done".into()] } else { vec![] },
+            ..Response::default()
+        }
+    }
+
+    #[test]
+    fn test_routes_by_request_id() {
+        let mut manager = SessionManager::new();
+        manager.add_session("a");
+        manager.add_session("b");
+        manager.track(&request("1", "a")).unwrap();
+        manager.track(&request("2", "b")).unwrap();
+        assert!(manager.track(&request("3", "c")).is_err());
+
+        assert_eq!(manager.route(response("2", false)).as_deref(), Some("b"));
+        assert_eq!(manager.route(response("1", true)).as_deref(), Some("a"));
+        assert_eq!(manager.route(response("9", true)), None);
+
+        assert_eq!(manager.pending("a"), Vec::<&str>::new());
+        assert_eq!(manager.pending("b"), vec!["2"]);
+        assert_eq!(manager.take_responses("b").len(), 1);
+        assert!(manager.take_responses("b").is_empty());
+
+        assert_eq!(manager.remove_session("b"), vec!["2".to_string()]);
+        assert!(!manager.is_pending("2"));
+        assert_eq!(manager.sessions(), vec!["a"]);
+    }
+}
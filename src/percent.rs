@@ -0,0 +1,60 @@
+//! Percent-encoding for the URL-based formats: magnet links and tracker
+//! announce queries.
+
+use crate::{BencodeError, Result};
+
+/// Encode `bytes`, keeping only RFC 3986 unreserved characters as-is.
+pub(crate) fn encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len());
+    for &b in bytes {
+        if b.is_ascii_alphanumeric() || b"-._~".contains(&b) {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{:02X}", b));
+        }
+    }
+    out
+}
+
+/// Decode `%XX` escapes; anything else is taken literally.
+pub(crate) fn decode(s: &str) -> Result<Vec<u8>> {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let byte = bytes
+                .get(i + 1..i + 3)
+                .and_then(|hex| std::str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                .ok_or_else(|| {
+                    BencodeError::msg(format!("Invalid percent escape at offset {}", i))
+                })?;
+            out.push(byte);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        assert_eq!(encode(b"a b/c~"), "a%20b%2Fc~");
+        assert_eq!(encode(&[0x00, 0xff]), "%00%FF");
+    }
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(decode("a%20b%2fc").unwrap(), b"a b/c");
+        assert_eq!(decode(&encode(&[0, 200, 255])).unwrap(), vec![0, 200, 255]);
+        assert!(decode("50%").is_err());
+        assert!(decode("%zz").is_err());
+    }
+}
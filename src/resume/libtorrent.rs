@@ -0,0 +1,271 @@
+use super::{fixed, strings, to_list};
+use crate::fields::{require, wrong_type, DictBuilder, Fields};
+use crate::{parse_bencode, BencodeError, EncodeOptions, FromBencode, Result, ToBencode, Value};
+use std::collections::HashMap;
+
+/// `file-format` of every libtorrent resume file.
+const FILE_FORMAT: &str = "libtorrent resume file";
+
+const FASTRESUME_FIELDS: &[&str] = &[
+    "file-format",
+    "file-version",
+    "info-hash",
+    "info-hash2",
+    "name",
+    "save_path",
+    "pieces",
+    "piece_priority",
+    "file_priority",
+    "trackers",
+    "url-list",
+    "added_time",
+    "completed_time",
+    "active_time",
+    "seeding_time",
+    "finished_time",
+    "total_uploaded",
+    "total_downloaded",
+    "paused",
+];
+
+/// A libtorrent `.fastresume` document.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FastResume {
+    pub file_version: Option<i64>,
+    pub info_hash: Option<[u8; 20]>,
+    /// `info-hash2`: the v2 infohash of v2 and hybrid torrents.
+    pub info_hash_v2: Option<[u8; 32]>,
+    pub name: Option<String>,
+    pub save_path: Option<String>,
+    /// One byte per piece: bit 0 is set when the piece is downloaded, bit 1
+    /// when it has also been verified.
+    pub pieces: Vec<u8>,
+    /// One byte per piece, 0 (skip) to 7 (top priority).
+    pub piece_priority: Vec<u8>,
+    /// One entry per file, 0 (skip) to 7 (top priority).
+    pub file_priority: Vec<u8>,
+    /// Tracker tiers, like a torrent's `announce-list`.
+    pub trackers: Vec<Vec<String>>,
+    /// `url-list`: web seeds.
+    pub url_list: Vec<String>,
+    /// Unix timestamp.
+    pub added_time: Option<i64>,
+    /// Unix timestamp.
+    pub completed_time: Option<i64>,
+    /// Seconds the torrent has been running.
+    pub active_time: Option<i64>,
+    pub seeding_time: Option<i64>,
+    pub finished_time: Option<i64>,
+    pub total_uploaded: Option<i64>,
+    pub total_downloaded: Option<i64>,
+    pub paused: Option<bool>,
+    pub extra: HashMap<String, Value>,
+}
+
+impl FastResume {
+    /// Decode a `.fastresume` file.
+    pub fn from_bytes(buf: &[u8]) -> Result<FastResume> {
+        match parse_bencode(&mut &buf[..])? {
+            Some(value) => FastResume::from_value(&value),
+            None => Err(BencodeError::msg("Empty resume file")),
+        }
+    }
+
+    /// Encode with sorted keys, as libtorrent writes it. Fails if a value in
+    /// `extra` holds a dictionary with a non-string key.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        self.to_value()
+            .write_bencode_with(&mut buf, EncodeOptions::strict())?;
+        Ok(buf)
+    }
+
+    /// Whether piece `index` is downloaded.
+    pub fn has_piece(&self, index: usize) -> bool {
+        self.pieces.get(index).is_some_and(|p| p & 1 != 0)
+    }
+
+    /// Mark piece `index` as downloaded or missing, growing the bitfield if
+    /// needed. Clearing a piece also clears its verified bit.
+    pub fn set_piece(&mut self, index: usize, have: bool) {
+        if index >= self.pieces.len() {
+            self.pieces.resize(index + 1, 0);
+        }
+        self.pieces[index] = if have { self.pieces[index] | 1 } else { 0 };
+    }
+
+    /// Number of downloaded pieces.
+    pub fn pieces_done(&self) -> usize {
+        self.pieces.iter().filter(|p| *p & 1 != 0).count()
+    }
+}
+
+impl FromBencode for FastResume {
+    fn from_value(value: &Value) -> Result<Self> {
+        let fields = Fields::new(value)?;
+        let format = require("file-format", fields.string("file-format")?)?;
+        if format != FILE_FORMAT {
+            return Err(BencodeError::msg(format!(
+                "expected `{}`, found `{}`",
+                FILE_FORMAT, format
+            ))
+            .in_field("file-format"));
+        }
+        let info_hash = match fields.bytes("info-hash")? {
+            Some(hash) => Some(fixed(hash, "info-hash")?),
+            None => None,
+        };
+        let info_hash_v2 = match fields.bytes("info-hash2")? {
+            Some(hash) => Some(fixed(hash, "info-hash2")?),
+            None => None,
+        };
+        let file_priority =
+            fields
+                .list("file_priority")?
+                .unwrap_or(&[])
+                .iter()
+                .map(|p| match p {
+                    Value::Int(p) if (0..=i64::from(u8::MAX)).contains(p) => Ok(*p as u8),
+                    Value::Int(p) => Err(BencodeError::msg(format!("{} is out of range", p))
+                        .in_field("file_priority")),
+                    _ => Err(wrong_type("file_priority", "list of integers", p)),
+                })
+                .collect::<Result<_>>()?;
+        let trackers = fields
+            .list("trackers")?
+            .unwrap_or(&[])
+            .iter()
+            .map(|tier| match tier {
+                Value::List(urls) => urls
+                    .iter()
+                    .map(|url| {
+                        url.as_bytes()
+                            .map(|b| String::from_utf8_lossy(b).into_owned())
+                            .ok_or_else(|| wrong_type("trackers", "list of strings", url))
+                    })
+                    .collect(),
+                _ => Err(wrong_type("trackers", "list of lists", tier)),
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(FastResume {
+            file_version: fields.int("file-version")?,
+            info_hash,
+            info_hash_v2,
+            name: fields.string("name")?,
+            save_path: fields.string("save_path")?,
+            pieces: fields.bytes("pieces")?.unwrap_or(&[]).to_vec(),
+            piece_priority: fields.bytes("piece_priority")?.unwrap_or(&[]).to_vec(),
+            file_priority,
+            trackers,
+            url_list: strings(&fields, "url-list")?,
+            added_time: fields.int("added_time")?,
+            completed_time: fields.int("completed_time")?,
+            active_time: fields.int("active_time")?,
+            seeding_time: fields.int("seeding_time")?,
+            finished_time: fields.int("finished_time")?,
+            total_uploaded: fields.int("total_uploaded")?,
+            total_downloaded: fields.int("total_downloaded")?,
+            paused: fields.int("paused")?.map(|p| p != 0),
+            extra: fields.rest(FASTRESUME_FIELDS),
+        })
+    }
+}
+
+impl ToBencode for FastResume {
+    fn to_value(&self) -> Value {
+        let non_empty = |bytes: &Vec<u8>| Some(bytes.clone()).filter(|b| !b.is_empty());
+        let file_priority = Value::List(
+            self.file_priority
+                .iter()
+                .map(|p| Value::from(i64::from(*p)))
+                .collect(),
+        );
+        let trackers = Value::List(
+            self.trackers
+                .iter()
+                .map(|tier| Value::List(tier.iter().map(|u| Value::from(u.as_str())).collect()))
+                .collect(),
+        );
+        DictBuilder::new()
+            .set("file-format", FILE_FORMAT)
+            .opt("file-version", self.file_version)
+            .opt("info-hash", self.info_hash.map(|h| h.to_vec()))
+            .opt("info-hash2", self.info_hash_v2.map(|h| h.to_vec()))
+            .opt("name", self.name.as_deref())
+            .opt("save_path", self.save_path.as_deref())
+            .opt("pieces", non_empty(&self.pieces))
+            .opt("piece_priority", non_empty(&self.piece_priority))
+            .opt(
+                "file_priority",
+                Some(file_priority).filter(|_| !self.file_priority.is_empty()),
+            )
+            .opt(
+                "trackers",
+                Some(trackers).filter(|_| !self.trackers.is_empty()),
+            )
+            .opt("url-list", to_list(&self.url_list))
+            .opt("added_time", self.added_time)
+            .opt("completed_time", self.completed_time)
+            .opt("active_time", self.active_time)
+            .opt("seeding_time", self.seeding_time)
+            .opt("finished_time", self.finished_time)
+            .opt("total_uploaded", self.total_uploaded)
+            .opt("total_downloaded", self.total_downloaded)
+            .opt("paused", self.paused.map(i64::from))
+            .extend(&self.extra)
+            .build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Vec<u8> {
+        let mut buf = b"d11:active_timei3600e10:added_timei1600000000e11:file-format22:libtorrent resume file12:file-versioni1e13:file_priorityli1ei0ei7ee9:info-hash20:".to_vec();
+        buf.extend_from_slice(&[7; 20]);
+        buf.extend_from_slice(
+            b"12:libtorrent-x1:y4:name3:abc6:pausedi1e6:pieces4:\x03\x01\x00\x03",
+        );
+        buf.extend_from_slice(b"9:save_path5:/data8:trackersll5:udp:a5:udp:bel6:http:ceee");
+        buf
+    }
+
+    #[test]
+    fn test_fastresume_roundtrip() {
+        let resume = FastResume::from_bytes(&sample()).unwrap();
+        assert_eq!(resume.info_hash, Some([7; 20]));
+        assert_eq!(resume.name.as_deref(), Some("abc"));
+        assert_eq!(resume.save_path.as_deref(), Some("/data"));
+        assert_eq!(resume.file_priority, vec![1, 0, 7]);
+        assert_eq!(
+            resume.trackers,
+            vec![vec!["udp:a", "udp:b"], vec!["http:c"]]
+        );
+        assert_eq!(resume.active_time, Some(3600));
+        assert_eq!(resume.paused, Some(true));
+        assert_eq!(resume.pieces_done(), 3);
+        assert!(resume.has_piece(1) && !resume.has_piece(2) && !resume.has_piece(9));
+        assert_eq!(resume.extra["libtorrent-x"], Value::from("y"));
+        assert_eq!(resume.to_bytes().unwrap(), sample());
+    }
+
+    #[test]
+    fn test_fastresume_edit_and_reject() {
+        let mut resume = FastResume::from_bytes(&sample()).unwrap();
+        resume.set_piece(0, false);
+        resume.set_piece(5, true);
+        assert_eq!(resume.pieces, vec![0, 1, 0, 3, 0, 1]);
+        let resume = FastResume::from_bytes(&resume.to_bytes().unwrap()).unwrap();
+        assert_eq!(resume.pieces_done(), 3);
+
+        let err = |buf: &[u8]| FastResume::from_bytes(buf).unwrap_err().to_string();
+        assert!(err(b"de").contains("Missing field `file-format`"));
+        assert!(err(b"d11:file-format3:fooe").contains("Field `file-format`"));
+        assert!(
+            err(b"d11:file-format22:libtorrent resume file9:info-hash1:xe")
+                .contains("expected 20 bytes")
+        );
+    }
+}
@@ -0,0 +1,49 @@
+//! Torrent clients' bencoded resume and state files.
+//!
+//! These are not part of any BEP; the types follow what the clients
+//! themselves write, and keep every key they do not model in `extra` so a
+//! file can be edited and written back without losing state.
+
+mod libtorrent;
+mod utorrent;
+
+pub use libtorrent::FastResume;
+pub use utorrent::{UtorrentEntry, UtorrentResume, UtorrentSettings};
+
+use crate::fields::{wrong_type, Fields};
+use crate::{BencodeError, Result, Value};
+
+fn fixed<const N: usize>(bytes: &[u8], key: &str) -> Result<[u8; N]> {
+    if bytes.len() != N {
+        return Err(
+            BencodeError::msg(format!("expected {} bytes, found {}", N, bytes.len())).in_field(key),
+        );
+    }
+    let mut out = [0; N];
+    out.copy_from_slice(bytes);
+    Ok(out)
+}
+
+/// A list of strings; missing means empty.
+fn strings(fields: &Fields, key: &str) -> Result<Vec<String>> {
+    fields
+        .list(key)?
+        .unwrap_or(&[])
+        .iter()
+        .map(|item| {
+            item.as_bytes()
+                .map(|b| String::from_utf8_lossy(b).into_owned())
+                .ok_or_else(|| wrong_type(key, "list of strings", item))
+        })
+        .collect()
+}
+
+/// `None` for an empty list, so it is left out of the output.
+fn to_list(strings: &[String]) -> Option<Value> {
+    if strings.is_empty() {
+        return None;
+    }
+    Some(Value::List(
+        strings.iter().map(|s| Value::from(s.as_str())).collect(),
+    ))
+}
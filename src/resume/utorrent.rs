@@ -0,0 +1,300 @@
+use super::{fixed, strings, to_list};
+use crate::fields::{expected, within, DictBuilder, Fields};
+use crate::hash::{to_hex, Sha1};
+use crate::{
+    parse_bencode, BencodeError, EncodeOptions, FromBencode, HMap, Result, ToBencode, Value,
+};
+use std::collections::{BTreeMap, HashMap};
+
+/// Checksum key uTorrent adds to its state files.
+const FILEGUARD: &str = ".fileguard";
+
+const ENTRY_FIELDS: &[&str] = &[
+    "caption",
+    "path",
+    "info",
+    "label",
+    "labels",
+    "added_on",
+    "completed_on",
+    "downloaded",
+    "uploaded",
+    "trackers",
+];
+
+/// uTorrent's `resume.dat`: one entry per torrent, keyed by the name of its
+/// `.torrent` file.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct UtorrentResume {
+    pub torrents: BTreeMap<String, UtorrentEntry>,
+    /// Keys that are not torrents, such as `rec` and `.fileguard`.
+    pub extra: HashMap<String, Value>,
+}
+
+/// A torrent's state in `resume.dat`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct UtorrentEntry {
+    /// `caption`: the name shown in the UI.
+    pub caption: Option<String>,
+    /// `path`: where the data is saved.
+    pub path: Option<String>,
+    /// `info`
+    pub info_hash: Option<[u8; 20]>,
+    /// `label`, from older versions.
+    pub label: Option<String>,
+    pub labels: Vec<String>,
+    /// Unix timestamp.
+    pub added_on: Option<i64>,
+    /// Unix timestamp, 0 while incomplete.
+    pub completed_on: Option<i64>,
+    pub downloaded: Option<i64>,
+    pub uploaded: Option<i64>,
+    pub trackers: Vec<String>,
+    pub extra: HashMap<String, Value>,
+}
+
+/// uTorrent's `settings.dat`: a flat dictionary of settings.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct UtorrentSettings {
+    pub settings: BTreeMap<String, Value>,
+}
+
+impl UtorrentResume {
+    /// Decode a `resume.dat` file.
+    pub fn from_bytes(buf: &[u8]) -> Result<UtorrentResume> {
+        UtorrentResume::from_value(&read(buf)?)
+    }
+
+    /// Encode with sorted keys, refreshing `.fileguard` if the file had one.
+    /// Fails if a value holds a dictionary with a non-string key.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        write(self.to_value())
+    }
+
+    /// Every torrent with label `label`, in either the old or new field.
+    pub fn labelled<'a>(
+        &'a self,
+        label: &'a str,
+    ) -> impl Iterator<Item = (&'a str, &'a UtorrentEntry)> {
+        self.torrents
+            .iter()
+            .filter(move |(_, entry)| {
+                entry.label.as_deref() == Some(label) || entry.labels.iter().any(|l| l == label)
+            })
+            .map(|(name, entry)| (name.as_str(), entry))
+    }
+}
+
+impl FromBencode for UtorrentResume {
+    fn from_value(value: &Value) -> Result<Self> {
+        let map = match value {
+            Value::Map(map) => map,
+            _ => return Err(expected("dictionary", value)),
+        };
+        let mut resume = UtorrentResume::default();
+        for (key, value) in &map.0 {
+            let key = match key.as_bytes() {
+                Some(key) => String::from_utf8_lossy(key).into_owned(),
+                None => continue,
+            };
+            // torrents are keyed by file name; `rec` and `.fileguard` are not
+            if key.ends_with(".torrent") {
+                let entry = within(&key, UtorrentEntry::from_value(value))?;
+                resume.torrents.insert(key, entry);
+            } else {
+                resume.extra.insert(key, value.clone());
+            }
+        }
+        Ok(resume)
+    }
+}
+
+impl ToBencode for UtorrentResume {
+    fn to_value(&self) -> Value {
+        let mut map: HashMap<Value, Value> = self
+            .torrents
+            .iter()
+            .map(|(name, entry)| (Value::from(name.as_str()), entry.to_value()))
+            .collect();
+        for (key, value) in &self.extra {
+            map.insert(Value::from(key.as_str()), value.clone());
+        }
+        Value::from(map)
+    }
+}
+
+impl FromBencode for UtorrentEntry {
+    fn from_value(value: &Value) -> Result<Self> {
+        let fields = Fields::new(value)?;
+        let info_hash = match fields.bytes("info")? {
+            Some(hash) => Some(fixed(hash, "info")?),
+            None => None,
+        };
+        Ok(UtorrentEntry {
+            caption: fields.string("caption")?,
+            path: fields.string("path")?,
+            info_hash,
+            label: fields.string("label")?,
+            labels: strings(&fields, "labels")?,
+            added_on: fields.int("added_on")?,
+            completed_on: fields.int("completed_on")?,
+            downloaded: fields.int("downloaded")?,
+            uploaded: fields.int("uploaded")?,
+            trackers: strings(&fields, "trackers")?,
+            extra: fields.rest(ENTRY_FIELDS),
+        })
+    }
+}
+
+impl ToBencode for UtorrentEntry {
+    fn to_value(&self) -> Value {
+        DictBuilder::new()
+            .opt("caption", self.caption.as_deref())
+            .opt("path", self.path.as_deref())
+            .opt("info", self.info_hash.map(|h| h.to_vec()))
+            .opt("label", self.label.as_deref())
+            .opt("labels", to_list(&self.labels))
+            .opt("added_on", self.added_on)
+            .opt("completed_on", self.completed_on)
+            .opt("downloaded", self.downloaded)
+            .opt("uploaded", self.uploaded)
+            .opt("trackers", to_list(&self.trackers))
+            .extend(&self.extra)
+            .build()
+    }
+}
+
+impl UtorrentSettings {
+    /// Decode a `settings.dat` file.
+    pub fn from_bytes(buf: &[u8]) -> Result<UtorrentSettings> {
+        UtorrentSettings::from_value(&read(buf)?)
+    }
+
+    /// Encode with sorted keys, refreshing `.fileguard` if the file had one.
+    /// Fails if a value holds a dictionary with a non-string key.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        write(self.to_value())
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.settings.get(key)
+    }
+
+    /// A string setting such as `dir_completed_download`.
+    pub fn string(&self, key: &str) -> Option<String> {
+        self.get(key)?
+            .as_bytes()
+            .map(|b| String::from_utf8_lossy(b).into_owned())
+    }
+
+    /// An integer setting; uTorrent stores flags as 0 or 1.
+    pub fn int(&self, key: &str) -> Option<i64> {
+        match self.get(key)? {
+            Value::Int(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn set<V: Into<Value>>(&mut self, key: &str, value: V) {
+        self.settings.insert(key.to_string(), value.into());
+    }
+}
+
+impl FromBencode for UtorrentSettings {
+    fn from_value(value: &Value) -> Result<Self> {
+        Ok(UtorrentSettings {
+            settings: Fields::new(value)?.rest(&[]),
+        })
+    }
+}
+
+impl ToBencode for UtorrentSettings {
+    fn to_value(&self) -> Value {
+        Value::Map(HMap(
+            self.settings
+                .iter()
+                .map(|(k, v)| (Value::from(k.as_str()), v.clone()))
+                .collect(),
+        ))
+    }
+}
+
+fn read(buf: &[u8]) -> Result<Value> {
+    match parse_bencode(&mut &buf[..])? {
+        Some(value) => Ok(value),
+        None => Err(BencodeError::msg("Empty uTorrent state file")),
+    }
+}
+
+/// Encode `value`, replacing any `.fileguard` with the uppercase hex SHA-1 of
+/// the file encoded without it, which is what uTorrent checks on load.
+fn write(mut value: Value) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    let guarded = match &mut value {
+        Value::Map(map) => map.0.remove(&Value::from(FILEGUARD)).is_some(),
+        _ => false,
+    };
+    value.write_bencode_with(&mut buf, EncodeOptions::strict())?;
+    if !guarded {
+        return Ok(buf);
+    }
+    if let Value::Map(map) = &mut value {
+        let guard = to_hex(&Sha1::digest(&buf)).to_ascii_uppercase();
+        map.0.insert(Value::from(FILEGUARD), Value::from(guard));
+    }
+    buf.clear();
+    value.write_bencode_with(&mut buf, EncodeOptions::strict())?;
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resume_dat() {
+        let mut buf =
+            b"d10:.fileguard3:old9:a.torrentd8:added_oni1600000000e7:caption1:a4:info20:".to_vec();
+        buf.extend_from_slice(&[3; 20]);
+        buf.extend_from_slice(b"6:labelsl5:moviee4:path6:C:\\a\\x8:trackersl5:udp:te5:x_keyi1ee");
+        buf.extend_from_slice(b"3:recd1:xi1ee9:b.torrentd5:label5:movie4:path1:bee");
+        let resume = UtorrentResume::from_bytes(&buf).unwrap();
+
+        let a = &resume.torrents["a.torrent"];
+        assert_eq!(a.info_hash, Some([3; 20]));
+        assert_eq!(a.path.as_deref(), Some("C:\\a\\x"));
+        assert_eq!(a.trackers, vec!["udp:t"]);
+        assert_eq!(a.extra["x_key"], Value::Int(1));
+        let movies: Vec<_> = resume.labelled("movie").map(|(name, _)| name).collect();
+        assert_eq!(movies, vec!["a.torrent", "b.torrent"]);
+        assert!(resume.extra.contains_key("rec"));
+
+        let out = resume.to_bytes().unwrap();
+        let reread = UtorrentResume::from_bytes(&out).unwrap();
+        assert_eq!(reread.torrents, resume.torrents);
+        let guard = reread.extra[FILEGUARD].as_bytes().unwrap().to_vec();
+        assert_eq!(guard.len(), 40);
+        let mut unguarded = resume.clone();
+        unguarded.extra.remove(FILEGUARD);
+        let expected = to_hex(&Sha1::digest(&unguarded.to_bytes().unwrap())).to_ascii_uppercase();
+        assert_eq!(guard, expected.as_bytes());
+    }
+
+    #[test]
+    fn test_settings_dat() {
+        let mut settings =
+            UtorrentSettings::from_bytes(b"d22:dir_completed_download4:D:\\x9:bind_porti6881ee")
+                .unwrap();
+        assert_eq!(
+            settings.string("dir_completed_download").as_deref(),
+            Some("D:\\x")
+        );
+        assert_eq!(settings.int("bind_port"), Some(6881));
+        settings.set("bind_port", 7000i64);
+        assert_eq!(
+            settings.to_bytes().unwrap(),
+            b"d9:bind_porti7000e22:dir_completed_download4:D:\\xe".to_vec()
+        );
+        assert!(UtorrentSettings::from_bytes(b"li1ee").is_err());
+    }
+}
@@ -0,0 +1,243 @@
+//! Request/response correlation over a bencode connection.
+//!
+//! Both nREPL and DHT KRPC tag each request dictionary with an id (`id` and
+//! `t` respectively) that the peer echoes back in its responses. [`RpcClient`]
+//! assigns those ids, routes responses to the matching caller from a
+//! background reader thread, and lets any number of requests be in flight at
+//! once.
+
+use crate::codec::ValueStream;
+use crate::{BencodeError, Result, Value};
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+
+/// Senders for in-flight requests, by id. `closed` is set by the reader
+/// thread when the connection ends, after which nothing would wake a new
+/// waiter.
+#[derive(Default)]
+struct Waiters {
+    senders: HashMap<Vec<u8>, Sender<Value>>,
+    closed: bool,
+}
+
+type Pending = Arc<Mutex<Waiters>>;
+
+/// Client side of a bencode RPC connection.
+pub struct RpcClient<W> {
+    writer: Mutex<W>,
+    pending: Pending,
+    next_id: AtomicU64,
+    id_key: Value,
+}
+
+/// Responses to one in-flight request. Peers such as nREPL may send several
+/// responses per request; each `recv` returns the next one.
+pub struct Call {
+    id: Vec<u8>,
+    rx: Receiver<Value>,
+    pending: Pending,
+}
+
+impl<W: Write> RpcClient<W> {
+    /// Start a client using `id` as the correlation key, as nREPL does.
+    pub fn new<R: Read + Send + 'static>(reader: R, writer: W) -> Self {
+        RpcClient::with_id_key(reader, writer, "id")
+    }
+
+    /// Start a client using a custom correlation key, e.g. `t` for KRPC.
+    pub fn with_id_key<R: Read + Send + 'static>(reader: R, writer: W, id_key: &str) -> Self {
+        let pending: Pending = Arc::default();
+        let id_key = Value::from(id_key);
+        spawn_reader(reader, Arc::clone(&pending), id_key.clone());
+        RpcClient {
+            writer: Mutex::new(writer),
+            pending,
+            next_id: AtomicU64::new(1),
+            id_key,
+        }
+    }
+
+    /// Send `request` (a dictionary) with a fresh id and return a handle for
+    /// its responses. Fails once the connection has closed.
+    pub fn send(&self, request: Value) -> Result<Call> {
+        let mut map = match request {
+            Value::Map(hm) => hm,
+            _ => return Err(BencodeError::msg("RPC request must be a dictionary")),
+        };
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed).to_string();
+        map.0.insert(self.id_key.clone(), Value::from(id.as_str()));
+
+        let (tx, rx) = mpsc::channel();
+        {
+            let mut pending = lock(&self.pending)?;
+            if pending.closed {
+                return Err(BencodeError::msg("RPC connection closed"));
+            }
+            pending.senders.insert(id.clone().into_bytes(), tx);
+        }
+        let call = Call {
+            id: id.into_bytes(),
+            rx,
+            pending: Arc::clone(&self.pending),
+        };
+
+        let mut writer = self
+            .writer
+            .lock()
+            .map_err(|_| BencodeError::msg("RPC writer lock poisoned"))?;
+        Value::Map(map).write_bencode(&mut *writer)?;
+        writer.flush()?;
+        Ok(call)
+    }
+
+    /// Send `request` and wait up to `timeout` for its first response.
+    pub fn call(&self, request: Value, timeout: Duration) -> Result<Value> {
+        self.send(request)?.recv(timeout)
+    }
+
+    /// Number of requests still waiting for responses.
+    pub fn in_flight(&self) -> usize {
+        lock(&self.pending).map_or(0, |p| p.senders.len())
+    }
+}
+
+impl Call {
+    /// The id assigned to this request.
+    pub fn id(&self) -> &[u8] {
+        &self.id
+    }
+
+    /// Wait up to `timeout` for the next response to this request.
+    pub fn recv(&self, timeout: Duration) -> Result<Value> {
+        match self.rx.recv_timeout(timeout) {
+            Ok(value) => Ok(value),
+            Err(RecvTimeoutError::Timeout) => Err(BencodeError::Io(io::ErrorKind::TimedOut.into())),
+            Err(RecvTimeoutError::Disconnected) => Err(BencodeError::msg("RPC connection closed")),
+        }
+    }
+}
+
+impl Drop for Call {
+    fn drop(&mut self) {
+        if let Ok(mut pending) = self.pending.lock() {
+            pending.senders.remove(&self.id);
+        }
+    }
+}
+
+fn lock(pending: &Pending) -> Result<MutexGuard<'_, Waiters>> {
+    pending
+        .lock()
+        .map_err(|_| BencodeError::msg("RPC state lock poisoned"))
+}
+
+fn spawn_reader<R: Read + Send + 'static>(reader: R, pending: Pending, id_key: Value) {
+    thread::spawn(move || {
+        for value in ValueStream::new(reader) {
+            let value = match value {
+                Ok(v) => v,
+                Err(_) => break,
+            };
+            let id = match &value {
+                Value::Map(hm) => hm
+                    .get(&id_key)
+                    .and_then(Value::as_bytes)
+                    .map(<[u8]>::to_vec),
+                _ => None,
+            };
+            // responses without a known id have nobody waiting for them
+            if let Some(id) = id {
+                if let Ok(pending) = pending.lock() {
+                    if let Some(tx) = pending.senders.get(&id) {
+                        let _ = tx.send(value);
+                    }
+                }
+            }
+        }
+        // connection gone: wake every waiter with a disconnect, and refuse
+        // new ones
+        if let Ok(mut pending) = pending.lock() {
+            pending.closed = true;
+            pending.senders.clear();
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{TcpListener, TcpStream};
+
+    // Echo server answering each request twice, in reverse order of arrival
+    // for every pair of requests.
+    fn serve(listener: TcpListener) {
+        let (stream, _) = listener.accept().unwrap();
+        let mut writer = stream.try_clone().unwrap();
+        let mut held: Option<Value> = None;
+        for request in ValueStream::new(stream) {
+            let request = request.unwrap();
+            match held.take() {
+                None => held = Some(request),
+                Some(first) => {
+                    for v in &[&request, &first, &request, &first] {
+                        v.write_bencode(&mut writer).unwrap();
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_rpc_routes_responses_by_id() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || serve(listener));
+
+        let stream = TcpStream::connect(addr).unwrap();
+        let client = RpcClient::new(stream.try_clone().unwrap(), stream);
+
+        let mut req = HashMap::new();
+        req.insert("op", "first");
+        let first = client.send(Value::from(req)).unwrap();
+        let mut req = HashMap::new();
+        req.insert("op", "second");
+        let second = client.send(Value::from(req)).unwrap();
+        assert_eq!(client.in_flight(), 2);
+
+        let timeout = Duration::from_secs(5);
+        for call in &[&first, &second] {
+            for _ in 0..2 {
+                let response = call.recv(timeout).unwrap();
+                let id = match &response {
+                    Value::Map(hm) => hm.get(&Value::from("id")).cloned(),
+                    _ => None,
+                };
+                assert_eq!(id.as_ref().and_then(Value::as_bytes), Some(call.id()));
+            }
+        }
+        assert!(first.recv(Duration::from_millis(10)).is_err());
+        drop(first);
+        drop(second);
+        assert_eq!(client.in_flight(), 0);
+    }
+
+    #[test]
+    fn test_rpc_send_fails_after_close() {
+        let client = RpcClient::new(io::empty(), io::sink());
+        let request = || Value::from(HashMap::from([("op", "ping")]));
+        // calls sent before the reader thread sees the end are woken by it
+        let err = loop {
+            match client.send(request()) {
+                Ok(call) => assert!(call.recv(Duration::from_secs(5)).is_err()),
+                Err(err) => break err,
+            }
+        };
+        assert!(err.to_string().contains("closed"));
+        assert_eq!(client.in_flight(), 0);
+    }
+}
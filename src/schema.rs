@@ -0,0 +1,406 @@
+//! Declarative structure checks for decoded values.
+//!
+//! A [`Schema`] describes what a value should look like: its type, integer
+//! ranges, string and list lengths, the schema of list elements, and which
+//! dictionary keys are required or allowed. [`Schema::validate`] walks a
+//! [`Value`] and reports every [`Violation`] it finds rather than stopping at
+//! the first, which makes it suitable for rejecting uploads or RPC arguments
+//! with a useful message.
+//!
+//! ```
+//! use bencode_rs::schema::Schema;
+//!
+//! let schema = Schema::dict()
+//!     .required("name", Schema::string().utf8().length(1, 255))
+//!     .required("files", Schema::list(Schema::int().range(0, i64::MAX)).length(1, 1000))
+//!     .optional("comment", Schema::string());
+//!
+//! let value = bencode_rs::parse_bencode(&mut &b"d5:filesli1ei-2ee4:name0:e"[..])
+//!     .unwrap()
+//!     .unwrap();
+//! let violations = schema.validate(&value);
+//! let messages: Vec<String> = violations.iter().map(|v| v.to_string()).collect();
+//! assert_eq!(
+//!     messages,
+//!     ["files[1]: -2 is below the minimum 0", "name: length 0 is below the minimum 1"]
+//! );
+//! ```
+
+use crate::fields::type_name;
+use crate::Value;
+use std::fmt::{self, Display};
+
+#[derive(Clone, Debug)]
+enum Kind {
+    Any,
+    Int {
+        min: Option<i64>,
+        max: Option<i64>,
+    },
+    Str {
+        utf8: bool,
+    },
+    List(Box<Schema>),
+    Dict {
+        fields: Vec<(String, Schema, bool)>,
+        deny_unknown: bool,
+    },
+    OneOf(Vec<Schema>),
+}
+
+/// The expected shape of a value. Build one with the constructors and
+/// refine it with the chained methods; methods that do not apply to a
+/// schema's type are ignored.
+#[derive(Clone, Debug)]
+pub struct Schema {
+    kind: Kind,
+    min_len: Option<usize>,
+    max_len: Option<usize>,
+}
+
+/// One way in which a value does not match its schema.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Violation {
+    /// Where the problem is, as in `info.files[3].length`; empty for the
+    /// value itself.
+    pub path: String,
+    pub message: String,
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{}: {}", self.path, self.message)
+        }
+    }
+}
+
+impl Schema {
+    fn new(kind: Kind) -> Schema {
+        Schema {
+            kind,
+            min_len: None,
+            max_len: None,
+        }
+    }
+
+    /// Matches any value.
+    pub fn any() -> Schema {
+        Schema::new(Kind::Any)
+    }
+
+    /// An integer, unbounded until [`range`](Schema::range) is set.
+    pub fn int() -> Schema {
+        Schema::new(Kind::Int {
+            min: None,
+            max: None,
+        })
+    }
+
+    /// A byte string, binary or text.
+    pub fn string() -> Schema {
+        Schema::new(Kind::Str { utf8: false })
+    }
+
+    /// A list whose elements all match `items`.
+    pub fn list(items: Schema) -> Schema {
+        Schema::new(Kind::List(Box::new(items)))
+    }
+
+    /// A dictionary with no declared keys; add them with
+    /// [`required`](Schema::required) and [`optional`](Schema::optional).
+    /// Undeclared keys are allowed unless [`deny_unknown`](Schema::deny_unknown)
+    /// is set.
+    pub fn dict() -> Schema {
+        Schema::new(Kind::Dict {
+            fields: Vec::new(),
+            deny_unknown: false,
+        })
+    }
+
+    /// Matches if any of `schemas` does. When none does, the violations of
+    /// the closest candidate are reported: one of the right type if there is
+    /// one, and among those the one with fewest violations.
+    pub fn one_of(schemas: Vec<Schema>) -> Schema {
+        Schema::new(Kind::OneOf(schemas))
+    }
+
+    /// Inclusive bounds for an integer schema.
+    pub fn range(mut self, min: i64, max: i64) -> Schema {
+        if let Kind::Int { .. } = self.kind {
+            self.kind = Kind::Int {
+                min: Some(min),
+                max: Some(max),
+            };
+        }
+        self
+    }
+
+    /// Inclusive bounds on the byte length of a string, the element count of
+    /// a list or the key count of a dictionary.
+    pub fn length(mut self, min: usize, max: usize) -> Schema {
+        self.min_len = Some(min);
+        self.max_len = Some(max);
+        self
+    }
+
+    /// Require a string schema's contents to be valid UTF-8.
+    pub fn utf8(mut self) -> Schema {
+        if let Kind::Str { .. } = self.kind {
+            self.kind = Kind::Str { utf8: true };
+        }
+        self
+    }
+
+    /// Declare a key that must be present in a dictionary.
+    pub fn required(self, key: &str, schema: Schema) -> Schema {
+        self.field(key, schema, true)
+    }
+
+    /// Declare a key that may be present in a dictionary.
+    pub fn optional(self, key: &str, schema: Schema) -> Schema {
+        self.field(key, schema, false)
+    }
+
+    fn field(mut self, key: &str, schema: Schema, required: bool) -> Schema {
+        if let Kind::Dict { fields, .. } = &mut self.kind {
+            fields.push((key.to_string(), schema, required));
+        }
+        self
+    }
+
+    /// Report keys of a dictionary that were not declared.
+    pub fn deny_unknown(mut self) -> Schema {
+        if let Kind::Dict { deny_unknown, .. } = &mut self.kind {
+            *deny_unknown = true;
+        }
+        self
+    }
+
+    /// Check `value` against this schema. An empty result means it matches;
+    /// violations are ordered by path.
+    pub fn validate(&self, value: &Value) -> Vec<Violation> {
+        let mut violations = Vec::new();
+        self.check(value, "", &mut violations);
+        violations.sort_by(|a, b| a.path.cmp(&b.path));
+        violations
+    }
+
+    /// Whether `value` matches, for callers that only need a yes or no.
+    pub fn matches(&self, value: &Value) -> bool {
+        self.validate(value).is_empty()
+    }
+
+    fn check(&self, value: &Value, path: &str, out: &mut Vec<Violation>) {
+        let mut report = |message: String| {
+            out.push(Violation {
+                path: path.to_string(),
+                message,
+            })
+        };
+        match (&self.kind, value) {
+            (Kind::Any, _) => {}
+            (Kind::Int { min, max }, Value::Int(n)) => {
+                if let Some(min) = min.filter(|min| n < min) {
+                    report(format!("{} is below the minimum {}", n, min));
+                }
+                if let Some(max) = max.filter(|max| n > max) {
+                    report(format!("{} is above the maximum {}", n, max));
+                }
+            }
+            (Kind::Str { .. }, Value::Str(s)) => {
+                self.check_length(s.len(), &mut report);
+            }
+            (Kind::Str { utf8 }, Value::Bytes(b)) => {
+                if *utf8 {
+                    report("expected UTF-8 text, found binary string".to_string());
+                }
+                self.check_length(b.len(), &mut report);
+            }
+            (Kind::List(items), Value::List(list)) => {
+                self.check_length(list.len(), &mut report);
+                for (i, item) in list.iter().enumerate() {
+                    items.check(item, &format!("{}[{}]", path, i), out);
+                }
+            }
+            (
+                Kind::Dict {
+                    fields,
+                    deny_unknown,
+                },
+                Value::Map(map),
+            ) => {
+                self.check_length(map.0.len(), &mut report);
+                for (key, schema, required) in fields {
+                    match map.get(&Value::Str(key.clone())) {
+                        Some(child) => schema.check(child, &join(path, key), out),
+                        None if *required => out.push(Violation {
+                            path: path.to_string(),
+                            message: format!("missing key `{}`", key),
+                        }),
+                        None => {}
+                    }
+                }
+                if *deny_unknown {
+                    let mut unknown: Vec<String> = map
+                        .0
+                        .keys()
+                        .filter_map(|key| key.as_bytes())
+                        .map(|key| String::from_utf8_lossy(key).into_owned())
+                        .filter(|key| !fields.iter().any(|(name, _, _)| name == key))
+                        .collect();
+                    unknown.sort();
+                    for key in unknown {
+                        out.push(Violation {
+                            path: path.to_string(),
+                            message: format!("unexpected key `{}`", key),
+                        });
+                    }
+                }
+            }
+            (Kind::OneOf(schemas), _) => {
+                let best = schemas
+                    .iter()
+                    .map(|schema| {
+                        let mut found = Vec::new();
+                        schema.check(value, path, &mut found);
+                        (!accepts_type(&schema.kind, value), found)
+                    })
+                    .min_by_key(|(wrong_type, found)| (*wrong_type, found.len()));
+                match best {
+                    Some((_, found)) => out.extend(found),
+                    None => report("no schema to match against".to_string()),
+                }
+            }
+            (kind, value) => report(format!(
+                "expected {}, found {}",
+                kind_name(kind),
+                type_name(value)
+            )),
+        }
+    }
+
+    fn check_length(&self, len: usize, report: &mut impl FnMut(String)) {
+        if let Some(min) = self.min_len.filter(|&min| len < min) {
+            report(format!("length {} is below the minimum {}", len, min));
+        }
+        if let Some(max) = self.max_len.filter(|&max| len > max) {
+            report(format!("length {} is above the maximum {}", len, max));
+        }
+    }
+}
+
+fn kind_name(kind: &Kind) -> &'static str {
+    match kind {
+        Kind::Int { .. } => "integer",
+        Kind::Str { .. } => "string",
+        Kind::List(_) => "list",
+        Kind::Dict { .. } => "dictionary",
+        Kind::Any | Kind::OneOf(_) => "value",
+    }
+}
+
+fn accepts_type(kind: &Kind, value: &Value) -> bool {
+    match (kind, value) {
+        (Kind::Int { .. }, Value::Int(_))
+        | (Kind::Str { .. }, Value::Str(_))
+        | (Kind::Str { .. }, Value::Bytes(_))
+        | (Kind::List(_), Value::List(_))
+        | (Kind::Dict { .. }, Value::Map(_)) => true,
+        (Kind::OneOf(schemas), _) => schemas.iter().any(|s| accepts_type(&s.kind, value)),
+        (Kind::Any, _) => true,
+        _ => false,
+    }
+}
+
+fn join(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(bytes: &[u8]) -> Value {
+        crate::parse_bencode(&mut &bytes[..]).unwrap().unwrap()
+    }
+
+    fn messages(schema: &Schema, bytes: &[u8]) -> Vec<String> {
+        schema
+            .validate(&parse(bytes))
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn test_schema_nested_paths() {
+        let file = Schema::dict()
+            .required("length", Schema::int().range(0, i64::MAX))
+            .required("path", Schema::list(Schema::string().utf8()).length(1, 64));
+        let schema = Schema::dict().required(
+            "info",
+            Schema::dict()
+                .required("name", Schema::string())
+                .required("files", Schema::list(file)),
+        );
+        assert!(schema.matches(&parse(
+            b"d4:infod5:filesld6:lengthi1e4:pathl1:aeee4:name1:xee"
+        )));
+        assert_eq!(
+            messages(
+                &schema,
+                b"d4:infod5:filesld6:lengthi-1e4:pathl1:\xffeed4:pathleeeee"
+            ),
+            [
+                "info: missing key `name`",
+                "info.files[0].length: -1 is below the minimum 0",
+                "info.files[0].path[0]: expected UTF-8 text, found binary string",
+                "info.files[1]: missing key `length`",
+                "info.files[1].path: length 0 is below the minimum 1",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_schema_types_and_unknown_keys() {
+        let schema = Schema::dict()
+            .required("t", Schema::string().length(2, 2))
+            .optional("a", Schema::dict())
+            .deny_unknown();
+        assert_eq!(
+            messages(&schema, b"d1:ali1ee1:t3:abc1:zi0e1:yi0ee"),
+            [
+                "unexpected key `y`",
+                "unexpected key `z`",
+                "a: expected dictionary, found list",
+                "t: length 3 is above the maximum 2",
+            ]
+        );
+        assert_eq!(
+            messages(&Schema::int(), b"1:x"),
+            ["expected integer, found string"]
+        );
+    }
+
+    #[test]
+    fn test_schema_one_of() {
+        // `announce-list` style: either a single URL or tiers of URLs
+        let schema = Schema::one_of(vec![
+            Schema::string(),
+            Schema::list(Schema::list(Schema::string())),
+        ]);
+        assert!(schema.matches(&parse(b"3:url")));
+        assert!(schema.matches(&parse(b"ll1:ael1:bee")));
+        assert_eq!(
+            messages(&schema, b"ll1:aei1ee"),
+            ["[1]: expected list, found integer"]
+        );
+    }
+}
@@ -0,0 +1,232 @@
+//! Assertions for round-trip tests over checked-in fixture files.
+//!
+//! [`check_roundtrip`] holds a document to two promises: re-encoding the
+//! parsed document reproduces the input byte for byte, and its canonical
+//! encoding parses back to the same value and encodes to the same bytes
+//! again. The `assert_*` functions panic with a hex diff of the first
+//! difference, which stays readable for binary data such as piece hashes.
+//!
+//! Golden files hold expected output. Run the tests with
+//! `BENCODE_UPDATE_GOLDEN=1` set to write the current output to them instead
+//! of comparing.
+//!
+//! ```no_run
+//! use bencode_rs::testing;
+//!
+//! testing::assert_fixtures_roundtrip("tests/fixtures");
+//! let output = std::fs::read("target/out.torrent").unwrap();
+//! testing::assert_golden("tests/fixtures/out.golden", &output);
+//! ```
+
+use crate::{Document, EncodeOptions, Value};
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+/// Set to rewrite golden files rather than compare against them.
+pub const UPDATE_GOLDEN_VAR: &str = "BENCODE_UPDATE_GOLDEN";
+
+/// Describe how `left` and `right` differ: their lengths and the first
+/// differing offset, with hexdumps of both around it. `None` if equal.
+pub fn diff_bytes(left: &[u8], right: &[u8]) -> Option<String> {
+    let offset = left
+        .iter()
+        .zip(right)
+        .position(|(l, r)| l != r)
+        .unwrap_or_else(|| left.len().min(right.len()));
+    if offset == left.len() && offset == right.len() {
+        return None;
+    }
+    let mut out = format!(
+        "bytes differ at offset {} (left {} bytes, right {} bytes)",
+        offset,
+        left.len(),
+        right.len()
+    );
+    // one line of context before the difference and one after
+    let first = (offset / 16).saturating_sub(1) * 16;
+    for line in (first..offset + 32).step_by(16) {
+        if line >= left.len() && line >= right.len() {
+            break;
+        }
+        for (label, bytes) in [(" left", left), ("right", right)] {
+            let _ = write!(out, "\n{} {:08x} ", label, line);
+            let chunk = bytes.get(line..).unwrap_or_default();
+            let chunk = &chunk[..chunk.len().min(16)];
+            for b in chunk {
+                let _ = write!(out, " {:02x}", b);
+            }
+            out.push_str(&"   ".repeat(16 - chunk.len()));
+            let ascii: String = chunk
+                .iter()
+                .map(|&b| if b.is_ascii_graphic() { b as char } else { '.' })
+                .collect();
+            let _ = write!(out, "  |{}|", ascii);
+        }
+        if (line..line + 16).contains(&offset) {
+            let _ = write!(out, "\n{}^^", " ".repeat(16 + 3 * (offset - line)));
+        }
+    }
+    Some(out)
+}
+
+/// Panic with a [`diff_bytes`] description unless the two are equal.
+pub fn assert_bytes_eq(left: &[u8], right: &[u8]) {
+    if let Some(diff) = diff_bytes(left, right) {
+        panic!("{}", diff);
+    }
+}
+
+/// Check that `bytes` survives parse → encode → parse; see the module
+/// documentation for exactly what is compared.
+pub fn check_roundtrip(bytes: &[u8]) -> Result<(), String> {
+    let doc = Document::parse(bytes).map_err(|e| format!("does not parse: {}", e))?;
+    if let Some(diff) = diff_bytes(bytes, &doc.to_bytes()) {
+        return Err(format!("re-encoding changed the document: {}", diff));
+    }
+    let value = doc.to_value();
+    let canonical = canonical(&value)?;
+    let reparsed = Document::parse(&canonical[..])
+        .map_err(|e| format!("canonical encoding does not parse: {}", e))?
+        .to_value();
+    if reparsed != value {
+        return Err(format!(
+            "canonical encoding parses to a different value: {}",
+            diff_bytes(&canonical, &self::canonical(&reparsed)?).unwrap_or_default()
+        ));
+    }
+    if let Some(diff) = diff_bytes(&canonical, &self::canonical(&reparsed)?) {
+        return Err(format!("canonical encoding is not stable: {}", diff));
+    }
+    Ok(())
+}
+
+fn canonical(value: &Value) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    value
+        .write_bencode_with(&mut out, EncodeOptions::strict())
+        .map_err(|e| format!("has no canonical encoding: {}", e))?;
+    Ok(out)
+}
+
+/// Panic unless [`check_roundtrip`] passes for `bytes`.
+pub fn assert_roundtrip(bytes: &[u8]) {
+    if let Err(e) = check_roundtrip(bytes) {
+        panic!("{}", e);
+    }
+}
+
+/// Panic unless the file at `path` round-trips.
+pub fn assert_fixture_roundtrips<P: AsRef<Path>>(path: P) {
+    let path = path.as_ref();
+    if let Err(e) = fixture(path) {
+        panic!("{}: {}", path.display(), e);
+    }
+}
+
+/// Check every file in `dir` and panic listing all that fail to round-trip.
+pub fn assert_fixtures_roundtrip<P: AsRef<Path>>(dir: P) {
+    let dir = dir.as_ref();
+    let mut paths: Vec<_> = fs::read_dir(dir)
+        .unwrap_or_else(|e| panic!("{}: {}", dir.display(), e))
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.is_file())
+        .collect();
+    paths.sort();
+    let failures: Vec<String> = paths
+        .iter()
+        .filter_map(|path| {
+            fixture(path)
+                .err()
+                .map(|e| format!("{}: {}", path.display(), e))
+        })
+        .collect();
+    if !failures.is_empty() {
+        panic!(
+            "{} of {} fixtures failed:\n{}",
+            failures.len(),
+            paths.len(),
+            failures.join("\n")
+        );
+    }
+}
+
+fn fixture(path: &Path) -> Result<(), String> {
+    let bytes = fs::read(path).map_err(|e| e.to_string())?;
+    check_roundtrip(&bytes)
+}
+
+/// Compare `actual` with the golden file at `path`, or write it there when
+/// [`UPDATE_GOLDEN_VAR`] is set.
+pub fn assert_golden<P: AsRef<Path>>(path: P, actual: &[u8]) {
+    let path = path.as_ref();
+    if std::env::var_os(UPDATE_GOLDEN_VAR).is_some() {
+        fs::write(path, actual).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+        return;
+    }
+    let expected = fs::read(path).unwrap_or_else(|e| {
+        panic!(
+            "{}: {} (set {}=1 to create it)",
+            path.display(),
+            e,
+            UPDATE_GOLDEN_VAR
+        )
+    });
+    if let Some(diff) = diff_bytes(&expected, actual) {
+        panic!(
+            "{} does not match (left is the golden file; set {}=1 to update it)\n{}",
+            path.display(),
+            UPDATE_GOLDEN_VAR,
+            diff
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures");
+
+    #[test]
+    fn test_fixtures_roundtrip() {
+        assert_fixtures_roundtrip(FIXTURES);
+        let source = fs::read(format!("{}/noncanonical.bencode", FIXTURES)).unwrap();
+        let value = Document::parse(source).unwrap().to_value();
+        assert_golden(
+            format!("{}/noncanonical.golden", FIXTURES),
+            &canonical(&value).unwrap(),
+        );
+    }
+
+    #[test]
+    fn test_diff_bytes() {
+        assert_eq!(diff_bytes(b"i1e", b"i1e"), None);
+        let left = b"d8:announce3:url4:infod6:lengthi3e4:name1:xee";
+        let right = b"d8:announce3:url4:infod6:lengthi4e4:name1:xee";
+        assert_eq!(
+            diff_bytes(left, right).unwrap(),
+            "bytes differ at offset 32 (left 45 bytes, right 45 bytes)
+ left 00000010  34 3a 69 6e 66 6f 64 36 3a 6c 65 6e 67 74 68 69  |4:infod6:lengthi|
+right 00000010  34 3a 69 6e 66 6f 64 36 3a 6c 65 6e 67 74 68 69  |4:infod6:lengthi|
+ left 00000020  33 65 34 3a 6e 61 6d 65 31 3a 78 65 65           |3e4:name1:xee|
+right 00000020  34 65 34 3a 6e 61 6d 65 31 3a 78 65 65           |4e4:name1:xee|
+                ^^"
+        );
+        // one is a prefix of the other
+        assert!(diff_bytes(b"le", b"lee")
+            .unwrap()
+            .starts_with("bytes differ at offset 2 (left 2 bytes, right 3 bytes)"));
+    }
+
+    #[test]
+    fn test_check_roundtrip_failures() {
+        assert!(check_roundtrip(b"d1:ai1e1:bi2ee").is_ok());
+        assert!(check_roundtrip(b"d1:ai1e")
+            .unwrap_err()
+            .starts_with("does not parse"));
+        let err = std::panic::catch_unwind(|| assert_bytes_eq(b"3:abc", b"3:abd")).unwrap_err();
+        let message = err.downcast_ref::<String>().unwrap();
+        assert!(message.contains("offset 4"), "{}", message);
+    }
+}
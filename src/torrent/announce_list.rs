@@ -0,0 +1,172 @@
+use super::metainfo::MetaInfo;
+use crate::fields::wrong_type;
+use crate::{FromBencode, Result, ToBencode, Value};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+
+/// Tracker tiers with the client-side semantics of BEP 12: tiers are tried in
+/// order, the URLs within a tier in random order, and a tracker that answers
+/// moves to the front of its tier.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AnnounceList {
+    pub tiers: Vec<Vec<String>>,
+}
+
+impl AnnounceList {
+    pub fn new(tiers: Vec<Vec<String>>) -> Self {
+        let mut list = AnnounceList { tiers };
+        list.tiers.retain(|tier| !tier.is_empty());
+        list
+    }
+
+    /// The torrent's `announce-list`, or a single tier with `announce` when
+    /// there is none, as BEP 12 prescribes.
+    pub fn from_meta(meta: &MetaInfo) -> Self {
+        if meta.announce_list.iter().any(|tier| !tier.is_empty()) {
+            AnnounceList::new(meta.announce_list.clone())
+        } else {
+            AnnounceList::new(meta.announce.iter().map(|url| vec![url.clone()]).collect())
+        }
+    }
+
+    /// Every URL in the order they should be tried, without duplicates.
+    pub fn flatten(&self) -> Vec<&str> {
+        let mut urls: Vec<&str> = Vec::new();
+        for url in self.tiers.iter().flatten() {
+            if !urls.contains(&url.as_str()) {
+                urls.push(url);
+            }
+        }
+        urls
+    }
+
+    /// Shuffle the URLs within each tier, as a client does once when it loads
+    /// the torrent.
+    pub fn shuffle(&mut self) {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_usize(self.tiers.len());
+        self.shuffle_with_seed(hasher.finish());
+    }
+
+    /// [`AnnounceList::shuffle`] with a fixed seed, for reproducible order.
+    pub fn shuffle_with_seed(&mut self, seed: u64) {
+        // xorshift64; the state must not be zero
+        let mut state = seed | 1;
+        let mut next = move |bound: usize| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % bound as u64) as usize
+        };
+        for tier in &mut self.tiers {
+            for i in (1..tier.len()).rev() {
+                tier.swap(i, next(i + 1));
+            }
+        }
+    }
+
+    /// Move `url` to the front of its tier after it answered. Returns whether
+    /// the URL was found.
+    pub fn promote(&mut self, url: &str) -> bool {
+        for tier in &mut self.tiers {
+            if let Some(i) = tier.iter().position(|u| u == url) {
+                let url = tier.remove(i);
+                tier.insert(0, url);
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Store these tiers in `meta`, with `announce` set to the first URL for
+    /// clients without BEP 12 support.
+    pub fn apply_to(&self, meta: &mut MetaInfo) {
+        meta.announce = self.flatten().first().map(|url| url.to_string());
+        meta.announce_list = self.tiers.clone();
+    }
+}
+
+impl FromBencode for AnnounceList {
+    fn from_value(value: &Value) -> Result<Self> {
+        let tiers = match value {
+            Value::List(tiers) => tiers
+                .iter()
+                .map(|tier| match tier {
+                    Value::List(urls) => Ok(urls
+                        .iter()
+                        .filter_map(|url| url.as_bytes())
+                        .map(|url| String::from_utf8_lossy(url).into_owned())
+                        .collect()),
+                    _ => Err(wrong_type("announce-list", "list of lists", tier)),
+                })
+                .collect::<Result<_>>()?,
+            _ => return Err(wrong_type("announce-list", "list", value)),
+        };
+        Ok(AnnounceList::new(tiers))
+    }
+}
+
+impl ToBencode for AnnounceList {
+    /// The `announce-list` value.
+    fn to_value(&self) -> Value {
+        Value::List(
+            self.tiers
+                .iter()
+                .map(|tier| Value::List(tier.iter().map(|u| Value::from(u.as_str())).collect()))
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list() -> AnnounceList {
+        AnnounceList::new(vec![
+            vec!["a".into(), "b".into(), "c".into()],
+            vec![],
+            vec!["d".into(), "a".into()],
+        ])
+    }
+
+    #[test]
+    fn test_flatten_and_promote() {
+        let mut list = list();
+        assert_eq!(list.tiers.len(), 2);
+        assert_eq!(list.flatten(), vec!["a", "b", "c", "d"]);
+        assert!(list.promote("c"));
+        assert_eq!(list.tiers[0], vec!["c", "a", "b"]);
+        assert!(!list.promote("x"));
+        assert_eq!(AnnounceList::from_value(&list.to_value()).unwrap(), list);
+    }
+
+    #[test]
+    fn test_shuffle_keeps_tiers() {
+        let mut list = list();
+        list.shuffle_with_seed(42);
+        let mut again = self::list();
+        again.shuffle_with_seed(42);
+        assert_eq!(list, again);
+        let mut first = list.tiers[0].clone();
+        first.sort();
+        assert_eq!(first, vec!["a", "b", "c"]);
+        assert_eq!(list.tiers[1].len(), 2);
+        list.shuffle();
+        assert_eq!(list.tiers[0].len(), 3);
+    }
+
+    #[test]
+    fn test_from_meta_and_apply() {
+        let mut buf =
+            b"d8:announce5:udp:x4:infod6:lengthi1e4:name1:a12:piece lengthi1e6:pieces20:".to_vec();
+        buf.extend_from_slice(&[0; 20]);
+        buf.extend_from_slice(b"ee");
+        let mut meta = MetaInfo::from_bytes(&buf).unwrap();
+        assert_eq!(AnnounceList::from_meta(&meta).tiers, vec![vec!["udp:x"]]);
+
+        list().apply_to(&mut meta);
+        assert_eq!(meta.announce.as_deref(), Some("a"));
+        assert_eq!(AnnounceList::from_meta(&meta), list());
+    }
+}
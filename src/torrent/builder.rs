@@ -0,0 +1,483 @@
+use super::metainfo::{FileEntry, Files, Info, MetaInfo};
+use super::path::path_components;
+use super::validate::PIECE_LENGTH_LIMIT;
+use crate::hash::Sha1;
+use crate::{BencodeError, Result};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Smallest piece length chosen automatically (16 KiB).
+const MIN_PIECE_LENGTH: u64 = 1 << 14;
+/// Largest piece length chosen automatically (16 MiB).
+const MAX_PIECE_LENGTH: u64 = 1 << 24;
+/// Piece count the automatic piece length aims for.
+const TARGET_PIECES: u64 = 1500;
+/// Upper bound on the data buffered for one round of parallel hashing.
+#[cfg(feature = "parallel")]
+const BATCH_BYTES: usize = 64 << 20;
+
+/// Creates a v1 torrent from a file or directory on disk.
+///
+/// ```no_run
+/// use bencode_rs::torrent::TorrentBuilder;
+///
+/// let meta = TorrentBuilder::new("dist/release.tar.gz")
+///     .announce("http://tracker.example/announce")
+///     .piece_length(1 << 18)
+///     .build()
+///     .unwrap();
+/// std::fs::write("release.torrent", meta.to_bytes().unwrap()).unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct TorrentBuilder {
+    path: PathBuf,
+    name: Option<String>,
+    piece_length: Option<u64>,
+    announce: Option<String>,
+    announce_list: Vec<Vec<String>>,
+    comment: Option<String>,
+    created_by: Option<String>,
+    creation_date: Option<i64>,
+    private: bool,
+    align_files: bool,
+}
+
+impl TorrentBuilder {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        TorrentBuilder {
+            path: path.into(),
+            name: None,
+            piece_length: None,
+            announce: None,
+            announce_list: Vec::new(),
+            comment: None,
+            created_by: Some(format!(
+                "{}/{}",
+                env!("CARGO_PKG_NAME"),
+                env!("CARGO_PKG_VERSION")
+            )),
+            creation_date: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .ok()
+                .map(|d| d.as_secs() as i64),
+            private: false,
+            align_files: false,
+        }
+    }
+
+    /// Override the torrent name, which defaults to the file or directory name.
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    /// Piece length in bytes; must be a power of two of at most 64 MiB. When
+    /// not set, one is picked from the total size.
+    pub fn piece_length(mut self, piece_length: u64) -> Self {
+        self.piece_length = Some(piece_length);
+        self
+    }
+
+    pub fn announce(mut self, url: &str) -> Self {
+        self.announce = Some(url.to_string());
+        self
+    }
+
+    /// Add a tier of trackers to `announce-list`.
+    pub fn tier(mut self, urls: &[&str]) -> Self {
+        self.announce_list
+            .push(urls.iter().map(|u| u.to_string()).collect());
+        self
+    }
+
+    pub fn comment(mut self, comment: &str) -> Self {
+        self.comment = Some(comment.to_string());
+        self
+    }
+
+    /// Set or clear `created by`.
+    pub fn created_by(mut self, created_by: Option<&str>) -> Self {
+        self.created_by = created_by.map(str::to_string);
+        self
+    }
+
+    /// Set or clear `creation date`, which defaults to now.
+    pub fn creation_date(mut self, secs: Option<i64>) -> Self {
+        self.creation_date = secs;
+        self
+    }
+
+    pub fn private(mut self, private: bool) -> Self {
+        self.private = private;
+        self
+    }
+
+    /// Start every file of a multi-file torrent on a piece boundary by adding
+    /// BEP 47 padding files, so each file can be verified on its own.
+    pub fn align_files(mut self, align: bool) -> Self {
+        self.align_files = align;
+        self
+    }
+
+    /// Walk the source path, hash its pieces and assemble the torrent.
+    pub fn build(&self) -> Result<MetaInfo> {
+        let name = match &self.name {
+            Some(name) => name.clone(),
+            None => match self.path.file_name() {
+                Some(name) => name.to_string_lossy().into_owned(),
+                None => {
+                    return Err(BencodeError::msg(format!(
+                        "Cannot name a torrent for {}",
+                        self.path.display()
+                    )))
+                }
+            },
+        };
+
+        let (files, sources) = if fs::metadata(&self.path)?.is_dir() {
+            let mut sources = Vec::new();
+            walk(&self.path, &mut sources)?;
+            if sources.is_empty() {
+                return Err(BencodeError::msg(format!(
+                    "No files under {}",
+                    self.path.display()
+                )));
+            }
+            let entries = sources
+                .iter()
+                .map(|(path, length)| {
+                    Ok(FileEntry {
+                        length: *length,
+                        path: path_components(path.strip_prefix(&self.path).unwrap_or(path))?,
+                        extra: HashMap::new(),
+                    })
+                })
+                .collect::<Result<_>>()?;
+            (Files::Multi(entries), sources)
+        } else {
+            let length = fs::metadata(&self.path)?.len();
+            (Files::Single { length }, vec![(self.path.clone(), length)])
+        };
+
+        let total = sources.iter().map(|(_, length)| length).sum();
+        let piece_length = match self.piece_length {
+            Some(n) if n.is_power_of_two() && n <= PIECE_LENGTH_LIMIT => n,
+            Some(n) => {
+                return Err(BencodeError::msg(format!(
+                    "Piece length {} is not a power of two of at most {}",
+                    n, PIECE_LENGTH_LIMIT
+                )))
+            }
+            None => auto_piece_length(total),
+        };
+
+        let files = match files {
+            Files::Multi(entries) if self.align_files => {
+                Files::Multi(aligned(entries, piece_length))
+            }
+            files => files,
+        };
+
+        let mut hasher = PieceHasher::new(piece_length as usize)?;
+        for (i, (path, length)) in sources.iter().enumerate() {
+            hasher.read_from(File::open(path)?)?;
+            if self.align_files && i + 1 < sources.len() {
+                let pad = padding(*length, piece_length);
+                hasher.read_from(io::repeat(0).take(pad))?;
+            }
+        }
+
+        Ok(MetaInfo {
+            announce: self.announce.clone(),
+            announce_list: self.announce_list.clone(),
+            comment: self.comment.clone(),
+            created_by: self.created_by.clone(),
+            creation_date: self.creation_date,
+            info: Info {
+                name,
+                piece_length,
+                pieces: hasher.finish(),
+                private: self.private,
+                files: Some(files),
+                meta_version: None,
+                file_tree: None,
+                extra: HashMap::new(),
+            },
+            piece_layers: HashMap::new(),
+            url_list: Vec::new(),
+            extra: HashMap::new(),
+            raw_info: None,
+        })
+    }
+}
+
+/// Splits a stream of bytes into pieces and hashes them, a batch of whole
+/// pieces at a time.
+pub(super) struct PieceHasher {
+    piece_length: usize,
+    batch_len: usize,
+    buf: Vec<u8>,
+    pieces: Vec<[u8; 20]>,
+}
+
+impl PieceHasher {
+    pub(super) fn new(piece_length: usize) -> Result<Self> {
+        let batch_len = piece_length
+            .checked_mul(batch_pieces(piece_length))
+            .ok_or_else(|| {
+                BencodeError::msg(format!("Piece length {} is too large", piece_length))
+            })?;
+        Ok(PieceHasher {
+            piece_length,
+            batch_len,
+            buf: Vec::with_capacity(batch_len),
+            pieces: Vec::new(),
+        })
+    }
+
+    /// Feed everything `reader` yields; pieces span file boundaries.
+    pub(super) fn read_from<R: Read>(&mut self, mut reader: R) -> Result<()> {
+        loop {
+            let start = self.buf.len();
+            self.buf.resize(self.batch_len, 0);
+            let n = reader.read(&mut self.buf[start..])?;
+            self.buf.truncate(start + n);
+            if n == 0 {
+                return Ok(());
+            }
+            if self.buf.len() == self.batch_len {
+                self.pieces
+                    .extend(hash_pieces(&self.buf, self.piece_length));
+                self.buf.clear();
+            }
+        }
+    }
+
+    pub(super) fn finish(mut self) -> Vec<[u8; 20]> {
+        self.pieces
+            .extend(hash_pieces(&self.buf, self.piece_length));
+        self.pieces
+    }
+}
+
+#[cfg(not(feature = "parallel"))]
+pub(super) fn batch_pieces(_piece_length: usize) -> usize {
+    1
+}
+
+/// Two pieces per thread, as long as that stays under `BATCH_BYTES`.
+#[cfg(feature = "parallel")]
+pub(super) fn batch_pieces(piece_length: usize) -> usize {
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    (threads * 2).min(BATCH_BYTES / piece_length).max(1)
+}
+
+/// SHA-1 of each consecutive `piece_length` chunk of `data`; the last chunk
+/// may be short.
+#[cfg(not(feature = "parallel"))]
+pub(super) fn hash_pieces(data: &[u8], piece_length: usize) -> Vec<[u8; 20]> {
+    data.chunks(piece_length).map(Sha1::digest).collect()
+}
+
+/// SHA-1 of each consecutive `piece_length` chunk of `data`, spread over one
+/// scoped thread per core.
+#[cfg(feature = "parallel")]
+pub(super) fn hash_pieces(data: &[u8], piece_length: usize) -> Vec<[u8; 20]> {
+    let pieces: Vec<&[u8]> = data.chunks(piece_length).collect();
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    if pieces.len() < 2 || threads < 2 {
+        return pieces.into_iter().map(Sha1::digest).collect();
+    }
+    let per_thread = pieces.len().div_ceil(threads);
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = pieces
+            .chunks(per_thread)
+            .map(|group| {
+                scope.spawn(move || group.iter().map(|p| Sha1::digest(p)).collect::<Vec<_>>())
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().unwrap_or_else(|e| std::panic::resume_unwind(e)))
+            .collect()
+    })
+}
+
+/// Collect every regular file below `dir` with its length, sorted by path so
+/// the same tree always produces the same torrent. Symlinks to files are
+/// followed; symlinks to directories are skipped, since they may loop.
+fn walk(dir: &Path, out: &mut Vec<(PathBuf, u64)>) -> Result<()> {
+    let mut entries = fs::read_dir(dir)?
+        .map(|e| e.map(|e| e.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort();
+    for path in entries {
+        let mut meta = fs::symlink_metadata(&path)?;
+        if meta.file_type().is_symlink() {
+            meta = fs::metadata(&path)?;
+            if meta.is_dir() {
+                continue;
+            }
+        } else if meta.is_dir() {
+            walk(&path, out)?;
+        }
+        if meta.is_file() {
+            out.push((path, meta.len()));
+        }
+    }
+    Ok(())
+}
+
+/// Insert a padding file after every file but the last that does not end on
+/// a piece boundary.
+pub(super) fn aligned(entries: Vec<FileEntry>, piece_length: u64) -> Vec<FileEntry> {
+    let count = entries.len();
+    let mut out = Vec::with_capacity(count * 2);
+    for (i, entry) in entries.into_iter().enumerate() {
+        let pad = padding(entry.length, piece_length);
+        out.push(entry);
+        if pad > 0 && i + 1 < count {
+            out.push(FileEntry::padding(pad));
+        }
+    }
+    out
+}
+
+/// Zero bytes needed after `length` bytes to reach the next piece boundary.
+pub(super) fn padding(length: u64, piece_length: u64) -> u64 {
+    (piece_length - length % piece_length) % piece_length
+}
+
+/// Smallest power of two that keeps the piece count near `TARGET_PIECES`.
+fn auto_piece_length(total: u64) -> u64 {
+    let mut piece_length = MIN_PIECE_LENGTH;
+    while piece_length < MAX_PIECE_LENGTH && total / piece_length > TARGET_PIECES {
+        piece_length *= 2;
+    }
+    piece_length
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("bencode_rs-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_build_multi_file_torrent() {
+        let dir = scratch_dir("builder");
+        let root = dir.join("content");
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(root.join("b.txt"), vec![b'b'; 40_000]).unwrap();
+        fs::write(root.join("sub").join("a.txt"), vec![b'a'; 10_000]).unwrap();
+
+        let meta = TorrentBuilder::new(&root)
+            .announce("http://tracker/announce")
+            .piece_length(1 << 14)
+            .creation_date(None)
+            .build()
+            .unwrap();
+
+        let mut content = vec![b'b'; 40_000];
+        content.extend_from_slice(&[b'a'; 10_000]);
+        let expected: Vec<_> = content.chunks(1 << 14).map(Sha1::digest).collect();
+        assert_eq!(meta.info.name, "content");
+        assert_eq!(meta.info.pieces, expected);
+        assert_eq!(meta.info.total_length().unwrap(), 50_000);
+        match &meta.info.files {
+            Some(Files::Multi(files)) => {
+                assert_eq!(files[0].path, vec!["b.txt"]);
+                assert_eq!(files[1].path, vec!["sub", "a.txt"]);
+            }
+            other => panic!("expected multi-file layout, got {:?}", other),
+        }
+        assert_eq!(
+            MetaInfo::from_bytes(&meta.to_bytes().unwrap()).unwrap(),
+            meta
+        );
+
+        assert!(TorrentBuilder::new(&root)
+            .piece_length(1000)
+            .build()
+            .is_err());
+        assert!(TorrentBuilder::new(&root)
+            .piece_length(1 << 40)
+            .build()
+            .is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_build_skips_directory_symlinks() {
+        use std::os::unix::fs::symlink;
+
+        let dir = scratch_dir("symlinks");
+        let root = dir.join("content");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(dir.join("outside"), vec![3; 10]).unwrap();
+        fs::write(root.join("a"), vec![1; 10]).unwrap();
+        symlink(&root, root.join("loop")).unwrap();
+        symlink(dir.join("outside"), root.join("b")).unwrap();
+
+        let meta = TorrentBuilder::new(&root).build().unwrap();
+        let paths: Vec<_> = match &meta.info.files {
+            Some(Files::Multi(files)) => files.iter().map(|f| f.path.join("/")).collect(),
+            other => panic!("expected multi-file layout, got {:?}", other),
+        };
+        assert_eq!(paths, vec!["a", "b"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_build_aligned_torrent() {
+        let dir = scratch_dir("aligned");
+        fs::write(dir.join("a"), vec![1; 20_000]).unwrap();
+        fs::write(dir.join("b"), vec![2; 100]).unwrap();
+
+        let meta = TorrentBuilder::new(&dir)
+            .piece_length(1 << 14)
+            .align_files(true)
+            .build()
+            .unwrap();
+        let files = match &meta.info.files {
+            Some(Files::Multi(files)) => files,
+            other => panic!("expected multi-file layout, got {:?}", other),
+        };
+        let lengths: Vec<_> = files.iter().map(|f| (f.length, f.is_padding())).collect();
+        assert_eq!(lengths, vec![(20_000, false), (12_768, true), (100, false)]);
+
+        let mut content = vec![1; 20_000];
+        content.resize(1 << 15, 0);
+        content.extend_from_slice(&[2; 100]);
+        let expected: Vec<_> = content.chunks(1 << 14).map(Sha1::digest).collect();
+        assert_eq!(meta.info.pieces, expected);
+        assert!(meta.validate().is_ok());
+        assert_eq!(meta.info.file_paths().unwrap().len(), 2);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_hash_pieces_in_order() {
+        let data: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+        let expected: Vec<_> = data.chunks(1000).map(Sha1::digest).collect();
+        assert_eq!(hash_pieces(&data, 1000), expected);
+        assert!(hash_pieces(&[], 1000).is_empty());
+    }
+
+    #[test]
+    fn test_auto_piece_length() {
+        assert_eq!(auto_piece_length(0), MIN_PIECE_LENGTH);
+        assert_eq!(auto_piece_length(4 << 30), 1 << 22);
+        assert_eq!(auto_piece_length(u64::MAX), MAX_PIECE_LENGTH);
+    }
+}
@@ -0,0 +1,228 @@
+use super::builder::{aligned, padding, PieceHasher};
+use super::metainfo::{Files, MetaInfo};
+use super::path::sanitize_path;
+use super::v2::{merkle, FileTree, PieceLayers, BLOCK_LEN};
+use super::verify::verify;
+use crate::fields::missing;
+use crate::hash::Sha256;
+use crate::{BencodeError, Result};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+impl MetaInfo {
+    /// Upgrade a v1 torrent to a BEP 52 hybrid, reading its data from the
+    /// download directory `root`.
+    ///
+    /// The data is first checked against the v1 piece hashes. Each file is
+    /// then hashed into a v2 Merkle tree, and the v1 layout is sorted by path
+    /// and gets padding files where needed so that every file starts on a
+    /// piece boundary, as hybrid torrents require. The result has new
+    /// infohashes; everything outside `info` is kept.
+    pub fn to_hybrid(&self, root: &Path) -> Result<MetaInfo> {
+        let info = &self.info;
+        if info.is_v2() {
+            return Err(BencodeError::msg("Torrent already has v2 metadata"));
+        }
+        let piece_length = info.piece_length;
+        if !piece_length.is_power_of_two() || piece_length < BLOCK_LEN as u64 {
+            return Err(BencodeError::msg(format!(
+                "Piece length {} must be a power of two of at least {} for v2",
+                piece_length, BLOCK_LEN
+            )));
+        }
+        for (i, ok) in verify(self, root)?.enumerate() {
+            if !ok? {
+                return Err(BencodeError::msg(format!(
+                    "Piece {} does not match the data under {}",
+                    i,
+                    root.display()
+                )));
+            }
+        }
+
+        let base = root.join(sanitize_path(&[info.name.as_str()])?);
+        let (files, sources) = match &info.files {
+            Some(Files::Multi(entries)) => {
+                let mut entries: Vec<_> = entries
+                    .iter()
+                    .filter(|e| !e.is_padding())
+                    .cloned()
+                    .collect();
+                // the v1 list must follow the order of the v2 file tree
+                entries.sort_by(|a, b| a.path.cmp(&b.path));
+                let sources = entries
+                    .iter()
+                    .map(|e| Ok((base.join(e.relative_path()?), e.path.clone(), e.length)))
+                    .collect::<Result<Vec<_>>>()?;
+                (Files::Multi(aligned(entries, piece_length)), sources)
+            }
+            Some(Files::Single { length }) => (
+                Files::Single { length: *length },
+                vec![(base, vec![info.name.clone()], *length)],
+            ),
+            // only reachable for an `Info` built by hand with neither layout
+            None => return Err(missing("length` or `files")),
+        };
+
+        let mut hasher = PieceHasher::new(piece_length as usize)?;
+        let mut tree = BTreeMap::new();
+        let mut piece_layers = PieceLayers::new();
+        let mut block = vec![0; BLOCK_LEN];
+        for (i, (path, components, length)) in sources.iter().enumerate() {
+            let mut file = File::open(path)?.take(*length);
+            let mut leaves = Vec::new();
+            loop {
+                let n = read_block(&mut file, &mut block)?;
+                if n == 0 {
+                    break;
+                }
+                hasher.read_from(&block[..n])?;
+                leaves.push(Sha256::digest(&block[..n]));
+            }
+            if i + 1 < sources.len() {
+                hasher.read_from(io::repeat(0).take(padding(*length, piece_length)))?;
+            }
+            let pieces_root = if leaves.is_empty() {
+                None
+            } else {
+                let (root, layer) = merkle(&leaves, piece_length);
+                if *length > piece_length {
+                    piece_layers.insert(root, layer);
+                }
+                Some(root)
+            };
+            insert(
+                &mut tree,
+                components,
+                FileTree::File {
+                    length: *length,
+                    pieces_root,
+                },
+            );
+        }
+
+        let mut hybrid = self.clone();
+        hybrid.info.files = Some(files);
+        hybrid.info.pieces = hasher.finish();
+        hybrid.info.meta_version = Some(2);
+        hybrid.info.file_tree = Some(FileTree::Dir(tree));
+        hybrid.piece_layers = piece_layers;
+        hybrid.raw_info = None;
+        Ok(hybrid)
+    }
+}
+
+/// Fill `block` as far as the reader allows; short only at the end.
+fn read_block<R: Read>(reader: &mut R, block: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < block.len() {
+        match reader.read(&mut block[filled..])? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(filled)
+}
+
+fn insert(tree: &mut BTreeMap<String, FileTree>, path: &[String], file: FileTree) {
+    match path {
+        [] => {}
+        [name] => {
+            tree.insert(name.clone(), file);
+        }
+        [dir, rest @ ..] => {
+            let node = tree
+                .entry(dir.clone())
+                .or_insert_with(|| FileTree::Dir(BTreeMap::new()));
+            if let FileTree::Dir(children) = node {
+                insert(children, rest, file);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::Sha1;
+    use crate::torrent::TorrentBuilder;
+    use std::{env, fs};
+
+    #[test]
+    fn test_convert_to_hybrid() {
+        let dir = env::temp_dir().join(format!("bencode_rs-hybrid-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let content = dir.join("content");
+        fs::create_dir_all(content.join("sub")).unwrap();
+        fs::write(content.join("a"), vec![1; 40_000]).unwrap();
+        fs::write(content.join("sub").join("b"), vec![2; 100]).unwrap();
+        fs::write(content.join("empty"), b"").unwrap();
+        let meta = TorrentBuilder::new(&content)
+            .piece_length(1 << 14)
+            .comment("kept")
+            .build()
+            .unwrap();
+
+        let hybrid = meta.to_hybrid(&dir).unwrap();
+        assert!(hybrid.info.is_hybrid());
+        assert_eq!(hybrid.comment.as_deref(), Some("kept"));
+        assert!(hybrid.validate().is_ok());
+        assert!(verify(&hybrid, &dir).unwrap().all(|ok| ok.unwrap()));
+
+        let files = hybrid.info.file_tree.as_ref().unwrap().files();
+        let paths: Vec<_> = files.iter().map(|f| f.path.join("/")).collect();
+        assert_eq!(paths, vec!["a", "empty", "sub/b"]);
+        assert_eq!(files[1].pieces_root, None);
+        assert_eq!(files[2].pieces_root, Some(Sha256::digest(&[2; 100])));
+        assert_eq!(hybrid.piece_layers.len(), 1);
+
+        let reread = MetaInfo::from_bytes(&hybrid.to_bytes().unwrap()).unwrap();
+        assert_eq!(reread, hybrid);
+        assert!(hybrid.to_hybrid(&dir).is_err());
+
+        fs::write(content.join("a"), vec![0; 40_000]).unwrap();
+        assert!(meta.to_hybrid(&dir).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_hybrid_sorts_v1_files() {
+        let dir = env::temp_dir().join(format!("bencode_rs-hybrid-order-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let content = dir.join("content");
+        fs::create_dir_all(&content).unwrap();
+        fs::write(content.join("a"), vec![1; 40_000]).unwrap();
+        fs::write(content.join("b"), vec![2; 100]).unwrap();
+        let mut meta = TorrentBuilder::new(&content)
+            .piece_length(1 << 14)
+            .build()
+            .unwrap();
+
+        // list `b` before `a`, as a torrent made elsewhere may
+        if let Some(Files::Multi(files)) = &mut meta.info.files {
+            files.reverse();
+        }
+        let mut data = vec![2; 100];
+        data.extend_from_slice(&[1; 40_000]);
+        meta.info.pieces = data.chunks(1 << 14).map(Sha1::digest).collect();
+        assert!(verify(&meta, &dir).unwrap().all(|ok| ok.unwrap()));
+
+        let hybrid = meta.to_hybrid(&dir).unwrap();
+        let v1: Vec<_> = match &hybrid.info.files {
+            Some(Files::Multi(files)) => files
+                .iter()
+                .filter(|f| !f.is_padding())
+                .map(|f| f.path.join("/"))
+                .collect(),
+            other => panic!("expected multi-file layout, got {:?}", other),
+        };
+        let v2: Vec<_> = hybrid.info.file_tree.as_ref().unwrap().files();
+        let v2: Vec<_> = v2.iter().map(|f| f.path.join("/")).collect();
+        assert_eq!(v1, vec!["a", "b"]);
+        assert_eq!(v1, v2);
+        assert!(verify(&hybrid, &dir).unwrap().all(|ok| ok.unwrap()));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,185 @@
+use crate::hash::to_hex;
+use crate::{BencodeError, Result};
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// A v1 (SHA-1) or v2 (SHA-256) infohash.
+///
+/// Displays as lowercase hex; parses from hex or from the unpadded base32
+/// form older magnet links use.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum InfoHash {
+    V1([u8; 20]),
+    V2([u8; 32]),
+}
+
+impl InfoHash {
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            InfoHash::V1(hash) => hash,
+            InfoHash::V2(hash) => hash,
+        }
+    }
+
+    /// The 20 bytes used where only v1-sized hashes fit, such as tracker
+    /// announces and the DHT: the hash itself, or a v2 hash truncated as
+    /// BEP 52 specifies.
+    pub fn truncated(&self) -> [u8; 20] {
+        let mut out = [0; 20];
+        out.copy_from_slice(&self.as_bytes()[..20]);
+        out
+    }
+
+    pub fn to_hex(&self) -> String {
+        to_hex(self.as_bytes())
+    }
+
+    /// Unpadded uppercase RFC 4648 base32.
+    pub fn to_base32(&self) -> String {
+        let mut out = String::new();
+        let (mut acc, mut bits) = (0u32, 0);
+        for &byte in self.as_bytes() {
+            acc = (acc << 8) | u32::from(byte);
+            bits += 8;
+            while bits >= 5 {
+                bits -= 5;
+                out.push(BASE32_ALPHABET[(acc >> bits) as usize & 31] as char);
+            }
+        }
+        if bits > 0 {
+            out.push(BASE32_ALPHABET[(acc << (5 - bits)) as usize & 31] as char);
+        }
+        out
+    }
+
+    /// Parse 40 or 64 hex digits.
+    pub fn from_hex(hex: &str) -> Result<InfoHash> {
+        from_hex(hex)
+            .and_then(|bytes| InfoHash::from_slice(&bytes))
+            .ok_or_else(|| invalid(hex))
+    }
+
+    /// Parse 32 or 52 base32 digits, in either case.
+    pub fn from_base32(s: &str) -> Result<InfoHash> {
+        from_base32(s)
+            .and_then(|bytes| InfoHash::from_slice(&bytes))
+            .ok_or_else(|| invalid(s))
+    }
+
+    fn from_slice(bytes: &[u8]) -> Option<InfoHash> {
+        match bytes.len() {
+            20 => {
+                let mut hash = [0; 20];
+                hash.copy_from_slice(bytes);
+                Some(InfoHash::V1(hash))
+            }
+            32 => {
+                let mut hash = [0; 32];
+                hash.copy_from_slice(bytes);
+                Some(InfoHash::V2(hash))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Display for InfoHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_hex())
+    }
+}
+
+impl FromStr for InfoHash {
+    type Err = BencodeError;
+
+    /// Hex or base32, told apart by length.
+    fn from_str(s: &str) -> Result<InfoHash> {
+        match s.len() {
+            40 | 64 => InfoHash::from_hex(s),
+            32 | 52 => InfoHash::from_base32(s),
+            _ => Err(invalid(s)),
+        }
+    }
+}
+
+impl From<[u8; 20]> for InfoHash {
+    fn from(hash: [u8; 20]) -> Self {
+        InfoHash::V1(hash)
+    }
+}
+
+impl From<[u8; 32]> for InfoHash {
+    fn from(hash: [u8; 32]) -> Self {
+        InfoHash::V2(hash)
+    }
+}
+
+fn invalid(s: &str) -> BencodeError {
+    BencodeError::msg(format!("Invalid infohash `{}`", s))
+}
+
+pub(super) fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// RFC 4648 base32 without padding, as used by older btih links.
+pub(super) fn from_base32(s: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(s.len() * 5 / 8);
+    let (mut acc, mut bits) = (0u32, 0);
+    for c in s.bytes() {
+        let v = match c.to_ascii_uppercase() {
+            c @ b'A'..=b'Z' => c - b'A',
+            c @ b'2'..=b'7' => c - b'2' + 26,
+            _ => return None,
+        };
+        acc = (acc << 5) | u32::from(v);
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+            acc &= (1 << bits) - 1;
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_v1_hex_and_base32() {
+        let hash: InfoHash = "12286841244fc9205af29b61a4c22796a472b460".parse().unwrap();
+        assert_eq!(hash.to_base32(), "CIUGQQJEJ7ESAWXSTNQ2JQRHS2SHFNDA");
+        assert_eq!(
+            "ciugqqjej7esawxstnq2jqrhs2shfnda"
+                .parse::<InfoHash>()
+                .unwrap(),
+            hash
+        );
+        assert_eq!(hash.to_string(), "12286841244fc9205af29b61a4c22796a472b460");
+        assert_eq!(&hash.truncated()[..], hash.as_bytes());
+    }
+
+    #[test]
+    fn test_v2_roundtrip_and_ordering() {
+        let hash = InfoHash::from([0xab; 32]);
+        assert_eq!(hash.to_base32().len(), 52);
+        assert_eq!(hash.to_base32().parse::<InfoHash>().unwrap(), hash);
+        assert_eq!(hash.to_hex().parse::<InfoHash>().unwrap(), hash);
+        assert_eq!(hash.truncated(), [0xab; 20]);
+        assert!(InfoHash::from([0; 20]) < InfoHash::from([1; 20]));
+        assert!("abc".parse::<InfoHash>().is_err());
+        assert!("zz286841244fc9205af29b61a4c22796a472b460"
+            .parse::<InfoHash>()
+            .is_err());
+    }
+}
@@ -0,0 +1,194 @@
+use crate::document::{Document, Node, NodeKind};
+use crate::{Result, Value};
+use std::fmt::{self, Display};
+
+/// Piece lengths above this (16 MiB) are rejected or handled poorly by
+/// many clients.
+const MAX_PIECE_LENGTH: i64 = 1 << 24;
+
+/// Keys written by old or non-standard tools that modern clients ignore.
+const DEPRECATED_KEYS: &[&str] = &["encoding", "codepage", "publisher.utf-8"];
+const DEPRECATED_INFO_KEYS: &[&str] = &["name.utf-8", "filehash", "ed2k"];
+
+/// A problem found by [`lint`] or [`lint_bytes`]. None of these stop a
+/// torrent from parsing, but each is worth fixing before publishing it.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Lint {
+    /// No `info` dictionary.
+    MissingInfo,
+    /// No `info.name`, so clients have to invent one.
+    MissingName,
+    /// Keys of the dictionary at this path are not in ascending byte order.
+    UnsortedKeys(String),
+    /// `piece length` is larger than 16 MiB.
+    OversizedPieceLength(i64),
+    /// `piece length` is not a power of two.
+    IrregularPieceLength(i64),
+    /// Some names are valid UTF-8 and some are not, a sign that paths were
+    /// written in different encodings.
+    MixedPathEncoding,
+    /// A key that is deprecated or was never standard.
+    DeprecatedKey(String),
+}
+
+impl Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Lint::MissingInfo => write!(f, "missing `info` dictionary"),
+            Lint::MissingName => write!(f, "missing `info.name`"),
+            Lint::UnsortedKeys(path) if path.is_empty() => {
+                write!(f, "top-level keys are not sorted")
+            }
+            Lint::UnsortedKeys(path) => write!(f, "keys of `{}` are not sorted", path),
+            Lint::OversizedPieceLength(n) => write!(f, "piece length {} exceeds 16 MiB", n),
+            Lint::IrregularPieceLength(n) => write!(f, "piece length {} is not a power of two", n),
+            Lint::MixedPathEncoding => write!(f, "file names mix UTF-8 and other encodings"),
+            Lint::DeprecatedKey(key) => write!(f, "deprecated key `{}`", key),
+        }
+    }
+}
+
+/// Check a decoded torrent for common problems. Key order is lost once
+/// decoded; use [`lint_bytes`] to check it too.
+pub fn lint(torrent: &Value) -> Vec<Lint> {
+    let mut lints = Vec::new();
+    for key in DEPRECATED_KEYS {
+        if get(torrent, key).is_some() {
+            lints.push(Lint::DeprecatedKey(key.to_string()));
+        }
+    }
+    let info = match get(torrent, "info") {
+        Some(info @ Value::Map(_)) => info,
+        _ => {
+            lints.push(Lint::MissingInfo);
+            return lints;
+        }
+    };
+    if get(info, "name").and_then(Value::as_bytes).is_none() {
+        lints.push(Lint::MissingName);
+    }
+    if let Some(Value::Int(n)) = get(info, "piece length") {
+        if *n > MAX_PIECE_LENGTH {
+            lints.push(Lint::OversizedPieceLength(*n));
+        }
+        if *n > 0 && !(*n as u64).is_power_of_two() {
+            lints.push(Lint::IrregularPieceLength(*n));
+        }
+    }
+    for key in DEPRECATED_INFO_KEYS {
+        if get(info, key).is_some() {
+            lints.push(Lint::DeprecatedKey(format!("info.{}", key)));
+        }
+    }
+
+    let mut names = Vec::new();
+    names.extend(get(info, "name"));
+    if let Some(Value::List(files)) = get(info, "files") {
+        for file in files {
+            if let Some(Value::List(path)) = get(file, "path") {
+                names.extend(path);
+            }
+            if get(file, "path.utf-8").is_some() {
+                lints.push(Lint::DeprecatedKey("info.files.path.utf-8".into()));
+            }
+        }
+    }
+    let utf8 = names.iter().filter(|n| matches!(n, Value::Str(_))).count();
+    let other = names
+        .iter()
+        .filter(|n| matches!(n, Value::Bytes(_)))
+        .count();
+    if utf8 > 0 && other > 0 {
+        lints.push(Lint::MixedPathEncoding);
+    }
+    lints.dedup();
+    lints
+}
+
+/// [`lint`] plus checks that need the encoded bytes, such as key order.
+pub fn lint_bytes(torrent: &[u8]) -> Result<Vec<Lint>> {
+    let doc = Document::parse(torrent)?;
+    let mut lints = Vec::new();
+    unsorted_keys(doc.root(), &mut String::new(), &mut lints);
+    lints.extend(lint(&doc.to_value()));
+    Ok(lints)
+}
+
+fn get<'a>(value: &'a Value, key: &str) -> Option<&'a Value> {
+    match value {
+        Value::Map(map) => map.get(&Value::from(key)),
+        _ => None,
+    }
+}
+
+fn unsorted_keys(node: &Node, path: &mut String, out: &mut Vec<Lint>) {
+    let children: Vec<(String, &Node)> = match node.kind() {
+        NodeKind::Leaf(_) => return,
+        NodeKind::List(items) => items
+            .iter()
+            .enumerate()
+            .map(|(i, item)| (i.to_string(), item))
+            .collect(),
+        NodeKind::Dict(entries) => {
+            let keys: Vec<Value> = entries.iter().map(|(k, _)| k.to_value()).collect();
+            let sorted = keys
+                .windows(2)
+                .all(|pair| pair[0].as_bytes() < pair[1].as_bytes());
+            if !sorted {
+                out.push(Lint::UnsortedKeys(path.clone()));
+            }
+            keys.iter()
+                .zip(entries)
+                .map(|(k, (_, v))| {
+                    (
+                        String::from_utf8_lossy(k.as_bytes().unwrap_or(&[])).into_owned(),
+                        v,
+                    )
+                })
+                .collect()
+        }
+    };
+    for (segment, child) in children {
+        let len = path.len();
+        if !path.is_empty() {
+            path.push('.');
+        }
+        path.push_str(&segment);
+        unsorted_keys(child, path, out);
+        path.truncate(len);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clean_torrent_has_no_lints() {
+        let mut buf = b"d4:infod6:lengthi1e4:name1:a12:piece lengthi16384e6:pieces20:".to_vec();
+        buf.extend_from_slice(&[0; 20]);
+        buf.extend_from_slice(b"ee");
+        assert_eq!(lint_bytes(&buf).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn test_lints_are_reported() {
+        let buf = b"d8:encoding3:GBK4:infod5:filesld6:lengthi1e4:pathl2:\xff\xfeeed6:lengthi1e4:pathl1:beee12:piece lengthi50000000e6:pieces0:10:name.utf-81:xe8:announce1:ue";
+        let lints = lint_bytes(buf).unwrap();
+        assert_eq!(
+            lints,
+            vec![
+                Lint::UnsortedKeys(String::new()),
+                Lint::UnsortedKeys("info".into()),
+                Lint::DeprecatedKey("encoding".into()),
+                Lint::MissingName,
+                Lint::OversizedPieceLength(50_000_000),
+                Lint::IrregularPieceLength(50_000_000),
+                Lint::DeprecatedKey("info.name.utf-8".into()),
+                Lint::MixedPathEncoding,
+            ]
+        );
+        assert_eq!(lints[1].to_string(), "keys of `info` are not sorted");
+        assert_eq!(lint(&Value::from(1)), vec![Lint::MissingInfo]);
+    }
+}
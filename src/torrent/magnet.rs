@@ -0,0 +1,199 @@
+use super::info_hash::{from_base32, from_hex};
+use super::metainfo::MetaInfo;
+use crate::hash::to_hex;
+use crate::{percent, BencodeError, Result};
+use std::fmt::{self, Display};
+
+/// Multihash prefix of a SHA-256 digest: function code 0x12, length 32.
+const SHA256_MULTIHASH: &str = "1220";
+
+/// A magnet link: enough to find a torrent's peers and fetch its metadata.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Magnet {
+    /// v1 infohash from a `urn:btih` topic.
+    pub info_hash: Option<[u8; 20]>,
+    /// v2 infohash from a `urn:btmh` topic.
+    pub info_hash_v2: Option<[u8; 32]>,
+    /// `dn`
+    pub display_name: Option<String>,
+    /// `tr`, in order of appearance.
+    pub trackers: Vec<String>,
+    /// `ws` (BEP 19)
+    pub web_seeds: Vec<String>,
+}
+
+impl Magnet {
+    /// Parse a `magnet:?` URI. At least one infohash is required; parameters
+    /// this type does not model are ignored.
+    pub fn parse(uri: &str) -> Result<Magnet> {
+        let query = match uri.strip_prefix("magnet:?") {
+            Some(query) => query,
+            None => return Err(invalid("missing `magnet:?` prefix")),
+        };
+        let mut magnet = Magnet::default();
+        for param in query.split('&').filter(|p| !p.is_empty()) {
+            let (key, value) = param.split_once('=').unwrap_or((param, ""));
+            // numbered keys such as `xt.1` are repeats of the same parameter
+            let key = key.split('.').next().unwrap_or(key);
+            match key {
+                "xt" => magnet.topic(&text(value)?)?,
+                "dn" => magnet.display_name = Some(text(&value.replace('+', " "))?),
+                "tr" => magnet.trackers.push(text(value)?),
+                "ws" => magnet.web_seeds.push(text(value)?),
+                _ => {}
+            }
+        }
+        if magnet.info_hash.is_none() && magnet.info_hash_v2.is_none() {
+            return Err(invalid("no `urn:btih` or `urn:btmh` topic"));
+        }
+        Ok(magnet)
+    }
+
+    fn topic(&mut self, urn: &str) -> Result<()> {
+        if let Some(hash) = urn.strip_prefix("urn:btih:") {
+            let bytes = match hash.len() {
+                40 => from_hex(hash),
+                32 => from_base32(hash),
+                _ => None,
+            };
+            let mut info_hash = [0; 20];
+            match bytes {
+                Some(bytes) if bytes.len() == 20 => info_hash.copy_from_slice(&bytes),
+                _ => return Err(invalid(&format!("bad btih hash `{}`", hash))),
+            }
+            self.info_hash = Some(info_hash);
+        } else if let Some(hash) = urn.strip_prefix("urn:btmh:") {
+            let bytes = hash.strip_prefix(SHA256_MULTIHASH).and_then(from_hex);
+            let mut info_hash = [0; 32];
+            match bytes {
+                Some(bytes) if bytes.len() == 32 => info_hash.copy_from_slice(&bytes),
+                _ => return Err(invalid(&format!("bad btmh hash `{}`", hash))),
+            }
+            self.info_hash_v2 = Some(info_hash);
+        }
+        Ok(())
+    }
+}
+
+impl Display for Magnet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut params = Vec::new();
+        if let Some(hash) = &self.info_hash {
+            params.push(format!("xt=urn:btih:{}", to_hex(hash)));
+        }
+        if let Some(hash) = &self.info_hash_v2 {
+            params.push(format!("xt=urn:btmh:{}{}", SHA256_MULTIHASH, to_hex(hash)));
+        }
+        if let Some(name) = &self.display_name {
+            params.push(format!("dn={}", percent::encode(name.as_bytes())));
+        }
+        for tracker in &self.trackers {
+            params.push(format!("tr={}", percent::encode(tracker.as_bytes())));
+        }
+        for seed in &self.web_seeds {
+            params.push(format!("ws={}", percent::encode(seed.as_bytes())));
+        }
+        write!(f, "magnet:?{}", params.join("&"))
+    }
+}
+
+impl MetaInfo {
+    /// Magnet link for this torrent, with its infohash(es), display name and
+    /// every tracker. v2 torrents get a `urn:btmh` topic as in BEP 52; v1 and
+    /// hybrid torrents a `urn:btih` one. Fails when the infohash does, see
+    /// [`MetaInfo::info_hash`].
+    pub fn magnet(&self) -> Result<Magnet> {
+        Ok(Magnet {
+            info_hash: if self.info.is_v1() || !self.info.is_v2() {
+                Some(self.info_hash()?)
+            } else {
+                None
+            },
+            info_hash_v2: self.info_hash_v2()?,
+            display_name: Some(self.info.name.clone()),
+            trackers: self.trackers().into_iter().map(str::to_string).collect(),
+            web_seeds: self.url_list.clone(),
+        })
+    }
+
+    /// [`MetaInfo::magnet`] as a URI string.
+    pub fn to_magnet(&self) -> Result<String> {
+        Ok(self.magnet()?.to_string())
+    }
+
+    /// `announce` followed by every `announce-list` URL, without duplicates.
+    pub fn trackers(&self) -> Vec<&str> {
+        let mut trackers: Vec<&str> = Vec::new();
+        let all = self
+            .announce
+            .iter()
+            .chain(self.announce_list.iter().flatten());
+        for url in all {
+            if !trackers.contains(&url.as_str()) {
+                trackers.push(url);
+            }
+        }
+        trackers
+    }
+}
+
+fn invalid(reason: &str) -> BencodeError {
+    BencodeError::msg(format!("Invalid magnet link: {}", reason))
+}
+
+fn text(value: &str) -> Result<String> {
+    Ok(String::from_utf8_lossy(&percent::decode(value)?).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_magnet_for_v1_torrent() {
+        let mut buf = b"d8:announce9:http://a/13:announce-listll9:http://a/el9:udp://b:1ee4:infod6:lengthi1e4:name7:my file12:piece lengthi16384e6:pieces20:".to_vec();
+        buf.extend_from_slice(&[0; 20]);
+        buf.extend_from_slice(b"ee");
+        let meta = MetaInfo::from_bytes(&buf).unwrap();
+        let uri = meta.to_magnet().unwrap();
+        assert_eq!(
+            uri,
+            format!(
+                "magnet:?xt=urn:btih:{}&dn=my%20file&tr=http%3A%2F%2Fa%2F&tr=udp%3A%2F%2Fb%3A1",
+                to_hex(&meta.info_hash().unwrap())
+            )
+        );
+        assert_eq!(Magnet::parse(&uri).unwrap(), meta.magnet().unwrap());
+    }
+
+    #[test]
+    fn test_magnet_for_v2_torrent() {
+        let buf = b"d4:infod9:file treed1:ad0:d6:lengthi0eeee12:meta versioni2e4:name1:x12:piece lengthi16384eee";
+        let meta = MetaInfo::from_bytes(buf).unwrap();
+        let hash = meta.info_hash_v2().unwrap().unwrap();
+        assert_eq!(
+            meta.to_magnet().unwrap(),
+            format!("magnet:?xt=urn:btmh:1220{}&dn=x", to_hex(&hash))
+        );
+    }
+
+    #[test]
+    fn test_parse_magnet() {
+        let magnet = Magnet::parse(
+            "magnet:?xt=urn:btih:CIUGQQJEJ7ESAWXSTNQ2JQRHS2SHFNDA&dn=a+b%21\
+             &tr.1=udp%3A%2F%2Ft%3A80&ws=http%3A%2F%2Fseed%2Ff&x.pe=1.2.3.4%3A5",
+        )
+        .unwrap();
+        assert_eq!(
+            to_hex(&magnet.info_hash.unwrap()),
+            "12286841244fc9205af29b61a4c22796a472b460"
+        );
+        assert_eq!(magnet.display_name.as_deref(), Some("a b!"));
+        assert_eq!(magnet.trackers, vec!["udp://t:80"]);
+        assert_eq!(magnet.web_seeds, vec!["http://seed/f"]);
+
+        assert!(Magnet::parse("magnet:?dn=x").is_err());
+        assert!(Magnet::parse("magnet:?xt=urn:btih:1234").is_err());
+        assert!(Magnet::parse("http://example.com").is_err());
+    }
+}
@@ -0,0 +1,730 @@
+use super::v2::{piece_layers_from_value, piece_layers_to_value, FileTree, PieceLayers};
+use crate::document::locate;
+use crate::fields::{missing, require, within, DictBuilder, Fields};
+use crate::hash::{Digest, DigestWriter, Sha1, Sha256};
+use crate::{
+    from_slice, parse_bencode, BencodeError, EncodeOptions, FromBencode, Result, ToBencode, Value,
+};
+use std::collections::HashMap;
+use std::io::{self, BufRead};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Length of one SHA-1 piece hash.
+const PIECE_HASH_LEN: usize = 20;
+
+const METAINFO_FIELDS: &[&str] = &[
+    "announce",
+    "announce-list",
+    "comment",
+    "created by",
+    "creation date",
+    "info",
+    "piece layers",
+    "url-list",
+];
+const INFO_FIELDS: &[&str] = &[
+    "name",
+    "piece length",
+    "pieces",
+    "private",
+    "length",
+    "files",
+    "meta version",
+    "file tree",
+];
+const FILE_FIELDS: &[&str] = &["length", "path"];
+
+/// A parsed `.torrent` file.
+#[derive(Clone, Debug)]
+pub struct MetaInfo {
+    pub announce: Option<String>,
+    /// Tracker tiers from BEP 12, each a list of URLs.
+    pub announce_list: Vec<Vec<String>>,
+    pub comment: Option<String>,
+    pub created_by: Option<String>,
+    /// Seconds since the Unix epoch.
+    pub creation_date: Option<i64>,
+    pub info: Info,
+    /// BEP 52 piece layers; empty for v1 torrents.
+    pub piece_layers: PieceLayers,
+    /// BEP 19 `url-list`: web seed URLs.
+    pub url_list: Vec<String>,
+    /// Any other top-level fields.
+    pub extra: HashMap<String, Value>,
+    /// The `info` dictionary exactly as it appeared in the source bytes.
+    pub(super) raw_info: Option<Vec<u8>>,
+}
+
+impl PartialEq for MetaInfo {
+    fn eq(&self, other: &MetaInfo) -> bool {
+        self.announce == other.announce
+            && self.announce_list == other.announce_list
+            && self.comment == other.comment
+            && self.created_by == other.created_by
+            && self.creation_date == other.creation_date
+            && self.info == other.info
+            && self.piece_layers == other.piece_layers
+            && self.url_list == other.url_list
+            && self.extra == other.extra
+    }
+}
+
+/// The `info` dictionary: what is being shared and how it is split into
+/// pieces.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Info {
+    /// File name for single-file torrents, directory name otherwise.
+    pub name: String,
+    pub piece_length: u64,
+    /// SHA-1 hash of every piece, in order; empty for v2-only torrents.
+    pub pieces: Vec<[u8; 20]>,
+    /// Whether the BEP 27 `private` flag is set.
+    pub private: bool,
+    /// v1 file layout; `None` for v2-only torrents.
+    pub files: Option<Files>,
+    /// BEP 52 `meta version`, 2 for v2 and hybrid torrents.
+    pub meta_version: Option<i64>,
+    /// BEP 52 file layout; `None` for v1-only torrents.
+    pub file_tree: Option<FileTree>,
+    /// Any other fields of the info dictionary, e.g. `md5sum`.
+    pub extra: HashMap<String, Value>,
+}
+
+/// File layout of a torrent.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Files {
+    /// A single file of the given length, named by [`Info::name`].
+    Single { length: u64 },
+    /// Several files inside the directory named by [`Info::name`].
+    Multi(Vec<FileEntry>),
+}
+
+/// One file of a multi-file torrent.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileEntry {
+    pub length: u64,
+    /// Path components below the torrent's directory.
+    pub path: Vec<String>,
+    pub extra: HashMap<String, Value>,
+}
+
+impl MetaInfo {
+    /// Decode a torrent file from its bencoded bytes, keeping the raw `info`
+    /// dictionary for [`MetaInfo::info_hash`]. Bytes after the top-level
+    /// dictionary are an error.
+    pub fn from_bytes(bytes: &[u8]) -> Result<MetaInfo> {
+        if bytes.is_empty() {
+            return Err(BencodeError::msg("Empty torrent file"));
+        }
+        let mut meta = MetaInfo::from_value(&from_slice(bytes)?)?;
+        meta.raw_info = locate(bytes, &["info"])?.map(|span| bytes[span].to_vec());
+        Ok(meta)
+    }
+
+    /// Decode a torrent file from a reader.
+    pub fn from_reader(reader: &mut dyn BufRead) -> Result<MetaInfo> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        MetaInfo::from_bytes(&bytes)
+    }
+
+    /// The v1 infohash: SHA-1 of the encoded `info` dictionary.
+    ///
+    /// For a torrent read with [`MetaInfo::from_bytes`] this hashes the
+    /// original bytes, so non-canonical encodings still produce the hash other
+    /// clients use. Once `info` has been modified, or for a torrent built in
+    /// code, the canonical encoding of `info` is hashed instead, which fails
+    /// if a value in an `extra` map holds a dictionary with a non-string key.
+    pub fn info_hash(&self) -> Result<[u8; 20]> {
+        self.hash_info(Sha1::new())
+    }
+
+    /// The v2 infohash: SHA-256 of the encoded `info` dictionary, or `None`
+    /// for a v1-only torrent. Uses the original bytes like
+    /// [`MetaInfo::info_hash`].
+    pub fn info_hash_v2(&self) -> Result<Option<[u8; 32]>> {
+        if self.info.is_v2() {
+            self.hash_info(Sha256::new()).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// `creation date` as a point in time.
+    pub fn creation_date(&self) -> Option<SystemTime> {
+        let secs = self.creation_date?;
+        let offset = Duration::from_secs(secs.unsigned_abs());
+        if secs < 0 {
+            UNIX_EPOCH.checked_sub(offset)
+        } else {
+            UNIX_EPOCH.checked_add(offset)
+        }
+    }
+
+    /// Set or clear `creation date`, stored as whole seconds since the Unix
+    /// epoch.
+    pub fn set_creation_date(&mut self, time: Option<SystemTime>) {
+        self.creation_date = time.map(|time| match time.duration_since(UNIX_EPOCH) {
+            Ok(after) => after.as_secs() as i64,
+            Err(before) => -(before.duration().as_secs() as i64),
+        });
+    }
+
+    /// The original `info` bytes, as long as [`MetaInfo::info`] still
+    /// decodes from them unchanged.
+    fn unchanged_info(&self) -> Option<&[u8]> {
+        let raw = self.raw_info.as_deref()?;
+        parse_bencode(&mut &raw[..])
+            .ok()
+            .flatten()
+            .and_then(|v| Info::from_value(&v).ok())
+            .filter(|info| *info == self.info)
+            .map(|_| raw)
+    }
+
+    fn hash_info<D: Digest>(&self, mut digest: D) -> Result<D::Output> {
+        if let Some(raw) = self.unchanged_info() {
+            digest.update(raw);
+            return Ok(digest.finalize());
+        }
+        let mut writer = DigestWriter::new(io::sink(), digest);
+        self.info
+            .to_value()
+            .write_bencode_with(&mut writer, EncodeOptions::strict())?;
+        Ok(writer.finalize().1)
+    }
+
+    /// Encode this torrent file to bytes, with keys sorted as BEP 3 requires.
+    ///
+    /// While `info` is unchanged its original bytes are written back
+    /// verbatim, so editing trackers, the comment or web seeds of a parsed
+    /// torrent never changes its infohash, even if the source did not use
+    /// the canonical encoding. Fails if a value in an `extra` map holds a
+    /// dictionary with a non-string key.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        self.to_value()
+            .write_bencode_with(&mut buf, EncodeOptions::strict())?;
+        if let Some(raw) = self.unchanged_info() {
+            if let Ok(Some(span)) = locate(&buf, &["info"]) {
+                buf.splice(span, raw.iter().copied());
+            }
+        }
+        Ok(buf)
+    }
+}
+
+impl Info {
+    /// Total size of all files in bytes. Fails if the file lengths add up to
+    /// more than `u64::MAX`, which only a crafted torrent does.
+    pub fn total_length(&self) -> Result<u64> {
+        let total = match (&self.files, &self.file_tree) {
+            (Some(Files::Single { length }), _) => Some(*length),
+            (Some(Files::Multi(files)), _) => files
+                .iter()
+                .try_fold(0u64, |sum, f| sum.checked_add(f.length)),
+            (None, Some(tree)) => tree.total_length(),
+            (None, None) => Some(0),
+        };
+        total.ok_or_else(|| {
+            BencodeError::msg("total length of the files overflows").in_field("info")
+        })
+    }
+
+    /// Whether this torrent can be used by v1 clients.
+    pub fn is_v1(&self) -> bool {
+        self.files.is_some()
+    }
+
+    /// Whether this torrent carries BEP 52 v2 metadata.
+    pub fn is_v2(&self) -> bool {
+        self.file_tree.is_some()
+    }
+
+    /// Whether this is a hybrid torrent usable by both v1 and v2 clients.
+    pub fn is_hybrid(&self) -> bool {
+        self.is_v1() && self.is_v2()
+    }
+
+    /// Number of pieces the content is split into.
+    pub fn piece_count(&self) -> usize {
+        self.pieces.len()
+    }
+}
+
+impl FromBencode for MetaInfo {
+    fn from_value(value: &Value) -> Result<Self> {
+        let fields = Fields::new(value)?;
+        let announce_list = match fields.list("announce-list")? {
+            None => Vec::new(),
+            Some(tiers) => tiers
+                .iter()
+                .enumerate()
+                .map(|(i, tier)| string_list(tier, &format!("announce-list[{}]", i)))
+                .collect::<Result<_>>()?,
+        };
+        let info = require("info", fields.get("info"))?;
+        let piece_layers = match fields.get("piece layers") {
+            Some(layers) => piece_layers_from_value(layers)?,
+            None => PieceLayers::new(),
+        };
+        // a single web seed may be given as a plain string
+        let url_list = match fields.get("url-list") {
+            None => Vec::new(),
+            Some(url) if url.as_bytes().is_some() => {
+                vec![require("url-list", fields.string("url-list")?)?]
+            }
+            Some(urls) => string_list(urls, "url-list")?,
+        };
+        Ok(MetaInfo {
+            announce: fields.string("announce")?,
+            announce_list,
+            comment: fields.string("comment")?,
+            created_by: fields.string("created by")?,
+            creation_date: fields.int("creation date")?,
+            info: within("info", Info::from_value(info))?,
+            piece_layers,
+            url_list,
+            extra: fields.rest(METAINFO_FIELDS),
+            raw_info: None,
+        })
+    }
+}
+
+impl ToBencode for MetaInfo {
+    fn to_value(&self) -> Value {
+        let announce_list = if self.announce_list.is_empty() {
+            None
+        } else {
+            Some(Value::List(
+                self.announce_list.iter().map(|t| to_list(t)).collect(),
+            ))
+        };
+        DictBuilder::new()
+            .opt("announce", self.announce.as_deref())
+            .opt("announce-list", announce_list)
+            .opt("comment", self.comment.as_deref())
+            .opt("created by", self.created_by.as_deref())
+            .opt("creation date", self.creation_date)
+            .set("info", self.info.to_value())
+            .opt(
+                "piece layers",
+                if self.piece_layers.is_empty() {
+                    None
+                } else {
+                    Some(piece_layers_to_value(&self.piece_layers))
+                },
+            )
+            .opt(
+                "url-list",
+                if self.url_list.is_empty() {
+                    None
+                } else {
+                    Some(to_list(&self.url_list))
+                },
+            )
+            .extend(&self.extra)
+            .build()
+    }
+}
+
+impl FromBencode for Info {
+    fn from_value(value: &Value) -> Result<Self> {
+        let fields = Fields::new(value)?;
+        let meta_version = fields.int("meta version")?;
+        let file_tree = match fields.get("file tree") {
+            Some(tree) => Some(within("file tree", FileTree::from_value(tree))?),
+            None => None,
+        };
+        match (meta_version, &file_tree) {
+            (None, _) | (Some(2), Some(_)) => {}
+            (Some(2), None) => return Err(missing("file tree")),
+            (Some(v), _) => {
+                return Err(BencodeError::msg(format!("unsupported version {}", v))
+                    .in_field("meta version"))
+            }
+        }
+
+        let files = match (fields.get("length"), fields.list("files")?) {
+            (Some(_), None) => Some(Files::Single {
+                length: length(&fields, "length")?,
+            }),
+            (None, Some(files)) => Some(Files::Multi(
+                files
+                    .iter()
+                    .enumerate()
+                    .map(|(i, f)| within(&format!("files[{}]", i), FileEntry::from_value(f)))
+                    .collect::<Result<_>>()?,
+            )),
+            (Some(_), Some(_)) => {
+                return Err(BencodeError::msg(
+                    "Fields `length` and `files` are mutually exclusive",
+                ))
+            }
+            (None, None) if file_tree.is_some() => None,
+            (None, None) => {
+                return Err(BencodeError::msg(
+                    "Missing field `length`, `files` or `file tree`",
+                ))
+            }
+        };
+
+        // v1 piece hashes are only required alongside a v1 file layout
+        let pieces = match fields.bytes("pieces")? {
+            Some(pieces) => pieces,
+            None if files.is_none() => &[],
+            None => return Err(missing("pieces")),
+        };
+        if pieces.len() % PIECE_HASH_LEN != 0 {
+            return Err(BencodeError::msg(format!(
+                "length {} is not a multiple of {}",
+                pieces.len(),
+                PIECE_HASH_LEN
+            ))
+            .in_field("pieces"));
+        }
+        let piece_length = length(&fields, "piece length")?;
+        if piece_length == 0 {
+            return Err(BencodeError::msg("must be positive").in_field("piece length"));
+        }
+
+        Ok(Info {
+            name: require("name", fields.string("name")?)?,
+            piece_length,
+            pieces: pieces
+                .chunks_exact(PIECE_HASH_LEN)
+                .map(|c| {
+                    let mut hash = [0; PIECE_HASH_LEN];
+                    hash.copy_from_slice(c);
+                    hash
+                })
+                .collect(),
+            private: fields.int("private")? == Some(1),
+            files,
+            meta_version,
+            file_tree,
+            extra: fields.rest(INFO_FIELDS),
+        })
+    }
+}
+
+impl ToBencode for Info {
+    fn to_value(&self) -> Value {
+        let (length, files) = match &self.files {
+            Some(Files::Single { length }) => (Some(*length as i64), None),
+            Some(Files::Multi(files)) => (
+                None,
+                Some(Value::List(files.iter().map(|f| f.to_value()).collect())),
+            ),
+            None => (None, None),
+        };
+        let pieces = if self.pieces.is_empty() && self.files.is_none() {
+            None
+        } else {
+            Some(self.pieces.concat())
+        };
+        DictBuilder::new()
+            .set("name", self.name.as_str())
+            .set("piece length", self.piece_length as i64)
+            .opt("pieces", pieces)
+            .opt("private", if self.private { Some(1) } else { None })
+            .opt("length", length)
+            .opt("files", files)
+            .opt("meta version", self.meta_version)
+            .opt("file tree", self.file_tree.as_ref().map(|t| t.to_value()))
+            .extend(&self.extra)
+            .build()
+    }
+}
+
+impl FileEntry {
+    /// A BEP 47 padding file of `length` zero bytes, which pushes the next
+    /// file to the start of a piece.
+    pub fn padding(length: u64) -> FileEntry {
+        let mut extra = HashMap::new();
+        extra.insert("attr".to_string(), Value::from("p"));
+        FileEntry {
+            length,
+            path: vec![".pad".to_string(), length.to_string()],
+            extra,
+        }
+    }
+
+    /// Whether BEP 47 `attr` marks this as a padding file. Clients do not
+    /// need to write padding files to disk.
+    pub fn is_padding(&self) -> bool {
+        self.extra
+            .get("attr")
+            .and_then(Value::as_bytes)
+            .is_some_and(|attr| attr.contains(&b'p'))
+    }
+}
+
+impl FromBencode for FileEntry {
+    fn from_value(value: &Value) -> Result<Self> {
+        let fields = Fields::new(value)?;
+        let path = require("path", fields.get("path"))?;
+        let path = string_list(path, "path")?;
+        if path.is_empty() {
+            return Err(BencodeError::msg("must not be empty").in_field("path"));
+        }
+        Ok(FileEntry {
+            length: length(&fields, "length")?,
+            path,
+            extra: fields.rest(FILE_FIELDS),
+        })
+    }
+}
+
+impl ToBencode for FileEntry {
+    fn to_value(&self) -> Value {
+        DictBuilder::new()
+            .set("length", self.length as i64)
+            .set("path", to_list(&self.path))
+            .extend(&self.extra)
+            .build()
+    }
+}
+
+/// SHA-1 of the `info` dictionary of the torrent file in `torrent`, taken over
+/// its exact bytes without decoding the rest of the file.
+pub fn info_hash(torrent: &[u8]) -> Result<[u8; 20]> {
+    match locate(torrent, &["info"])? {
+        Some(span) => Ok(Sha1::digest(&torrent[span])),
+        None => Err(missing("info")),
+    }
+}
+
+/// SHA-256 of the `info` dictionary of the v2 torrent file in `torrent`, taken
+/// over its exact bytes.
+pub fn info_hash_v2(torrent: &[u8]) -> Result<[u8; 32]> {
+    match locate(torrent, &["info"])? {
+        Some(span) => Ok(Sha256::digest(&torrent[span])),
+        None => Err(missing("info")),
+    }
+}
+
+/// A required, non-negative integer field.
+pub(super) fn length(fields: &Fields, key: &str) -> Result<u64> {
+    require(key, fields.uint(key)?)
+}
+
+fn string_list(value: &Value, what: &str) -> Result<Vec<String>> {
+    match value {
+        Value::List(items) => items
+            .iter()
+            .map(|v| {
+                v.as_bytes()
+                    .map(|b| String::from_utf8_lossy(b).into_owned())
+                    .ok_or_else(|| BencodeError::msg("expected a list of strings").in_field(what))
+            })
+            .collect(),
+        _ => Err(BencodeError::msg(format!(
+            "expected list, found {}",
+            crate::fields::type_name(value)
+        ))
+        .in_field(what)),
+    }
+}
+
+fn to_list(strings: &[String]) -> Value {
+    Value::List(strings.iter().map(|s| Value::from(s.as_str())).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ErrorKind;
+
+    fn single_file() -> Vec<u8> {
+        let mut buf = b"d8:announce23:http://tracker/announce7:comment2:hi13:creation datei1589000000e4:infod6:lengthi3000000000e4:name8:file.bin12:piece lengthi262144e6:pieces40:".to_vec();
+        buf.extend_from_slice(&[0xab; 40]);
+        buf.extend_from_slice(b"ee");
+        buf
+    }
+
+    #[test]
+    fn test_parse_single_file_torrent() {
+        let meta = MetaInfo::from_bytes(&single_file()).unwrap();
+        assert_eq!(meta.announce.as_deref(), Some("http://tracker/announce"));
+        assert_eq!(meta.comment.as_deref(), Some("hi"));
+        assert_eq!(meta.creation_date, Some(1_589_000_000));
+        assert_eq!(meta.info.name, "file.bin");
+        assert_eq!(meta.info.piece_length, 262_144);
+        assert_eq!(meta.info.pieces, vec![[0xab; 20], [0xab; 20]]);
+        // larger than i32::MAX
+        assert_eq!(
+            meta.info.files,
+            Some(Files::Single {
+                length: 3_000_000_000
+            })
+        );
+        assert!(!meta.info.is_v2());
+        assert_eq!(meta.info_hash_v2().unwrap(), None);
+        assert_eq!(
+            MetaInfo::from_bytes(&meta.to_bytes().unwrap()).unwrap(),
+            meta
+        );
+    }
+
+    #[test]
+    fn test_trailing_bytes_are_rejected() {
+        let mut buf = single_file();
+        let end = buf.len();
+        buf.extend_from_slice(b"GARBAGE");
+        let err = MetaInfo::from_bytes(&buf).unwrap_err();
+        assert_eq!(err.kind(), Some(ErrorKind::Trailing));
+        assert_eq!(err.position(), Some(end));
+        assert!(MetaInfo::from_bytes(b"").is_err());
+    }
+
+    #[test]
+    fn test_creation_date_as_system_time() {
+        let mut meta = MetaInfo::from_bytes(&single_file()).unwrap();
+        let time = meta.creation_date().unwrap();
+        assert_eq!(time, UNIX_EPOCH + Duration::from_secs(1_589_000_000));
+
+        meta.set_creation_date(Some(UNIX_EPOCH - Duration::from_millis(1500)));
+        assert_eq!(meta.creation_date, Some(-1));
+        meta.set_creation_date(Some(time + Duration::from_millis(999)));
+        assert_eq!(meta.creation_date, Some(1_589_000_000));
+        meta.set_creation_date(None);
+        assert_eq!(meta.creation_date(), None);
+    }
+
+    #[test]
+    fn test_parse_multi_file_torrent() {
+        let mut buf = b"d13:announce-listll1:ael1:b1:cee4:infod5:filesld6:lengthi5e4:pathl1:d1:xeed6:lengthi7e4:pathl1:yeee4:name3:dir12:piece lengthi16e6:pieces20:".to_vec();
+        buf.extend_from_slice(&[0; 20]);
+        buf.extend_from_slice(b"7:privatei1eee");
+        let meta = MetaInfo::from_bytes(&buf).unwrap();
+        assert_eq!(meta.announce_list, vec![vec!["a"], vec!["b", "c"]]);
+        assert!(meta.info.private);
+        assert_eq!(meta.info.total_length().unwrap(), 12);
+        match &meta.info.files {
+            Some(Files::Multi(files)) => assert_eq!(files[0].path, vec!["d", "x"]),
+            other => panic!("expected multi-file layout, got {:?}", other),
+        }
+        assert_eq!(meta.to_bytes().unwrap(), buf);
+    }
+
+    #[test]
+    fn test_padding_files() {
+        let buf = b"d6:lengthi7e4:pathl4:.pad1:7e4:attr2:pxe";
+        let value = parse_bencode(&mut &buf[..]).unwrap().unwrap();
+        let entry = FileEntry::from_value(&value).unwrap();
+        assert!(entry.is_padding());
+        assert_eq!(FileEntry::padding(7).path, entry.path);
+        assert!(FileEntry::padding(7).is_padding());
+
+        let value = parse_bencode(&mut &b"d6:lengthi7e4:pathl1:aee"[..])
+            .unwrap()
+            .unwrap();
+        assert!(!FileEntry::from_value(&value).unwrap().is_padding());
+    }
+
+    #[test]
+    fn test_info_hash_uses_original_bytes() {
+        // `private` sorts after `pieces` but appears first: not canonical
+        let mut buf =
+            b"d4:infod7:privatei1e6:lengthi1e4:name1:a12:piece lengthi1e6:pieces20:".to_vec();
+        buf.extend_from_slice(&[7; 20]);
+        buf.extend_from_slice(b"ee");
+        let start = b"d4:info".len();
+        let expected = Sha1::digest(&buf[start..buf.len() - 1]);
+
+        assert_eq!(info_hash(&buf).unwrap(), expected);
+        let mut meta = MetaInfo::from_bytes(&buf).unwrap();
+        assert_eq!(meta.info_hash().unwrap(), expected);
+
+        // after an edit the canonical encoding is hashed
+        meta.info.name = "b".into();
+        let canonical = meta.to_bytes().unwrap();
+        assert_ne!(meta.info_hash().unwrap(), expected);
+        assert_eq!(meta.info_hash().unwrap(), info_hash(&canonical).unwrap());
+    }
+
+    #[test]
+    fn test_info_hash_with_unsorted_top_level_keys() {
+        // `info` sorts before `url-list` but appears after it
+        let mut buf =
+            b"d8:url-list9:http://ws4:infod7:privatei1e6:lengthi1e4:name1:a12:piece lengthi1e6:pieces20:"
+                .to_vec();
+        buf.extend_from_slice(&[7; 20]);
+        buf.extend_from_slice(b"ee");
+        let start = b"d8:url-list9:http://ws4:info".len();
+        let expected = Sha1::digest(&buf[start..buf.len() - 1]);
+
+        assert_eq!(info_hash(&buf).unwrap(), expected);
+        let meta = MetaInfo::from_bytes(&buf).unwrap();
+        assert_eq!(meta.info_hash().unwrap(), expected);
+        assert_eq!(info_hash(&meta.to_bytes().unwrap()).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_edits_preserve_info_bytes() {
+        let mut buf = b"d8:announce5:udp:a4:infod7:privatei1e6:lengthi1e4:name1:a12:piece lengthi1e6:pieces20:".to_vec();
+        buf.extend_from_slice(&[7; 20]);
+        buf.extend_from_slice(b"e8:url-list9:http://wse");
+        let mut meta = MetaInfo::from_bytes(&buf).unwrap();
+        assert_eq!(meta.url_list, vec!["http://ws"]);
+        let original = meta.info_hash().unwrap();
+
+        meta.announce = Some("udp:b".into());
+        meta.announce_list = vec![vec!["udp:b".into(), "udp:c".into()]];
+        meta.comment = Some("edited".into());
+        meta.url_list.push("http://ws2".into());
+        let edited = meta.to_bytes().unwrap();
+        assert_eq!(info_hash(&edited).unwrap(), original);
+        let reread = MetaInfo::from_bytes(&edited).unwrap();
+        assert_eq!(reread, meta);
+        assert_eq!(reread.info_hash().unwrap(), original);
+    }
+
+    #[test]
+    fn test_parse_v2_torrent() {
+        let mut buf = b"d4:infod9:file treed1:ad0:d6:lengthi20000e11:pieces root32:".to_vec();
+        buf.extend_from_slice(&[1; 32]);
+        buf.extend_from_slice(
+            b"eee12:meta versioni2e4:name1:x12:piece lengthi16384ee12:piece layersd32:",
+        );
+        buf.extend_from_slice(&[1; 32]);
+        buf.extend_from_slice(b"64:");
+        buf.extend_from_slice(&[2; 64]);
+        buf.extend_from_slice(b"ee");
+
+        let meta = MetaInfo::from_bytes(&buf).unwrap();
+        assert!(meta.info.is_v2() && !meta.info.is_v1());
+        assert_eq!(meta.info.total_length().unwrap(), 20_000);
+        assert_eq!(meta.piece_layers[&[1; 32]], vec![[2; 32], [2; 32]]);
+        assert_eq!(meta.to_bytes().unwrap(), buf);
+
+        let expected = info_hash_v2(&buf).unwrap();
+        assert_eq!(meta.info_hash_v2().unwrap(), Some(expected));
+        let start = b"d4:info".len();
+        let end = buf.len() - b"12:piece layersd32:".len() - 32 - b"64:".len() - 64 - 2;
+        assert_eq!(expected, Sha256::digest(&buf[start..end]));
+    }
+
+    #[test]
+    fn test_invalid_fields_are_reported() {
+        let err = |bytes: &[u8]| MetaInfo::from_bytes(bytes).unwrap_err().to_string();
+        assert!(err(b"d8:announce1:ae").contains("Missing field `info`"));
+        assert!(
+            err(b"d4:infod4:name1:a12:piece lengthi1e6:pieces3:abc6:lengthi1eee")
+                .contains("Field `info.pieces`: length 3")
+        );
+        assert!(
+            err(b"d4:infod4:name1:a12:piece lengthi1e6:pieces0:6:lengthi-1eee")
+                .contains("must not be negative")
+        );
+        assert!(
+            err(b"d4:infod4:name1:a12:piece lengthi1e6:pieces0:5:filesld4:pathl1:aeeeee")
+                .contains("Field `info.files[0]`: Missing field `length`")
+        );
+        assert!(err(
+            b"d4:infod4:name1:a12:piece lengthi1e6:pieces0:5:filesld6:lengthi1e4:path1:aeeee"
+        )
+        .contains("Field `info.files[0].path`: expected list, found string"));
+    }
+}
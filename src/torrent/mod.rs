@@ -0,0 +1,37 @@
+//! BitTorrent metainfo (`.torrent`) files.
+//!
+//! [`MetaInfo`] is a typed view of a torrent file as described in BEP 3:
+//! tracker URLs and descriptive fields at the top level, and the `info`
+//! dictionary with the piece hashes and file layout. Keys this module does not
+//! know about are kept in `extra` maps so they survive a round trip.
+//!
+//! BitTorrent v2 (BEP 52) torrents carry a [`FileTree`] and piece layers
+//! instead of, or in hybrid torrents alongside, the v1 file list and piece
+//! hashes.
+//!
+//! [`TorrentBuilder`] goes the other way and creates a torrent from files on
+//! disk.
+
+mod announce_list;
+mod builder;
+mod convert;
+mod info_hash;
+mod lint;
+mod magnet;
+mod metainfo;
+mod path;
+mod v2;
+mod validate;
+mod verify;
+mod web_seed;
+
+pub use announce_list::AnnounceList;
+pub use builder::TorrentBuilder;
+pub use info_hash::InfoHash;
+pub use lint::{lint, lint_bytes, Lint};
+pub use magnet::Magnet;
+pub use metainfo::{info_hash, info_hash_v2, FileEntry, Files, Info, MetaInfo};
+pub use path::{path_components, sanitize_path};
+pub use v2::{FileTree, PieceLayers, TreeFile};
+pub use verify::{verify, Verify};
+pub use web_seed::WebSeed;
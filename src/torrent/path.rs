@@ -0,0 +1,167 @@
+use super::metainfo::{FileEntry, Files, Info};
+use super::v2::TreeFile;
+use crate::{BencodeError, Result};
+use std::path::{Component, Path, PathBuf};
+
+/// Device names Windows reserves in every directory, with or without an
+/// extension.
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Join torrent path components into a relative path that cannot leave the
+/// download directory. Rejects empty paths and components, `.` and `..`,
+/// separators, drive prefixes, control characters and reserved device names.
+pub fn sanitize_path<S: AsRef<str>>(components: &[S]) -> Result<PathBuf> {
+    if components.is_empty() {
+        return Err(unsafe_path("", "path is empty"));
+    }
+    let mut path = PathBuf::new();
+    for component in components {
+        let component = component.as_ref();
+        check_component(component)?;
+        path.push(component);
+    }
+    Ok(path)
+}
+
+/// The reverse of [`sanitize_path`]: split a relative path into torrent path
+/// components, refusing anything [`sanitize_path`] would reject.
+pub fn path_components(path: &Path) -> Result<Vec<String>> {
+    let mut components = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => match name.to_str() {
+                Some(name) => components.push(name.to_string()),
+                None => {
+                    return Err(unsafe_path(
+                        &name.to_string_lossy(),
+                        "name is not valid UTF-8",
+                    ))
+                }
+            },
+            Component::CurDir => {}
+            _ => {
+                return Err(unsafe_path(
+                    &path.display().to_string(),
+                    "path is not relative",
+                ))
+            }
+        }
+    }
+    sanitize_path(&components)?;
+    Ok(components)
+}
+
+impl FileEntry {
+    /// [`FileEntry::path`] as a sanitized relative path.
+    pub fn relative_path(&self) -> Result<PathBuf> {
+        sanitize_path(&self.path)
+    }
+}
+
+impl TreeFile {
+    /// [`TreeFile::path`] as a sanitized relative path.
+    pub fn relative_path(&self) -> Result<PathBuf> {
+        sanitize_path(&self.path)
+    }
+}
+
+impl Info {
+    /// Where each file of the torrent goes relative to the download
+    /// directory, with its length. Multi-file torrents are placed in a
+    /// directory named after the torrent, as clients do. Padding files are
+    /// left out.
+    pub fn file_paths(&self) -> Result<Vec<(PathBuf, u64)>> {
+        let root = sanitize_path(&[self.name.as_str()])?;
+        match (&self.files, &self.file_tree) {
+            (Some(Files::Single { length }), _) => Ok(vec![(root, *length)]),
+            (Some(Files::Multi(entries)), _) => entries
+                .iter()
+                .filter(|e| !e.is_padding())
+                .map(|e| Ok((root.join(e.relative_path()?), e.length)))
+                .collect(),
+            (None, Some(tree)) => tree
+                .files()
+                .iter()
+                .map(|f| Ok((root.join(f.relative_path()?), f.length)))
+                .collect(),
+            (None, None) => Ok(Vec::new()),
+        }
+    }
+}
+
+fn check_component(name: &str) -> Result<()> {
+    let reason = if name.is_empty() {
+        "empty component"
+    } else if name == "." || name == ".." {
+        "relative component"
+    } else if name.contains(['/', '\\']) {
+        "contains a path separator"
+    } else if name.contains(':') {
+        "contains a drive or stream separator"
+    } else if name.chars().any(char::is_control) {
+        "contains a control character"
+    } else if is_reserved(name) {
+        "reserved device name"
+    } else {
+        return Ok(());
+    };
+    Err(unsafe_path(name, reason))
+}
+
+fn is_reserved(name: &str) -> bool {
+    let stem = name.split('.').next().unwrap_or(name).trim_end();
+    RESERVED_NAMES.iter().any(|r| r.eq_ignore_ascii_case(stem))
+}
+
+fn unsafe_path(name: &str, reason: &str) -> BencodeError {
+    BencodeError::msg(format!("Unsafe path `{}`: {}", name, reason))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanitize_path() {
+        assert_eq!(
+            sanitize_path(&["dir", "file.txt"]).unwrap(),
+            Path::new("dir").join("file.txt")
+        );
+        for bad in [
+            &["..", "etc"][..],
+            &["a", ""],
+            &["/etc"],
+            &["a\\..\\b"],
+            &["C:"],
+            &["nul.txt"],
+            &["Com1"],
+            &["a\0b"],
+            &[],
+        ] {
+            assert!(sanitize_path(bad).is_err(), "{:?}", bad);
+        }
+        assert!(sanitize_path(&["console", "nullable.txt"]).is_ok());
+    }
+
+    #[test]
+    fn test_path_components() {
+        let path = Path::new("a").join(".").join("b.txt");
+        assert_eq!(path_components(&path).unwrap(), vec!["a", "b.txt"]);
+        assert!(path_components(&Path::new("a").join("..").join("b")).is_err());
+        assert!(path_components(&std::env::temp_dir()).is_err());
+    }
+
+    #[test]
+    fn test_info_file_paths() {
+        let buf = b"d4:infod5:filesld6:lengthi1e4:pathl1:a1:beed6:lengthi2e4:pathl2:..1:ceee4:name1:x12:piece lengthi16384e6:pieces0:ee";
+        let meta = crate::torrent::MetaInfo::from_bytes(buf).unwrap();
+        let err = meta.info.file_paths().unwrap_err().to_string();
+        assert!(err.contains("Unsafe path `..`"), "{}", err);
+        if let Some(Files::Multi(files)) = &meta.info.files {
+            assert_eq!(files[0].relative_path().unwrap(), Path::new("a").join("b"));
+        }
+    }
+}
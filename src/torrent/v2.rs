@@ -0,0 +1,280 @@
+use super::metainfo::length;
+use crate::fields::{expected, missing, type_name, within, DictBuilder, Fields};
+use crate::hash::{Digest, Sha256};
+use crate::{BencodeError, FromBencode, HMap, Result, ToBencode, Value};
+use std::collections::{BTreeMap, HashMap};
+
+/// Length of one SHA-256 hash in a v2 torrent.
+const HASH_LEN: usize = 32;
+/// Size of the blocks whose hashes are the leaves of a file's Merkle tree.
+pub(super) const BLOCK_LEN: usize = 1 << 14;
+
+/// Node of a BEP 52 `file tree`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FileTree {
+    /// A file. `pieces_root` is the Merkle root of its 16 KiB blocks and is
+    /// absent for empty files.
+    File {
+        length: u64,
+        pieces_root: Option<[u8; 32]>,
+    },
+    /// A directory, keyed by path component.
+    Dir(BTreeMap<String, FileTree>),
+}
+
+/// A file in a [`FileTree`] together with its path.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TreeFile {
+    pub path: Vec<String>,
+    pub length: u64,
+    pub pieces_root: Option<[u8; 32]>,
+}
+
+/// Hashes of the piece layer of each file larger than one piece, keyed by the
+/// file's pieces root.
+pub type PieceLayers = HashMap<[u8; 32], Vec<[u8; 32]>>;
+
+impl FileTree {
+    /// Every file in the tree, depth first in key order.
+    pub fn files(&self) -> Vec<TreeFile> {
+        let mut files = Vec::new();
+        self.collect(&mut Vec::new(), &mut files);
+        files
+    }
+
+    /// Total size of all files in bytes, or `None` if it does not fit in a
+    /// `u64`.
+    pub fn total_length(&self) -> Option<u64> {
+        match self {
+            FileTree::File { length, .. } => Some(*length),
+            FileTree::Dir(children) => children
+                .values()
+                .try_fold(0u64, |sum, child| sum.checked_add(child.total_length()?)),
+        }
+    }
+
+    fn collect(&self, path: &mut Vec<String>, out: &mut Vec<TreeFile>) {
+        match self {
+            FileTree::File {
+                length,
+                pieces_root,
+            } => out.push(TreeFile {
+                path: path.clone(),
+                length: *length,
+                pieces_root: *pieces_root,
+            }),
+            FileTree::Dir(children) => {
+                for (name, child) in children {
+                    path.push(name.clone());
+                    child.collect(path, out);
+                    path.pop();
+                }
+            }
+        }
+    }
+}
+
+impl FromBencode for FileTree {
+    fn from_value(value: &Value) -> Result<Self> {
+        let map = match value {
+            Value::Map(map) => map,
+            _ => return Err(expected("dictionary", value)),
+        };
+        // a file is a dictionary whose only key is the empty string
+        if let Some(file) = map.get(&Value::from("")) {
+            if map.0.len() != 1 {
+                return Err(BencodeError::msg("File entry must not have sibling keys"));
+            }
+            let fields = Fields::new(file)?;
+            let length = length(&fields, "length")?;
+            let pieces_root = match fields.bytes("pieces root")? {
+                Some(root) => Some(hash32(root, "pieces root")?),
+                None if length > 0 => return Err(missing("pieces root")),
+                None => None,
+            };
+            return Ok(FileTree::File {
+                length,
+                pieces_root,
+            });
+        }
+
+        let mut children = BTreeMap::new();
+        for (key, child) in &map.0 {
+            let name = match key.as_bytes() {
+                Some(name) => String::from_utf8_lossy(name).into_owned(),
+                None => return Err(BencodeError::msg("File tree keys must be strings")),
+            };
+            let node = within(&name, FileTree::from_value(child))?;
+            children.insert(name, node);
+        }
+        Ok(FileTree::Dir(children))
+    }
+}
+
+impl ToBencode for FileTree {
+    fn to_value(&self) -> Value {
+        match self {
+            FileTree::File {
+                length,
+                pieces_root,
+            } => {
+                let file = DictBuilder::new()
+                    .set("length", *length as i64)
+                    .opt("pieces root", pieces_root.map(|r| r.to_vec()))
+                    .build();
+                DictBuilder::new().set("", file).build()
+            }
+            FileTree::Dir(children) => Value::Map(HMap(
+                children
+                    .iter()
+                    .map(|(name, child)| (Value::from(name.as_str()), child.to_value()))
+                    .collect(),
+            )),
+        }
+    }
+}
+
+pub(super) fn piece_layers_from_value(value: &Value) -> Result<PieceLayers> {
+    let map = match value {
+        Value::Map(map) => map,
+        _ => {
+            return Err(BencodeError::msg(format!(
+                "expected dictionary, found {}",
+                type_name(value)
+            ))
+            .in_field("piece layers"))
+        }
+    };
+    let mut layers = HashMap::new();
+    for (root, hashes) in &map.0 {
+        let root = match root.as_bytes() {
+            Some(root) => hash32(root, "piece layers")?,
+            None => return Err(BencodeError::msg("keys must be strings").in_field("piece layers")),
+        };
+        let hashes = match hashes.as_bytes() {
+            Some(hashes) if hashes.len() % HASH_LEN == 0 => hashes
+                .chunks_exact(HASH_LEN)
+                .map(|c| hash32(c, "piece layers"))
+                .collect::<Result<_>>()?,
+            _ => {
+                return Err(BencodeError::msg(format!(
+                    "expected concatenated {}-byte hashes",
+                    HASH_LEN
+                ))
+                .in_field("piece layers"))
+            }
+        };
+        layers.insert(root, hashes);
+    }
+    Ok(layers)
+}
+
+pub(super) fn piece_layers_to_value(layers: &PieceLayers) -> Value {
+    Value::Map(HMap(
+        layers
+            .iter()
+            .map(|(root, hashes)| (Value::from(root.to_vec()), Value::from(hashes.concat())))
+            .collect(),
+    ))
+}
+
+/// Merkle root and piece layer of a file from the SHA-256 hashes of its
+/// 16 KiB blocks. Leaves are padded with zero hashes to a power of two; the
+/// piece layer only means something for files longer than one piece.
+pub(super) fn merkle(leaves: &[[u8; 32]], piece_length: u64) -> ([u8; 32], Vec<[u8; 32]>) {
+    let blocks_per_piece = (piece_length / BLOCK_LEN as u64) as usize;
+    let mut layer = leaves.to_vec();
+    layer.resize(leaves.len().next_power_of_two(), [0; HASH_LEN]);
+    let mut piece_layer = Vec::new();
+    let mut width = 1;
+    loop {
+        if width == blocks_per_piece {
+            piece_layer = layer[..leaves.len().div_ceil(width)].to_vec();
+        }
+        if layer.len() <= 1 {
+            break;
+        }
+        layer = layer
+            .chunks_exact(2)
+            .map(|pair| {
+                let mut digest = Sha256::new();
+                digest.update(&pair[0]);
+                digest.update(&pair[1]);
+                digest.finalize()
+            })
+            .collect();
+        width *= 2;
+    }
+    (layer[0], piece_layer)
+}
+
+fn hash32(bytes: &[u8], key: &str) -> Result<[u8; 32]> {
+    if bytes.len() != HASH_LEN {
+        return Err(BencodeError::msg(format!(
+            "expected a {}-byte hash, found {} bytes",
+            HASH_LEN,
+            bytes.len()
+        ))
+        .in_field(key));
+    }
+    let mut hash = [0; HASH_LEN];
+    hash.copy_from_slice(bytes);
+    Ok(hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_bencode;
+
+    #[test]
+    fn test_file_tree_roundtrip() {
+        let mut buf = b"d3:dird1:bd0:d6:lengthi0eee1:ad0:d6:lengthi3e11:pieces root32:".to_vec();
+        buf.extend_from_slice(&[1; 32]);
+        buf.extend_from_slice(b"eeee");
+        let value = parse_bencode(&mut &buf[..]).unwrap().unwrap();
+        let tree = FileTree::from_value(&value).unwrap();
+
+        let files = tree.files();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].path, vec!["dir", "a"]);
+        assert_eq!(files[0].pieces_root, Some([1; 32]));
+        assert_eq!(files[1].path, vec!["dir", "b"]);
+        assert_eq!(files[1].pieces_root, None);
+        assert_eq!(tree.total_length(), Some(3));
+        assert_eq!(FileTree::from_value(&tree.to_value()).unwrap(), tree);
+    }
+
+    #[test]
+    fn test_merkle_pads_with_zero_leaves() {
+        let leaves = [[1; 32], [2; 32], [3; 32]];
+        let pair = |a: &[u8; 32], b: &[u8; 32]| {
+            let mut digest = Sha256::new();
+            digest.update(a);
+            digest.update(b);
+            digest.finalize()
+        };
+        let root = pair(&pair(&leaves[0], &leaves[1]), &pair(&leaves[2], &[0; 32]));
+        let (got, layer) = merkle(&leaves, BLOCK_LEN as u64);
+        assert_eq!(got, root);
+        assert_eq!(layer, leaves.to_vec());
+
+        let (got, layer) = merkle(&leaves, 2 * BLOCK_LEN as u64);
+        assert_eq!(got, root);
+        assert_eq!(layer.len(), 2);
+        assert_eq!(merkle(&leaves[..1], 1 << 20).0, leaves[0]);
+    }
+
+    #[test]
+    fn test_file_tree_rejects_missing_root() {
+        let value = parse_bencode(&mut &b"d1:ad0:d6:lengthi3eeee"[..])
+            .unwrap()
+            .unwrap();
+        let err = FileTree::from_value(&value).unwrap_err().to_string();
+        assert!(
+            err.contains("Field `a`: Missing field `pieces root`"),
+            "{}",
+            err
+        );
+    }
+}
@@ -0,0 +1,145 @@
+use super::metainfo::MetaInfo;
+use crate::hash::to_hex;
+use crate::{BencodeError, Result};
+
+/// Smallest piece length BEP 52 allows (16 KiB).
+const MIN_V2_PIECE_LENGTH: u64 = 1 << 14;
+/// Largest piece length accepted (64 MiB). Pieces are buffered whole while
+/// hashing, so this bounds the memory a torrent can make us allocate.
+pub(super) const PIECE_LENGTH_LIMIT: u64 = 1 << 26;
+
+impl MetaInfo {
+    /// Cross-check the file layout against the piece hashes: the number of
+    /// v1 `pieces` must match the total length in `piece length` pieces, and
+    /// every v2 file longer than one piece needs a piece layer of the right
+    /// size. Pieces longer than 64 MiB are rejected. Parsing only checks
+    /// each field on its own; run this before trusting a torrent from
+    /// elsewhere.
+    pub fn validate(&self) -> Result<()> {
+        let info = &self.info;
+        let piece_length = info.piece_length;
+        if piece_length > PIECE_LENGTH_LIMIT {
+            return Err(BencodeError::msg(format!(
+                "info: piece length {} is over the limit of {}",
+                piece_length, PIECE_LENGTH_LIMIT
+            )));
+        }
+        let total_length = info.total_length()?;
+        if info.is_v1() {
+            let expected = total_length.div_ceil(piece_length);
+            if info.pieces.len() as u64 != expected {
+                return Err(BencodeError::msg(format!(
+                    "info: {} piece hashes for {} bytes in {}-byte pieces, expected {}",
+                    info.pieces.len(),
+                    total_length,
+                    piece_length,
+                    expected
+                )));
+            }
+        }
+
+        if let Some(tree) = &info.file_tree {
+            if !piece_length.is_power_of_two() || piece_length < MIN_V2_PIECE_LENGTH {
+                return Err(BencodeError::msg(format!(
+                    "info: piece length {} must be a power of two of at least {} in a v2 torrent",
+                    piece_length, MIN_V2_PIECE_LENGTH
+                )));
+            }
+            for file in tree.files() {
+                if file.length <= piece_length {
+                    continue;
+                }
+                let path = file.path.join("/");
+                // files of one piece or more always have a root
+                let root = file.pieces_root.unwrap_or_default();
+                let expected = file.length.div_ceil(piece_length);
+                match self.piece_layers.get(&root) {
+                    None => {
+                        return Err(BencodeError::msg(format!(
+                            "piece layers: missing layer for `{}` (root {})",
+                            path,
+                            to_hex(&root)
+                        )))
+                    }
+                    Some(layer) if layer.len() as u64 != expected => {
+                        return Err(BencodeError::msg(format!(
+                            "piece layers: {} hashes for `{}`, expected {}",
+                            layer.len(),
+                            path,
+                            expected
+                        )))
+                    }
+                    Some(_) => {}
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::torrent::metainfo::{FileEntry, Files};
+
+    fn v1(length: u64, pieces: usize) -> MetaInfo {
+        let mut buf = format!(
+            "d4:infod6:lengthi{}e4:name1:x12:piece lengthi16384e6:pieces{}:",
+            length,
+            pieces * 20
+        )
+        .into_bytes();
+        buf.extend(vec![0; pieces * 20]);
+        buf.extend_from_slice(b"ee");
+        MetaInfo::from_bytes(&buf).unwrap()
+    }
+
+    #[test]
+    fn test_validate_v1_piece_count() {
+        assert!(v1(0, 0).validate().is_ok());
+        assert!(v1(16384, 1).validate().is_ok());
+        assert!(v1(16385, 2).validate().is_ok());
+        let err = v1(16385, 1).validate().unwrap_err().to_string();
+        assert!(err.contains("1 piece hashes for 16385 bytes"), "{}", err);
+        assert!(v1(10, 3).validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_rejects_overflowing_total_length() {
+        let mut meta = v1(0, 0);
+        meta.info.files = Some(Files::Multi(vec![FileEntry::padding(i64::MAX as u64); 3]));
+        let err = meta.validate().unwrap_err().to_string();
+        assert!(
+            err.contains("total length of the files overflows"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_validate_v2_piece_layers() {
+        let mut buf = b"d4:infod9:file treed1:ad0:d6:lengthi40000e11:pieces root32:".to_vec();
+        buf.extend_from_slice(&[5; 32]);
+        buf.extend_from_slice(
+            b"eee12:meta versioni2e4:name1:x12:piece lengthi16384ee12:piece layersd32:",
+        );
+        buf.extend_from_slice(&[5; 32]);
+        buf.extend_from_slice(b"96:");
+        buf.extend_from_slice(&[0; 96]);
+        buf.extend_from_slice(b"ee");
+        let mut meta = MetaInfo::from_bytes(&buf).unwrap();
+        assert!(meta.validate().is_ok());
+
+        meta.piece_layers.get_mut(&[5; 32]).unwrap().pop();
+        let err = meta.validate().unwrap_err().to_string();
+        assert!(err.contains("2 hashes for `a`, expected 3"), "{}", err);
+        meta.piece_layers.clear();
+        assert!(meta
+            .validate()
+            .unwrap_err()
+            .to_string()
+            .contains("missing layer"));
+        meta.info.piece_length = 1000;
+        assert!(meta.validate().is_err());
+    }
+}
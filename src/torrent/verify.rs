@@ -0,0 +1,224 @@
+use super::builder::{batch_pieces, hash_pieces};
+use super::metainfo::{Files, MetaInfo};
+use super::path::sanitize_path;
+use crate::{BencodeError, Result};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+/// Check the data of `meta` below the download directory `root` against its
+/// v1 piece hashes, as a client's "force recheck" does. `meta` must pass
+/// [`MetaInfo::validate`] first.
+///
+/// Files are streamed one piece at a time, or with the `parallel` feature a
+/// batch of pieces at a time hashed on every core; the returned iterator
+/// yields whether each piece matches, in order. Missing or short files make
+/// the pieces they cover fail rather than end the check; other I/O errors
+/// are yielded as errors.
+///
+/// ```no_run
+/// use bencode_rs::torrent::{verify, MetaInfo};
+///
+/// let meta = MetaInfo::from_bytes(&std::fs::read("a.torrent").unwrap()).unwrap();
+/// let bad: Vec<usize> = verify(&meta, "downloads".as_ref())
+///     .unwrap()
+///     .enumerate()
+///     .filter(|(_, ok)| !matches!(ok, Ok(true)))
+///     .map(|(i, _)| i)
+///     .collect();
+/// ```
+pub fn verify<'a>(meta: &'a MetaInfo, root: &Path) -> Result<Verify<'a>> {
+    // the piece length and file lengths size the read buffer
+    meta.validate()?;
+    let info = &meta.info;
+    let base = root.join(sanitize_path(&[info.name.as_str()])?);
+    let sources = match &info.files {
+        Some(Files::Single { length }) => vec![(Some(base), *length)],
+        Some(Files::Multi(entries)) => entries
+            .iter()
+            .map(|e| {
+                let path = if e.is_padding() {
+                    None
+                } else {
+                    Some(base.join(e.relative_path()?))
+                };
+                Ok((path, e.length))
+            })
+            .collect::<Result<_>>()?,
+        None => {
+            return Err(BencodeError::msg(
+                "Cannot verify a v2-only torrent against v1 piece hashes",
+            ))
+        }
+    };
+    Ok(Verify {
+        pieces: &info.pieces,
+        index: 0,
+        batch: batch_pieces(info.piece_length as usize),
+        piece_length: info.piece_length,
+        remaining: info.total_length()?,
+        sources,
+        source: 0,
+        offset: 0,
+        reader: None,
+        buf: Vec::new(),
+        ready: VecDeque::new(),
+    })
+}
+
+/// Iterator returned by [`verify`].
+pub struct Verify<'a> {
+    pieces: &'a [[u8; 20]],
+    index: usize,
+    /// Pieces read and hashed together.
+    batch: usize,
+    piece_length: u64,
+    remaining: u64,
+    /// Every file in order; `None` for padding files.
+    sources: Vec<(Option<PathBuf>, u64)>,
+    source: usize,
+    offset: u64,
+    // `Some(None)` once the current file turned out to be missing or short
+    reader: Option<Option<BufReader<File>>>,
+    buf: Vec<u8>,
+    /// Results of the current batch not yet yielded.
+    ready: VecDeque<Result<bool>>,
+}
+
+impl Verify<'_> {
+    /// Read the next `len` bytes into `buf`. Returns whether all of them came
+    /// from disk.
+    fn fill(&mut self, len: u64) -> io::Result<bool> {
+        let mut complete = true;
+        let mut need = len;
+        while need > 0 {
+            let (path, length) = match self.sources.get(self.source) {
+                Some((path, length)) => (path.clone(), *length),
+                None => return Ok(false),
+            };
+            let n = need.min(length - self.offset);
+            let start = self.buf.len();
+            self.buf.resize(start + n as usize, 0);
+            if let Some(path) = &path {
+                if self.reader.is_none() {
+                    self.reader = Some(match File::open(path) {
+                        Ok(file) => Some(BufReader::new(file)),
+                        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+                        Err(e) => return Err(e),
+                    });
+                }
+                let buf = &mut self.buf[start..];
+                let reader = self.reader.as_mut().and_then(Option::as_mut);
+                match reader.map(|r| r.read_exact(buf)) {
+                    Some(Ok(())) => {}
+                    Some(Err(e)) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                        self.reader = Some(None);
+                        complete = false;
+                    }
+                    Some(Err(e)) => return Err(e),
+                    None => complete = false,
+                }
+            }
+            self.offset += n;
+            need -= n;
+            if self.offset == length {
+                self.source += 1;
+                self.offset = 0;
+                self.reader = None;
+            }
+        }
+        Ok(complete)
+    }
+
+    /// Read the next batch of pieces, hash them and queue the results. An
+    /// I/O error is queued after the pieces read before it and ends the
+    /// check.
+    fn next_batch(&mut self) {
+        let first = self.index;
+        let mut complete = Vec::with_capacity(self.batch);
+        let mut error = None;
+        self.buf.clear();
+        while complete.len() < self.batch && self.index < self.pieces.len() {
+            let len = self.remaining.min(self.piece_length);
+            let start = self.buf.len();
+            match self.fill(len) {
+                Ok(piece_complete) => complete.push(piece_complete),
+                Err(e) => {
+                    self.buf.truncate(start);
+                    self.index = self.pieces.len();
+                    error = Some(e);
+                    break;
+                }
+            }
+            self.index += 1;
+            self.remaining -= len;
+        }
+        let hashes = hash_pieces(&self.buf, self.piece_length as usize);
+        let expected = &self.pieces[first..];
+        for ((complete, hash), expected) in complete.into_iter().zip(hashes).zip(expected) {
+            self.ready.push_back(Ok(complete && hash == *expected));
+        }
+        if let Some(e) = error {
+            self.ready.push_back(Err(e.into()));
+        }
+    }
+}
+
+impl Iterator for Verify<'_> {
+    type Item = Result<bool>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.ready.is_empty() {
+            self.next_batch();
+        }
+        self.ready.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::torrent::TorrentBuilder;
+    use std::{env, fs};
+
+    #[test]
+    fn test_verify_pieces() {
+        let dir = env::temp_dir().join(format!("bencode_rs-verify-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let content = dir.join("content");
+        fs::create_dir_all(&content).unwrap();
+        fs::write(content.join("a"), vec![1; 40_000]).unwrap();
+        fs::write(content.join("b"), vec![2; 30_000]).unwrap();
+        let meta = TorrentBuilder::new(&content)
+            .piece_length(1 << 14)
+            .build()
+            .unwrap();
+
+        let check =
+            || -> Vec<bool> { verify(&meta, &dir).unwrap().map(|ok| ok.unwrap()).collect() };
+        assert_eq!(check(), vec![true; 5]);
+
+        // corrupt the second piece and truncate the last file
+        let mut a = fs::read(content.join("a")).unwrap();
+        a[20_000] = 0;
+        fs::write(content.join("a"), a).unwrap();
+        fs::write(content.join("b"), vec![2; 20_000]).unwrap();
+        assert_eq!(check(), vec![true, false, true, false, false]);
+
+        fs::remove_file(content.join("a")).unwrap();
+        assert_eq!(check(), vec![false, false, false, false, false]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_verify_rejects_huge_pieces() {
+        let mut buf =
+            b"d4:infod6:lengthi1e4:name1:x12:piece lengthi1099511627776e6:pieces20:".to_vec();
+        buf.extend_from_slice(&[0; 20]);
+        buf.extend_from_slice(b"ee");
+        let meta = MetaInfo::from_bytes(&buf).unwrap();
+        let err = verify(&meta, &env::temp_dir()).err().unwrap().to_string();
+        assert!(err.contains("piece length 1099511627776"), "{}", err);
+    }
+}
@@ -0,0 +1,146 @@
+use super::metainfo::{Files, Info, MetaInfo};
+use crate::{percent, BencodeError, Result};
+use std::fmt::{self, Display};
+
+/// A BEP 19 web seed: an HTTP(S) server holding the torrent's files.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WebSeed {
+    /// `http` or `https`, lowercased.
+    pub scheme: String,
+    /// Host name or address; IPv6 addresses keep their brackets.
+    pub host: String,
+    pub port: Option<u16>,
+    /// Everything after the authority, starting with `/`; may be empty.
+    pub path: String,
+}
+
+impl WebSeed {
+    /// Parse and check a web seed URL.
+    pub fn parse(url: &str) -> Result<WebSeed> {
+        if url.chars().any(|c| c.is_whitespace() || c.is_control()) {
+            return Err(invalid(url, "contains whitespace"));
+        }
+        let (scheme, rest) = url
+            .split_once("://")
+            .ok_or_else(|| invalid(url, "missing scheme"))?;
+        let scheme = scheme.to_ascii_lowercase();
+        if scheme != "http" && scheme != "https" {
+            return Err(invalid(url, "scheme must be http or https"));
+        }
+        let (authority, path) = match rest.find(['/', '?', '#']) {
+            Some(i) => rest.split_at(i),
+            None => (rest, ""),
+        };
+        // `user:pass@` is not meaningful for a web seed but harmless
+        let authority = authority.rsplit('@').next().unwrap_or(authority);
+        let (host, port) = match authority.rfind(':') {
+            Some(i) if !authority[i..].contains(']') => {
+                let port = authority[i + 1..]
+                    .parse::<u16>()
+                    .map_err(|_| invalid(url, "bad port"))?;
+                (&authority[..i], Some(port))
+            }
+            _ => (authority, None),
+        };
+        if host.is_empty() {
+            return Err(invalid(url, "missing host"));
+        }
+        Ok(WebSeed {
+            scheme,
+            host: host.to_string(),
+            port,
+            path: path.to_string(),
+        })
+    }
+
+    /// URL of a file of `info`: `path` is the file's path inside a
+    /// multi-file torrent and empty for a single-file one. A seed URL ending
+    /// in `/` is a directory the torrent's name is appended to.
+    pub fn file_url(&self, info: &Info, path: &[String]) -> String {
+        let mut url = self.to_string();
+        if url.ends_with('/') {
+            url.push_str(&percent::encode(info.name.as_bytes()));
+        }
+        if let Some(Files::Multi(_)) = &info.files {
+            for component in path {
+                url.push('/');
+                url.push_str(&percent::encode(component.as_bytes()));
+            }
+        }
+        url
+    }
+}
+
+impl Display for WebSeed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}://{}", self.scheme, self.host)?;
+        if let Some(port) = self.port {
+            write!(f, ":{}", port)?;
+        }
+        write!(f, "{}", self.path)
+    }
+}
+
+impl MetaInfo {
+    /// `url-list` as checked [`WebSeed`]s. Fails on the first URL that is not
+    /// a valid HTTP(S) URL.
+    pub fn web_seeds(&self) -> Result<Vec<WebSeed>> {
+        self.url_list
+            .iter()
+            .map(|url| WebSeed::parse(url))
+            .collect()
+    }
+
+    /// Replace `url-list` with `seeds`.
+    pub fn set_web_seeds(&mut self, seeds: &[WebSeed]) {
+        self.url_list = seeds.iter().map(WebSeed::to_string).collect();
+    }
+}
+
+fn invalid(url: &str, reason: &str) -> BencodeError {
+    BencodeError::msg(format!("Invalid web seed `{}`: {}", url, reason))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_web_seed() {
+        let seed = WebSeed::parse("HTTP://[::1]:8080/files/").unwrap();
+        assert_eq!(seed.scheme, "http");
+        assert_eq!(seed.host, "[::1]");
+        assert_eq!(seed.port, Some(8080));
+        assert_eq!(seed.path, "/files/");
+        assert_eq!(seed.to_string(), "http://[::1]:8080/files/");
+        assert_eq!(WebSeed::parse("https://a.b").unwrap().path, "");
+
+        for bad in [
+            "ftp://a/",
+            "a/b",
+            "http:///x",
+            "http://a:99999/",
+            "http://a b/",
+        ] {
+            assert!(WebSeed::parse(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_web_seed_file_urls() {
+        let buf = b"d4:infod5:filesld6:lengthi1e4:pathl1:d5:a b.ceee4:name2:my12:piece lengthi16384e6:pieces20:aaaaaaaaaaaaaaaaaaaae8:url-listl11:http://a/x/10:http://b/yee";
+        let mut meta = MetaInfo::from_bytes(buf).unwrap();
+        let seeds = meta.web_seeds().unwrap();
+        let path = vec!["d".to_string(), "a b.c".to_string()];
+        assert_eq!(
+            seeds[0].file_url(&meta.info, &path),
+            "http://a/x/my/d/a%20b.c"
+        );
+        assert_eq!(seeds[1].file_url(&meta.info, &path), "http://b/y/d/a%20b.c");
+
+        meta.set_web_seeds(&seeds[1..]);
+        assert_eq!(meta.url_list, vec!["http://b/y"]);
+        meta.url_list.push("nonsense".into());
+        assert!(meta.web_seeds().is_err());
+    }
+}
@@ -0,0 +1,380 @@
+use super::compact;
+use crate::fields::{require, type_name, within, DictBuilder, Fields};
+use crate::{parse_bencode, percent, BencodeError, FromBencode, Result, ToBencode, Value};
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::net::{IpAddr, SocketAddr, SocketAddrV4, SocketAddrV6};
+
+const RESPONSE_FIELDS: &[&str] = &[
+    "failure reason",
+    "warning message",
+    "interval",
+    "min interval",
+    "tracker id",
+    "complete",
+    "incomplete",
+    "peers",
+    "peers6",
+];
+
+/// The body of a tracker's reply to an announce request.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AnnounceResponse {
+    /// Set when the tracker refused the request; the other fields are then
+    /// usually absent.
+    pub failure_reason: Option<String>,
+    pub warning_message: Option<String>,
+    /// Seconds to wait before the next regular announce.
+    pub interval: Option<u64>,
+    pub min_interval: Option<u64>,
+    pub tracker_id: Option<String>,
+    /// Number of seeders.
+    pub complete: Option<u64>,
+    /// Number of leechers.
+    pub incomplete: Option<u64>,
+    /// IPv4 and IPv6 peers, from `peers` and `peers6` alike.
+    pub peers: Vec<Peer>,
+    pub extra: HashMap<String, Value>,
+}
+
+/// A peer returned by a tracker.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Peer {
+    pub addr: SocketAddr,
+    /// Only present in the dictionary model of the peer list.
+    pub peer_id: Option<Vec<u8>>,
+}
+
+/// The query of an HTTP announce request.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AnnounceRequest {
+    pub info_hash: [u8; 20],
+    pub peer_id: [u8; 20],
+    pub port: u16,
+    pub uploaded: u64,
+    pub downloaded: u64,
+    /// Bytes still to download.
+    pub left: u64,
+    /// Ask for the compact peer list (BEP 23).
+    pub compact: bool,
+    pub event: Option<AnnounceEvent>,
+    pub numwant: Option<u32>,
+    pub key: Option<String>,
+    /// The `tracker id` from an earlier response.
+    pub tracker_id: Option<String>,
+}
+
+/// The `event` of an announce; regular re-announces have none.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AnnounceEvent {
+    Started,
+    Completed,
+    Stopped,
+}
+
+impl AnnounceRequest {
+    /// A request with nothing transferred yet and compact peers requested.
+    pub fn new(info_hash: [u8; 20], peer_id: [u8; 20], port: u16) -> Self {
+        AnnounceRequest {
+            info_hash,
+            peer_id,
+            port,
+            uploaded: 0,
+            downloaded: 0,
+            left: 0,
+            compact: true,
+            event: None,
+            numwant: None,
+            key: None,
+            tracker_id: None,
+        }
+    }
+
+    /// The GET URL for tracker `announce`, which may already carry a query.
+    /// The infohash and peer id are percent-encoded byte by byte, as
+    /// trackers expect.
+    pub fn url(&self, announce: &str) -> String {
+        let mut params = vec![
+            format!("info_hash={}", percent::encode(&self.info_hash)),
+            format!("peer_id={}", percent::encode(&self.peer_id)),
+            format!("port={}", self.port),
+            format!("uploaded={}", self.uploaded),
+            format!("downloaded={}", self.downloaded),
+            format!("left={}", self.left),
+            format!("compact={}", u8::from(self.compact)),
+        ];
+        if let Some(event) = self.event {
+            params.push(format!("event={}", event));
+        }
+        if let Some(numwant) = self.numwant {
+            params.push(format!("numwant={}", numwant));
+        }
+        if let Some(key) = &self.key {
+            params.push(format!("key={}", percent::encode(key.as_bytes())));
+        }
+        if let Some(id) = &self.tracker_id {
+            params.push(format!("trackerid={}", percent::encode(id.as_bytes())));
+        }
+        let separator = if !announce.contains('?') {
+            "?"
+        } else if announce.ends_with(['?', '&']) {
+            ""
+        } else {
+            "&"
+        };
+        format!("{}{}{}", announce, separator, params.join("&"))
+    }
+}
+
+impl Display for AnnounceEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            AnnounceEvent::Started => "started",
+            AnnounceEvent::Completed => "completed",
+            AnnounceEvent::Stopped => "stopped",
+        })
+    }
+}
+
+impl AnnounceResponse {
+    /// Decode an HTTP response body.
+    pub fn from_bytes(body: &[u8]) -> Result<AnnounceResponse> {
+        match parse_bencode(&mut &body[..])? {
+            Some(value) => AnnounceResponse::from_value(&value),
+            None => Err(BencodeError::msg("Empty tracker response")),
+        }
+    }
+
+    /// Whether the tracker rejected the announce.
+    pub fn is_failure(&self) -> bool {
+        self.failure_reason.is_some()
+    }
+}
+
+impl FromBencode for AnnounceResponse {
+    fn from_value(value: &Value) -> Result<Self> {
+        let fields = Fields::new(value)?;
+        let failure_reason = fields.string("failure reason")?;
+        let interval = fields.uint("interval")?;
+        if failure_reason.is_none() {
+            require("interval", interval)?;
+        }
+        let mut peers: Vec<Peer> = match fields.get("peers") {
+            None => Vec::new(),
+            Some(Value::List(peers)) => peers
+                .iter()
+                .enumerate()
+                .map(|(i, p)| within(&format!("peers[{}]", i), Peer::from_value(p)))
+                .collect::<Result<_>>()?,
+            Some(peers) => match peers.as_bytes() {
+                Some(bytes) => compact::decode_peers(bytes)?
+                    .into_iter()
+                    .map(Peer::from)
+                    .collect(),
+                None => {
+                    return Err(BencodeError::msg(format!(
+                        "expected list or string, found {}",
+                        type_name(peers)
+                    ))
+                    .in_field("peers"))
+                }
+            },
+        };
+        if let Some(bytes) = fields.bytes("peers6")? {
+            peers.extend(compact::decode_peers6(bytes)?.into_iter().map(Peer::from));
+        }
+        Ok(AnnounceResponse {
+            failure_reason,
+            warning_message: fields.string("warning message")?,
+            interval,
+            min_interval: fields.uint("min interval")?,
+            tracker_id: fields.string("tracker id")?,
+            complete: fields.uint("complete")?,
+            incomplete: fields.uint("incomplete")?,
+            peers,
+            extra: fields.rest(RESPONSE_FIELDS),
+        })
+    }
+}
+
+impl ToBencode for AnnounceResponse {
+    fn to_value(&self) -> Value {
+        // compact whenever nothing would be lost, as nearly every tracker does
+        let compact = self.peers.iter().all(|p| p.peer_id.is_none());
+        let (peers, peers6) = if self.failure_reason.is_some() && self.peers.is_empty() {
+            (None, None)
+        } else if compact {
+            let mut v4: Vec<SocketAddrV4> = Vec::new();
+            let mut v6: Vec<SocketAddrV6> = Vec::new();
+            for peer in &self.peers {
+                match peer.addr {
+                    SocketAddr::V4(addr) => v4.push(addr),
+                    SocketAddr::V6(addr) => v6.push(addr),
+                }
+            }
+            let peers6 = if v6.is_empty() {
+                None
+            } else {
+                Some(Value::from(compact::encode_peers6(&v6)))
+            };
+            (Some(Value::from(compact::encode_peers(&v4))), peers6)
+        } else {
+            let list = self.peers.iter().map(|p| p.to_value()).collect();
+            (Some(Value::List(list)), None)
+        };
+        DictBuilder::new()
+            .opt("failure reason", self.failure_reason.as_deref())
+            .opt("warning message", self.warning_message.as_deref())
+            .opt("interval", self.interval.map(|n| n as i64))
+            .opt("min interval", self.min_interval.map(|n| n as i64))
+            .opt("tracker id", self.tracker_id.as_deref())
+            .opt("complete", self.complete.map(|n| n as i64))
+            .opt("incomplete", self.incomplete.map(|n| n as i64))
+            .opt("peers", peers)
+            .opt("peers6", peers6)
+            .extend(&self.extra)
+            .build()
+    }
+}
+
+impl From<SocketAddrV4> for Peer {
+    fn from(addr: SocketAddrV4) -> Self {
+        Peer {
+            addr: SocketAddr::V4(addr),
+            peer_id: None,
+        }
+    }
+}
+
+impl From<SocketAddrV6> for Peer {
+    fn from(addr: SocketAddrV6) -> Self {
+        Peer {
+            addr: SocketAddr::V6(addr),
+            peer_id: None,
+        }
+    }
+}
+
+impl FromBencode for Peer {
+    fn from_value(value: &Value) -> Result<Self> {
+        let fields = Fields::new(value)?;
+        let ip = require("ip", fields.string("ip")?)?;
+        let ip: IpAddr = ip.parse().map_err(|_| {
+            BencodeError::msg(format!("`{}` is not an IP address", ip)).in_field("ip")
+        })?;
+        let port = require("port", fields.uint("port")?)?;
+        if port > u64::from(u16::MAX) {
+            return Err(BencodeError::msg(format!("{} is out of range", port)).in_field("port"));
+        }
+        Ok(Peer {
+            addr: SocketAddr::new(ip, port as u16),
+            peer_id: fields.bytes("peer id")?.map(<[u8]>::to_vec),
+        })
+    }
+}
+
+impl ToBencode for Peer {
+    fn to_value(&self) -> Value {
+        DictBuilder::new()
+            .opt("peer id", self.peer_id.clone())
+            .set("ip", self.addr.ip().to_string())
+            .set("port", i64::from(self.addr.port()))
+            .build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_announce_url() {
+        let mut hash = [0xff; 20];
+        hash[..4].copy_from_slice(b"a b~");
+        let mut req = AnnounceRequest::new(hash, *b"-BR0100-abcdefghijkl", 6881);
+        req.left = 1000;
+        req.event = Some(AnnounceEvent::Started);
+        req.key = Some("k/1".into());
+        let url = req.url("http://t/announce");
+        assert_eq!(
+            url,
+            format!(
+                "http://t/announce?info_hash=a%20b~{}&peer_id=-BR0100-abcdefghijkl\
+                 &port=6881&uploaded=0&downloaded=0&left=1000&compact=1&event=started&key=k%2F1",
+                "%FF".repeat(16)
+            )
+        );
+        assert!(req
+            .url("http://t/announce?passkey=x")
+            .starts_with("http://t/announce?passkey=x&info_hash="));
+        assert!(req.url("http://t/a?").starts_with("http://t/a?info_hash="));
+    }
+
+    #[test]
+    fn test_compact_announce_response() {
+        let mut body =
+            b"d8:completei5e10:incompletei2e8:intervali1800e12:min intervali900e5:peers12:"
+                .to_vec();
+        body.extend_from_slice(&[10, 0, 0, 1, 0x1a, 0xe1, 192, 168, 1, 2, 0, 80]);
+        body.extend_from_slice(b"e");
+        let resp = AnnounceResponse::from_bytes(&body).unwrap();
+        assert_eq!(resp.interval, Some(1800));
+        assert_eq!(resp.min_interval, Some(900));
+        assert_eq!(resp.complete, Some(5));
+        assert_eq!(resp.incomplete, Some(2));
+        let addrs: Vec<_> = resp.peers.iter().map(|p| p.addr.to_string()).collect();
+        assert_eq!(addrs, vec!["10.0.0.1:6881", "192.168.1.2:80"]);
+        assert!(!resp.is_failure());
+
+        let mut out = Vec::new();
+        resp.to_value()
+            .write_bencode_with(&mut out, crate::EncodeOptions::strict())
+            .unwrap();
+        assert_eq!(out, body);
+    }
+
+    #[test]
+    fn test_dual_stack_announce_response() {
+        let mut body = b"d8:intervali60e5:peers6:".to_vec();
+        body.extend_from_slice(&[127, 0, 0, 1, 0, 1]);
+        body.extend_from_slice(b"6:peers618:");
+        body.extend_from_slice(&std::net::Ipv6Addr::LOCALHOST.octets());
+        body.extend_from_slice(&[0, 2]);
+        body.extend_from_slice(b"e");
+        let resp = AnnounceResponse::from_bytes(&body).unwrap();
+        let addrs: Vec<_> = resp.peers.iter().map(|p| p.addr.to_string()).collect();
+        assert_eq!(addrs, vec!["127.0.0.1:1", "[::1]:2"]);
+
+        let mut out = Vec::new();
+        resp.to_value()
+            .write_bencode_with(&mut out, crate::EncodeOptions::strict())
+            .unwrap();
+        assert_eq!(out, body);
+    }
+
+    #[test]
+    fn test_dictionary_announce_response() {
+        let body = b"d8:intervali60e5:peersld2:ip3:::17:peer id3:abc4:porti6881eeee";
+        let resp = AnnounceResponse::from_bytes(body).unwrap();
+        assert_eq!(resp.peers[0].addr.to_string(), "[::1]:6881");
+        assert_eq!(resp.peers[0].peer_id.as_deref(), Some(&b"abc"[..]));
+        assert_eq!(
+            AnnounceResponse::from_value(&resp.to_value()).unwrap(),
+            resp
+        );
+    }
+
+    #[test]
+    fn test_failure_and_invalid_responses() {
+        let resp = AnnounceResponse::from_bytes(b"d14:failure reason9:not founde").unwrap();
+        assert!(resp.is_failure());
+        assert_eq!(resp.failure_reason.as_deref(), Some("not found"));
+
+        let err = |body: &[u8]| AnnounceResponse::from_bytes(body).unwrap_err().to_string();
+        assert!(err(b"d5:peers0:e").contains("Missing field `interval`"));
+        assert!(err(b"d8:intervali1e5:peers5:abcdee").contains("not a multiple of 6"));
+        assert!(
+            err(b"d8:intervali1e5:peersld2:ip4:host4:porti1eeee").contains("Field `peers[0].ip`")
+        );
+    }
+}
@@ -0,0 +1,100 @@
+//! Compact peer lists: the packed binary `peers` strings of BEP 23 and the
+//! `peers6` strings of BEP 7.
+
+use crate::{BencodeError, Result};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddrV4, SocketAddrV6};
+
+/// Bytes per peer in the compact IPv4 format: address and port.
+const PEER_V4_LEN: usize = 6;
+/// Bytes per peer in the compact IPv6 format.
+const PEER_V6_LEN: usize = 18;
+
+/// Decode peers packed as 4 address bytes and 2 port bytes each, big-endian.
+pub fn decode_peers(bytes: &[u8]) -> Result<Vec<SocketAddrV4>> {
+    check_len(bytes, PEER_V4_LEN, "peers")?;
+    Ok(bytes
+        .chunks_exact(PEER_V4_LEN)
+        .map(|c| {
+            SocketAddrV4::new(
+                Ipv4Addr::new(c[0], c[1], c[2], c[3]),
+                u16::from_be_bytes([c[4], c[5]]),
+            )
+        })
+        .collect())
+}
+
+/// Pack peers into the compact IPv4 format.
+pub fn encode_peers(peers: &[SocketAddrV4]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(peers.len() * PEER_V4_LEN);
+    for peer in peers {
+        out.extend_from_slice(&peer.ip().octets());
+        out.extend_from_slice(&peer.port().to_be_bytes());
+    }
+    out
+}
+
+/// Decode peers packed as 16 address bytes and 2 port bytes each, big-endian.
+pub fn decode_peers6(bytes: &[u8]) -> Result<Vec<SocketAddrV6>> {
+    check_len(bytes, PEER_V6_LEN, "peers6")?;
+    Ok(bytes
+        .chunks_exact(PEER_V6_LEN)
+        .map(|c| {
+            let mut ip = [0; 16];
+            ip.copy_from_slice(&c[..16]);
+            SocketAddrV6::new(Ipv6Addr::from(ip), u16::from_be_bytes([c[16], c[17]]), 0, 0)
+        })
+        .collect())
+}
+
+/// Pack peers into the compact IPv6 format.
+pub fn encode_peers6(peers: &[SocketAddrV6]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(peers.len() * PEER_V6_LEN);
+    for peer in peers {
+        out.extend_from_slice(&peer.ip().octets());
+        out.extend_from_slice(&peer.port().to_be_bytes());
+    }
+    out
+}
+
+fn check_len(bytes: &[u8], peer_len: usize, key: &str) -> Result<()> {
+    if bytes.len().is_multiple_of(peer_len) {
+        Ok(())
+    } else {
+        Err(BencodeError::msg(format!(
+            "length {} is not a multiple of {}",
+            bytes.len(),
+            peer_len
+        ))
+        .in_field(key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compact_peers_roundtrip() {
+        let bytes = [10, 0, 0, 1, 0x1a, 0xe1, 192, 168, 1, 2, 0, 80];
+        let peers = decode_peers(&bytes).unwrap();
+        assert_eq!(
+            peers,
+            vec![
+                SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 1), 6881),
+                SocketAddrV4::new(Ipv4Addr::new(192, 168, 1, 2), 80),
+            ]
+        );
+        assert_eq!(encode_peers(&peers), bytes);
+        assert!(decode_peers(&bytes[..5]).is_err());
+    }
+
+    #[test]
+    fn test_compact_peers6_roundtrip() {
+        let mut bytes = Ipv6Addr::LOCALHOST.octets().to_vec();
+        bytes.extend_from_slice(&[0x1a, 0xe1]);
+        let peers = decode_peers6(&bytes).unwrap();
+        assert_eq!(peers[0].to_string(), "[::1]:6881");
+        assert_eq!(encode_peers6(&peers), bytes);
+        assert!(decode_peers6(&bytes[..17]).is_err());
+    }
+}
@@ -0,0 +1,12 @@
+//! BitTorrent tracker protocol messages.
+//!
+//! Trackers answer HTTP announce and scrape requests with a bencoded
+//! dictionary. The types here decode those bodies, and encode them for
+//! tracker implementations. [`AnnounceRequest`] builds the announce URL itself.
+
+mod announce;
+pub mod compact;
+mod scrape;
+
+pub use announce::{AnnounceEvent, AnnounceRequest, AnnounceResponse, Peer};
+pub use scrape::{scrape_url, scrape_url_for, ScrapeResponse, ScrapeStats};
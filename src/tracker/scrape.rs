@@ -0,0 +1,192 @@
+use crate::fields::{within, DictBuilder, Fields};
+use crate::{parse_bencode, percent, BencodeError, FromBencode, HMap, Result, ToBencode, Value};
+use std::collections::HashMap;
+
+const RESPONSE_FIELDS: &[&str] = &["files", "failure reason"];
+const STATS_FIELDS: &[&str] = &["complete", "downloaded", "incomplete", "name"];
+
+/// The body of a tracker's reply to a scrape request.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ScrapeResponse {
+    pub failure_reason: Option<String>,
+    /// Statistics per torrent, keyed by infohash.
+    pub files: HashMap<[u8; 20], ScrapeStats>,
+    pub extra: HashMap<String, Value>,
+}
+
+/// Swarm statistics for one torrent.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ScrapeStats {
+    /// Number of seeders.
+    pub complete: u64,
+    /// Number of completed downloads the tracker has seen.
+    pub downloaded: u64,
+    /// Number of leechers.
+    pub incomplete: u64,
+    pub name: Option<String>,
+    pub extra: HashMap<String, Value>,
+}
+
+impl ScrapeResponse {
+    /// Decode an HTTP response body.
+    pub fn from_bytes(body: &[u8]) -> Result<ScrapeResponse> {
+        match parse_bencode(&mut &body[..])? {
+            Some(value) => ScrapeResponse::from_value(&value),
+            None => Err(BencodeError::msg("Empty tracker response")),
+        }
+    }
+}
+
+impl FromBencode for ScrapeResponse {
+    fn from_value(value: &Value) -> Result<Self> {
+        let fields = Fields::new(value)?;
+        let mut files = HashMap::new();
+        match fields.get("files") {
+            None => {}
+            Some(Value::Map(map)) => {
+                for (hash, stats) in &map.0 {
+                    let hash = match hash.as_bytes() {
+                        Some(hash) if hash.len() == 20 => {
+                            let mut info_hash = [0; 20];
+                            info_hash.copy_from_slice(hash);
+                            info_hash
+                        }
+                        _ => {
+                            return Err(BencodeError::msg("keys must be 20-byte infohashes")
+                                .in_field("files"))
+                        }
+                    };
+                    let context = format!("files[{}]", crate::hash::to_hex(&hash));
+                    let stats = within(&context, ScrapeStats::from_value(stats))?;
+                    files.insert(hash, stats);
+                }
+            }
+            Some(v) => {
+                return Err(BencodeError::msg(format!(
+                    "expected dictionary, found {}",
+                    crate::fields::type_name(v)
+                ))
+                .in_field("files"))
+            }
+        }
+        Ok(ScrapeResponse {
+            failure_reason: fields.string("failure reason")?,
+            files,
+            extra: fields.rest(RESPONSE_FIELDS),
+        })
+    }
+}
+
+impl ToBencode for ScrapeResponse {
+    fn to_value(&self) -> Value {
+        let files = Value::Map(HMap(
+            self.files
+                .iter()
+                .map(|(hash, stats)| (Value::from(hash.to_vec()), stats.to_value()))
+                .collect(),
+        ));
+        let files = if self.failure_reason.is_some() && self.files.is_empty() {
+            None
+        } else {
+            Some(files)
+        };
+        DictBuilder::new()
+            .opt("failure reason", self.failure_reason.as_deref())
+            .opt("files", files)
+            .extend(&self.extra)
+            .build()
+    }
+}
+
+impl FromBencode for ScrapeStats {
+    fn from_value(value: &Value) -> Result<Self> {
+        let fields = Fields::new(value)?;
+        Ok(ScrapeStats {
+            complete: fields.uint("complete")?.unwrap_or(0),
+            downloaded: fields.uint("downloaded")?.unwrap_or(0),
+            incomplete: fields.uint("incomplete")?.unwrap_or(0),
+            name: fields.string("name")?,
+            extra: fields.rest(STATS_FIELDS),
+        })
+    }
+}
+
+impl ToBencode for ScrapeStats {
+    fn to_value(&self) -> Value {
+        DictBuilder::new()
+            .set("complete", self.complete as i64)
+            .set("downloaded", self.downloaded as i64)
+            .set("incomplete", self.incomplete as i64)
+            .opt("name", self.name.as_deref())
+            .extend(&self.extra)
+            .build()
+    }
+}
+
+/// The scrape URL for an announce URL, by the convention that replaces a
+/// final path segment starting with `announce` by `scrape`. Returns `None`
+/// when the tracker does not follow it and so cannot be scraped.
+pub fn scrape_url(announce: &str) -> Option<String> {
+    let (path, query) = match announce.find('?') {
+        Some(i) => announce.split_at(i),
+        None => (announce, ""),
+    };
+    let slash = path.rfind('/')?;
+    let rest = path[slash + 1..].strip_prefix("announce")?;
+    Some(format!("{}scrape{}{}", &path[..=slash], rest, query))
+}
+
+/// [`scrape_url`] with an `info_hash` parameter for each torrent to ask about.
+pub fn scrape_url_for(announce: &str, info_hashes: &[[u8; 20]]) -> Option<String> {
+    let mut url = scrape_url(announce)?;
+    for hash in info_hashes {
+        url.push(if url.contains('?') { '&' } else { '?' });
+        url.push_str("info_hash=");
+        url.push_str(&percent::encode(hash));
+    }
+    Some(url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scrape_response() {
+        let mut body = b"d5:filesd20:".to_vec();
+        body.extend_from_slice(&[0xaa; 20]);
+        body.extend_from_slice(b"d8:completei5e10:downloadedi50e10:incompletei10eeee");
+        let resp = ScrapeResponse::from_bytes(&body).unwrap();
+        let stats = &resp.files[&[0xaa; 20]];
+        assert_eq!(
+            (stats.complete, stats.downloaded, stats.incomplete),
+            (5, 50, 10)
+        );
+
+        let mut out = Vec::new();
+        resp.to_value()
+            .write_bencode_with(&mut out, crate::EncodeOptions::strict())
+            .unwrap();
+        assert_eq!(out, body);
+
+        assert!(ScrapeResponse::from_bytes(b"d5:filesd3:abcdeee").is_err());
+    }
+
+    #[test]
+    fn test_scrape_url() {
+        assert_eq!(
+            scrape_url("http://t.example/announce").as_deref(),
+            Some("http://t.example/scrape")
+        );
+        assert_eq!(
+            scrape_url("http://t.example/x/announce.php?passkey=1").as_deref(),
+            Some("http://t.example/x/scrape.php?passkey=1")
+        );
+        assert_eq!(scrape_url("http://t.example/a"), None);
+        assert_eq!(scrape_url("http://t.example/announce/x"), None);
+        assert_eq!(
+            scrape_url_for("http://t/announce?k=1", &[[0x41; 20]]).unwrap(),
+            format!("http://t/scrape?k=1&info_hash={}", "A".repeat(20))
+        );
+    }
+}
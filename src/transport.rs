@@ -0,0 +1,87 @@
+//! Bencode messaging over a child process's stdin and stdout, as used by
+//! editor plugins and other tools that speak bencode over pipes.
+
+use crate::codec::ValueStream;
+use crate::rpc::RpcClient;
+use crate::{BencodeError, Result, Value};
+use std::io::Write;
+use std::process::{Child, ChildStdin, ChildStdout, Command, ExitStatus, Stdio};
+
+/// A spawned child process exchanging bencode values over its stdio.
+pub struct StdioTransport {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: ValueStream<ChildStdout>,
+}
+
+impl StdioTransport {
+    /// Spawn `command` with piped stdin and stdout. Stderr is inherited.
+    pub fn spawn(command: &mut Command) -> Result<Self> {
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdin = child.stdin.take();
+        let stdout = child.stdout.take();
+        match (stdin, stdout) {
+            (Some(stdin), Some(stdout)) => Ok(StdioTransport {
+                child,
+                stdin,
+                stdout: ValueStream::new(stdout),
+            }),
+            _ => Err(BencodeError::msg("Child process has no stdio pipes")),
+        }
+    }
+
+    /// Write one value to the child's stdin and flush it.
+    pub fn send(&mut self, value: &Value) -> Result<()> {
+        value.write_bencode(&mut self.stdin)?;
+        self.stdin.flush()?;
+        Ok(())
+    }
+
+    /// Block until the child writes a complete value. `None` once its stdout
+    /// is closed.
+    pub fn recv(&mut self) -> Option<Result<Value>> {
+        self.stdout.next()
+    }
+
+    /// Iterator over every value the child writes.
+    pub fn responses(&mut self) -> &mut ValueStream<ChildStdout> {
+        &mut self.stdout
+    }
+
+    pub fn child(&mut self) -> &mut Child {
+        &mut self.child
+    }
+
+    /// Hand the pipes to an [`RpcClient`] for id-correlated requests. Call this
+    /// before receiving anything, since partially read output is dropped. The
+    /// returned `Child` must be waited on by the caller.
+    pub fn into_rpc(self) -> (RpcClient<ChildStdin>, Child) {
+        let stdout = self.stdout.into_inner();
+        (RpcClient::new(stdout, self.stdin), self.child)
+    }
+
+    /// Close the child's stdin and wait for it to exit.
+    pub fn close(mut self) -> Result<ExitStatus> {
+        drop(self.stdin);
+        Ok(self.child.wait()?)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stdio_roundtrip_through_cat() {
+        let mut transport = StdioTransport::spawn(&mut Command::new("cat")).unwrap();
+        let msg = Value::List(vec![Value::from("ping"), Value::Int(1)]);
+        transport.send(&msg).unwrap();
+        transport.send(&Value::Int(2)).unwrap();
+        assert_eq!(transport.recv().unwrap().unwrap(), msg);
+        assert_eq!(transport.recv().unwrap().unwrap(), Value::Int(2));
+        assert!(transport.close().unwrap().success());
+    }
+}
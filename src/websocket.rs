@@ -0,0 +1,76 @@
+//! Carrying bencode values in binary WebSocket frames.
+//!
+//! The helpers are generic over the message type of whichever WebSocket
+//! library is in use; implement [`BinaryMessage`] for it (usually a couple of
+//! lines matching its `Binary` variant) and values convert both ways.
+
+use crate::{from_slice, Result, Value};
+
+/// A WebSocket message that may carry a binary payload.
+pub trait BinaryMessage: Sized {
+    /// Payload of a binary frame; `None` for text, ping, close, etc.
+    fn as_bytes(&self) -> Option<&[u8]>;
+
+    /// Build a binary frame holding `bytes`.
+    fn from_bytes(bytes: Vec<u8>) -> Self;
+}
+
+impl BinaryMessage for Vec<u8> {
+    fn as_bytes(&self) -> Option<&[u8]> {
+        Some(self)
+    }
+
+    fn from_bytes(bytes: Vec<u8>) -> Self {
+        bytes
+    }
+}
+
+/// Encode `value` as a single binary frame.
+pub fn to_message<M: BinaryMessage>(value: &Value) -> Result<M> {
+    let mut bytes = Vec::new();
+    value.write_bencode(&mut bytes)?;
+    Ok(M::from_bytes(bytes))
+}
+
+/// Decode the value carried by a binary frame. Non-binary frames yield
+/// `Ok(None)`; a binary frame must hold exactly one value.
+pub fn from_message<M: BinaryMessage>(message: &M) -> Result<Option<Value>> {
+    message.as_bytes().map(from_slice).transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    enum Message {
+        Text,
+        Binary(Vec<u8>),
+    }
+
+    impl BinaryMessage for Message {
+        fn as_bytes(&self) -> Option<&[u8]> {
+            match self {
+                Message::Binary(b) => Some(b),
+                Message::Text => None,
+            }
+        }
+
+        fn from_bytes(bytes: Vec<u8>) -> Self {
+            Message::Binary(bytes)
+        }
+    }
+
+    #[test]
+    fn test_websocket_roundtrip() {
+        let value = Value::List(vec![Value::from(vec![0xff, 0x00]), Value::Int(3)]);
+        let msg: Message = to_message(&value).unwrap();
+        assert_eq!(from_message(&msg).unwrap(), Some(value));
+
+        assert_eq!(from_message(&Message::Text).unwrap(), None);
+
+        let err = from_message(&b"i1ei2e".to_vec()).unwrap_err();
+        assert_eq!(err.kind(), Some(crate::ErrorKind::Trailing));
+        let err = from_message(&Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), Some(crate::ErrorKind::UnexpectedEof));
+    }
+}
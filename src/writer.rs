@@ -0,0 +1,235 @@
+//! Incremental writer for producing bencode without building a [`Value`].
+//!
+//! ```
+//! use bencode_rs::writer::BencodeWriter;
+//!
+//! let mut w = BencodeWriter::new(Vec::new());
+//! w.begin_dict().unwrap();
+//! w.key("files").unwrap();
+//! w.begin_list().unwrap();
+//! for i in 0..3 {
+//!     w.int(i).unwrap();
+//! }
+//! w.end_list().unwrap();
+//! w.end_dict().unwrap();
+//! assert_eq!(w.into_inner().unwrap(), b"d5:filesli0ei1ei2eee");
+//! ```
+
+use crate::encode::{self, IoSink};
+use crate::{BencodeError, Result, Value};
+use std::borrow::Borrow;
+use std::io::Write;
+
+enum Frame {
+    List,
+    Dict { expect_key: bool },
+}
+
+/// Streaming bencode writer checking that calls form a well-nested document.
+pub struct BencodeWriter<W: Write> {
+    inner: W,
+    stack: Vec<Frame>,
+}
+
+impl<W: Write> BencodeWriter<W> {
+    pub fn new(inner: W) -> Self {
+        BencodeWriter {
+            inner,
+            stack: vec![],
+        }
+    }
+
+    pub fn begin_dict(&mut self) -> Result<()> {
+        self.begin_value()?;
+        self.inner.write_all(b"d")?;
+        self.stack.push(Frame::Dict { expect_key: true });
+        Ok(())
+    }
+
+    pub fn end_dict(&mut self) -> Result<()> {
+        match self.stack.last() {
+            Some(Frame::Dict { expect_key: true }) => {
+                self.stack.pop();
+                self.inner.write_all(b"e")?;
+                Ok(())
+            }
+            Some(Frame::Dict { expect_key: false }) => {
+                Err(BencodeError::msg("Dictionary key without value"))
+            }
+            _ => Err(BencodeError::msg("end_dict outside of a dictionary")),
+        }
+    }
+
+    pub fn begin_list(&mut self) -> Result<()> {
+        self.begin_value()?;
+        self.inner.write_all(b"l")?;
+        self.stack.push(Frame::List);
+        Ok(())
+    }
+
+    pub fn end_list(&mut self) -> Result<()> {
+        match self.stack.last() {
+            Some(Frame::List) => {
+                self.stack.pop();
+                self.inner.write_all(b"e")?;
+                Ok(())
+            }
+            _ => Err(BencodeError::msg("end_list outside of a list")),
+        }
+    }
+
+    /// Write the next dictionary key. Must be followed by exactly one value.
+    pub fn key<K: AsRef<[u8]>>(&mut self, key: K) -> Result<()> {
+        match self.stack.last_mut() {
+            Some(Frame::Dict { expect_key }) if *expect_key => {
+                *expect_key = false;
+                encode::put_str(key.as_ref(), &mut IoSink(&mut self.inner))
+            }
+            Some(Frame::Dict { .. }) => Err(BencodeError::msg("Expected a value, found a key")),
+            _ => Err(BencodeError::msg("Key outside of a dictionary")),
+        }
+    }
+
+    /// Write a complete value.
+    pub fn value(&mut self, value: &Value) -> Result<()> {
+        self.begin_value()?;
+        encode::encode(value, &mut IoSink(&mut self.inner))
+    }
+
+    pub fn int(&mut self, i: i64) -> Result<()> {
+        self.begin_value()?;
+        encode::put_int(i, &mut IoSink(&mut self.inner))
+    }
+
+    pub fn bytes<B: AsRef<[u8]>>(&mut self, bytes: B) -> Result<()> {
+        self.begin_value()?;
+        encode::put_str(bytes.as_ref(), &mut IoSink(&mut self.inner))
+    }
+
+    /// Nesting depth of currently open lists and dictionaries.
+    pub fn depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.inner.flush()?;
+        Ok(())
+    }
+
+    /// Return the underlying writer. Fails if a list or dictionary is still open.
+    pub fn into_inner(self) -> Result<W> {
+        if self.stack.is_empty() {
+            Ok(self.inner)
+        } else {
+            Err(BencodeError::msg(format!(
+                "{} unclosed list(s) or dictionary(ies)",
+                self.stack.len()
+            )))
+        }
+    }
+
+    fn begin_value(&mut self) -> Result<()> {
+        match self.stack.last_mut() {
+            Some(Frame::Dict { expect_key }) if *expect_key => {
+                Err(BencodeError::msg("Expected a dictionary key"))
+            }
+            Some(Frame::Dict { expect_key }) => {
+                *expect_key = true;
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Encode a dictionary straight from an iterator of pairs. With `sort_keys`
+/// the pairs are buffered and written in key order, as canonical bencode
+/// requires; otherwise they stream out in iteration order.
+pub fn encode_dict_from_iter<W, I, K, V>(iter: I, writer: &mut W, sort_keys: bool) -> Result<()>
+where
+    W: Write + ?Sized,
+    I: IntoIterator<Item = (K, V)>,
+    K: AsRef<[u8]>,
+    V: Borrow<Value>,
+{
+    let mut w = BencodeWriter::new(writer);
+    w.begin_dict()?;
+    if sort_keys {
+        let mut pairs: Vec<(K, V)> = iter.into_iter().collect();
+        pairs.sort_by(|a, b| a.0.as_ref().cmp(b.0.as_ref()));
+        for (k, v) in pairs {
+            w.key(k)?;
+            w.value(v.borrow())?;
+        }
+    } else {
+        for (k, v) in iter {
+            w.key(k)?;
+            w.value(v.borrow())?;
+        }
+    }
+    w.end_dict()
+}
+
+/// Encode a list straight from an iterator of values.
+pub fn encode_list_from_iter<W, I, V>(iter: I, writer: &mut W) -> Result<()>
+where
+    W: Write + ?Sized,
+    I: IntoIterator<Item = V>,
+    V: Borrow<Value>,
+{
+    let mut w = BencodeWriter::new(writer);
+    w.begin_list()?;
+    for v in iter {
+        w.value(v.borrow())?;
+    }
+    w.end_list()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_writer_nested() {
+        let mut w = BencodeWriter::new(Vec::new());
+        w.begin_dict().unwrap();
+        w.key("a").unwrap();
+        w.value(&Value::List(vec![Value::from("x")])).unwrap();
+        w.key("b").unwrap();
+        w.begin_dict().unwrap();
+        w.end_dict().unwrap();
+        w.key("c").unwrap();
+        w.bytes("yz").unwrap();
+        w.end_dict().unwrap();
+        assert_eq!(w.into_inner().unwrap(), b"d1:al1:xe1:bde1:c2:yze");
+    }
+
+    #[test]
+    fn test_writer_rejects_misuse() {
+        let mut w = BencodeWriter::new(Vec::new());
+        w.begin_dict().unwrap();
+        assert!(w.int(1).is_err());
+        assert!(w.end_list().is_err());
+        w.key("k").unwrap();
+        assert!(w.key("again").is_err());
+        assert!(w.end_dict().is_err());
+        w.int(1).unwrap();
+        assert!(w.into_inner().is_err());
+    }
+
+    #[test]
+    fn test_encode_from_iter() {
+        let pairs = vec![("b", Value::Int(2)), ("a", Value::Int(1))];
+        let mut out = Vec::new();
+        encode_dict_from_iter(pairs.clone(), &mut out, true).unwrap();
+        assert_eq!(out, b"d1:ai1e1:bi2ee");
+
+        out.clear();
+        encode_dict_from_iter(pairs, &mut out, false).unwrap();
+        assert_eq!(out, b"d1:bi2e1:ai1ee");
+
+        out.clear();
+        encode_list_from_iter((1..4).map(Value::Int), &mut out).unwrap();
+        assert_eq!(out, b"li1ei2ei3ee");
+    }
+}